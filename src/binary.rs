@@ -1,13 +1,29 @@
+use anyhow::Context as _;
+
+pub mod builder;
 mod decode;
+mod encode;
 pub mod instr;
 pub mod ty;
 pub mod value;
 
+pub use decode::Span;
+
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Module {
     pub type_section: Option<TypeSection>,
+    pub import_section: Option<ImportSection>,
     pub func_section: Option<FuncSection>,
+    pub table_section: Option<TableSection>,
+    pub memory_section: Option<MemorySection>,
+    pub global_section: Option<GlobalSection>,
+    pub export_section: Option<ExportSection>,
+    pub start_section: Option<StartSection>,
+    pub element_section: Option<ElementSection>,
+    pub data_count_section: Option<DataCountSection>,
     pub code_section: Option<CodeSection>,
+    pub data_section: Option<DataSection>,
+    pub name_section: Option<NameSection>,
 }
 
 impl Module {
@@ -16,6 +32,49 @@ impl Module {
     ) -> anyhow::Result<Self> {
         bytes.into().decode()
     }
+
+    /// Decodes a module along with the byte spans of its sections, types,
+    /// functions and function body instructions
+    pub fn decode_with_source_map<R: std::io::Read>(
+        bytes: impl Into<decode::ByteReader<R>>,
+    ) -> anyhow::Result<(Self, SourceMap)> {
+        let mut bytes = bytes.into().with_source_map();
+        let module = bytes.decode()?;
+        Ok((module, bytes.take_source_map().unwrap_or_default()))
+    }
+
+    /// Decodes a module, rejecting constructs of the proposals not enabled in
+    /// `features`
+    pub fn decode_with_features<R: std::io::Read>(
+        bytes: impl Into<decode::ByteReader<R>>,
+        features: crate::features::WasmFeatures,
+    ) -> anyhow::Result<Self> {
+        bytes.into().with_features(features).decode()
+    }
+
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        self.encode_to(Vec::new())
+    }
+
+    pub fn encode_to<W: std::io::Write>(&self, writer: W) -> anyhow::Result<W> {
+        let mut bytes = encode::ByteWriter::new(writer);
+        bytes.encode(self)?;
+        Ok(bytes.into_inner())
+    }
+}
+
+/// Byte spans of decoded items, indexed in the same order as they appear in
+/// the `Module`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Contents of each section, excluding its ID and byte count
+    pub sections: Vec<(SectionId, decode::Span)>,
+    /// Each entry of `TypeSection`
+    pub types: Vec<decode::Span>,
+    /// Each entry of `CodeSection`, including its byte count
+    pub funcs: Vec<decode::Span>,
+    /// Each instruction of each function body in `CodeSection`
+    pub instrs: Vec<Vec<decode::Span>>,
 }
 
 impl<R: std::io::Read> decode::Decode<R> for Module {
@@ -25,22 +84,154 @@ impl<R: std::io::Read> decode::Decode<R> for Module {
         let mut module = Self::default();
         bytes.consume_constant("\0asm")?;
         bytes.consume_constant(1u32.to_le_bytes())?;
+        let mut last_order = 0;
         while !bytes.is_finished() {
             let section_id: SectionId = bytes.decode()?;
+            if let Some(order) = section_id.order() {
+                anyhow::ensure!(
+                    last_order < order,
+                    "unexpected {section_id:?} section: sections must appear once each in order"
+                );
+                last_order = order;
+            }
             let byte_count: u32 = bytes.decode()?;
+            let start = bytes.offset();
             match section_id {
-                SectionId::Custom => bytes.skip_bytes(byte_count as usize)?,
+                SectionId::Custom => {
+                    let name: String = bytes.decode()?;
+                    let remaining = (start + byte_count as usize)
+                        .checked_sub(bytes.offset())
+                        .context("custom section name exceeds its section")?;
+                    if "name" == name {
+                        let contents = bytes.next_bytes(remaining)?;
+                        // A malformed name section doesn't make the module
+                        // malformed, so it is just ignored
+                        module.name_section = decode::ByteReader::from(contents).decode().ok();
+                    } else {
+                        bytes.skip_bytes(remaining)?;
+                    }
+                }
                 SectionId::Type => module.type_section = Some(bytes.decode()?),
+                SectionId::Import => module.import_section = Some(bytes.decode()?),
                 SectionId::Func => module.func_section = Some(bytes.decode()?),
+                SectionId::Table => module.table_section = Some(bytes.decode()?),
+                SectionId::Memory => module.memory_section = Some(bytes.decode()?),
+                SectionId::Global => module.global_section = Some(bytes.decode()?),
+                SectionId::Export => module.export_section = Some(bytes.decode()?),
+                SectionId::Start => module.start_section = Some(bytes.decode()?),
+                SectionId::Element => module.element_section = Some(bytes.decode()?),
+                SectionId::DataCount => {
+                    bytes.require(crate::features::Feature::BulkMemory)?;
+                    module.data_count_section = Some(bytes.decode()?);
+                }
                 SectionId::Code => module.code_section = Some(bytes.decode()?),
+                SectionId::Data => module.data_section = Some(bytes.decode()?),
                 _ => anyhow::bail!("unimplemented section ID: {section_id:?}"),
             }
+            anyhow::ensure!(
+                bytes.offset() - start == byte_count as usize,
+                "section size mismatch: {section_id:?} section declares {byte_count} bytes, \
+                 but its contents take {}",
+                bytes.offset() - start
+            );
+            let span = decode::Span {
+                start,
+                end: bytes.offset(),
+            };
+            if let Some(source_map) = bytes.source_map_mut() {
+                source_map.sections.push((section_id, span));
+            }
+        }
+        let func_count = module
+            .func_section
+            .as_ref()
+            .map_or(0, |section| section.0.len());
+        let code_count = module
+            .code_section
+            .as_ref()
+            .map_or(0, |section| section.0.len());
+        anyhow::ensure!(
+            func_count == code_count,
+            "function and code section have inconsistent lengths: {func_count} and {code_count}"
+        );
+        if let Some(DataCountSection(count)) = module.data_count_section {
+            let data_count = module
+                .data_section
+                .as_ref()
+                .map_or(0, |section| section.0.len());
+            anyhow::ensure!(
+                count as usize == data_count,
+                "data count and data section have inconsistent lengths: {count} and {data_count}"
+            );
         }
         Ok(module)
     }
 }
 
-#[derive(Debug)]
+impl<W: std::io::Write> encode::Encode<W> for Module {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.write_bytes("\0asm")?;
+        bytes.write_bytes(1u32.to_le_bytes())?;
+        if let Some(section) = &self.type_section {
+            bytes.encode(&SectionId::Type)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.import_section {
+            bytes.encode(&SectionId::Import)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.func_section {
+            bytes.encode(&SectionId::Func)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.table_section {
+            bytes.encode(&SectionId::Table)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.memory_section {
+            bytes.encode(&SectionId::Memory)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.global_section {
+            bytes.encode(&SectionId::Global)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.export_section {
+            bytes.encode(&SectionId::Export)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.start_section {
+            bytes.encode(&SectionId::Start)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.element_section {
+            bytes.encode(&SectionId::Element)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.data_count_section {
+            bytes.encode(&SectionId::DataCount)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.code_section {
+            bytes.encode(&SectionId::Code)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.data_section {
+            bytes.encode(&SectionId::Data)?;
+            bytes.encode_sized(|bytes| bytes.encode(section))?;
+        }
+        if let Some(section) = &self.name_section {
+            bytes.encode(&SectionId::Custom)?;
+            bytes.encode_sized(|bytes| {
+                bytes.encode(&String::from("name"))?;
+                bytes.encode(section)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SectionId {
     Custom,
     Type,
@@ -58,6 +249,31 @@ pub enum SectionId {
     Tag,
 }
 
+impl SectionId {
+    /// Position of a non-custom section in a module, where each may appear at
+    /// most once. The data count section comes between the element and code
+    /// sections despite its ID
+    fn order(self) -> Option<u8> {
+        use SectionId::*;
+        Some(match self {
+            Custom => return None,
+            Type => 1,
+            Import => 2,
+            Func => 3,
+            Table => 4,
+            Memory => 5,
+            Tag => 6,
+            Global => 7,
+            Export => 8,
+            Start => 9,
+            Element => 10,
+            DataCount => 11,
+            Code => 12,
+            Data => 13,
+        })
+    }
+}
+
 impl decode::DecodeTag for SectionId {
     fn decode_tag(byte: u8) -> Option<Self> {
         use SectionId::*;
@@ -81,18 +297,99 @@ impl decode::DecodeTag for SectionId {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl encode::EncodeTag for SectionId {
+    fn encode_tag(&self) -> u8 {
+        use SectionId::*;
+        match self {
+            Custom => 0,
+            Type => 1,
+            Import => 2,
+            Func => 3,
+            Table => 4,
+            Memory => 5,
+            Global => 6,
+            Export => 7,
+            Start => 8,
+            Element => 9,
+            Code => 10,
+            Data => 11,
+            DataCount => 12,
+            Tag => 13,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TypeSection(pub Vec<ty::Recursive>);
 
 impl<R: std::io::Read> decode::Decode<R> for TypeSection {
     type Tag = ();
 
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        let len: u32 = bytes.decode()?;
+        let mut types = Vec::new();
+        for _ in 0..len {
+            let (ty, span) = bytes.decode_spanned()?;
+            if let Some(source_map) = bytes.source_map_mut() {
+                source_map.types.push(span);
+            }
+            types.push(ty);
+        }
+        Ok(Self(types))
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for TypeSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSection(pub Vec<Import>);
+
+impl<R: std::io::Read> decode::Decode<R> for ImportSection {
+    type Tag = ();
+
     fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
         bytes.decode().map(Self)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl<W: std::io::Write> encode::Encode<W> for ImportSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: ty::External,
+}
+
+impl<R: std::io::Read> decode::Decode<R> for Import {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        Ok(Self {
+            module: bytes.decode()?,
+            name: bytes.decode()?,
+            ty: bytes.decode()?,
+        })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for Import {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.module)?;
+        bytes.encode(&self.name)?;
+        bytes.encode(&self.ty)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FuncSection(pub Vec<u32>);
 
 impl<R: std::io::Read> decode::Decode<R> for FuncSection {
@@ -103,18 +400,363 @@ impl<R: std::io::Read> decode::Decode<R> for FuncSection {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl<W: std::io::Write> encode::Encode<W> for FuncSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableSection(pub Vec<ty::Table>);
+
+impl<R: std::io::Read> decode::Decode<R> for TableSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for TableSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemorySection(pub Vec<ty::Memory>);
+
+impl<R: std::io::Read> decode::Decode<R> for MemorySection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for MemorySection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalSection(pub Vec<Global>);
+
+impl<R: std::io::Read> decode::Decode<R> for GlobalSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for GlobalSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Global {
+    pub ty: ty::Global,
+    pub init: instr::Expression,
+}
+
+impl<R: std::io::Read> decode::Decode<R> for Global {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        Ok(Self {
+            ty: bytes.decode()?,
+            init: bytes.decode()?,
+        })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for Global {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.ty)?;
+        bytes.encode(&self.init)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportSection(pub Vec<Export>);
+
+impl<R: std::io::Read> decode::Decode<R> for ExportSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for ExportSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub index: ExternalIndex,
+}
+
+impl<R: std::io::Read> decode::Decode<R> for Export {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: bytes.decode()?,
+            index: bytes.decode()?,
+        })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for Export {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.name)?;
+        bytes.encode(&self.index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalIndex {
+    Func(u32),
+    Table(u32),
+    Memory(u32),
+    Global(u32),
+    Tag(u32),
+}
+
+impl<R: std::io::Read> decode::Decode<R> for ExternalIndex {
+    type Tag = ty::ExternalTag;
+
+    fn decode(bytes: &mut decode::ByteReader<R>, tag: ty::ExternalTag) -> anyhow::Result<Self> {
+        let index = bytes.decode()?;
+        Ok(match tag {
+            ty::ExternalTag::Func => Self::Func(index),
+            ty::ExternalTag::Table => Self::Table(index),
+            ty::ExternalTag::Memory => Self::Memory(index),
+            ty::ExternalTag::Global => Self::Global(index),
+            ty::ExternalTag::Tag => Self::Tag(index),
+        })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for ExternalIndex {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        let (tag, index) = match self {
+            Self::Func(index) => (0x00, index),
+            Self::Table(index) => (0x01, index),
+            Self::Memory(index) => (0x02, index),
+            Self::Global(index) => (0x03, index),
+            Self::Tag(index) => (0x04, index),
+        };
+        bytes.write(tag)?;
+        bytes.encode(index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartSection(pub u32);
+
+impl<R: std::io::Read> decode::Decode<R> for StartSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for StartSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementSection(pub Vec<Element>);
+
+impl<R: std::io::Read> decode::Decode<R> for ElementSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for ElementSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    pub mode: ElementMode,
+    pub init: ElementInit,
+}
+
+impl Element {
+    /// Type of the elements of segments given as function indices, and of
+    /// the expressions of segments without an explicit type
+    pub const FUNC_REF: ty::Reference = ty::Reference {
+        heap: ty::Heap::Abstract(ty::AbsHeap::Func),
+        is_nullable: true,
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElementMode {
+    Passive,
+    Active {
+        table: u32,
+        offset: instr::Expression,
+    },
+    Declarative,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElementInit {
+    Funcs(Vec<u32>),
+    Exprs {
+        ty: ty::Reference,
+        exprs: Vec<instr::Expression>,
+    },
+}
+
+impl<R: std::io::Read> decode::Decode<R> for Element {
+    type Tag = ();
+
+    /// Decodes one of the eight encodings of element segments, whose flags
+    /// tell whether the segment is passive or declarative, has an explicit
+    /// table index or element type, and consists of expressions
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        let flags: u32 = bytes.decode()?;
+        anyhow::ensure!(flags < 8, "malformed element segment flags: {flags}");
+        if 0 != flags {
+            bytes.require(crate::features::Feature::BulkMemory)?;
+        }
+        let mode = match flags & 0b011 {
+            0b000 => ElementMode::Active {
+                table: 0,
+                offset: bytes.decode()?,
+            },
+            0b010 => ElementMode::Active {
+                table: bytes.decode()?,
+                offset: bytes.decode()?,
+            },
+            0b001 => ElementMode::Passive,
+            _ => ElementMode::Declarative,
+        };
+        let has_type = 0 != flags & 0b011;
+        let init = if 0 == flags & 0b100 {
+            if has_type {
+                bytes
+                    .consume_constant([0x00])
+                    .context("malformed element kind")?;
+            }
+            ElementInit::Funcs(bytes.decode()?)
+        } else {
+            ElementInit::Exprs {
+                ty: match has_type {
+                    true => bytes.decode()?,
+                    false => Self::FUNC_REF,
+                },
+                exprs: bytes.decode()?,
+            }
+        };
+        Ok(Self { mode, init })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for Element {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        let mut flags = match self.mode {
+            ElementMode::Active { table: 0, .. } => 0b000,
+            ElementMode::Passive => 0b001,
+            ElementMode::Active { .. } => 0b010,
+            ElementMode::Declarative => 0b011,
+        };
+        if let ElementInit::Exprs { ty, .. } = &self.init {
+            flags |= 0b100;
+            if 0b100 == flags && Self::FUNC_REF != *ty {
+                // The element type can only be given along with the table
+                flags |= 0b010;
+            }
+        }
+        bytes.encode(&(flags as u32))?;
+        if let ElementMode::Active { table, offset } = &self.mode {
+            if 0 != flags & 0b010 {
+                bytes.encode(table)?;
+            }
+            bytes.encode(offset)?;
+        }
+        let has_type = 0 != flags & 0b011;
+        match &self.init {
+            ElementInit::Funcs(funcs) => {
+                if has_type {
+                    bytes.write(0x00)?;
+                }
+                bytes.encode(funcs)
+            }
+            ElementInit::Exprs { ty, exprs } => {
+                if has_type {
+                    bytes.encode(ty)?;
+                }
+                bytes.encode(exprs)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataCountSection(pub u32);
+
+impl<R: std::io::Read> decode::Decode<R> for DataCountSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for DataCountSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CodeSection(pub Vec<Func>);
 
 impl<R: std::io::Read> decode::Decode<R> for CodeSection {
     type Tag = ();
 
     fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
-        bytes.decode().map(Self)
+        let len: u32 = bytes.decode()?;
+        let mut funcs = Vec::new();
+        for _ in 0..len {
+            let (func, span) = bytes.decode_spanned()?;
+            if let Some(source_map) = bytes.source_map_mut() {
+                source_map.funcs.push(span);
+            }
+            funcs.push(func);
+        }
+        Ok(Self(funcs))
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl<W: std::io::Write> encode::Encode<W> for CodeSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Func {
     pub locals: Vec<Local>,
     pub expr: instr::Expression,
@@ -125,14 +767,25 @@ impl<R: std::io::Read> decode::Decode<R> for Func {
 
     fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
         let _byte_count: u32 = bytes.decode()?;
-        Ok(Self {
-            locals: bytes.decode()?,
-            expr: bytes.decode()?,
+        let locals = bytes.decode()?;
+        let (expr, spans) = instr::Expression::decode_spanned(bytes)?;
+        if let Some(source_map) = bytes.source_map_mut() {
+            source_map.instrs.push(spans);
+        }
+        Ok(Self { locals, expr })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for Func {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode_sized(|bytes| {
+            bytes.encode(&self.locals)?;
+            bytes.encode(&self.expr)
         })
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Local {
     pub num: u32,
     pub ty: ty::Value,
@@ -149,6 +802,205 @@ impl<R: std::io::Read> decode::Decode<R> for Local {
     }
 }
 
+impl<W: std::io::Write> encode::Encode<W> for Local {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.num)?;
+        bytes.encode(&self.ty)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataSection(pub Vec<Data>);
+
+impl<R: std::io::Read> decode::Decode<R> for DataSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        bytes.decode().map(Self)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for DataSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Data {
+    pub mode: DataMode,
+    pub init: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataMode {
+    Passive,
+    Active {
+        memory: u32,
+        offset: instr::Expression,
+    },
+}
+
+impl<R: std::io::Read> decode::Decode<R> for Data {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        let flags: u32 = bytes.decode()?;
+        if 0 != flags {
+            bytes.require(crate::features::Feature::BulkMemory)?;
+        }
+        let mode = match flags {
+            0 => DataMode::Active {
+                memory: 0,
+                offset: bytes.decode()?,
+            },
+            1 => DataMode::Passive,
+            2 => DataMode::Active {
+                memory: bytes.decode()?,
+                offset: bytes.decode()?,
+            },
+            flags => anyhow::bail!("malformed data segment flags: {flags}"),
+        };
+        let len: u32 = bytes.decode()?;
+        Ok(Self {
+            mode,
+            init: bytes.next_bytes(len as usize)?,
+        })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for Data {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match &self.mode {
+            DataMode::Active { memory: 0, offset } => {
+                bytes.encode(&0u32)?;
+                bytes.encode(offset)?;
+            }
+            DataMode::Passive => bytes.encode(&1u32)?,
+            DataMode::Active { memory, offset } => {
+                bytes.encode(&2u32)?;
+                bytes.encode(memory)?;
+                bytes.encode(offset)?;
+            }
+        }
+        bytes.encode(&(self.init.len() as u32))?;
+        bytes.write_bytes(&self.init)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NameSection {
+    pub module: Option<String>,
+    pub funcs: Vec<Naming>,
+    pub locals: Vec<IndirectNaming>,
+    pub types: Vec<Naming>,
+    pub tables: Vec<Naming>,
+    pub memories: Vec<Naming>,
+    pub globals: Vec<Naming>,
+    pub fields: Vec<IndirectNaming>,
+}
+
+impl<R: std::io::Read> decode::Decode<R> for NameSection {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        let mut section = Self::default();
+        while !bytes.is_finished() {
+            let id = bytes.next()?;
+            let byte_count: u32 = bytes.decode()?;
+            match id {
+                0 => section.module = Some(bytes.decode()?),
+                1 => section.funcs = bytes.decode()?,
+                2 => section.locals = bytes.decode()?,
+                4 => section.types = bytes.decode()?,
+                5 => section.tables = bytes.decode()?,
+                6 => section.memories = bytes.decode()?,
+                7 => section.globals = bytes.decode()?,
+                10 => section.fields = bytes.decode()?,
+                _ => bytes.skip_bytes(byte_count as usize)?,
+            }
+        }
+        Ok(section)
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for NameSection {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        if let Some(module) = &self.module {
+            bytes.write(0)?;
+            bytes.encode_sized(|bytes| bytes.encode(module))?;
+        }
+        // Subsections must be in the order of their IDs
+        fn encode_subsection<W: std::io::Write, E: encode::Encode<Vec<u8>>>(
+            bytes: &mut encode::ByteWriter<W>,
+            id: u8,
+            map: &Vec<E>,
+        ) -> anyhow::Result<()> {
+            if map.is_empty() {
+                return Ok(());
+            }
+            bytes.write(id)?;
+            bytes.encode_sized(|bytes| bytes.encode(map))
+        }
+        encode_subsection(bytes, 1, &self.funcs)?;
+        encode_subsection(bytes, 2, &self.locals)?;
+        encode_subsection(bytes, 4, &self.types)?;
+        encode_subsection(bytes, 5, &self.tables)?;
+        encode_subsection(bytes, 6, &self.memories)?;
+        encode_subsection(bytes, 7, &self.globals)?;
+        encode_subsection(bytes, 10, &self.fields)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Naming {
+    pub index: u32,
+    pub name: String,
+}
+
+impl<R: std::io::Read> decode::Decode<R> for Naming {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        Ok(Self {
+            index: bytes.decode()?,
+            name: bytes.decode()?,
+        })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for Naming {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.index)?;
+        bytes.encode(&self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndirectNaming {
+    pub index: u32,
+    pub names: Vec<Naming>,
+}
+
+impl<R: std::io::Read> decode::Decode<R> for IndirectNaming {
+    type Tag = ();
+
+    fn decode(bytes: &mut decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        Ok(Self {
+            index: bytes.decode()?,
+            names: bytes.decode()?,
+        })
+    }
+}
+
+impl<W: std::io::Write> encode::Encode<W> for IndirectNaming {
+    fn encode(&self, bytes: &mut encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.index)?;
+        bytes.encode(&self.names)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,6 +1030,7 @@ mod tests {
                     locals: vec![],
                     expr: instr::Expression(vec![instr::Instruction::End]),
                 }])),
+                ..Default::default()
             },
             Module::decode(wasm)?,
         );
@@ -205,6 +1058,7 @@ mod tests {
                     locals: vec![],
                     expr: instr::Expression(vec![instr::Instruction::End]),
                 }])),
+                ..Default::default()
             },
             Module::decode(wasm)?,
         );
@@ -238,6 +1092,7 @@ mod tests {
                     ],
                     expr: instr::Expression(vec![instr::Instruction::End]),
                 }])),
+                ..Default::default()
             },
             Module::decode(wasm)?,
         );
@@ -280,12 +1135,61 @@ mod tests {
                         instr::Instruction::End,
                     ]),
                 }])),
+                ..Default::default()
             },
             Module::decode(wasm)?,
         );
         Ok(())
     }
 
+    #[test]
+    fn decode_source_map() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            "\
+(module
+    (func (param i32 i32) (result i32)
+        (local.get 0)
+        (local.get 1)
+        i32.add
+    )
+)
+",
+        )?;
+        let span = |start, end| decode::Span { start, end };
+        assert_eq!(
+            SourceMap {
+                sections: vec![
+                    (SectionId::Type, span(10, 17)),
+                    (SectionId::Func, span(19, 21)),
+                    (SectionId::Code, span(23, 32)),
+                ],
+                types: vec![span(11, 17)],
+                funcs: vec![span(24, 32)],
+                instrs: vec![vec![span(26, 28), span(28, 30), span(30, 31), span(31, 32)]],
+            },
+            Module::decode_with_source_map(wasm)?.1,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_untrusted_sizes() {
+        // A type section claiming u32::MAX types must fail at EOF rather
+        // than allocate for all of them
+        let huge = b"\0asm\x01\0\0\0\x01\x05\xff\xff\xff\xff\x0f";
+        assert!(Module::decode(huge.to_vec()).is_err());
+        for wasm in [
+            &b"\0asm\x01\0\0\0\x01\x02\x00\x00"[..],
+            &b"\0asm\x01\0\0\0\x01\x00\x00"[..],
+        ] {
+            let error = Module::decode(wasm.to_vec()).unwrap_err();
+            assert!(
+                format!("{error:#}").contains("section size mismatch"),
+                "{error:#}"
+            );
+        }
+    }
+
     #[test]
     fn decode_recursive_struct() -> anyhow::Result<()> {
         let wasm = wat::parse_str(
@@ -338,10 +1242,224 @@ mod tests {
                         ])
                     },
                 ])])),
+                name_section: Some(NameSection {
+                    types: vec![
+                        Naming {
+                            index: 0,
+                            name: "A".to_owned(),
+                        },
+                        Naming {
+                            index: 1,
+                            name: "B".to_owned(),
+                        },
+                    ],
+                    ..Default::default()
+                }),
                 ..Default::default()
             },
             Module::decode(wasm)?,
         );
         Ok(())
     }
+
+    #[test]
+    fn decode_bottom_heap_types() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            "(module (type (struct (field nullref nullexternref nullfuncref nullexnref))))",
+        )?;
+        let field = |heap| ty::Field {
+            storage: ty::Storage::Value(ty::Value::Ref(ty::Reference {
+                heap: ty::Heap::Abstract(heap),
+                is_nullable: true,
+            })),
+            is_mutable: false,
+        };
+        assert_eq!(
+            Some(TypeSection(vec![ty::Recursive(vec![ty::Sub {
+                is_final: true,
+                supers: vec![],
+                composite: ty::Composite::Struct(vec![
+                    field(ty::AbsHeap::None),
+                    field(ty::AbsHeap::NoExtern),
+                    field(ty::AbsHeap::NoFunc),
+                    field(ty::AbsHeap::NoException),
+                ]),
+            }])])),
+            Module::decode(wasm)?.type_section,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_segments() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+(module
+    (table 2 externref)
+    (memory 1)
+    (func)
+    (start 0)
+    (elem (table 0) (i32.const 1) externref (ref.null extern))
+    (elem declare func 0)
+    (data "\01\02")
+    (data (i32.const 16) "hi")
+)
+"#,
+        )?;
+        let module = Module::decode(wasm)?;
+        let externref = ty::Reference {
+            heap: ty::Heap::Abstract(ty::AbsHeap::Extern),
+            is_nullable: true,
+        };
+        let expr = |instr| instr::Expression(vec![instr, instr::Instruction::End]);
+        assert_eq!(
+            Some(TableSection(vec![ty::Table {
+                reference: externref.clone(),
+                limit: ty::Limit {
+                    address: ty::Address::I32,
+                    min: 2,
+                    max: None,
+                },
+            }])),
+            module.table_section,
+        );
+        assert_eq!(Some(StartSection(0)), module.start_section);
+        assert_eq!(
+            Some(ElementSection(vec![
+                Element {
+                    mode: ElementMode::Active {
+                        table: 0,
+                        offset: expr(instr::Instruction::I32Const(1)),
+                    },
+                    init: ElementInit::Exprs {
+                        ty: externref,
+                        exprs: vec![expr(instr::Instruction::RefNull(ty::Heap::Abstract(
+                            ty::AbsHeap::Extern,
+                        )))],
+                    },
+                },
+                Element {
+                    mode: ElementMode::Declarative,
+                    init: ElementInit::Funcs(vec![0]),
+                },
+            ])),
+            module.element_section,
+        );
+        assert_eq!(
+            Some(DataSection(vec![
+                Data {
+                    mode: DataMode::Passive,
+                    init: vec![1, 2],
+                },
+                Data {
+                    mode: DataMode::Active {
+                        memory: 0,
+                        offset: expr(instr::Instruction::I32Const(16)),
+                    },
+                    init: b"hi".to_vec(),
+                },
+            ])),
+            module.data_section,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_features() -> anyhow::Result<()> {
+        use crate::features::WasmFeatures;
+
+        let message = |wat: &str, features| -> anyhow::Result<_> {
+            let error = Module::decode_with_features(wat::parse_str(wat)?, features).unwrap_err();
+            Ok(format!("{:#}", error.root_cause()))
+        };
+        let mvp = WasmFeatures::mvp();
+        let fixtures = [
+            ("(module (type (struct)))", "gc"),
+            ("(module (func (param v128)))", "simd"),
+            ("(module (func (param externref)))", "reference-types"),
+            ("(module (memory i64 1))", "memory64"),
+            (
+                "(module (func (result i32 i32) unreachable))",
+                "multi-value",
+            ),
+            (
+                "(module (func (drop (i32.extend8_s (i32.const 0)))))",
+                "sign-ext",
+            ),
+            (
+                "(module (func (drop (ref.is_null (ref.null func)))))",
+                "reference-types",
+            ),
+            ("(module (memory 1) (data \"\"))", "bulk-memory"),
+        ];
+        for (wat, feature) in fixtures {
+            assert_eq!(
+                format!("feature {feature} not enabled"),
+                message(wat, mvp)?,
+                "{wat}",
+            );
+        }
+        assert_eq!(
+            "feature function-references not enabled",
+            message("(module (func (param (ref func))))", WasmFeatures::wasm2())?,
+        );
+        assert_eq!(
+            "feature exceptions not enabled",
+            message(
+                "(module (type (func)) (import \"m\" \"t\" (tag (type 0))))",
+                WasmFeatures::wasm2()
+            )?,
+        );
+        assert_eq!(
+            "feature reference-types not enabled",
+            message("(module (func (local funcref)))", mvp)?,
+        );
+        let fixtures = [
+            "(module (func (result i32) (i32.add (i32.const 1) (i32.const 2))))",
+            "(module (table 1 funcref) (func) (elem (i32.const 0) 0))",
+            "(module (type (func)) (table 1 funcref) (func (call_indirect (type 0) (i32.const 0))))",
+        ];
+        for wat in fixtures {
+            Module::decode_with_features(wat::parse_str(wat)?, mvp)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn encode_round_trip() -> anyhow::Result<()> {
+        let fixtures = [
+            "(module)",
+            "(module (func))",
+            "(module (func (param i32 i64)))",
+            "(module (func (local i32) (local i64 i64)))",
+            "(module (func (param i32 i32) (result i32) local.get 0 local.get 1 i32.add))",
+            "(module (type (func (param f32 f64 v128) (result externref funcref))))",
+            "(module (type (array (mut i8))) (type (array i16)))",
+            "(module (type (struct (field (ref null exn) (ref none) (ref noextern)))))",
+            "(module (type (struct (field (ref nofunc) (ref null noexn) (ref i31)))))",
+            "(module (type $t (sub (struct))) (type (sub final $t (struct))))",
+            "(module (rec (type $A (struct (field (mut (ref null $B))))) (type $B (array (ref $A)))))",
+            r#"(module (import "env" "f" (func)) (memory (export "m") 1 2) (global i64 (i64.const -1)))"#,
+            "(module (memory 1) (func (param i32) (result i64) (i64.load offset=8 align=4 (local.get 0))))",
+            "(module (func (block (loop (br_table 0 1 (i32.const 3)))) (if (i32.const 1) (then) (else))))",
+            "(module (func (result f32) (f64.const -0.5) (f32.demote_f64) (i32.trunc_sat_f32_s) (drop) (f32.const 1.5)))",
+            "(module (table 1 funcref) (table i64 2 3 externref) (func) (start 0) (elem (i32.const 0) func 0))",
+            "(module (table 1 funcref) (table 1 (ref null func)) (func) (elem (table 1) (i32.const 0) func 0 0))",
+            "(module (func) (elem func 0) (elem declare funcref (ref.func 0)) (elem externref (ref.null extern)))",
+            "(module (table 1 funcref) (elem (table 0) (i32.const 0) (ref null func) (ref.null func)))",
+            r#"(module (memory 1) (memory 1) (data (i32.const 8) "ab") (data "c") (data (memory 1) (i32.const 0)))"#,
+        ];
+        for fixture in fixtures {
+            let module = Module::decode(wat::parse_str(fixture)?)?;
+            assert_eq!(module, Module::decode(module.encode()?)?, "{fixture}");
+        }
+
+        let wasm = wat::parse_str("(module (func (param i32) (result i32) local.get 0))")?;
+        assert_eq!(wasm, Module::decode(wasm.clone())?.encode()?);
+        let wasm = wat::parse_str(
+            r#"(module (table 1 funcref) (memory 1) (func) (elem (i32.const 0) func 0) (data (i32.const 1) "x"))"#,
+        )?;
+        assert_eq!(wasm, Module::decode(wasm.clone())?.encode()?);
+        Ok(())
+    }
 }
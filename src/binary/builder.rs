@@ -0,0 +1,736 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Context as _;
+
+use super::{instr, ty};
+
+/// Identifies a `ModuleBuilder`, so that it only accepts back the
+/// `FunctionBuilder`s it handed out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Owner(u64);
+
+impl Default for Owner {
+    fn default() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Builds a `Module` while assigning indices the way the binary format does:
+/// imported items come first in each index space, followed by definitions
+#[derive(Debug, Default)]
+pub struct ModuleBuilder {
+    owner: Owner,
+    types: Vec<ty::Recursive>,
+    imports: Vec<super::Import>,
+    funcs: Vec<u32>,
+    codes: Vec<Option<super::Func>>,
+    tables: Vec<ty::Table>,
+    memories: Vec<ty::Memory>,
+    globals: Vec<super::Global>,
+    exports: Vec<super::Export>,
+    elems: Vec<super::Element>,
+    datas: Vec<super::Data>,
+    imported_func_count: u32,
+    imported_table_count: u32,
+    imported_memory_count: u32,
+    imported_global_count: u32,
+}
+
+impl ModuleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the index of a function type, adding it to the type section
+    /// unless an identical one is already there
+    pub fn func_type(&mut self, params: &[ty::Value], returns: &[ty::Value]) -> u32 {
+        let ty = ty::Recursive(vec![ty::Sub {
+            is_final: true,
+            supers: vec![],
+            composite: ty::Composite::Func {
+                params: params.to_vec(),
+                returns: returns.to_vec(),
+            },
+        }]);
+        let index = match self.types.iter().position(|t| *t == ty) {
+            Some(index) => index,
+            None => {
+                self.types.push(ty);
+                self.types.len() - 1
+            }
+        };
+        index as u32
+    }
+
+    pub fn import_func(
+        &mut self,
+        module: &str,
+        name: &str,
+        params: &[ty::Value],
+        returns: &[ty::Value],
+    ) -> anyhow::Result<u32> {
+        anyhow::ensure!(
+            self.funcs.is_empty(),
+            "functions must be imported before any is defined"
+        );
+        let ty = self.func_type(params, returns);
+        self.import(module, name, ty::External::Func(ty));
+        self.imported_func_count += 1;
+        Ok(self.imported_func_count - 1)
+    }
+
+    pub fn import_table(&mut self, module: &str, name: &str, ty: ty::Table) -> anyhow::Result<u32> {
+        anyhow::ensure!(
+            self.tables.is_empty(),
+            "tables must be imported before any is defined"
+        );
+        self.import(module, name, ty::External::Table(ty));
+        self.imported_table_count += 1;
+        Ok(self.imported_table_count - 1)
+    }
+
+    pub fn import_memory(
+        &mut self,
+        module: &str,
+        name: &str,
+        limit: ty::Limit,
+    ) -> anyhow::Result<u32> {
+        anyhow::ensure!(
+            self.memories.is_empty(),
+            "memories must be imported before any is defined"
+        );
+        self.import(module, name, ty::External::Memory(ty::Memory(limit)));
+        self.imported_memory_count += 1;
+        Ok(self.imported_memory_count - 1)
+    }
+
+    pub fn import_global(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: ty::Global,
+    ) -> anyhow::Result<u32> {
+        anyhow::ensure!(
+            self.globals.is_empty(),
+            "globals must be imported before any is defined"
+        );
+        self.import(module, name, ty::External::Global(ty));
+        self.imported_global_count += 1;
+        Ok(self.imported_global_count - 1)
+    }
+
+    fn import(&mut self, module: &str, name: &str, ty: ty::External) {
+        self.imports.push(super::Import {
+            module: module.to_owned(),
+            name: name.to_owned(),
+            ty,
+        });
+    }
+
+    /// Declares a function and returns a builder for its body, which must be
+    /// passed back to `finish_func` before `build`
+    pub fn func(&mut self, params: &[ty::Value], returns: &[ty::Value]) -> FunctionBuilder {
+        let ty = self.func_type(params, returns);
+        self.funcs.push(ty);
+        self.codes.push(None);
+        FunctionBuilder {
+            owner: self.owner,
+            index: self.imported_func_count + self.funcs.len() as u32 - 1,
+            param_count: params.len() as u32,
+            locals: vec![],
+            instrs: vec![],
+            labels: vec![],
+        }
+    }
+
+    pub fn finish_func(&mut self, func: FunctionBuilder) -> anyhow::Result<u32> {
+        let index = func.index;
+        anyhow::ensure!(
+            func.owner == self.owner,
+            "function {index} is not declared by this builder"
+        );
+        let code = (index.checked_sub(self.imported_func_count))
+            .and_then(|i| self.codes.get_mut(i as usize))
+            .with_context(|| format!("function {index} is not declared by this builder"))?;
+        anyhow::ensure!(code.is_none(), "function {index} is already finished");
+        *code = Some(func.finish()?);
+        Ok(index)
+    }
+
+    pub fn table(&mut self, ty: ty::Table) -> u32 {
+        self.tables.push(ty);
+        self.imported_table_count + self.tables.len() as u32 - 1
+    }
+
+    pub fn memory(&mut self, limit: ty::Limit) -> u32 {
+        self.memories.push(ty::Memory(limit));
+        self.imported_memory_count + self.memories.len() as u32 - 1
+    }
+
+    pub fn global(&mut self, ty: ty::Global, init: instr::Expression) -> u32 {
+        self.globals.push(super::Global { ty, init });
+        self.imported_global_count + self.globals.len() as u32 - 1
+    }
+
+    pub fn export(&mut self, name: &str, index: super::ExternalIndex) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.exports.iter().all(|export| export.name != name),
+            "duplicate export name: {name:?}"
+        );
+        self.exports.push(super::Export {
+            name: name.to_owned(),
+            index,
+        });
+        Ok(())
+    }
+
+    /// Adds an element segment and returns its index
+    pub fn elem(&mut self, elem: super::Element) -> u32 {
+        self.elems.push(elem);
+        self.elems.len() as u32 - 1
+    }
+
+    /// Adds a data segment and returns its index
+    pub fn data(&mut self, data: super::Data) -> u32 {
+        self.datas.push(data);
+        self.datas.len() as u32 - 1
+    }
+
+    pub fn build(self) -> anyhow::Result<super::Module> {
+        let codes = self
+            .codes
+            .into_iter()
+            .enumerate()
+            .map(|(i, code)| {
+                code.ok_or_else(|| {
+                    let index = self.imported_func_count as usize + i;
+                    anyhow::anyhow!("function {index} is not finished")
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // `memory.init` and `data.drop` are only valid with a data count
+        let data_count = (codes.iter().flat_map(|code| &code.expr.0))
+            .any(|instr| {
+                matches!(
+                    instr,
+                    instr::Instruction::MemoryInit(..) | instr::Instruction::DataDrop(..)
+                )
+            })
+            .then_some(super::DataCountSection(self.datas.len() as u32));
+        fn section<T, S>(items: Vec<T>, f: impl FnOnce(Vec<T>) -> S) -> Option<S> {
+            (!items.is_empty()).then(|| f(items))
+        }
+        Ok(super::Module {
+            type_section: section(self.types, super::TypeSection),
+            import_section: section(self.imports, super::ImportSection),
+            func_section: section(self.funcs, super::FuncSection),
+            table_section: section(self.tables, super::TableSection),
+            memory_section: section(self.memories, super::MemorySection),
+            global_section: section(self.globals, super::GlobalSection),
+            export_section: section(self.exports, super::ExportSection),
+            element_section: section(self.elems, super::ElementSection),
+            data_count_section: data_count,
+            code_section: section(codes, super::CodeSection),
+            data_section: section(self.datas, super::DataSection),
+            ..Default::default()
+        })
+    }
+}
+
+/// Refers to an enclosing `block`, `loop` or `if` opened by a `FunctionBuilder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+#[derive(Debug)]
+pub struct FunctionBuilder {
+    owner: Owner,
+    index: u32,
+    param_count: u32,
+    locals: Vec<super::Local>,
+    instrs: Vec<instr::Instruction>,
+    labels: Vec<Label>,
+}
+
+impl FunctionBuilder {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Adds a local and returns its index, which follows the parameters
+    pub fn local(&mut self, ty: ty::Value) -> u32 {
+        match self.locals.last_mut() {
+            Some(local) if local.ty == ty => local.num += 1,
+            _ => self.locals.push(super::Local { num: 1, ty }),
+        }
+        self.param_count + self.locals.iter().map(|local| local.num).sum::<u32>() - 1
+    }
+
+    pub fn instr(&mut self, instr: instr::Instruction) -> &mut Self {
+        self.instrs.push(instr);
+        self
+    }
+
+    fn label(&self, label: Label) -> anyhow::Result<u32> {
+        let depth = self.labels.iter().rposition(|l| *l == label);
+        let depth = depth.context("label must refer to an enclosing block")?;
+        Ok((self.labels.len() - 1 - depth) as u32)
+    }
+
+    fn open(&mut self, instr: instr::Instruction) -> Label {
+        let label = Label(self.instrs.len());
+        self.instrs.push(instr);
+        self.labels.push(label);
+        label
+    }
+
+    // control
+
+    pub fn unreachable(&mut self) -> &mut Self {
+        self.instr(instr::Instruction::Unreachable)
+    }
+
+    pub fn block(&mut self, ty: instr::BlockType) -> Label {
+        self.open(instr::Instruction::Block(ty))
+    }
+
+    pub fn loop_(&mut self, ty: instr::BlockType) -> Label {
+        self.open(instr::Instruction::Loop(ty))
+    }
+
+    pub fn if_(&mut self, ty: instr::BlockType) -> Label {
+        self.open(instr::Instruction::If(ty))
+    }
+
+    pub fn else_(&mut self) -> &mut Self {
+        self.instr(instr::Instruction::Else)
+    }
+
+    /// Closes the innermost block
+    pub fn end(&mut self) -> anyhow::Result<&mut Self> {
+        self.labels.pop().context("no block to end")?;
+        Ok(self.instr(instr::Instruction::End))
+    }
+
+    pub fn br(&mut self, label: Label) -> anyhow::Result<&mut Self> {
+        let depth = self.label(label)?;
+        Ok(self.instr(instr::Instruction::Br(depth)))
+    }
+
+    pub fn br_if(&mut self, label: Label) -> anyhow::Result<&mut Self> {
+        let depth = self.label(label)?;
+        Ok(self.instr(instr::Instruction::BrIf(depth)))
+    }
+
+    pub fn br_table(&mut self, labels: &[Label], default: Label) -> anyhow::Result<&mut Self> {
+        let labels = (labels.iter())
+            .map(|label| self.label(*label))
+            .collect::<anyhow::Result<_>>()?;
+        let default = self.label(default)?;
+        Ok(self.instr(instr::Instruction::BrTable(labels, default)))
+    }
+
+    pub fn return_(&mut self) -> &mut Self {
+        self.instr(instr::Instruction::Return)
+    }
+
+    pub fn call(&mut self, func: u32) -> &mut Self {
+        self.instr(instr::Instruction::Call(func))
+    }
+
+    pub fn call_indirect(&mut self, ty: u32, table: u32) -> &mut Self {
+        self.instr(instr::Instruction::CallIndirect(ty, table))
+    }
+
+    // parametric
+
+    pub fn drop(&mut self) -> &mut Self {
+        self.instr(instr::Instruction::Drop)
+    }
+
+    pub fn select(&mut self) -> &mut Self {
+        self.instr(instr::Instruction::Select)
+    }
+
+    // variable
+
+    pub fn local_get(&mut self, local: u32) -> &mut Self {
+        self.instr(instr::Instruction::LocalGet(local))
+    }
+
+    pub fn local_set(&mut self, local: u32) -> &mut Self {
+        self.instr(instr::Instruction::LocalSet(local))
+    }
+
+    pub fn local_tee(&mut self, local: u32) -> &mut Self {
+        self.instr(instr::Instruction::LocalTee(local))
+    }
+
+    pub fn global_get(&mut self, global: u32) -> &mut Self {
+        self.instr(instr::Instruction::GlobalGet(global))
+    }
+
+    pub fn global_set(&mut self, global: u32) -> &mut Self {
+        self.instr(instr::Instruction::GlobalSet(global))
+    }
+
+    // table
+
+    pub fn table_get(&mut self, table: u32) -> &mut Self {
+        self.instr(instr::Instruction::TableGet(table))
+    }
+
+    pub fn table_set(&mut self, table: u32) -> &mut Self {
+        self.instr(instr::Instruction::TableSet(table))
+    }
+
+    pub fn table_init(&mut self, elem: u32, table: u32) -> &mut Self {
+        self.instr(instr::Instruction::TableInit(elem, table))
+    }
+
+    pub fn elem_drop(&mut self, elem: u32) -> &mut Self {
+        self.instr(instr::Instruction::ElemDrop(elem))
+    }
+
+    pub fn table_copy(&mut self, dst: u32, src: u32) -> &mut Self {
+        self.instr(instr::Instruction::TableCopy(dst, src))
+    }
+
+    pub fn table_grow(&mut self, table: u32) -> &mut Self {
+        self.instr(instr::Instruction::TableGrow(table))
+    }
+
+    pub fn table_size(&mut self, table: u32) -> &mut Self {
+        self.instr(instr::Instruction::TableSize(table))
+    }
+
+    pub fn table_fill(&mut self, table: u32) -> &mut Self {
+        self.instr(instr::Instruction::TableFill(table))
+    }
+
+    // memory
+
+    pub fn memory_size(&mut self, memory: u32) -> &mut Self {
+        self.instr(instr::Instruction::MemorySize(memory))
+    }
+
+    pub fn memory_grow(&mut self, memory: u32) -> &mut Self {
+        self.instr(instr::Instruction::MemoryGrow(memory))
+    }
+
+    pub fn memory_init(&mut self, data: u32, memory: u32) -> &mut Self {
+        self.instr(instr::Instruction::MemoryInit(data, memory))
+    }
+
+    pub fn data_drop(&mut self, data: u32) -> &mut Self {
+        self.instr(instr::Instruction::DataDrop(data))
+    }
+
+    pub fn memory_copy(&mut self, dst: u32, src: u32) -> &mut Self {
+        self.instr(instr::Instruction::MemoryCopy(dst, src))
+    }
+
+    pub fn memory_fill(&mut self, memory: u32) -> &mut Self {
+        self.instr(instr::Instruction::MemoryFill(memory))
+    }
+
+    // numeric
+
+    pub fn i32_const(&mut self, value: i32) -> &mut Self {
+        self.instr(instr::Instruction::I32Const(value))
+    }
+
+    pub fn i64_const(&mut self, value: i64) -> &mut Self {
+        self.instr(instr::Instruction::I64Const(value))
+    }
+
+    pub fn f32_const(&mut self, value: f32) -> &mut Self {
+        self.instr(instr::Instruction::F32Const(value.to_bits()))
+    }
+
+    pub fn f64_const(&mut self, value: f64) -> &mut Self {
+        self.instr(instr::Instruction::F64Const(value.to_bits()))
+    }
+
+    fn finish(mut self) -> anyhow::Result<super::Func> {
+        anyhow::ensure!(
+            self.labels.is_empty(),
+            "function {} has {} unclosed blocks",
+            self.index,
+            self.labels.len(),
+        );
+        self.instrs.push(instr::Instruction::End);
+        Ok(super::Func {
+            locals: self.locals,
+            expr: instr::Expression(self.instrs),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Data, DataMode, Element, ElementInit, ElementMode, ExternalIndex, Module};
+    use super::*;
+
+    const I32: ty::Value = ty::Value::Num(ty::Number::I32);
+
+    #[test]
+    fn build_imports_and_exports() -> anyhow::Result<()> {
+        let mut builder = ModuleBuilder::new();
+        let log = builder.import_func("env", "log", &[I32], &[])?;
+        let memory = builder.memory(ty::Limit {
+            address: ty::Address::I32,
+            min: 1,
+            max: None,
+        });
+        let counter = builder.global(
+            ty::Global {
+                value: I32,
+                is_mutable: true,
+            },
+            instr::Expression(vec![
+                instr::Instruction::I32Const(0),
+                instr::Instruction::End,
+            ]),
+        );
+        let mut func = builder.func(&[I32, I32], &[I32]);
+        func.local_get(0)
+            .local_get(1)
+            .instr(instr::Instruction::I32Add)
+            .call(log)
+            .global_get(counter);
+        let add = builder.finish_func(func)?;
+        builder.export("memory", ExternalIndex::Memory(memory))?;
+        builder.export("add", ExternalIndex::Func(add))?;
+        assert!(builder.export("add", ExternalIndex::Func(add)).is_err());
+        assert!(builder.import_func("env", "late", &[], &[]).is_err());
+
+        let wasm = wat::parse_str(
+            r#"
+(module
+    (import "env" "log" (func (param i32)))
+    (memory (export "memory") 1)
+    (global (mut i32) (i32.const 0))
+    (func (export "add") (param i32 i32) (result i32)
+        local.get 0
+        local.get 1
+        i32.add
+        call 0
+        global.get 0
+    )
+)
+"#,
+        )?;
+        let module = builder.build()?;
+        assert_eq!(Module::decode(wasm)?, module);
+        assert_eq!(module, Module::decode(module.encode()?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn build_labels() -> anyhow::Result<()> {
+        let mut builder = ModuleBuilder::new();
+        let mut func = builder.func(&[I32], &[I32]);
+        let sum = func.local(I32);
+        let outer = func.block(instr::BlockType::Empty);
+        let inner = func.loop_(instr::BlockType::Empty);
+        func.local_get(0)
+            .instr(instr::Instruction::I32Eqz)
+            .br_if(outer)?
+            .local_get(sum)
+            .local_get(0)
+            .instr(instr::Instruction::I32Add)
+            .local_set(sum)
+            .local_get(0)
+            .i32_const(1)
+            .instr(instr::Instruction::I32Sub)
+            .local_set(0)
+            .br(inner)?
+            .end()?
+            .end()?
+            .local_get(sum);
+        builder.finish_func(func)?;
+
+        let wasm = wat::parse_str(
+            "\
+(module
+    (func (param i32) (result i32) (local i32)
+        block
+            loop
+                local.get 0
+                i32.eqz
+                br_if 1
+                local.get 1
+                local.get 0
+                i32.add
+                local.set 1
+                local.get 0
+                i32.const 1
+                i32.sub
+                local.set 0
+                br 0
+            end
+        end
+        local.get 1
+    )
+)
+",
+        )?;
+        assert_eq!(Module::decode(wasm)?, builder.build()?);
+        Ok(())
+    }
+
+    #[test]
+    fn build_segments() -> anyhow::Result<()> {
+        let mut builder = ModuleBuilder::new();
+        let limit = |min| ty::Limit {
+            address: ty::Address::I32,
+            min,
+            max: None,
+        };
+        let offset = |offset| {
+            instr::Expression(vec![
+                instr::Instruction::I32Const(offset),
+                instr::Instruction::End,
+            ])
+        };
+        let table = builder.import_table(
+            "env",
+            "table",
+            ty::Table {
+                reference: Element::FUNC_REF,
+                limit: limit(1),
+            },
+        )?;
+        let memory = builder.memory(limit(1));
+        let unary = builder.func_type(&[I32], &[I32]);
+        let mut func = builder.func(&[I32], &[I32]);
+        func.local_get(0).i32_const(0).call_indirect(unary, table);
+        let func = builder.finish_func(func)?;
+        builder.elem(Element {
+            mode: ElementMode::Active {
+                table,
+                offset: offset(0),
+            },
+            init: ElementInit::Funcs(vec![func]),
+        });
+        builder.data(Data {
+            mode: DataMode::Active {
+                memory,
+                offset: offset(8),
+            },
+            init: b"hi".to_vec(),
+        });
+        builder.table(ty::Table {
+            reference: Element::FUNC_REF,
+            limit: limit(2),
+        });
+        let late = ty::Table {
+            reference: Element::FUNC_REF,
+            limit: limit(1),
+        };
+        assert!(builder.import_table("env", "late", late).is_err());
+        assert!(builder.import_func("env", "late", &[], &[]).is_err());
+
+        let wasm = wat::parse_str(
+            r#"
+(module
+    (import "env" "table" (table 1 funcref))
+    (table 2 funcref)
+    (memory 1)
+    (func (param i32) (result i32)
+        local.get 0
+        i32.const 0
+        call_indirect (type 0)
+    )
+    (elem (i32.const 0) func 0)
+    (data (i32.const 8) "hi")
+)
+"#,
+        )?;
+        assert_eq!(Module::decode(wasm)?, builder.build()?);
+        Ok(())
+    }
+
+    #[test]
+    fn build_data_count() -> anyhow::Result<()> {
+        let mut builder = ModuleBuilder::new();
+        let memory = builder.memory(ty::Limit {
+            address: ty::Address::I32,
+            min: 1,
+            max: None,
+        });
+        let data = builder.data(Data {
+            mode: DataMode::Passive,
+            init: b"hi".to_vec(),
+        });
+        let mut func = builder.func(&[], &[]);
+        func.i32_const(0)
+            .i32_const(0)
+            .i32_const(2)
+            .memory_init(data, memory)
+            .data_drop(data);
+        builder.finish_func(func)?;
+
+        let wasm = wat::parse_str(
+            r#"
+(module
+    (memory 1)
+    (func
+        i32.const 0
+        i32.const 0
+        i32.const 2
+        memory.init 0
+        data.drop 0
+    )
+    (data "hi")
+)
+"#,
+        )?;
+        assert_eq!(Module::decode(wasm)?, builder.build()?);
+        Ok(())
+    }
+
+    #[test]
+    fn build_unfinished() {
+        let mut builder = ModuleBuilder::new();
+        let mut func = builder.func(&[], &[]);
+        func.block(instr::BlockType::Empty);
+        assert!(builder.finish_func(func).is_err());
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    fn build_misuse() {
+        let mut builder = ModuleBuilder::new();
+        let mut func = builder.func(&[], &[]);
+        let block = func.block(instr::BlockType::Empty);
+        assert!(func.end().is_ok());
+        assert_eq!("no block to end", func.end().unwrap_err().to_string());
+        assert_eq!(
+            "label must refer to an enclosing block",
+            func.br(block).unwrap_err().to_string()
+        );
+        assert!(func.br_table(&[block], block).is_err());
+
+        let mut other = ModuleBuilder::new();
+        let first = other.func(&[], &[]);
+        let second = other.func(&[], &[]);
+        assert_eq!(
+            "function 1 is not declared by this builder",
+            builder.finish_func(second).unwrap_err().to_string()
+        );
+        let mut imports = ModuleBuilder::new();
+        imports.import_func("env", "f", &[], &[]).unwrap();
+        assert!(imports.finish_func(first).is_err());
+
+        // A foreign function is rejected even if its index fits
+        let mut twin = ModuleBuilder::new();
+        let foreign = twin.func(&[], &[]);
+        assert_eq!(
+            "function 0 is not declared by this builder",
+            builder.finish_func(foreign).unwrap_err().to_string()
+        );
+        assert!(builder.finish_func(func).is_ok());
+    }
+}
@@ -30,10 +30,19 @@ impl<R: std::io::Read> Decode<R> for () {
     }
 }
 
+/// Half-open range of byte offsets `start..end` in the decoded input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 pub struct ByteReader<R> {
     bytes: std::io::Bytes<std::io::BufReader<R>>,
     next_byte: Option<std::io::Result<u8>>,
     offset: usize,
+    source_map: Option<super::SourceMap>,
+    features: crate::features::WasmFeatures,
 }
 
 impl<R: std::io::Read> From<R> for ByteReader<R> {
@@ -56,9 +65,40 @@ impl<R: std::io::Read> ByteReader<R> {
             bytes,
             next_byte,
             offset: 0,
+            source_map: None,
+            features: Default::default(),
         }
     }
 
+    /// Rejects constructs of the proposals not enabled in `features`
+    pub fn with_features(mut self, features: crate::features::WasmFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Fails unless the feature is enabled for decoding
+    pub fn require(&self, feature: crate::features::Feature) -> anyhow::Result<()> {
+        self.features.require(feature)
+    }
+
+    /// Records spans of decoded items into a `SourceMap` while decoding
+    pub fn with_source_map(mut self) -> Self {
+        self.source_map = Some(Default::default());
+        self
+    }
+
+    pub fn source_map_mut(&mut self) -> Option<&mut super::SourceMap> {
+        self.source_map.as_mut()
+    }
+
+    pub fn take_source_map(&mut self) -> Option<super::SourceMap> {
+        self.source_map.take()
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     pub fn next(&mut self) -> anyhow::Result<u8> {
         let res = self.next_byte.take().context("EOF")??;
         self.next_byte = self.bytes.next();
@@ -66,6 +106,14 @@ impl<R: std::io::Read> ByteReader<R> {
         Ok(res)
     }
 
+    pub fn next_array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let mut array = [0; N];
+        for byte in &mut array {
+            *byte = self.next()?;
+        }
+        Ok(array)
+    }
+
     pub fn is_finished(&self) -> bool {
         self.next_byte.is_none()
     }
@@ -85,11 +133,27 @@ impl<R: std::io::Read> ByteReader<R> {
                 "failed to decode `{}` at byte offset 0x{:0>8X}..=0x{:0>8X}",
                 std::any::type_name::<D>(),
                 start_offset,
-                self.offset - 1,
+                // Nothing is consumed when the input is empty
+                self.offset.saturating_sub(1),
             )
         })
     }
 
+    pub fn decode_spanned<D: Decode<R>>(&mut self) -> anyhow::Result<(D, Span)>
+    where
+        D::Tag: Decode<R, Tag = ()>,
+    {
+        let start = self.offset;
+        let decoded = self.decode()?;
+        Ok((
+            decoded,
+            Span {
+                start,
+                end: self.offset,
+            },
+        ))
+    }
+
     pub fn consume_constant(&mut self, expected: impl AsRef<[u8]>) -> anyhow::Result<()> {
         let expected = expected.as_ref();
         let mut actual = Vec::with_capacity(expected.len());
@@ -100,6 +164,14 @@ impl<R: std::io::Read> ByteReader<R> {
         Ok(())
     }
 
+    pub fn next_bytes(&mut self, count: usize) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for _ in 0..count {
+            bytes.push(self.next()?);
+        }
+        Ok(bytes)
+    }
+
     pub fn skip_bytes(&mut self, count: usize) -> anyhow::Result<()> {
         for _ in 0..count {
             self.next()?;
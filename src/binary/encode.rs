@@ -0,0 +1,72 @@
+use anyhow::Context as _;
+
+pub trait Encode<W> {
+    fn encode(&self, bytes: &mut ByteWriter<W>) -> anyhow::Result<()>;
+}
+
+pub trait EncodeTag {
+    fn encode_tag(&self) -> u8;
+}
+
+impl<W: std::io::Write, T: EncodeTag> Encode<W> for T {
+    fn encode(&self, bytes: &mut ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.write(self.encode_tag())
+    }
+}
+
+pub struct ByteWriter<W> {
+    writer: W,
+    offset: usize,
+}
+
+impl<W: std::io::Write> From<W> for ByteWriter<W> {
+    fn from(value: W) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<W: std::io::Write> ByteWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, offset: 0 }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub fn write(&mut self, byte: u8) -> anyhow::Result<()> {
+        self.write_bytes([byte])
+    }
+
+    pub fn write_bytes(&mut self, bytes: impl AsRef<[u8]>) -> anyhow::Result<()> {
+        let bytes = bytes.as_ref();
+        self.writer.write_all(bytes)?;
+        self.offset += bytes.len();
+        Ok(())
+    }
+
+    pub fn encode<E: Encode<W> + ?Sized>(&mut self, value: &E) -> anyhow::Result<()> {
+        let start_offset = self.offset;
+        value.encode(self).with_context(|| {
+            format!(
+                "failed to encode `{}` at byte offset 0x{:0>8X}",
+                std::any::type_name::<E>(),
+                start_offset,
+            )
+        })
+    }
+
+    /// Writes the content written by `f` prefixed with its byte count, as
+    /// sections and function bodies are
+    pub fn encode_sized(
+        &mut self,
+        f: impl FnOnce(&mut ByteWriter<Vec<u8>>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut content = ByteWriter::new(Vec::new());
+        f(&mut content)?;
+        let content = content.into_inner();
+        let byte_count: u32 = content.len().try_into().context("content too large")?;
+        self.encode(&byte_count)?;
+        self.write_bytes(content)
+    }
+}
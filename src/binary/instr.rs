@@ -1,50 +1,1090 @@
-#[derive(Debug, PartialEq, Eq)]
+use anyhow::Context as _;
+
+use crate::features::Feature;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Expression(pub Vec<Instruction>);
 
-impl<R: std::io::Read> super::decode::Decode<R> for Expression {
-    type Tag = ();
-    fn decode(bytes: &mut super::decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+impl Expression {
+    /// Decodes an expression along with the byte span of each instruction
+    pub fn decode_spanned<R: std::io::Read>(
+        bytes: &mut super::decode::ByteReader<R>,
+    ) -> anyhow::Result<(Self, Vec<super::decode::Span>)> {
         let mut instructions = Vec::new();
+        let mut spans = Vec::new();
+        // The expression ends with the `end` that doesn't close a nested block
+        let mut depth = 0;
         loop {
-            let instr = bytes.decode()?;
-            let is_end = matches!(instr, Instruction::End);
+            let (instr, span) = bytes.decode_spanned()?;
+            let is_end = match instr {
+                Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => {
+                    depth += 1;
+                    false
+                }
+                Instruction::End if 0 < depth => {
+                    depth -= 1;
+                    false
+                }
+                Instruction::End => true,
+                _ => false,
+            };
             instructions.push(instr);
+            spans.push(span);
             if is_end {
                 break;
             }
         }
-        Ok(Self(instructions))
+        Ok((Self(instructions), spans))
+    }
+}
+
+impl<R: std::io::Read> super::decode::Decode<R> for Expression {
+    type Tag = ();
+    fn decode(bytes: &mut super::decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        Self::decode_spanned(bytes).map(|(expr, _)| expr)
+    }
+}
+
+impl<W: std::io::Write> super::encode::Encode<W> for Expression {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        for instr in &self.0 {
+            bytes.encode(instr)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(super::ty::Value),
+    Index(u32), // type index encoded as `s33`
+}
+
+pub enum BlockTypeTag {
+    Empty,
+    Value(super::ty::ValueTag),
+    Index(super::value::SignedIntByte),
+}
+
+impl super::decode::DecodeTag for BlockTypeTag {
+    fn decode_tag(byte: u8) -> Option<Self> {
+        if 0x40 == byte {
+            return Some(Self::Empty);
+        }
+        if let Some(tag) = super::ty::ValueTag::decode_tag(byte) {
+            return Some(Self::Value(tag));
+        }
+        let byte = super::value::SignedIntByte::decode_tag(byte)?;
+        if !matches!(byte, super::value::SignedIntByte::LastNegative(_)) {
+            Some(Self::Index(byte))
+        } else {
+            None
+        }
+    }
+}
+
+impl<R: std::io::Read> super::decode::Decode<R> for BlockType {
+    type Tag = BlockTypeTag;
+
+    fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
+        Ok(match tag {
+            BlockTypeTag::Empty => Self::Empty,
+            BlockTypeTag::Value(tag) => Self::Value(bytes.decode_with_tag(tag)?),
+            BlockTypeTag::Index(byte) => {
+                let int = bytes
+                    .decode_with_tag::<super::value::SignedInt<33, i64>>(byte)?
+                    .0;
+                Self::Index(int.try_into().context("invalid type index")?)
+            }
+        })
+    }
+}
+
+impl<W: std::io::Write> super::encode::Encode<W> for BlockType {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self {
+            Self::Empty => bytes.write(0x40),
+            Self::Value(value) => bytes.encode(value),
+            Self::Index(index) => {
+                bytes.encode(&super::value::SignedInt::<33, i64>((*index).into()))
+            }
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemArg {
+    pub align: u32,
+    pub offset: u64,
+    pub memory: u32,
+}
+
+impl MemArg {
+    /// Flag in the alignment field indicating an explicit memory index
+    const MEMORY_FLAG: u32 = 1 << 6;
+}
+
+impl<R: std::io::Read> super::decode::Decode<R> for MemArg {
+    type Tag = ();
+
+    fn decode(bytes: &mut super::decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        let flags: u32 = bytes.decode()?;
+        let memory = if 0 < flags & Self::MEMORY_FLAG {
+            bytes.decode()?
+        } else {
+            0
+        };
+        Ok(Self {
+            align: flags & !Self::MEMORY_FLAG,
+            offset: bytes.decode()?,
+            memory,
+        })
+    }
+}
+
+impl<W: std::io::Write> super::encode::Encode<W> for MemArg {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        if 0 == self.memory {
+            bytes.encode(&self.align)?;
+        } else {
+            bytes.encode(&(self.align | Self::MEMORY_FLAG))?;
+            bytes.encode(&self.memory)?;
+        }
+        bytes.encode(&self.offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
-    // parametric
+    // control
+    Unreachable,
     Nop,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    CallIndirect(u32, u32), // type index, table index
+
+    // reference
+    RefNull(super::ty::Heap),
+    RefIsNull,
+    RefFunc(u32),
+
+    // parametric
+    Drop,
+    Select,
+    SelectTyped(Vec<super::ty::Value>),
 
     // variable
     LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+
+    // table
+    TableGet(u32),
+    TableSet(u32),
+    TableInit(u32, u32), // element index, table index
+    ElemDrop(u32),
+    TableCopy(u32, u32), // destination, source table index
+    TableGrow(u32),
+    TableSize(u32),
+    TableFill(u32),
+
+    // memory
+    I32Load(MemArg),
+    I64Load(MemArg),
+    F32Load(MemArg),
+    F64Load(MemArg),
+    I32Load8S(MemArg),
+    I32Load8U(MemArg),
+    I32Load16S(MemArg),
+    I32Load16U(MemArg),
+    I64Load8S(MemArg),
+    I64Load8U(MemArg),
+    I64Load16S(MemArg),
+    I64Load16U(MemArg),
+    I64Load32S(MemArg),
+    I64Load32U(MemArg),
+    I32Store(MemArg),
+    I64Store(MemArg),
+    F32Store(MemArg),
+    F64Store(MemArg),
+    I32Store8(MemArg),
+    I32Store16(MemArg),
+    I64Store8(MemArg),
+    I64Store16(MemArg),
+    I64Store32(MemArg),
+    MemorySize(u32),
+    MemoryGrow(u32),
+    MemoryInit(u32, u32), // data index, memory index
+    DataDrop(u32),
+    MemoryCopy(u32, u32), // destination, source memory index
+    MemoryFill(u32),
 
     // numeric
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(u32), // IEEE 754 bit pattern
+    F64Const(u64), // IEEE 754 bit pattern
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64LtU,
+    I64GtS,
+    I64GtU,
+    I64LeS,
+    I64LeU,
+    I64GeS,
+    I64GeU,
+    F32Eq,
+    F32Ne,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
     I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32DivU,
+    I32RemS,
+    I32RemU,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I32Rotl,
+    I32Rotr,
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemS,
+    I64RemU,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    I64ShrU,
+    I64Rotl,
+    I64Rotr,
+    F32Abs,
+    F32Neg,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F32Sqrt,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    F32Min,
+    F32Max,
+    F32Copysign,
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Sqrt,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    F64Max,
+    F64Copysign,
+    I32WrapI64,
+    I32TruncF32S,
+    I32TruncF32U,
+    I32TruncF64S,
+    I32TruncF64U,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    I64TruncF32S,
+    I64TruncF32U,
+    I64TruncF64S,
+    I64TruncF64U,
+    F32ConvertI32S,
+    F32ConvertI32U,
+    F32ConvertI64S,
+    F32ConvertI64U,
+    F32DemoteF64,
+    F64ConvertI32S,
+    F64ConvertI32U,
+    F64ConvertI64S,
+    F64ConvertI64U,
+    F64PromoteF32,
+    I32ReinterpretF32,
+    I64ReinterpretF64,
+    F32ReinterpretI32,
+    F64ReinterpretI64,
+    I32Extend8S,
+    I32Extend16S,
+    I64Extend8S,
+    I64Extend16S,
+    I64Extend32S,
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
+}
 
-    End,
+impl Instruction {
+    /// Returns the alignment of a memory instruction assumed when omitted, as
+    /// the exponent of 2
+    pub fn natural_alignment(&self) -> Option<u32> {
+        use Instruction::*;
+        Some(match self {
+            I32Load8S(_) | I32Load8U(_) | I64Load8S(_) | I64Load8U(_) | I32Store8(_)
+            | I64Store8(_) => 0,
+            I32Load16S(_) | I32Load16U(_) | I64Load16S(_) | I64Load16U(_) | I32Store16(_)
+            | I64Store16(_) => 1,
+            I32Load(_) | F32Load(_) | I64Load32S(_) | I64Load32U(_) | I32Store(_) | F32Store(_)
+            | I64Store32(_) => 2,
+            I64Load(_) | F64Load(_) | I64Store(_) | F64Store(_) => 3,
+            _ => return None,
+        })
+    }
+
+    pub fn mem_arg(&self) -> Option<&MemArg> {
+        use Instruction::*;
+        match self {
+            I32Load(mem_arg) | I64Load(mem_arg) | F32Load(mem_arg) | F64Load(mem_arg)
+            | I32Load8S(mem_arg) | I32Load8U(mem_arg) | I32Load16S(mem_arg)
+            | I32Load16U(mem_arg) | I64Load8S(mem_arg) | I64Load8U(mem_arg)
+            | I64Load16S(mem_arg) | I64Load16U(mem_arg) | I64Load32S(mem_arg)
+            | I64Load32U(mem_arg) | I32Store(mem_arg) | I64Store(mem_arg) | F32Store(mem_arg)
+            | F64Store(mem_arg) | I32Store8(mem_arg) | I32Store16(mem_arg) | I64Store8(mem_arg)
+            | I64Store16(mem_arg) | I64Store32(mem_arg) => Some(mem_arg),
+            _ => None,
+        }
+    }
+
+    /// Returns the proposal the instruction, or its use of an immediate,
+    /// requires beyond the MVP
+    fn feature(&self) -> Option<Feature> {
+        use Instruction::*;
+        if self.mem_arg().is_some_and(|mem_arg| 0 != mem_arg.memory) {
+            return Some(Feature::MultiMemory);
+        }
+        Some(match self {
+            Block(BlockType::Index(_)) | Loop(BlockType::Index(_)) | If(BlockType::Index(_)) => {
+                Feature::MultiValue
+            }
+            CallIndirect(_, table) if 0 != *table => Feature::ReferenceTypes,
+            RefNull(_) | RefIsNull | RefFunc(_) | SelectTyped(_) => Feature::ReferenceTypes,
+            MemorySize(memory) | MemoryGrow(memory) if 0 != *memory => Feature::MultiMemory,
+            MemoryInit(_, memory) | MemoryFill(memory) if 0 != *memory => Feature::MultiMemory,
+            MemoryCopy(dst, src) if 0 != *dst || 0 != *src => Feature::MultiMemory,
+            TableInit(_, table) if 0 != *table => Feature::ReferenceTypes,
+            TableCopy(dst, src) if 0 != *dst || 0 != *src => Feature::ReferenceTypes,
+            MemoryInit(..) | DataDrop(_) | MemoryCopy(..) | MemoryFill(_) | TableInit(..)
+            | ElemDrop(_) | TableCopy(..) => Feature::BulkMemory,
+            TableGet(_) | TableSet(_) | TableGrow(_) | TableSize(_) | TableFill(_) => {
+                Feature::ReferenceTypes
+            }
+            I32Extend8S | I32Extend16S | I64Extend8S | I64Extend16S | I64Extend32S => {
+                Feature::SignExt
+            }
+            I32TruncSatF32S | I32TruncSatF32U | I32TruncSatF64S | I32TruncSatF64U
+            | I64TruncSatF32S | I64TruncSatF32U | I64TruncSatF64S | I64TruncSatF64U => {
+                Feature::SaturatingFloatToInt
+            }
+            _ => return None,
+        })
+    }
 }
 
 impl<R: std::io::Read> super::decode::Decode<R> for Instruction {
     type Tag = ();
     fn decode(bytes: &mut super::decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
         use Instruction::*;
-        Ok(match bytes.next()? {
+        let instr = match bytes.next()? {
+            0x00 => Unreachable,
             0x01 => Nop,
+            0x02 => Block(bytes.decode()?),
+            0x03 => Loop(bytes.decode()?),
+            0x04 => If(bytes.decode()?),
+            0x05 => Else,
+            0x0b => End,
+            0x0c => Br(bytes.decode()?),
+            0x0d => BrIf(bytes.decode()?),
+            0x0e => BrTable(bytes.decode()?, bytes.decode()?),
+            0x0f => Return,
+            0x10 => Call(bytes.decode()?),
+            0x11 => CallIndirect(bytes.decode()?, bytes.decode()?),
+
+            0xd0 => RefNull(bytes.decode()?),
+            0xd1 => RefIsNull,
+            0xd2 => RefFunc(bytes.decode()?),
+
+            0x1a => Drop,
+            0x1b => Select,
+            0x1c => SelectTyped(bytes.decode()?),
 
             0x20 => LocalGet(bytes.decode()?),
+            0x21 => LocalSet(bytes.decode()?),
+            0x22 => LocalTee(bytes.decode()?),
+            0x23 => GlobalGet(bytes.decode()?),
+            0x24 => GlobalSet(bytes.decode()?),
+            0x25 => TableGet(bytes.decode()?),
+            0x26 => TableSet(bytes.decode()?),
 
+            0x28 => I32Load(bytes.decode()?),
+            0x29 => I64Load(bytes.decode()?),
+            0x2a => F32Load(bytes.decode()?),
+            0x2b => F64Load(bytes.decode()?),
+            0x2c => I32Load8S(bytes.decode()?),
+            0x2d => I32Load8U(bytes.decode()?),
+            0x2e => I32Load16S(bytes.decode()?),
+            0x2f => I32Load16U(bytes.decode()?),
+            0x30 => I64Load8S(bytes.decode()?),
+            0x31 => I64Load8U(bytes.decode()?),
+            0x32 => I64Load16S(bytes.decode()?),
+            0x33 => I64Load16U(bytes.decode()?),
+            0x34 => I64Load32S(bytes.decode()?),
+            0x35 => I64Load32U(bytes.decode()?),
+            0x36 => I32Store(bytes.decode()?),
+            0x37 => I64Store(bytes.decode()?),
+            0x38 => F32Store(bytes.decode()?),
+            0x39 => F64Store(bytes.decode()?),
+            0x3a => I32Store8(bytes.decode()?),
+            0x3b => I32Store16(bytes.decode()?),
+            0x3c => I64Store8(bytes.decode()?),
+            0x3d => I64Store16(bytes.decode()?),
+            0x3e => I64Store32(bytes.decode()?),
+            0x3f => MemorySize(bytes.decode()?),
+            0x40 => MemoryGrow(bytes.decode()?),
+
+            0x41 => I32Const(bytes.decode::<super::value::SignedInt<32, i32>>()?.0),
+            0x42 => I64Const(bytes.decode::<super::value::SignedInt<64, i64>>()?.0),
+            0x43 => F32Const(u32::from_le_bytes(bytes.next_array()?)),
+            0x44 => F64Const(u64::from_le_bytes(bytes.next_array()?)),
+            0x45 => I32Eqz,
+            0x46 => I32Eq,
+            0x47 => I32Ne,
+            0x48 => I32LtS,
+            0x49 => I32LtU,
+            0x4a => I32GtS,
+            0x4b => I32GtU,
+            0x4c => I32LeS,
+            0x4d => I32LeU,
+            0x4e => I32GeS,
+            0x4f => I32GeU,
+            0x50 => I64Eqz,
+            0x51 => I64Eq,
+            0x52 => I64Ne,
+            0x53 => I64LtS,
+            0x54 => I64LtU,
+            0x55 => I64GtS,
+            0x56 => I64GtU,
+            0x57 => I64LeS,
+            0x58 => I64LeU,
+            0x59 => I64GeS,
+            0x5a => I64GeU,
+            0x5b => F32Eq,
+            0x5c => F32Ne,
+            0x5d => F32Lt,
+            0x5e => F32Gt,
+            0x5f => F32Le,
+            0x60 => F32Ge,
+            0x61 => F64Eq,
+            0x62 => F64Ne,
+            0x63 => F64Lt,
+            0x64 => F64Gt,
+            0x65 => F64Le,
+            0x66 => F64Ge,
+            0x67 => I32Clz,
+            0x68 => I32Ctz,
+            0x69 => I32Popcnt,
             0x6a => I32Add,
+            0x6b => I32Sub,
+            0x6c => I32Mul,
+            0x6d => I32DivS,
+            0x6e => I32DivU,
+            0x6f => I32RemS,
+            0x70 => I32RemU,
+            0x71 => I32And,
+            0x72 => I32Or,
+            0x73 => I32Xor,
+            0x74 => I32Shl,
+            0x75 => I32ShrS,
+            0x76 => I32ShrU,
+            0x77 => I32Rotl,
+            0x78 => I32Rotr,
+            0x79 => I64Clz,
+            0x7a => I64Ctz,
+            0x7b => I64Popcnt,
+            0x7c => I64Add,
+            0x7d => I64Sub,
+            0x7e => I64Mul,
+            0x7f => I64DivS,
+            0x80 => I64DivU,
+            0x81 => I64RemS,
+            0x82 => I64RemU,
+            0x83 => I64And,
+            0x84 => I64Or,
+            0x85 => I64Xor,
+            0x86 => I64Shl,
+            0x87 => I64ShrS,
+            0x88 => I64ShrU,
+            0x89 => I64Rotl,
+            0x8a => I64Rotr,
+            0x8b => F32Abs,
+            0x8c => F32Neg,
+            0x8d => F32Ceil,
+            0x8e => F32Floor,
+            0x8f => F32Trunc,
+            0x90 => F32Nearest,
+            0x91 => F32Sqrt,
+            0x92 => F32Add,
+            0x93 => F32Sub,
+            0x94 => F32Mul,
+            0x95 => F32Div,
+            0x96 => F32Min,
+            0x97 => F32Max,
+            0x98 => F32Copysign,
+            0x99 => F64Abs,
+            0x9a => F64Neg,
+            0x9b => F64Ceil,
+            0x9c => F64Floor,
+            0x9d => F64Trunc,
+            0x9e => F64Nearest,
+            0x9f => F64Sqrt,
+            0xa0 => F64Add,
+            0xa1 => F64Sub,
+            0xa2 => F64Mul,
+            0xa3 => F64Div,
+            0xa4 => F64Min,
+            0xa5 => F64Max,
+            0xa6 => F64Copysign,
+            0xa7 => I32WrapI64,
+            0xa8 => I32TruncF32S,
+            0xa9 => I32TruncF32U,
+            0xaa => I32TruncF64S,
+            0xab => I32TruncF64U,
+            0xac => I64ExtendI32S,
+            0xad => I64ExtendI32U,
+            0xae => I64TruncF32S,
+            0xaf => I64TruncF32U,
+            0xb0 => I64TruncF64S,
+            0xb1 => I64TruncF64U,
+            0xb2 => F32ConvertI32S,
+            0xb3 => F32ConvertI32U,
+            0xb4 => F32ConvertI64S,
+            0xb5 => F32ConvertI64U,
+            0xb6 => F32DemoteF64,
+            0xb7 => F64ConvertI32S,
+            0xb8 => F64ConvertI32U,
+            0xb9 => F64ConvertI64S,
+            0xba => F64ConvertI64U,
+            0xbb => F64PromoteF32,
+            0xbc => I32ReinterpretF32,
+            0xbd => I64ReinterpretF64,
+            0xbe => F32ReinterpretI32,
+            0xbf => F64ReinterpretI64,
+            0xc0 => I32Extend8S,
+            0xc1 => I32Extend16S,
+            0xc2 => I64Extend8S,
+            0xc3 => I64Extend16S,
+            0xc4 => I64Extend32S,
 
-            0x0b => End,
+            0xfc => match bytes.decode::<u32>()? {
+                0 => I32TruncSatF32S,
+                1 => I32TruncSatF32U,
+                2 => I32TruncSatF64S,
+                3 => I32TruncSatF64U,
+                4 => I64TruncSatF32S,
+                5 => I64TruncSatF32U,
+                6 => I64TruncSatF64S,
+                7 => I64TruncSatF64U,
+                8 => MemoryInit(bytes.decode()?, bytes.decode()?),
+                9 => DataDrop(bytes.decode()?),
+                10 => MemoryCopy(bytes.decode()?, bytes.decode()?),
+                11 => MemoryFill(bytes.decode()?),
+                12 => TableInit(bytes.decode()?, bytes.decode()?),
+                13 => ElemDrop(bytes.decode()?),
+                14 => TableCopy(bytes.decode()?, bytes.decode()?),
+                15 => TableGrow(bytes.decode()?),
+                16 => TableSize(bytes.decode()?),
+                17 => TableFill(bytes.decode()?),
+                opcode => anyhow::bail!("unimplemented instruction: '0xfc {opcode}'"),
+            },
 
             byte => anyhow::bail!("unimplemented instruction: '0x{byte:0>2x}'"),
-        })
+        };
+        if let Some(feature) = instr.feature() {
+            bytes.require(feature)?;
+        }
+        Ok(instr)
+    }
+}
+
+impl<W: std::io::Write> super::encode::Encode<W> for Instruction {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        use Instruction::*;
+        match self {
+            Unreachable => bytes.write(0x00),
+            Nop => bytes.write(0x01),
+            Block(ty) => {
+                bytes.write(0x02)?;
+                bytes.encode(ty)
+            }
+            Loop(ty) => {
+                bytes.write(0x03)?;
+                bytes.encode(ty)
+            }
+            If(ty) => {
+                bytes.write(0x04)?;
+                bytes.encode(ty)
+            }
+            Else => bytes.write(0x05),
+            End => bytes.write(0x0b),
+            Br(label) => {
+                bytes.write(0x0c)?;
+                bytes.encode(label)
+            }
+            BrIf(label) => {
+                bytes.write(0x0d)?;
+                bytes.encode(label)
+            }
+            BrTable(labels, default) => {
+                bytes.write(0x0e)?;
+                bytes.encode(labels)?;
+                bytes.encode(default)
+            }
+            Return => bytes.write(0x0f),
+            Call(func) => {
+                bytes.write(0x10)?;
+                bytes.encode(func)
+            }
+            CallIndirect(ty, table) => {
+                bytes.write(0x11)?;
+                bytes.encode(ty)?;
+                bytes.encode(table)
+            }
+
+            RefNull(heap) => {
+                bytes.write(0xd0)?;
+                bytes.encode(heap)
+            }
+            RefIsNull => bytes.write(0xd1),
+            RefFunc(func) => {
+                bytes.write(0xd2)?;
+                bytes.encode(func)
+            }
+
+            Drop => bytes.write(0x1a),
+            Select => bytes.write(0x1b),
+            SelectTyped(types) => {
+                bytes.write(0x1c)?;
+                bytes.encode(types)
+            }
+
+            LocalGet(index) => {
+                bytes.write(0x20)?;
+                bytes.encode(index)
+            }
+            LocalSet(index) => {
+                bytes.write(0x21)?;
+                bytes.encode(index)
+            }
+            LocalTee(index) => {
+                bytes.write(0x22)?;
+                bytes.encode(index)
+            }
+            GlobalGet(index) => {
+                bytes.write(0x23)?;
+                bytes.encode(index)
+            }
+            GlobalSet(index) => {
+                bytes.write(0x24)?;
+                bytes.encode(index)
+            }
+            TableGet(table) => {
+                bytes.write(0x25)?;
+                bytes.encode(table)
+            }
+            TableSet(table) => {
+                bytes.write(0x26)?;
+                bytes.encode(table)
+            }
+
+            I32Load(arg) => {
+                bytes.write(0x28)?;
+                bytes.encode(arg)
+            }
+            I64Load(arg) => {
+                bytes.write(0x29)?;
+                bytes.encode(arg)
+            }
+            F32Load(arg) => {
+                bytes.write(0x2a)?;
+                bytes.encode(arg)
+            }
+            F64Load(arg) => {
+                bytes.write(0x2b)?;
+                bytes.encode(arg)
+            }
+            I32Load8S(arg) => {
+                bytes.write(0x2c)?;
+                bytes.encode(arg)
+            }
+            I32Load8U(arg) => {
+                bytes.write(0x2d)?;
+                bytes.encode(arg)
+            }
+            I32Load16S(arg) => {
+                bytes.write(0x2e)?;
+                bytes.encode(arg)
+            }
+            I32Load16U(arg) => {
+                bytes.write(0x2f)?;
+                bytes.encode(arg)
+            }
+            I64Load8S(arg) => {
+                bytes.write(0x30)?;
+                bytes.encode(arg)
+            }
+            I64Load8U(arg) => {
+                bytes.write(0x31)?;
+                bytes.encode(arg)
+            }
+            I64Load16S(arg) => {
+                bytes.write(0x32)?;
+                bytes.encode(arg)
+            }
+            I64Load16U(arg) => {
+                bytes.write(0x33)?;
+                bytes.encode(arg)
+            }
+            I64Load32S(arg) => {
+                bytes.write(0x34)?;
+                bytes.encode(arg)
+            }
+            I64Load32U(arg) => {
+                bytes.write(0x35)?;
+                bytes.encode(arg)
+            }
+            I32Store(arg) => {
+                bytes.write(0x36)?;
+                bytes.encode(arg)
+            }
+            I64Store(arg) => {
+                bytes.write(0x37)?;
+                bytes.encode(arg)
+            }
+            F32Store(arg) => {
+                bytes.write(0x38)?;
+                bytes.encode(arg)
+            }
+            F64Store(arg) => {
+                bytes.write(0x39)?;
+                bytes.encode(arg)
+            }
+            I32Store8(arg) => {
+                bytes.write(0x3a)?;
+                bytes.encode(arg)
+            }
+            I32Store16(arg) => {
+                bytes.write(0x3b)?;
+                bytes.encode(arg)
+            }
+            I64Store8(arg) => {
+                bytes.write(0x3c)?;
+                bytes.encode(arg)
+            }
+            I64Store16(arg) => {
+                bytes.write(0x3d)?;
+                bytes.encode(arg)
+            }
+            I64Store32(arg) => {
+                bytes.write(0x3e)?;
+                bytes.encode(arg)
+            }
+            MemorySize(memory) => {
+                bytes.write(0x3f)?;
+                bytes.encode(memory)
+            }
+            MemoryGrow(memory) => {
+                bytes.write(0x40)?;
+                bytes.encode(memory)
+            }
+            MemoryInit(data, memory) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&8u32)?;
+                bytes.encode(data)?;
+                bytes.encode(memory)
+            }
+            DataDrop(data) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&9u32)?;
+                bytes.encode(data)
+            }
+            MemoryCopy(dst, src) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&10u32)?;
+                bytes.encode(dst)?;
+                bytes.encode(src)
+            }
+            MemoryFill(memory) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&11u32)?;
+                bytes.encode(memory)
+            }
+            TableInit(elem, table) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&12u32)?;
+                bytes.encode(elem)?;
+                bytes.encode(table)
+            }
+            ElemDrop(elem) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&13u32)?;
+                bytes.encode(elem)
+            }
+            TableCopy(dst, src) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&14u32)?;
+                bytes.encode(dst)?;
+                bytes.encode(src)
+            }
+            TableGrow(table) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&15u32)?;
+                bytes.encode(table)
+            }
+            TableSize(table) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&16u32)?;
+                bytes.encode(table)
+            }
+            TableFill(table) => {
+                bytes.write(0xfc)?;
+                bytes.encode(&17u32)?;
+                bytes.encode(table)
+            }
+
+            I32Const(value) => {
+                bytes.write(0x41)?;
+                bytes.encode(&super::value::SignedInt::<32, i32>(*value))
+            }
+            I64Const(value) => {
+                bytes.write(0x42)?;
+                bytes.encode(&super::value::SignedInt::<64, i64>(*value))
+            }
+            F32Const(bits) => {
+                bytes.write(0x43)?;
+                bytes.write_bytes(bits.to_le_bytes())
+            }
+            F64Const(bits) => {
+                bytes.write(0x44)?;
+                bytes.write_bytes(bits.to_le_bytes())
+            }
+            I32Eqz => bytes.write(0x45),
+            I32Eq => bytes.write(0x46),
+            I32Ne => bytes.write(0x47),
+            I32LtS => bytes.write(0x48),
+            I32LtU => bytes.write(0x49),
+            I32GtS => bytes.write(0x4a),
+            I32GtU => bytes.write(0x4b),
+            I32LeS => bytes.write(0x4c),
+            I32LeU => bytes.write(0x4d),
+            I32GeS => bytes.write(0x4e),
+            I32GeU => bytes.write(0x4f),
+            I64Eqz => bytes.write(0x50),
+            I64Eq => bytes.write(0x51),
+            I64Ne => bytes.write(0x52),
+            I64LtS => bytes.write(0x53),
+            I64LtU => bytes.write(0x54),
+            I64GtS => bytes.write(0x55),
+            I64GtU => bytes.write(0x56),
+            I64LeS => bytes.write(0x57),
+            I64LeU => bytes.write(0x58),
+            I64GeS => bytes.write(0x59),
+            I64GeU => bytes.write(0x5a),
+            F32Eq => bytes.write(0x5b),
+            F32Ne => bytes.write(0x5c),
+            F32Lt => bytes.write(0x5d),
+            F32Gt => bytes.write(0x5e),
+            F32Le => bytes.write(0x5f),
+            F32Ge => bytes.write(0x60),
+            F64Eq => bytes.write(0x61),
+            F64Ne => bytes.write(0x62),
+            F64Lt => bytes.write(0x63),
+            F64Gt => bytes.write(0x64),
+            F64Le => bytes.write(0x65),
+            F64Ge => bytes.write(0x66),
+            I32Clz => bytes.write(0x67),
+            I32Ctz => bytes.write(0x68),
+            I32Popcnt => bytes.write(0x69),
+            I32Add => bytes.write(0x6a),
+            I32Sub => bytes.write(0x6b),
+            I32Mul => bytes.write(0x6c),
+            I32DivS => bytes.write(0x6d),
+            I32DivU => bytes.write(0x6e),
+            I32RemS => bytes.write(0x6f),
+            I32RemU => bytes.write(0x70),
+            I32And => bytes.write(0x71),
+            I32Or => bytes.write(0x72),
+            I32Xor => bytes.write(0x73),
+            I32Shl => bytes.write(0x74),
+            I32ShrS => bytes.write(0x75),
+            I32ShrU => bytes.write(0x76),
+            I32Rotl => bytes.write(0x77),
+            I32Rotr => bytes.write(0x78),
+            I64Clz => bytes.write(0x79),
+            I64Ctz => bytes.write(0x7a),
+            I64Popcnt => bytes.write(0x7b),
+            I64Add => bytes.write(0x7c),
+            I64Sub => bytes.write(0x7d),
+            I64Mul => bytes.write(0x7e),
+            I64DivS => bytes.write(0x7f),
+            I64DivU => bytes.write(0x80),
+            I64RemS => bytes.write(0x81),
+            I64RemU => bytes.write(0x82),
+            I64And => bytes.write(0x83),
+            I64Or => bytes.write(0x84),
+            I64Xor => bytes.write(0x85),
+            I64Shl => bytes.write(0x86),
+            I64ShrS => bytes.write(0x87),
+            I64ShrU => bytes.write(0x88),
+            I64Rotl => bytes.write(0x89),
+            I64Rotr => bytes.write(0x8a),
+            F32Abs => bytes.write(0x8b),
+            F32Neg => bytes.write(0x8c),
+            F32Ceil => bytes.write(0x8d),
+            F32Floor => bytes.write(0x8e),
+            F32Trunc => bytes.write(0x8f),
+            F32Nearest => bytes.write(0x90),
+            F32Sqrt => bytes.write(0x91),
+            F32Add => bytes.write(0x92),
+            F32Sub => bytes.write(0x93),
+            F32Mul => bytes.write(0x94),
+            F32Div => bytes.write(0x95),
+            F32Min => bytes.write(0x96),
+            F32Max => bytes.write(0x97),
+            F32Copysign => bytes.write(0x98),
+            F64Abs => bytes.write(0x99),
+            F64Neg => bytes.write(0x9a),
+            F64Ceil => bytes.write(0x9b),
+            F64Floor => bytes.write(0x9c),
+            F64Trunc => bytes.write(0x9d),
+            F64Nearest => bytes.write(0x9e),
+            F64Sqrt => bytes.write(0x9f),
+            F64Add => bytes.write(0xa0),
+            F64Sub => bytes.write(0xa1),
+            F64Mul => bytes.write(0xa2),
+            F64Div => bytes.write(0xa3),
+            F64Min => bytes.write(0xa4),
+            F64Max => bytes.write(0xa5),
+            F64Copysign => bytes.write(0xa6),
+            I32WrapI64 => bytes.write(0xa7),
+            I32TruncF32S => bytes.write(0xa8),
+            I32TruncF32U => bytes.write(0xa9),
+            I32TruncF64S => bytes.write(0xaa),
+            I32TruncF64U => bytes.write(0xab),
+            I64ExtendI32S => bytes.write(0xac),
+            I64ExtendI32U => bytes.write(0xad),
+            I64TruncF32S => bytes.write(0xae),
+            I64TruncF32U => bytes.write(0xaf),
+            I64TruncF64S => bytes.write(0xb0),
+            I64TruncF64U => bytes.write(0xb1),
+            F32ConvertI32S => bytes.write(0xb2),
+            F32ConvertI32U => bytes.write(0xb3),
+            F32ConvertI64S => bytes.write(0xb4),
+            F32ConvertI64U => bytes.write(0xb5),
+            F32DemoteF64 => bytes.write(0xb6),
+            F64ConvertI32S => bytes.write(0xb7),
+            F64ConvertI32U => bytes.write(0xb8),
+            F64ConvertI64S => bytes.write(0xb9),
+            F64ConvertI64U => bytes.write(0xba),
+            F64PromoteF32 => bytes.write(0xbb),
+            I32ReinterpretF32 => bytes.write(0xbc),
+            I64ReinterpretF64 => bytes.write(0xbd),
+            F32ReinterpretI32 => bytes.write(0xbe),
+            F64ReinterpretI64 => bytes.write(0xbf),
+            I32Extend8S => bytes.write(0xc0),
+            I32Extend16S => bytes.write(0xc1),
+            I64Extend8S => bytes.write(0xc2),
+            I64Extend16S => bytes.write(0xc3),
+            I64Extend32S => bytes.write(0xc4),
+            I32TruncSatF32S => {
+                bytes.write(0xfc)?;
+                bytes.encode(&0u32)
+            }
+            I32TruncSatF32U => {
+                bytes.write(0xfc)?;
+                bytes.encode(&1u32)
+            }
+            I32TruncSatF64S => {
+                bytes.write(0xfc)?;
+                bytes.encode(&2u32)
+            }
+            I32TruncSatF64U => {
+                bytes.write(0xfc)?;
+                bytes.encode(&3u32)
+            }
+            I64TruncSatF32S => {
+                bytes.write(0xfc)?;
+                bytes.encode(&4u32)
+            }
+            I64TruncSatF32U => {
+                bytes.write(0xfc)?;
+                bytes.encode(&5u32)
+            }
+            I64TruncSatF64S => {
+                bytes.write(0xfc)?;
+                bytes.encode(&6u32)
+            }
+            I64TruncSatF64U => {
+                bytes.write(0xfc)?;
+                bytes.encode(&7u32)
+            }
+        }
     }
 }
@@ -1,8 +1,10 @@
 use anyhow::Context as _;
 
+use crate::features::Feature;
+
 // Number Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Number {
     F64,
     F32,
@@ -22,9 +24,20 @@ impl super::decode::DecodeTag for Number {
     }
 }
 
+impl super::encode::EncodeTag for Number {
+    fn encode_tag(&self) -> u8 {
+        match self {
+            Self::F64 => 0x7c,
+            Self::F32 => 0x7d,
+            Self::I64 => 0x7e,
+            Self::I32 => 0x7f,
+        }
+    }
+}
+
 // Vector Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Vector {
     Vec,
 }
@@ -38,9 +51,17 @@ impl super::decode::DecodeTag for Vector {
     }
 }
 
+impl super::encode::EncodeTag for Vector {
+    fn encode_tag(&self) -> u8 {
+        match self {
+            Self::Vec => 0x7b,
+        }
+    }
+}
+
 // Heap Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AbsHeap {
     Exception,
     Array,
@@ -67,15 +88,46 @@ impl super::decode::DecodeTag for AbsHeap {
             0x6e => Self::Any,
             0x6f => Self::Extern,
             0x70 => Self::Func,
-            0x71 => Self::NoExtern,
-            0x72 => Self::NoFunc,
-            0x73 => Self::NoException,
+            0x71 => Self::None,
+            0x72 => Self::NoExtern,
+            0x73 => Self::NoFunc,
+            0x74 => Self::NoException,
             _ => return None,
         })
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl AbsHeap {
+    /// Returns the proposal introducing the heap type
+    fn feature(&self) -> Feature {
+        match self {
+            Self::Func | Self::Extern => Feature::ReferenceTypes,
+            Self::Exception | Self::NoException => Feature::Exceptions,
+            _ => Feature::Gc,
+        }
+    }
+}
+
+impl super::encode::EncodeTag for AbsHeap {
+    fn encode_tag(&self) -> u8 {
+        match self {
+            Self::Exception => 0x69,
+            Self::Array => 0x6a,
+            Self::Struct => 0x6b,
+            Self::I31 => 0x6c,
+            Self::Eq => 0x6d,
+            Self::Any => 0x6e,
+            Self::Extern => 0x6f,
+            Self::Func => 0x70,
+            Self::None => 0x71,
+            Self::NoExtern => 0x72,
+            Self::NoFunc => 0x73,
+            Self::NoException => 0x74,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Heap {
     Abstract(AbsHeap),
     Concrete(u32), // type index encoded as `s33`
@@ -105,8 +157,12 @@ impl<R: std::io::Read> super::decode::Decode<R> for Heap {
 
     fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
         Ok(match tag {
-            HeapTag::Abstract(abs) => Self::Abstract(abs),
+            HeapTag::Abstract(abs) => {
+                bytes.require(abs.feature())?;
+                Self::Abstract(abs)
+            }
             HeapTag::Concrete(byte) => {
+                bytes.require(Feature::FunctionReferences)?;
                 let int = bytes
                     .decode_with_tag::<super::value::SignedInt<33, i64>>(byte)?
                     .0;
@@ -116,9 +172,20 @@ impl<R: std::io::Read> super::decode::Decode<R> for Heap {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Heap {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self {
+            Self::Abstract(abs) => bytes.encode(abs),
+            Self::Concrete(index) => {
+                bytes.encode(&super::value::SignedInt::<33, i64>((*index).into()))
+            }
+        }
+    }
+}
+
 // Reference Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Reference {
     pub heap: Heap,
     pub is_nullable: bool,
@@ -144,6 +211,15 @@ impl<R: std::io::Read> super::decode::Decode<R> for Reference {
     type Tag = ReferenceTag;
 
     fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
+        // `funcref` of MVP tables needs no proposal, unlike reference types
+        // as values, which `Value` checks
+        match &tag {
+            ReferenceTag::Nullable | ReferenceTag::NonNullable => {
+                bytes.require(Feature::FunctionReferences)?;
+            }
+            ReferenceTag::AbsHeap(AbsHeap::Func) => {}
+            ReferenceTag::AbsHeap(abs) => bytes.require(abs.feature())?,
+        }
         Ok(match tag {
             ReferenceTag::Nullable => Self {
                 heap: bytes.decode()?,
@@ -161,9 +237,34 @@ impl<R: std::io::Read> super::decode::Decode<R> for Reference {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Reference {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self {
+            Self {
+                heap: Heap::Abstract(abs),
+                is_nullable: true,
+            } => bytes.encode(abs),
+            Self {
+                heap,
+                is_nullable: true,
+            } => {
+                bytes.write(0x63)?;
+                bytes.encode(heap)
+            }
+            Self {
+                heap,
+                is_nullable: false,
+            } => {
+                bytes.write(0x64)?;
+                bytes.encode(heap)
+            }
+        }
+    }
+}
+
 // Value Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Value {
     Num(Number),
     Vec(Vector),
@@ -190,12 +291,28 @@ impl<R: std::io::Read> super::decode::Decode<R> for Value {
     fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
         Ok(match tag {
             ValueTag::Num(num) => Value::Num(num),
-            ValueTag::Vec(vec) => Value::Vec(vec),
-            ValueTag::Ref(tag) => Value::Ref(bytes.decode_with_tag(tag)?),
+            ValueTag::Vec(vec) => {
+                bytes.require(Feature::Simd)?;
+                Value::Vec(vec)
+            }
+            ValueTag::Ref(tag) => {
+                bytes.require(Feature::ReferenceTypes)?;
+                Value::Ref(bytes.decode_with_tag(tag)?)
+            }
         })
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Value {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self {
+            Value::Num(num) => bytes.encode(num),
+            Value::Vec(vec) => bytes.encode(vec),
+            Value::Ref(reference) => bytes.encode(reference),
+        }
+    }
+}
+
 // Composite Types
 
 enum Mutability {
@@ -213,7 +330,26 @@ impl super::decode::DecodeTag for Mutability {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl super::encode::EncodeTag for Mutability {
+    fn encode_tag(&self) -> u8 {
+        match self {
+            Self::Immutable => 0x00,
+            Self::Mutable => 0x01,
+        }
+    }
+}
+
+impl From<bool> for Mutability {
+    fn from(is_mutable: bool) -> Self {
+        if is_mutable {
+            Self::Mutable
+        } else {
+            Self::Immutable
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Composite {
     Array(Field),
     Struct(Vec<Field>),
@@ -244,18 +380,47 @@ impl<R: std::io::Read> super::decode::Decode<R> for Composite {
     type Tag = CompositeTag;
 
     fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
-        Ok(match tag {
+        if !matches!(tag, CompositeTag::Func) {
+            bytes.require(Feature::Gc)?;
+        }
+        let composite = match tag {
             CompositeTag::Array => Self::Array(bytes.decode()?),
             CompositeTag::Struct => Self::Struct(bytes.decode()?),
             CompositeTag::Func => Self::Func {
                 params: bytes.decode()?,
                 returns: bytes.decode()?,
             },
-        })
+        };
+        if let Self::Func { returns, .. } = &composite
+            && 1 < returns.len()
+        {
+            bytes.require(Feature::MultiValue)?;
+        }
+        Ok(composite)
+    }
+}
+
+impl<W: std::io::Write> super::encode::Encode<W> for Composite {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self {
+            Self::Array(field) => {
+                bytes.write(0x5e)?;
+                bytes.encode(field)
+            }
+            Self::Struct(fields) => {
+                bytes.write(0x5f)?;
+                bytes.encode(fields)
+            }
+            Self::Func { params, returns } => {
+                bytes.write(0x60)?;
+                bytes.encode(params)?;
+                bytes.encode(returns)
+            }
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Field {
     pub storage: Storage,
     pub is_mutable: bool,
@@ -272,7 +437,14 @@ impl<R: std::io::Read> super::decode::Decode<R> for Field {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl<W: std::io::Write> super::encode::Encode<W> for Field {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.storage)?;
+        bytes.encode(&Mutability::from(self.is_mutable))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Storage {
     Value(Value),
     Pack(Pack),
@@ -301,7 +473,16 @@ impl<R: std::io::Read> super::decode::Decode<R> for Storage {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl<W: std::io::Write> super::encode::Encode<W> for Storage {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self {
+            Storage::Value(value) => bytes.encode(value),
+            Storage::Pack(pack) => bytes.encode(pack),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Pack {
     I16,
     I8,
@@ -317,9 +498,18 @@ impl super::decode::DecodeTag for Pack {
     }
 }
 
+impl super::encode::EncodeTag for Pack {
+    fn encode_tag(&self) -> u8 {
+        match self {
+            Self::I16 => 0x77,
+            Self::I8 => 0x78,
+        }
+    }
+}
+
 // Recursive Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Recursive(pub Vec<Sub>);
 
 pub enum RecursiveTag {
@@ -341,13 +531,28 @@ impl<R: std::io::Read> super::decode::Decode<R> for Recursive {
 
     fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
         Ok(match tag {
-            RecursiveTag::Recursive => Self(bytes.decode()?),
+            RecursiveTag::Recursive => {
+                bytes.require(Feature::Gc)?;
+                Self(bytes.decode()?)
+            }
             RecursiveTag::Sub(tag) => Self(vec![bytes.decode_with_tag(tag)?]),
         })
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl<W: std::io::Write> super::encode::Encode<W> for Recursive {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self.0.as_slice() {
+            [sub] => bytes.encode(sub),
+            _ => {
+                bytes.write(0x4e)?;
+                bytes.encode(&self.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Sub {
     pub is_final: bool,
     pub supers: Vec<u32>,
@@ -374,6 +579,9 @@ impl<R: std::io::Read> super::decode::Decode<R> for Sub {
     type Tag = SubTag;
 
     fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
+        if !matches!(tag, SubTag::Composite(_)) {
+            bytes.require(Feature::Gc)?;
+        }
         Ok(match tag {
             SubTag::Final => Self {
                 is_final: true,
@@ -394,9 +602,19 @@ impl<R: std::io::Read> super::decode::Decode<R> for Sub {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Sub {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        if !self.is_final || !self.supers.is_empty() {
+            bytes.write(if self.is_final { 0x4f } else { 0x50 })?;
+            bytes.encode(&self.supers)?;
+        }
+        bytes.encode(&self.composite)
+    }
+}
+
 // Address Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Address {
     I32,
     I64,
@@ -404,7 +622,7 @@ pub enum Address {
 
 // Limits
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Limit {
     pub address: Address,
     pub min: u64,
@@ -434,6 +652,9 @@ impl<R: std::io::Read> super::decode::Decode<R> for Limit {
     type Tag = LimitTag;
 
     fn decode(bytes: &mut super::decode::ByteReader<R>, tag: Self::Tag) -> anyhow::Result<Self> {
+        if matches!(tag, LimitTag::I64 | LimitTag::I64WithMax) {
+            bytes.require(Feature::Memory64)?;
+        }
         Ok(Self {
             address: match tag {
                 LimitTag::I32 | LimitTag::I32WithMax => Address::I32,
@@ -448,9 +669,25 @@ impl<R: std::io::Read> super::decode::Decode<R> for Limit {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Limit {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.write(match (&self.address, self.max) {
+            (Address::I32, None) => 0x00,
+            (Address::I32, Some(_)) => 0x01,
+            (Address::I64, None) => 0x04,
+            (Address::I64, Some(_)) => 0x05,
+        })?;
+        bytes.encode(&self.min)?;
+        if let Some(max) = &self.max {
+            bytes.encode(max)?;
+        }
+        Ok(())
+    }
+}
+
 // Tag Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Tag(pub u32);
 
 impl<R: std::io::Read> super::decode::Decode<R> for Tag {
@@ -462,9 +699,16 @@ impl<R: std::io::Read> super::decode::Decode<R> for Tag {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Tag {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.write(0x00)?;
+        bytes.encode(&self.0)
+    }
+}
+
 // Global Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Global {
     pub value: Value,
     pub is_mutable: bool,
@@ -481,9 +725,16 @@ impl<R: std::io::Read> super::decode::Decode<R> for Global {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Global {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.value)?;
+        bytes.encode(&Mutability::from(self.is_mutable))
+    }
+}
+
 // Memory Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Memory(pub Limit);
 
 impl<R: std::io::Read> super::decode::Decode<R> for Memory {
@@ -494,12 +745,18 @@ impl<R: std::io::Read> super::decode::Decode<R> for Memory {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Memory {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.0)
+    }
+}
+
 // Table Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Table {
-    reference: Reference,
-    limit: Limit,
+    pub reference: Reference,
+    pub limit: Limit,
 }
 
 impl<R: std::io::Read> super::decode::Decode<R> for Table {
@@ -513,9 +770,16 @@ impl<R: std::io::Read> super::decode::Decode<R> for Table {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for Table {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&self.reference)?;
+        bytes.encode(&self.limit)
+    }
+}
+
 // External Types
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum External {
     Func(u32),
     Table(Table),
@@ -554,7 +818,37 @@ impl<R: std::io::Read> super::decode::Decode<R> for External {
             ExternalTag::Table => bytes.decode().map(Self::Table),
             ExternalTag::Memory => bytes.decode().map(Self::Memory),
             ExternalTag::Global => bytes.decode().map(Self::Global),
-            ExternalTag::Tag => bytes.decode().map(Self::Tag),
+            ExternalTag::Tag => {
+                bytes.require(Feature::Exceptions)?;
+                bytes.decode().map(Self::Tag)
+            }
+        }
+    }
+}
+
+impl<W: std::io::Write> super::encode::Encode<W> for External {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        match self {
+            Self::Func(index) => {
+                bytes.write(0x00)?;
+                bytes.encode(index)
+            }
+            Self::Table(table) => {
+                bytes.write(0x01)?;
+                bytes.encode(table)
+            }
+            Self::Memory(memory) => {
+                bytes.write(0x02)?;
+                bytes.encode(memory)
+            }
+            Self::Global(global) => {
+                bytes.write(0x03)?;
+                bytes.encode(global)
+            }
+            Self::Tag(tag) => {
+                bytes.write(0x04)?;
+                bytes.encode(tag)
+            }
         }
     }
 }
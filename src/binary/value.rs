@@ -6,7 +6,8 @@ where
 
     fn decode(bytes: &mut super::decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
         let len: u32 = bytes.decode()?;
-        let mut vec = Vec::with_capacity(len as usize);
+        // The length is untrusted, so the vector grows as items are decoded
+        let mut vec = Vec::new();
         for _ in 0..len {
             vec.push(bytes.decode()?);
         }
@@ -14,6 +15,17 @@ where
     }
 }
 
+impl<W: std::io::Write, E: super::encode::Encode<W>> super::encode::Encode<W> for Vec<E> {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        let len: u32 = self.len().try_into()?;
+        bytes.encode(&len)?;
+        for item in self {
+            bytes.encode(item)?;
+        }
+        Ok(())
+    }
+}
+
 impl<R: std::io::Read> super::decode::Decode<R> for u32 {
     type Tag = ();
 
@@ -22,6 +34,12 @@ impl<R: std::io::Read> super::decode::Decode<R> for u32 {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for u32 {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&UnsignedInt::<32, u32>(*self))
+    }
+}
+
 impl<R: std::io::Read> super::decode::Decode<R> for u64 {
     type Tag = ();
 
@@ -30,6 +48,33 @@ impl<R: std::io::Read> super::decode::Decode<R> for u64 {
     }
 }
 
+impl<W: std::io::Write> super::encode::Encode<W> for u64 {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        bytes.encode(&UnsignedInt::<64, u64>(*self))
+    }
+}
+
+impl<R: std::io::Read> super::decode::Decode<R> for String {
+    type Tag = ();
+
+    fn decode(bytes: &mut super::decode::ByteReader<R>, _: Self::Tag) -> anyhow::Result<Self> {
+        let len: u32 = bytes.decode()?;
+        let mut vec = Vec::new();
+        for _ in 0..len {
+            vec.push(bytes.next()?);
+        }
+        Ok(String::from_utf8(vec)?)
+    }
+}
+
+impl<W: std::io::Write> super::encode::Encode<W> for String {
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        let len: u32 = self.len().try_into()?;
+        bytes.encode(&len)?;
+        bytes.write_bytes(self)
+    }
+}
+
 pub struct UnsignedInt<const N: u8, I>(pub I);
 
 pub enum UnsignedIntByte {
@@ -83,9 +128,28 @@ where
     }
 }
 
+impl<W: std::io::Write, const N: u8, I> super::encode::Encode<W> for UnsignedInt<N, I>
+where
+    I: Copy + Into<u64>,
+{
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        let mut value: u64 = self.0.into();
+        anyhow::ensure!(N >= 64 || 0 == value >> N, "overflowed `u{N}`");
+        loop {
+            let byte = (value & 0b0111_1111) as u8;
+            value >>= UnsignedIntByte::BIT_COUNT;
+            if 0 == value {
+                break bytes.write(byte);
+            }
+            bytes.write(0b1000_0000 | byte)?;
+        }
+    }
+}
+
 #[cfg(test)]
 mod uleb128_tests {
     use super::super::decode::ByteReader;
+    use super::super::encode::ByteWriter;
     use super::*;
 
     #[test]
@@ -113,6 +177,25 @@ mod uleb128_tests {
         assert!(bytes.decode::<UnsignedInt<6, u8>>().is_err());
         Ok(())
     }
+
+    #[test]
+    fn encode_round_trip() -> anyhow::Result<()> {
+        let values = (0..64).flat_map(|shift| [1u64 << shift, (1u64 << shift) - 1, !0u64 >> shift]);
+        for value in values {
+            let mut writer = ByteWriter::new(Vec::new());
+            writer.encode(&UnsignedInt::<64, u64>(value))?;
+            let mut bytes = ByteReader::from(writer.into_inner());
+            assert_eq!(value, bytes.decode::<UnsignedInt<64, u64>>()?.0);
+            assert!(bytes.is_finished());
+        }
+
+        let mut writer = ByteWriter::new(Vec::new());
+        writer.encode(&UnsignedInt::<8, u8>(3))?;
+        assert_eq!(vec![0b0000_0011], writer.into_inner());
+        let mut writer = ByteWriter::new(Vec::new());
+        assert!(writer.encode(&UnsignedInt::<6, u8>(64)).is_err());
+        Ok(())
+    }
 }
 
 pub struct SignedInt<const N: u8, I>(pub I);
@@ -200,9 +283,32 @@ where
     }
 }
 
+impl<W: std::io::Write, const N: u8, I> super::encode::Encode<W> for SignedInt<N, I>
+where
+    I: Copy + Into<i64>,
+{
+    fn encode(&self, bytes: &mut super::encode::ByteWriter<W>) -> anyhow::Result<()> {
+        let mut value: i64 = self.0.into();
+        if N < 64 {
+            let bound = 1i64 << (N - 1);
+            anyhow::ensure!((-bound..bound).contains(&value), "overflowed `s{N}`");
+        }
+        loop {
+            let byte = (value & 0b0111_1111) as u8;
+            value >>= SignedIntByte::BIT_COUNT;
+            let is_sign_bit_set = 0 < byte & 0b0100_0000;
+            if (0 == value && !is_sign_bit_set) || (-1 == value && is_sign_bit_set) {
+                break bytes.write(byte);
+            }
+            bytes.write(0b1000_0000 | byte)?;
+        }
+    }
+}
+
 #[cfg(test)]
 mod sleb128_tests {
     use super::super::decode::ByteReader;
+    use super::super::encode::ByteWriter;
     use super::*;
 
     #[test]
@@ -234,4 +340,32 @@ mod sleb128_tests {
         assert!(bytes.decode::<SignedInt<5, i8>>().is_err());
         Ok(())
     }
+
+    #[test]
+    fn encode_round_trip() -> anyhow::Result<()> {
+        let values = (0..64).flat_map(|shift| {
+            let value = 1i64 << shift;
+            [
+                value,
+                value.wrapping_sub(1),
+                value.wrapping_neg(),
+                !value,
+                i64::MAX >> shift,
+            ]
+        });
+        for value in values {
+            let mut writer = ByteWriter::new(Vec::new());
+            writer.encode(&SignedInt::<64, i64>(value))?;
+            let mut bytes = ByteReader::from(writer.into_inner());
+            assert_eq!(value, bytes.decode::<SignedInt<64, i64>>()?.0);
+            assert!(bytes.is_finished());
+        }
+
+        let mut writer = ByteWriter::new(Vec::new());
+        writer.encode(&SignedInt::<16, i16>(-2))?;
+        assert_eq!(vec![0b0111_1110], writer.into_inner());
+        let mut writer = ByteWriter::new(Vec::new());
+        assert!(writer.encode(&SignedInt::<6, i8>(-33)).is_err());
+        Ok(())
+    }
 }
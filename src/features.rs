@@ -0,0 +1,158 @@
+use std::fmt;
+
+/// Proposals a module may use beyond the MVP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    MutableGlobals,
+    SaturatingFloatToInt,
+    SignExt,
+    MultiValue,
+    BulkMemory,
+    ReferenceTypes,
+    Simd,
+    RelaxedSimd,
+    Threads,
+    TailCall,
+    Gc,
+    Exceptions,
+    Memory64,
+    MultiMemory,
+    ExtendedConst,
+    FunctionReferences,
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MutableGlobals => "mutable-globals",
+            Self::SaturatingFloatToInt => "saturating-float-to-int",
+            Self::SignExt => "sign-ext",
+            Self::MultiValue => "multi-value",
+            Self::BulkMemory => "bulk-memory",
+            Self::ReferenceTypes => "reference-types",
+            Self::Simd => "simd",
+            Self::RelaxedSimd => "relaxed-simd",
+            Self::Threads => "threads",
+            Self::TailCall => "tail-call",
+            Self::Gc => "gc",
+            Self::Exceptions => "exceptions",
+            Self::Memory64 => "memory64",
+            Self::MultiMemory => "multi-memory",
+            Self::ExtendedConst => "extended-const",
+            Self::FunctionReferences => "function-references",
+        })
+    }
+}
+
+/// Set of proposals enabled when decoding and validating a module. The
+/// default enables all of them; `WasmFeatures::mvp()` enables none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WasmFeatures {
+    pub mutable_globals: bool,
+    pub saturating_float_to_int: bool,
+    pub sign_ext: bool,
+    pub multi_value: bool,
+    pub bulk_memory: bool,
+    pub reference_types: bool,
+    pub simd: bool,
+    pub relaxed_simd: bool,
+    pub threads: bool,
+    pub tail_call: bool,
+    pub gc: bool,
+    pub exceptions: bool,
+    pub memory64: bool,
+    pub multi_memory: bool,
+    pub extended_const: bool,
+    pub function_references: bool,
+}
+
+impl Default for WasmFeatures {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl WasmFeatures {
+    pub const fn all() -> Self {
+        Self {
+            mutable_globals: true,
+            saturating_float_to_int: true,
+            sign_ext: true,
+            multi_value: true,
+            bulk_memory: true,
+            reference_types: true,
+            simd: true,
+            relaxed_simd: true,
+            threads: true,
+            tail_call: true,
+            gc: true,
+            exceptions: true,
+            memory64: true,
+            multi_memory: true,
+            extended_const: true,
+            function_references: true,
+        }
+    }
+
+    pub const fn mvp() -> Self {
+        Self {
+            mutable_globals: false,
+            saturating_float_to_int: false,
+            sign_ext: false,
+            multi_value: false,
+            bulk_memory: false,
+            reference_types: false,
+            simd: false,
+            relaxed_simd: false,
+            threads: false,
+            tail_call: false,
+            gc: false,
+            exceptions: false,
+            memory64: false,
+            multi_memory: false,
+            extended_const: false,
+            function_references: false,
+        }
+    }
+
+    /// Features of the WebAssembly 2.0 specification
+    pub const fn wasm2() -> Self {
+        Self {
+            mutable_globals: true,
+            saturating_float_to_int: true,
+            sign_ext: true,
+            multi_value: true,
+            bulk_memory: true,
+            reference_types: true,
+            simd: true,
+            ..Self::mvp()
+        }
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::MutableGlobals => self.mutable_globals,
+            Feature::SaturatingFloatToInt => self.saturating_float_to_int,
+            Feature::SignExt => self.sign_ext,
+            Feature::MultiValue => self.multi_value,
+            Feature::BulkMemory => self.bulk_memory,
+            Feature::ReferenceTypes => self.reference_types,
+            Feature::Simd => self.simd,
+            Feature::RelaxedSimd => self.relaxed_simd,
+            Feature::Threads => self.threads,
+            Feature::TailCall => self.tail_call,
+            Feature::Gc => self.gc,
+            Feature::Exceptions => self.exceptions,
+            Feature::Memory64 => self.memory64,
+            Feature::MultiMemory => self.multi_memory,
+            Feature::ExtendedConst => self.extended_const,
+            Feature::FunctionReferences => self.function_references,
+        }
+    }
+
+    /// Fails unless the feature is enabled
+    pub fn require(&self, feature: Feature) -> anyhow::Result<()> {
+        anyhow::ensure!(self.is_enabled(feature), "feature {feature} not enabled");
+        Ok(())
+    }
+}
@@ -1,2 +1,5 @@
 pub mod binary;
+pub mod features;
+pub mod runtime;
+pub mod text;
 pub mod validation;
@@ -0,0 +1,1729 @@
+use std::rc::Rc;
+
+use anyhow::Context as _;
+
+use crate::binary;
+use crate::validation::{self, ty};
+
+mod epoch;
+mod exec;
+mod host;
+mod limits;
+mod linker;
+mod memory;
+mod table;
+mod trap;
+mod typed;
+mod val;
+
+pub use epoch::EpochCounter;
+pub use exec::StackLimits;
+pub use host::Caller;
+pub use limits::{ResourceLimiter, StoreLimits};
+pub use linker::Linker;
+pub use memory::{LittleEndian, Memory, PAGE_SIZE};
+pub use table::Table;
+pub use trap::{FrameInfo, Trap, TrapCode};
+pub use typed::{TypedFunc, WasmTy, WasmTypeList};
+pub use val::{AnyRef, ExnRef, ExternRef, Val};
+
+/// Owner of the runtime objects of all instances, which handles such as
+/// `Instance` and `Func` refer to by their address in the store
+#[derive(Default)]
+pub struct Store {
+    funcs: Vec<FuncInst>,
+    globals: Vec<GlobalInst>,
+    tables: Vec<table::TableInst>,
+    memories: Vec<memory::MemoryInst>,
+    tags: Vec<TagInst>,
+    instances: Vec<InstanceInst>,
+    /// Host values referenced by `ExternRef`s
+    externs: Vec<Box<dyn std::any::Any>>,
+    /// Stacks of the last call from the host, reused by the next one
+    thread: Option<exec::Thread>,
+    /// Fuel left when metering, which each instruction executed consumes
+    fuel: Option<u64>,
+    /// Last call from the host that ran out of fuel
+    suspended: Option<exec::Thread>,
+    /// Number of host functions running, whose calls back into WebAssembly
+    /// can't be resumed
+    host_calls: usize,
+    epoch: EpochCounter,
+    /// Epoch at which execution is interrupted, if checked
+    epoch_deadline: Option<u64>,
+    epoch_callback: Option<epoch::EpochCallback>,
+    limiter: Option<Box<dyn ResourceLimiter>>,
+    stack_limits: StackLimits,
+    /// Frames and operand stack values of the calls waiting on host
+    /// functions, which count towards the limits of the calls those make
+    outer_frames: usize,
+    outer_values: usize,
+}
+
+impl Store {
+    /// Meters execution with an amount of fuel, of which each instruction
+    /// consumes one unit, or stops metering with `None`
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Returns the fuel left, or `None` when not metering
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Returns a handle to the epoch counter, which execution checks on
+    /// function entries and loop iterations against the deadline
+    pub fn epoch_counter(&self) -> EpochCounter {
+        self.epoch.clone()
+    }
+
+    /// Sets the deadline to a number of epochs after the current one, or
+    /// stops checking the epoch with `None`
+    pub fn set_epoch_deadline(&mut self, delta: Option<u64>) {
+        self.epoch_deadline = delta.map(|delta| self.epoch.get().saturating_add(delta));
+    }
+
+    /// Calls a function on reaching the deadline instead of trapping, which
+    /// is passed the current epoch and returns the number of epochs to extend
+    /// the deadline by, or fails to stop execution
+    pub fn set_epoch_callback(
+        &mut self,
+        callback: impl FnMut(u64) -> anyhow::Result<u64> + 'static,
+    ) {
+        self.epoch_callback = Some(Box::new(callback));
+    }
+
+    /// Consults a limiter before allocating or growing memories and tables
+    /// and creating instances, or stops limiting them beyond their types with
+    /// `None`
+    pub fn set_limiter(&mut self, limiter: Option<Box<dyn ResourceLimiter>>) {
+        self.limiter = limiter;
+    }
+
+    /// Sets the capacities of the call and operand stacks of calls from the
+    /// host, which trap on exceeding them
+    pub fn set_stack_limits(&mut self, limits: StackLimits) {
+        self.stack_limits = limits;
+    }
+
+    pub fn stack_limits(&self) -> StackLimits {
+        self.stack_limits
+    }
+
+    /// Continues the last call from the host that ran out of fuel at the
+    /// instruction it stopped at, returning its results. Calls made by host
+    /// functions only trap on running out of fuel, as does their caller.
+    pub fn resume(&mut self) -> anyhow::Result<Vec<Val>> {
+        let thread = self.suspended.take();
+        let mut thread = thread.context("no call ran out of fuel")?;
+        thread.resume(self)
+    }
+}
+
+struct FuncInst {
+    ty: ty::Defined,
+    kind: FuncKind,
+}
+
+enum FuncKind {
+    Wasm {
+        /// Index in the function index space of the module
+        index: u32,
+        /// Address of the instance defining the function
+        instance: usize,
+        code: Rc<exec::Code>,
+    },
+    Host(host::HostFunc),
+}
+
+struct GlobalInst {
+    ty: ty::Global,
+    value: Val,
+}
+
+struct TagInst {
+    ty: ty::Defined,
+}
+
+struct InstanceInst {
+    module: Rc<validation::Validated>,
+    /// Addresses of the functions in the function index space
+    funcs: Vec<usize>,
+    /// Addresses of the globals in the global index space
+    globals: Vec<usize>,
+    /// Addresses of the tables in the table index space
+    tables: Vec<usize>,
+    /// Addresses of the memories in the memory index space
+    memories: Vec<usize>,
+    /// Addresses of the tags in the tag index space
+    tags: Vec<usize>,
+    /// References of the element segments, which are emptied when dropped
+    elems: Vec<Rc<[Val]>>,
+    /// Contents of the data segments, which are emptied when dropped
+    datas: Vec<Rc<[u8]>>,
+}
+
+/// Handle to an instance of a module in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instance(usize);
+
+impl Instance {
+    /// Instantiates a validated module with items for its imports in order,
+    /// initializing its globals, tables and memories then running its start
+    /// function
+    pub fn new(
+        store: &mut Store,
+        module: &Rc<validation::Validated>,
+        imports: &[Extern],
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            imports.len() == module.imports.len(),
+            "expected {} imports but found {}",
+            module.imports.len(),
+            imports.len(),
+        );
+        let instance = store.instances.len();
+        if let Some(limiter) = &mut store.limiter {
+            anyhow::ensure!(
+                limiter.instance_creating(instance)?,
+                "failed to create an instance beyond the limit of the store"
+            );
+        }
+        let mut inst = InstanceInst {
+            module: module.clone(),
+            funcs: vec![],
+            globals: vec![],
+            tables: vec![],
+            memories: vec![],
+            tags: vec![],
+            elems: vec![],
+            datas: module
+                .datas
+                .iter()
+                .map(|data| data.init.as_slice().into())
+                .collect(),
+        };
+        for (import, item) in module.imports.iter().zip(imports) {
+            let ty = item.ty(store);
+            anyhow::ensure!(
+                ty.matches(&import.ty),
+                "incompatible import type for {:?} {:?}: expected {} but found {ty}",
+                import.module,
+                import.name,
+                import.ty,
+            );
+            match *item {
+                Extern::Func(func) => inst.funcs.push(func.0),
+                Extern::Table(table) => inst.tables.push(table.0),
+                Extern::Memory(memory) => inst.memories.push(memory.0),
+                Extern::Global(global) => inst.globals.push(global.0),
+                Extern::Tag(tag) => inst.tags.push(tag.0),
+            }
+        }
+        for func in &module.funcs {
+            let code = exec::Code::new(&module.types, &func.ty, &func.locals, &func.expr)?;
+            let index = inst.funcs.len();
+            inst.funcs.push(store.funcs.len());
+            store.funcs.push(FuncInst {
+                ty: module.func_types[index].clone(),
+                kind: FuncKind::Wasm {
+                    index: index as u32,
+                    instance,
+                    code: Rc::new(code),
+                },
+            });
+        }
+        for ty in &module.tables[inst.tables.len()..] {
+            inst.tables.push(store.tables.len());
+            let init = Val::null(&ty.reference.heap);
+            let table = table::TableInst::new(ty.clone(), init, &mut store.limiter)?;
+            store.tables.push(table);
+        }
+        for ty in &module.memories[inst.memories.len()..] {
+            inst.memories.push(store.memories.len());
+            let memory = memory::MemoryInst::new(ty.clone(), &mut store.limiter)?;
+            store.memories.push(memory);
+        }
+        let imported_globals = inst.globals.len();
+        store.instances.push(inst);
+
+        for (ty, init) in module.globals[imported_globals..]
+            .iter()
+            .zip(&module.global_inits)
+        {
+            let value = exec::Thread::default()
+                .eval(store, instance, &ty.value, init)
+                .context("failed to initialize a global")?;
+            let inst = &mut store.instances[instance];
+            inst.globals.push(store.globals.len());
+            store.globals.push(GlobalInst {
+                ty: ty.clone(),
+                value,
+            });
+        }
+        for (i, elem) in module.elems.iter().enumerate() {
+            let ty = ty::Value::Ref(elem.ty.clone());
+            let init = (elem.init.iter())
+                .map(|expr| exec::Thread::default().eval(store, instance, &ty, expr))
+                .collect::<anyhow::Result<_>>()
+                .with_context(|| format!("failed to initialize element segment {i}"))?;
+            store.instances[instance].elems.push(init);
+        }
+        for (i, elem) in module.elems.iter().enumerate() {
+            match &elem.mode {
+                validation::ElementMode::Active { table, offset } => {
+                    let address = module.tables[*table as usize].limit.address.value();
+                    let mut init = || {
+                        let offset =
+                            exec::Thread::default().eval(store, instance, &address, offset)?;
+                        let table = store.instances[instance].tables[*table as usize];
+                        let elems = store.instances[instance].elems[i].clone();
+                        store.tables[table]
+                            .elems_mut(offset_u64(offset), elems.len() as u64)?
+                            .copy_from_slice(&elems);
+                        store.instances[instance].elems[i] = Rc::default();
+                        anyhow::Ok(())
+                    };
+                    init().with_context(|| format!("failed to initialize element segment {i}"))?;
+                }
+                validation::ElementMode::Declarative => {
+                    store.instances[instance].elems[i] = Rc::default();
+                }
+                validation::ElementMode::Passive => {}
+            }
+        }
+        for (i, data) in module.datas.iter().enumerate() {
+            if let validation::DataMode::Active { memory, offset } = &data.mode {
+                let address = module.memories[*memory as usize].0.address.value();
+                let mut init = || {
+                    let offset = exec::Thread::default().eval(store, instance, &address, offset)?;
+                    let offset = offset_u64(offset);
+                    let memory = store.instances[instance].memories[*memory as usize];
+                    let bytes = store.memories[memory].bytes_mut(offset, 0, data.init.len())?;
+                    bytes.copy_from_slice(&data.init);
+                    store.instances[instance].datas[i] = Rc::default();
+                    anyhow::Ok(())
+                };
+                init().with_context(|| format!("failed to initialize data segment {i}"))?;
+            }
+        }
+
+        let instance = Self(instance);
+        if let Some(start) = module.start {
+            // The instance is lost if the start function runs out of fuel, so
+            // it can't be resumed, unlike the last call that could
+            let suspended = store.suspended.take();
+            let result = Func(store.instances[instance.0].funcs[start as usize]).call(store, &[]);
+            store.suspended = suspended;
+            result?;
+        }
+        Ok(instance)
+    }
+
+    /// Returns the item an instance exports under a name
+    pub fn get_export(&self, store: &Store, name: &str) -> Option<Extern> {
+        let inst = &store.instances[self.0];
+        let export = inst
+            .module
+            .exports
+            .iter()
+            .find(|export| export.name == name)?;
+        Some(match export.index {
+            binary::ExternalIndex::Func(index) => Extern::Func(Func(inst.funcs[index as usize])),
+            binary::ExternalIndex::Table(index) => {
+                Extern::Table(Table(inst.tables[index as usize]))
+            }
+            binary::ExternalIndex::Memory(index) => {
+                Extern::Memory(Memory(inst.memories[index as usize]))
+            }
+            binary::ExternalIndex::Global(index) => {
+                Extern::Global(Global(inst.globals[index as usize]))
+            }
+            binary::ExternalIndex::Tag(index) => Extern::Tag(Tag(inst.tags[index as usize])),
+        })
+    }
+
+    /// Returns the names and items of the exports of an instance in order
+    pub fn exports(&self, store: &Store) -> Vec<(String, Extern)> {
+        let exports = &store.instances[self.0].module.exports;
+        (exports.iter())
+            .map(|export| {
+                let item = self.get_export(store, &export.name).expect("exported");
+                (export.name.clone(), item)
+            })
+            .collect()
+    }
+
+    pub fn get_func(&self, store: &Store, name: &str) -> Option<Func> {
+        match self.get_export(store, name)? {
+            Extern::Func(func) => Some(func),
+            _ => None,
+        }
+    }
+
+    pub fn get_typed_func<Params: WasmTypeList, Results: WasmTypeList>(
+        &self,
+        store: &Store,
+        name: &str,
+    ) -> anyhow::Result<TypedFunc<Params, Results>> {
+        let func = self.get_func(store, name);
+        let func = func.with_context(|| format!("unknown function export {name:?}"))?;
+        func.typed(store)
+    }
+
+    pub fn get_table(&self, store: &Store, name: &str) -> Option<Table> {
+        match self.get_export(store, name)? {
+            Extern::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    pub fn get_memory(&self, store: &Store, name: &str) -> Option<Memory> {
+        match self.get_export(store, name)? {
+            Extern::Memory(memory) => Some(memory),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an exported global
+    pub fn get_global(&self, store: &Store, name: &str) -> Option<Val> {
+        match self.get_export(store, name)? {
+            Extern::Global(global) => Some(global.get(store)),
+            _ => None,
+        }
+    }
+
+    /// Calls an exported function, returning its results
+    pub fn invoke(&self, store: &mut Store, name: &str, args: &[Val]) -> anyhow::Result<Vec<Val>> {
+        let func = self.get_func(store, name);
+        let func = func.with_context(|| format!("unknown function export {name:?}"))?;
+        func.call(store, args)
+    }
+}
+
+/// Converts the offset of an active segment, an `i32` or `i64` address, to
+/// an unsigned offset
+fn offset_u64(offset: Val) -> u64 {
+    match offset {
+        Val::I32(offset) => offset as u32 as u64,
+        Val::I64(offset) => offset as u64,
+        _ => unreachable!("validated"),
+    }
+}
+
+/// Handle to a function in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Func(usize);
+
+impl Func {
+    pub fn ty(&self, store: &Store) -> ty::Func {
+        let func = store.funcs[self.0].ty.func();
+        func.expect("functions have function types")
+    }
+
+    /// Calls the function with arguments of its parameter types, returning
+    /// its results
+    pub fn call(&self, store: &mut Store, args: &[Val]) -> anyhow::Result<Vec<Val>> {
+        let ty = self.ty(store);
+        let types: Vec<_> = args.iter().map(|arg| arg.ty(store)).collect();
+        anyhow::ensure!(
+            types.len() == ty.params.len()
+                && types
+                    .iter()
+                    .zip(&ty.params)
+                    .all(|(arg, param)| arg.matches(param)),
+            "type mismatch: expected {} but found {}",
+            validation::types(&ty.params),
+            validation::types(&types),
+        );
+        exec::Thread::cached(store, |thread, store| thread.call(store, self.0, args))
+    }
+
+    /// Checks the signature of the function against Rust types for calls
+    /// without checking each of them
+    pub fn typed<Params: WasmTypeList, Results: WasmTypeList>(
+        &self,
+        store: &Store,
+    ) -> anyhow::Result<TypedFunc<Params, Results>> {
+        TypedFunc::new(store, *self)
+    }
+}
+
+/// Handle to a global in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Global(usize);
+
+impl Global {
+    /// Creates a global with a value of its type
+    pub fn new(store: &mut Store, ty: ty::Global, value: Val) -> anyhow::Result<Self> {
+        value.check(store, &ty.value)?;
+        store.globals.push(GlobalInst { ty, value });
+        Ok(Self(store.globals.len() - 1))
+    }
+
+    pub fn ty(&self, store: &Store) -> ty::Global {
+        store.globals[self.0].ty.clone()
+    }
+
+    pub fn get(&self, store: &Store) -> Val {
+        store.globals[self.0].value
+    }
+
+    /// Sets a mutable global to a value of its type
+    pub fn set(&self, store: &mut Store, value: Val) -> anyhow::Result<()> {
+        let ty = &store.globals[self.0].ty;
+        anyhow::ensure!(ty.is_mutable, "global is immutable");
+        value.check(store, &ty.value)?;
+        store.globals[self.0].value = value;
+        Ok(())
+    }
+}
+
+/// Handle to an exception tag in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag(usize);
+
+impl Tag {
+    /// Creates a tag whose exceptions carry values of the parameter types
+    pub fn new(store: &mut Store, ty: ty::Func) -> Self {
+        store.tags.push(TagInst {
+            ty: ty::Defined::from(ty),
+        });
+        Self(store.tags.len() - 1)
+    }
+
+    pub fn ty(&self, store: &Store) -> ty::Func {
+        let tag = store.tags[self.0].ty.func();
+        tag.expect("tags have function types")
+    }
+}
+
+/// Item of a store that a module can import or an instance can export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extern {
+    Func(Func),
+    Table(Table),
+    Memory(Memory),
+    Global(Global),
+    Tag(Tag),
+}
+
+impl Extern {
+    /// Returns the type of the item, with the current sizes of tables and
+    /// memories as their minimum
+    pub fn ty(&self, store: &Store) -> ty::External {
+        match self {
+            Self::Func(func) => ty::External::Func(store.funcs[func.0].ty.clone()),
+            Self::Table(table) => ty::External::Table(table.ty(store)),
+            Self::Memory(memory) => ty::External::Memory(memory.ty(store)),
+            Self::Global(global) => ty::External::Global(global.ty(store)),
+            Self::Tag(tag) => ty::External::Tag(store.tags[tag.0].ty.clone()),
+        }
+    }
+}
+
+impl From<Func> for Extern {
+    fn from(func: Func) -> Self {
+        Self::Func(func)
+    }
+}
+
+impl From<Table> for Extern {
+    fn from(table: Table) -> Self {
+        Self::Table(table)
+    }
+}
+
+impl From<Memory> for Extern {
+    fn from(memory: Memory) -> Self {
+        Self::Memory(memory)
+    }
+}
+
+impl From<Global> for Extern {
+    fn from(global: Global) -> Self {
+        Self::Global(global)
+    }
+}
+
+impl From<Tag> for Extern {
+    fn from(tag: Tag) -> Self {
+        Self::Tag(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instantiate(store: &mut Store, source: &str) -> anyhow::Result<Instance> {
+        let module = binary::Module::decode(wat::parse_str(source)?)?;
+        let module = validation::validate(&module, Default::default())?;
+        Instance::new(store, &Rc::new(module), &[])
+    }
+
+    #[test]
+    fn invoke() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (global $counter (export "counter") (mut i32) (i32.const 10))
+    (global i64 (i64.add (i64.const 1) (i64.const 2)))
+    (func (export "add") (param i32 i32) (result i32)
+        (i32.add (local.get 0) (local.get 1)))
+    (func $fac (export "fac") (param i64) (result i64)
+        (if (result i64) (i64.eqz (local.get 0))
+            (then (i64.const 1))
+            (else (i64.mul (local.get 0) (call $fac (i64.sub (local.get 0) (i64.const 1)))))))
+    (func (export "sum") (param $n i32) (result i32) (local $sum i32)
+        (block $done
+            (loop $next
+                (br_if $done (i32.eqz (local.get $n)))
+                (local.set $sum (i32.add (local.get $sum) (local.get $n)))
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (br $next)))
+        (local.get $sum))
+    (func (export "switch") (param i32) (result i32)
+        (block (block (block (br_table 0 1 2 (local.get 0)))
+            (return (i32.const 10)))
+            (return (i32.const 20)))
+        (i32.const 30))
+    (func (export "swap") (param i32 f64) (result f64 i32)
+        (local.get 1) (local.get 0))
+    (func $tick (global.set $counter (i32.add (global.get $counter) (i32.const 1))))
+    (start $tick)
+)
+"#,
+        )?;
+        let invoke = |store: &mut Store, name, args: &[Val]| instance.invoke(store, name, args);
+        assert_eq!(
+            vec![Val::I32(5)],
+            invoke(&mut store, "add", &[Val::I32(2), Val::I32(3)])?
+        );
+        assert_eq!(
+            vec![Val::I32(i32::MIN)],
+            invoke(&mut store, "add", &[Val::I32(i32::MAX), Val::I32(1)])?
+        );
+        assert_eq!(
+            vec![Val::I64(2_432_902_008_176_640_000)],
+            invoke(&mut store, "fac", &[Val::I64(20)])?
+        );
+        assert_eq!(
+            vec![Val::I32(5050)],
+            invoke(&mut store, "sum", &[Val::I32(100)])?
+        );
+        for (index, result) in [(0, 10), (1, 20), (2, 30), (7, 30)] {
+            assert_eq!(
+                vec![Val::I32(result)],
+                invoke(&mut store, "switch", &[Val::I32(index)])?
+            );
+        }
+        assert_eq!(
+            vec![Val::F64(1.5f64.to_bits()), Val::I32(1)],
+            invoke(
+                &mut store,
+                "swap",
+                &[Val::I32(1), Val::F64(1.5f64.to_bits())]
+            )?
+        );
+        assert_eq!(Some(Val::I32(11)), instance.get_global(&store, "counter"));
+
+        let message = |result: anyhow::Result<_>| format!("{:#}", result.unwrap_err());
+        assert_eq!(
+            "type mismatch: expected [i32 i32] but found [i32 i64]",
+            message(invoke(&mut store, "add", &[Val::I32(2), Val::I64(3)])),
+        );
+        assert_eq!(
+            "unknown function export \"sub\"",
+            message(invoke(&mut store, "sub", &[])),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn numeric() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (func (export "i32.div_s") (param i32 i32) (result i32) (i32.div_s (local.get 0) (local.get 1)))
+    (func (export "i32.rem_s") (param i32 i32) (result i32) (i32.rem_s (local.get 0) (local.get 1)))
+    (func (export "i32.rotr") (param i32 i32) (result i32) (i32.rotr (local.get 0) (local.get 1)))
+    (func (export "i64.shr_u") (param i64 i64) (result i64) (i64.shr_u (local.get 0) (local.get 1)))
+    (func (export "i64.clz") (param i64) (result i64) (i64.clz (local.get 0)))
+    (func (export "f32.nearest") (param f32) (result f32) (f32.nearest (local.get 0)))
+    (func (export "f64.min") (param f64 f64) (result f64) (f64.min (local.get 0) (local.get 1)))
+    (func (export "f32.neg") (param f32) (result f32) (f32.neg (local.get 0)))
+    (func (export "i32.trunc_f32_s") (param f32) (result i32) (i32.trunc_f32_s (local.get 0)))
+    (func (export "i64.trunc_f64_u") (param f64) (result i64) (i64.trunc_f64_u (local.get 0)))
+    (func (export "i32.trunc_sat_f64_u") (param f64) (result i32) (i32.trunc_sat_f64_u (local.get 0)))
+    (func (export "f32.convert_i64_u") (param i64) (result f32) (f32.convert_i64_u (local.get 0)))
+    (func (export "i64.extend16_s") (param i64) (result i64) (i64.extend16_s (local.get 0)))
+    (func (export "unreachable") (unreachable))
+)
+"#,
+        )?;
+        let f32 = |value: f32| Val::F32(value.to_bits());
+        let f64 = |value: f64| Val::F64(value.to_bits());
+        let cases = [
+            ("i32.div_s", vec![Val::I32(-7), Val::I32(2)], Val::I32(-3)),
+            (
+                "i32.rem_s",
+                vec![Val::I32(i32::MIN), Val::I32(-1)],
+                Val::I32(0),
+            ),
+            (
+                "i32.rotr",
+                vec![Val::I32(1), Val::I32(33)],
+                Val::I32(i32::MIN),
+            ),
+            (
+                "i64.shr_u",
+                vec![Val::I64(-1), Val::I64(65)],
+                Val::I64(i64::MAX),
+            ),
+            ("i64.clz", vec![Val::I64(1)], Val::I64(63)),
+            ("f32.nearest", vec![f32(2.5)], f32(2.0)),
+            ("f32.nearest", vec![f32(-3.5)], f32(-4.0)),
+            ("f64.min", vec![f64(0.0), f64(-0.0)], f64(-0.0)),
+            (
+                "f32.neg",
+                vec![Val::F32(0x7fc0_0001)],
+                Val::F32(0xffc0_0001),
+            ),
+            (
+                "i32.trunc_f32_s",
+                vec![f32(-2147483648.0)],
+                Val::I32(i32::MIN),
+            ),
+            (
+                "i64.trunc_f64_u",
+                vec![f64(1e19)],
+                Val::I64(1e19 as u64 as i64),
+            ),
+            ("i32.trunc_sat_f64_u", vec![f64(-1.0)], Val::I32(0)),
+            ("i32.trunc_sat_f64_u", vec![f64(f64::NAN)], Val::I32(0)),
+            ("f32.convert_i64_u", vec![Val::I64(-1)], f32(1.8446744e19)),
+            ("i64.extend16_s", vec![Val::I64(0x8000)], Val::I64(-0x8000)),
+        ];
+        for (name, args, result) in cases {
+            let results = instance.invoke(&mut store, name, &args)?;
+            assert_eq!(vec![result], results, "{name} {args:?}");
+        }
+        let f64_min = instance.invoke(&mut store, "f64.min", &[f64(f64::NAN), f64(0.0)])?;
+        assert!(matches!(f64_min[..], [Val::F64(bits)] if f64::from_bits(bits).is_nan()));
+
+        let traps = [
+            (
+                "i32.div_s",
+                vec![Val::I32(1), Val::I32(0)],
+                "integer divide by zero",
+            ),
+            (
+                "i32.div_s",
+                vec![Val::I32(i32::MIN), Val::I32(-1)],
+                "integer overflow",
+            ),
+            (
+                "i32.trunc_f32_s",
+                vec![f32(2147483648.0)],
+                "integer overflow",
+            ),
+            (
+                "i32.trunc_f32_s",
+                vec![f32(f32::NAN)],
+                "invalid conversion to integer",
+            ),
+            ("i64.trunc_f64_u", vec![f64(-1.0)], "integer overflow"),
+            ("unreachable", vec![], "unreachable"),
+        ];
+        for (name, args, message) in traps {
+            let error = instance.invoke(&mut store, name, &args).unwrap_err();
+            assert_eq!(message, error.to_string(), "{name} {args:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn typed_func() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (func (export "div") (param i64 i64) (result i64 i64)
+        (i64.div_u (local.get 0) (local.get 1))
+        (i64.rem_u (local.get 0) (local.get 1)))
+    (func (export "scale") (param f32) (result f64)
+        (f64.mul (f64.promote_f32 (local.get 0)) (f64.const 2)))
+    (func (export "nop"))
+)
+"#,
+        )?;
+        let div = instance.get_typed_func::<(i64, i64), (i64, i64)>(&store, "div")?;
+        assert_eq!((3, 1), div.call(&mut store, (7, 2))?);
+        let scale = instance.get_typed_func::<f32, f64>(&store, "scale")?;
+        assert_eq!(3.0, scale.call(&mut store, 1.5)?);
+        let nop = instance.get_typed_func::<(), ()>(&store, "nop")?;
+        nop.call(&mut store, ())?;
+
+        // Calls reuse the stacks of the last one, emptied even by a trap
+        assert!(div.call(&mut store, (7, 0)).is_err());
+        let thread = store.thread.as_ref().unwrap();
+        assert!(thread.vals.is_empty() && thread.vals.capacity() > 0);
+        assert_eq!((3, 1), div.call(&mut store, (7, 2))?);
+
+        let message =
+            |result: anyhow::Result<TypedFunc<i32, f64>>| format!("{:#}", result.err().unwrap());
+        assert_eq!(
+            "type mismatch: expected [i32] -> [f64] but found [f32] -> [f64]",
+            message(instance.get_typed_func(&store, "scale")),
+        );
+        assert_eq!(
+            "unknown function export \"mul\"",
+            message(instance.get_typed_func(&store, "mul")),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn references() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (type $f (func (result i32)))
+    (global $g (mut funcref) (ref.func $answer))
+    (func $answer (export "answer") (type $f) (i32.const 42))
+    (func (export "answer-ref") (result (ref $f)) (ref.func $answer))
+    (func (export "get") (result funcref) (global.get $g))
+    (func (export "set") (param funcref) (global.set $g (local.get 0)))
+    (func (export "is-null") (param externref) (result i32) (ref.is_null (local.get 0)))
+    (func (export "id") (param externref) (result externref) (local.get 0))
+    (func (export "null") (result anyref) (ref.null i31))
+)
+"#,
+        )?;
+        let answer = instance.get_func(&store, "answer").unwrap();
+        assert_eq!(
+            vec![Val::FuncRef(Some(answer))],
+            instance.invoke(&mut store, "answer-ref", &[])?
+        );
+        let get = instance.get_typed_func::<(), Option<Func>>(&store, "get")?;
+        assert_eq!(Some(answer), get.call(&mut store, ())?);
+        instance.invoke(&mut store, "set", &[Val::FuncRef(None)])?;
+        assert_eq!(None, get.call(&mut store, ())?);
+
+        let host = ExternRef::new(&mut store, String::from("host"));
+        let id = instance.get_typed_func::<Option<ExternRef>, Option<ExternRef>>(&store, "id")?;
+        let result = id.call(&mut store, Some(host))?.unwrap();
+        assert_eq!(
+            Some("host"),
+            result
+                .data(&store)
+                .downcast_ref::<String>()
+                .map(String::as_str)
+        );
+        let is_null = instance.get_typed_func::<Option<ExternRef>, i32>(&store, "is-null")?;
+        assert_eq!(
+            (1, 0),
+            (
+                is_null.call(&mut store, None)?,
+                is_null.call(&mut store, Some(host))?
+            )
+        );
+        assert_eq!(
+            vec![Val::AnyRef(None)],
+            instance.invoke(&mut store, "null", &[])?
+        );
+
+        let message = |result: anyhow::Result<_>| format!("{:#}", result.unwrap_err());
+        assert_eq!(
+            "type mismatch: expected [(ref null func)] but found [(ref extern)]",
+            message(
+                instance
+                    .invoke(&mut store, "set", &[Val::ExternRef(Some(host))])
+                    .map(drop)
+            ),
+        );
+        assert_eq!(
+            "type mismatch: expected [] -> [(ref func)] but found [] -> [(ref null func)]",
+            message(instance.get_typed_func::<(), Func>(&store, "get").map(drop)),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Val::I32(-1), Val::from(-1));
+        assert_eq!(Val::F64(0.5f64.to_bits()), Val::from(0.5));
+        assert_eq!(
+            Val::AnyRef(Some(AnyRef::I31(0x7fff_ffff))),
+            AnyRef::i31(-1).into()
+        );
+        assert_eq!(1.5f32, f32::try_from(Val::from(1.5f32)).unwrap());
+        assert_eq!(
+            "type mismatch: expected i64 but found i32 3",
+            i64::try_from(Val::I32(3)).unwrap_err().to_string(),
+        );
+        assert_eq!(
+            "type mismatch: expected funcref but found ref.null extern",
+            Option::<Func>::try_from(Val::ExternRef(None))
+                .unwrap_err()
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn memory() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (memory (export "memory") 1 2)
+    (memory $wide i64 1)
+    (data (i32.const 8) "\01\02\03\04")
+    (data $passive "hello")
+    (func (export "load") (param i32) (result i32) (i32.load (local.get 0)))
+    (func (export "load8_s") (param i32) (result i32) (i32.load8_s offset=1 (local.get 0)))
+    (func (export "store16") (param i32 i64) (i64.store16 (local.get 0) (local.get 1)))
+    (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0)))
+    (func (export "size") (result i32) (memory.size))
+    (func (export "wide") (param i64) (result i64)
+        (i64.store $wide (local.get 0) (i64.const -2))
+        (i64.load $wide (local.get 0)))
+    (func (export "init") (param i32 i32 i32)
+        (memory.init $passive (local.get 0) (local.get 1) (local.get 2)))
+    (func (export "drop") (data.drop $passive))
+    (func (export "copy") (param i32 i32 i32)
+        (memory.copy (local.get 0) (local.get 1) (local.get 2)))
+    (func (export "fill") (param i32 i32 i32)
+        (memory.fill (local.get 0) (local.get 1) (local.get 2)))
+)
+"#,
+        )?;
+        let memory = instance.get_memory(&store, "memory").unwrap();
+        let load = instance.get_typed_func::<i32, i32>(&store, "load")?;
+        assert_eq!(0x0403_0201, load.call(&mut store, 8)?);
+        memory.write(&mut store, 16, &[0xff])?;
+        let load8_s = instance.get_typed_func::<i32, i32>(&store, "load8_s")?;
+        assert_eq!(-1, load8_s.call(&mut store, 15)?);
+        instance.invoke(&mut store, "store16", &[Val::I32(0), Val::I64(0x1_abcd)])?;
+        assert_eq!(0xabcd, memory.read_le::<u16>(&store, 0)?);
+        memory.write_le(&mut store, 4, 1.5f32)?;
+        let mut bytes = [0; 4];
+        memory.read(&store, 4, &mut bytes)?;
+        assert_eq!(1.5f32.to_le_bytes(), bytes);
+
+        let grow = instance.get_typed_func::<i32, i32>(&store, "grow")?;
+        assert_eq!(
+            (1, -1),
+            (grow.call(&mut store, 1)?, grow.call(&mut store, 1)?)
+        );
+        assert_eq!(vec![Val::I32(2)], instance.invoke(&mut store, "size", &[])?);
+        assert_eq!(2 * PAGE_SIZE as usize, memory.data(&store).len());
+        assert!(memory.grow(&mut store, 1).is_err());
+        let wide = instance.get_typed_func::<i64, i64>(&store, "wide")?;
+        assert_eq!(-2, wide.call(&mut store, PAGE_SIZE as i64 - 8)?);
+
+        let init = instance.get_typed_func::<(i32, i32, i32), ()>(&store, "init")?;
+        init.call(&mut store, (100, 1, 4))?;
+        let copy = instance.get_typed_func::<(i32, i32, i32), ()>(&store, "copy")?;
+        copy.call(&mut store, (102, 100, 4))?;
+        let fill = instance.get_typed_func::<(i32, i32, i32), ()>(&store, "fill")?;
+        fill.call(&mut store, (106, b'!' as i32, 2))?;
+        assert_eq!(b"elello!!", &memory.data(&store)[100..108]);
+
+        fn message<T>(result: anyhow::Result<T>) -> String {
+            format!("{:#}", result.err().unwrap())
+        }
+        let size = 2 * PAGE_SIZE as i32;
+        assert_eq!(
+            "out of bounds memory access",
+            message(load.call(&mut store, size - 3))
+        );
+        assert_eq!(
+            "out of bounds memory access",
+            message(load.call(&mut store, -1))
+        );
+        assert_eq!(
+            "out of bounds memory access",
+            message(wide.call(&mut store, PAGE_SIZE as i64 - 7))
+        );
+        assert_eq!(
+            "out of bounds memory access",
+            message(copy.call(&mut store, (size - 1, 0, 2)))
+        );
+        assert_eq!(
+            "out of bounds memory access",
+            message(init.call(&mut store, (0, 4, 2)))
+        );
+        init.call(&mut store, (size, 5, 0))?;
+        instance.invoke(&mut store, "drop", &[])?;
+        assert_eq!(
+            "out of bounds memory access",
+            message(init.call(&mut store, (0, 0, 1)))
+        );
+        init.call(&mut store, (0, 0, 0))?;
+
+        assert_eq!(
+            "failed to initialize data segment 0: out of bounds memory access",
+            message(instantiate(
+                &mut store,
+                r#"(module (memory 1) (data (i32.const 65535) "ab"))"#
+            )),
+        );
+        let host = Memory::new(
+            &mut store,
+            ty::Memory(ty::Limit {
+                address: ty::Address::I32,
+                min: 1,
+                max: None,
+            }),
+        )?;
+        assert_eq!(
+            (1, 1),
+            (host.grow(&mut store, 1)?, host.ty(&store).0.min - 1)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tables() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (type $unary (func (param i32) (result i32)))
+    (type $sup (sub (func (result i32))))
+    (type $sub (sub $sup (func (result i32))))
+    (table $t (export "table") 4 8 funcref)
+    (table $refs 2 externref)
+    (elem (i32.const 1) $inc $answer)
+    (elem $passive func $inc $dec)
+    (elem $declared declare func $dec)
+    (func $inc (type $unary) (i32.add (local.get 0) (i32.const 1)))
+    (func $dec (type $unary) (i32.sub (local.get 0) (i32.const 1)))
+    (func $answer (type $sub) (i32.const 42))
+    (func (export "apply") (param i32 i32) (result i32)
+        (call_indirect $t (type $unary) (local.get 1) (local.get 0)))
+    (func (export "answer") (param i32) (result i32)
+        (call_indirect $t (type $sup) (local.get 0)))
+    (func (export "init") (param i32 i32 i32)
+        (table.init $t $passive (local.get 0) (local.get 1) (local.get 2)))
+    (func (export "init-declared") (param i32)
+        (table.init $t $declared (i32.const 0) (i32.const 0) (local.get 0)))
+    (func (export "drop") (elem.drop $passive))
+    (func (export "copy") (param i32 i32 i32)
+        (table.copy $t $t (local.get 0) (local.get 1) (local.get 2)))
+    (func (export "grow") (param i32) (result i32) (table.grow $t (ref.null func) (local.get 0)))
+    (func (export "size") (result i32) (table.size $t))
+    (func (export "swap") (param externref) (result externref)
+        (table.get $refs (i32.const 0))
+        (table.set $refs (i32.const 0) (local.get 0)))
+    (func (export "fill") (param i32 externref i32)
+        (table.fill $refs (local.get 0) (local.get 1) (local.get 2)))
+)
+"#,
+        )?;
+        let apply = instance.get_typed_func::<(i32, i32), i32>(&store, "apply")?;
+        assert_eq!(8, apply.call(&mut store, (1, 7))?);
+        let answer = instance.get_typed_func::<i32, i32>(&store, "answer")?;
+        assert_eq!(42, answer.call(&mut store, 2)?);
+
+        fn message<T>(result: anyhow::Result<T>) -> String {
+            format!("{:#}", result.err().unwrap())
+        }
+        assert_eq!(
+            "indirect call type mismatch",
+            message(apply.call(&mut store, (2, 0)))
+        );
+        assert_eq!(
+            "indirect call type mismatch",
+            message(answer.call(&mut store, 1))
+        );
+        assert_eq!(
+            "uninitialized element",
+            message(apply.call(&mut store, (0, 0)))
+        );
+        assert_eq!("undefined element", message(apply.call(&mut store, (4, 0))));
+        assert_eq!(
+            "undefined element",
+            message(apply.call(&mut store, (-1, 0)))
+        );
+
+        let init = instance.get_typed_func::<(i32, i32, i32), ()>(&store, "init")?;
+        init.call(&mut store, (2, 0, 2))?;
+        assert_eq!(6, apply.call(&mut store, (3, 7))?);
+        let copy = instance.get_typed_func::<(i32, i32, i32), ()>(&store, "copy")?;
+        copy.call(&mut store, (0, 2, 2))?;
+        assert_eq!(8, apply.call(&mut store, (0, 7))?);
+        assert_eq!(
+            "out of bounds table access",
+            message(init.call(&mut store, (3, 0, 2)))
+        );
+        assert_eq!(
+            "out of bounds table access",
+            message(copy.call(&mut store, (3, 0, 2)))
+        );
+        instance.invoke(&mut store, "drop", &[])?;
+        init.call(&mut store, (0, 0, 0))?;
+        assert_eq!(
+            "out of bounds table access",
+            message(init.call(&mut store, (0, 0, 1)))
+        );
+        let init_declared = instance.get_typed_func::<i32, ()>(&store, "init-declared")?;
+        init_declared.call(&mut store, 0)?;
+        assert_eq!(
+            "out of bounds table access",
+            message(init_declared.call(&mut store, 1))
+        );
+
+        let grow = instance.get_typed_func::<i32, i32>(&store, "grow")?;
+        assert_eq!(
+            (4, -1),
+            (grow.call(&mut store, 2)?, grow.call(&mut store, 3)?)
+        );
+        assert_eq!(vec![Val::I32(6)], instance.invoke(&mut store, "size", &[])?);
+        let table = instance.get_table(&store, "table").unwrap();
+        assert_eq!(Some(Val::FuncRef(None)), table.get(&store, 5));
+        assert_eq!(None, table.get(&store, 6));
+
+        let host = ExternRef::new(&mut store, 1u8);
+        let swap =
+            instance.get_typed_func::<Option<ExternRef>, Option<ExternRef>>(&store, "swap")?;
+        assert_eq!(None, swap.call(&mut store, Some(host))?);
+        assert_eq!(Some(host), swap.call(&mut store, None)?);
+        let fill = instance.get_typed_func::<(i32, Option<ExternRef>, i32), ()>(&store, "fill")?;
+        fill.call(&mut store, (0, Some(host), 2))?;
+        assert_eq!(Some(host), swap.call(&mut store, None)?);
+        assert_eq!(
+            "out of bounds table access",
+            message(fill.call(&mut store, (1, None, 2)))
+        );
+
+        // Types are canonical across modules, so a function of an
+        // equivalent type defined elsewhere passes the check
+        let other = instantiate(
+            &mut store,
+            r#"(module (type (func (param i32) (result i32))) (func (export "twice") (type 0) (i32.mul (local.get 0) (i32.const 2))))"#,
+        )?;
+        let twice = other.get_func(&store, "twice").unwrap();
+        table.set(&mut store, 0, twice.into())?;
+        assert_eq!(14, apply.call(&mut store, (0, 7))?);
+        assert_eq!(
+            "type mismatch: expected (ref null func) but found (ref extern)",
+            message(table.set(&mut store, 0, host.into())),
+        );
+
+        assert_eq!(
+            "failed to initialize element segment 0: out of bounds table access",
+            message(instantiate(
+                &mut store,
+                "(module (table 1 funcref) (func $f) (elem (i32.const 1) $f))"
+            )),
+        );
+        let host = Table::new(
+            &mut store,
+            ty::Table {
+                reference: ty::Reference {
+                    is_nullable: true,
+                    heap: ty::Heap::Abstract(ty::AbsHeap::Func),
+                },
+                limit: ty::Limit {
+                    address: ty::Address::I32,
+                    min: 1,
+                    max: Some(2),
+                },
+            },
+            Val::FuncRef(Some(twice)),
+        )?;
+        assert_eq!(1, host.grow(&mut store, 1, Val::FuncRef(None))?);
+        assert_eq!(Some(Val::FuncRef(Some(twice))), host.get(&store, 0));
+        assert!(host.grow(&mut store, 1, Val::FuncRef(None)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn linker() -> anyhow::Result<()> {
+        use std::cell::RefCell;
+
+        let mut store = Store::default();
+        let mut linker = Linker::new();
+        let lib = instantiate(
+            &mut store,
+            r#"
+(module
+    (memory (export "memory") 1)
+    (table (export "table") 2 funcref)
+    (global (export "counter") (mut i32) (i32.const 0))
+    (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2)))
+)
+"#,
+        )?;
+        linker.instance(&store, "lib", lib)?;
+
+        let printed = Rc::new(RefCell::new(vec![]));
+        let sink = printed.clone();
+        linker.func_wrap(
+            &mut store,
+            "env",
+            "print",
+            move |caller: Caller<'_>, (address, len): (i32, i32)| {
+                let memory = caller.memory().unwrap();
+                let mut bytes = vec![0; len as usize];
+                memory.read(caller.store(), address as u64, &mut bytes)?;
+                sink.borrow_mut().push(String::from_utf8(bytes)?);
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(
+            &mut store,
+            "env",
+            "call-export",
+            |mut caller: Caller<'_>, value: i32| {
+                let Some(Extern::Func(func)) = caller.get_export("inc") else {
+                    anyhow::bail!("missing export");
+                };
+                let inc = func.typed::<i32, i32>(caller.store())?;
+                inc.call(caller.store_mut(), value)
+            },
+        )?;
+        let scale = Global::new(
+            &mut store,
+            ty::Global {
+                value: ty::Value::Num(ty::Number::F64),
+                is_mutable: false,
+            },
+            Val::from(1.5),
+        )?;
+        linker.define("env", "scale", scale)?;
+
+        let module = |source: &str| -> anyhow::Result<Rc<validation::Validated>> {
+            let module = binary::Module::decode(wat::parse_str(source)?)?;
+            Ok(Rc::new(validation::validate(&module, Default::default())?))
+        };
+        let app = linker.instantiate(
+            &mut store,
+            &module(
+                r#"
+(module
+    (import "env" "print" (func $print (param i32 i32)))
+    (import "env" "call-export" (func $call (param i32) (result i32)))
+    (import "env" "scale" (global $scale f64))
+    (import "lib" "double" (func $double (param i32) (result i32)))
+    (import "lib" "counter" (global $counter (mut i32)))
+    (import "lib" "table" (table 1 funcref))
+    (import "lib" "memory" (memory 1))
+    (data (i32.const 0) "hello")
+    (elem (i32.const 1) $inc)
+    (func $inc (export "inc") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+    (func (export "run") (param i32) (result i32)
+        (call $print (i32.const 0) (i32.const 5))
+        (global.set $counter (call $double (local.get 0)))
+        (call $call (global.get $counter)))
+    (func (export "scaled") (param f64) (result f64) (f64.mul (local.get 0) (global.get $scale)))
+)
+"#,
+            )?,
+        )?;
+        let run = app.get_typed_func::<i32, i32>(&store, "run")?;
+        assert_eq!(7, run.call(&mut store, 3)?);
+        assert_eq!(vec!["hello"], *printed.borrow());
+        assert_eq!(Some(Val::I32(6)), lib.get_global(&store, "counter"));
+        let memory = lib.get_memory(&store, "memory").unwrap();
+        assert_eq!(b"hello", &memory.data(&store)[..5]);
+        let table = lib.get_table(&store, "table").unwrap();
+        assert_eq!(
+            app.get_func(&store, "inc").map(Val::from),
+            table.get(&store, 1)
+        );
+        let scaled = app.get_typed_func::<f64, f64>(&store, "scaled")?;
+        assert_eq!(3.0, scaled.call(&mut store, 2.0)?);
+
+        let tag = Tag::new(
+            &mut store,
+            ty::Func {
+                params: vec![ty::Value::Num(ty::Number::I32)],
+                returns: vec![],
+            },
+        );
+        linker.define("env", "error", tag)?;
+        linker.instantiate(
+            &mut store,
+            &module(r#"(module (import "env" "error" (tag (param i32))))"#)?,
+        )?;
+        assert_eq!(1, tag.ty(&store).params.len());
+
+        let wrong = Func::new(
+            &mut store,
+            ty::Func {
+                params: vec![],
+                returns: vec![ty::Value::Num(ty::Number::I32)],
+            },
+            |caller, _| Ok(vec![Val::I64(caller.instance().map_or(0, |_| 1))]),
+        );
+        fn message<T>(result: anyhow::Result<T>) -> String {
+            format!("{:#}", result.err().unwrap())
+        }
+        assert_eq!(
+            "type mismatch: expected [i32] but host function returned [i64]",
+            message(wrong.call(&mut store, &[])),
+        );
+        assert_eq!(
+            "duplicate definition of \"env\" \"scale\"",
+            message(linker.define("env", "scale", wrong).map(drop)),
+        );
+        assert_eq!(
+            "unknown import \"env\" \"missing\"",
+            message(linker.instantiate(
+                &mut store,
+                &module(r#"(module (import "env" "missing" (func)))"#)?
+            )),
+        );
+        assert_eq!(
+            "incompatible import type for \"env\" \"print\": expected (func (param i64)) but found (func (param i32 i32))",
+            message(linker.instantiate(
+                &mut store,
+                &module(r#"(module (import "env" "print" (func (param i64))))"#)?
+            )),
+        );
+        assert_eq!(
+            "incompatible import type for \"lib\" \"memory\": expected (memory 2) but found (memory 1)",
+            message(linker.instantiate(
+                &mut store,
+                &module(r#"(module (import "lib" "memory" (memory 2)))"#)?
+            )),
+        );
+        assert_eq!(
+            "incompatible import type for \"lib\" \"counter\": expected (global i32) but found (global (mut i32))",
+            message(linker.instantiate(
+                &mut store,
+                &module(r#"(module (import "lib" "counter" (global i32)))"#)?
+            )),
+        );
+        assert_eq!(
+            "incompatible import type for \"env\" \"error\": expected (tag (param i64)) but found (tag (param i32))",
+            message(linker.instantiate(
+                &mut store,
+                &module(r#"(module (import "env" "error" (tag (param i64))))"#)?
+            )),
+        );
+        assert_eq!(
+            "expected 1 imports but found 0",
+            message(Instance::new(
+                &mut store,
+                &module(r#"(module (import "env" "print" (func (param i32 i32))))"#)?,
+                &[]
+            )),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn traps() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let mut linker = Linker::new();
+        linker.func_wrap(&mut store, "env", "fail", |_, ()| -> anyhow::Result<()> {
+            anyhow::bail!("host failure")
+        })?;
+        let module = binary::Module::decode(wat::parse_str(
+            r#"
+(module
+    (import "env" "fail" (func $fail))
+    (func $inner (param i32) (result i32)
+        (i32.div_u (i32.const 1) (local.get 0)))
+    (func $outer (export "outer") (param i32) (result i32)
+        (nop)
+        (call $inner (local.get 0)))
+    (func (export "fail") (call $fail))
+    (func $recurse (export "recurse") (call $recurse))
+    (func (export "anonymous") (unreachable))
+)
+"#,
+        )?)?;
+        let module = validation::validate(&module, Default::default())?;
+        let instance = linker.instantiate(&mut store, &Rc::new(module))?;
+
+        let outer = instance.get_typed_func::<i32, i32>(&store, "outer")?;
+        assert_eq!(1, outer.call(&mut store, 1)?);
+        let error = outer.call(&mut store, 0).unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!(TrapCode::IntegerDivideByZero, trap.code());
+        assert_eq!("integer divide by zero", error.to_string());
+        let frame = |func, name: &str, offset| FrameInfo {
+            func,
+            name: Some(name.to_owned()),
+            offset,
+        };
+        assert_eq!(
+            [frame(1, "inner", 2), frame(2, "outer", 2)],
+            trap.backtrace()
+        );
+        assert_eq!(
+            "$inner (func 1) at instruction 2",
+            trap.backtrace()[0].to_string()
+        );
+
+        let error = instance.invoke(&mut store, "fail", &[]).unwrap_err();
+        assert!(error.downcast_ref::<Trap>().is_none());
+        assert_eq!("host failure", error.to_string());
+
+        let error = instance.invoke(&mut store, "recurse", &[]).unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!(
+            (TrapCode::StackOverflow, "call stack exhausted"),
+            (trap.code(), error.to_string().as_str())
+        );
+        assert_eq!(Some(&frame(4, "recurse", 0)), trap.backtrace().first());
+
+        let error = instance.invoke(&mut store, "anonymous", &[]).unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!("func 5 at instruction 0", trap.backtrace()[0].to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn stack_limits() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (func $fac (export "fac") (param i64) (result i64)
+        (if (result i64) (i64.eqz (local.get 0))
+            (then (i64.const 1))
+            (else (i64.mul (local.get 0) (call $fac (i64.sub (local.get 0) (i64.const 1)))))))
+    (func $even (export "even") (param i64) (result i32)
+        (if (result i32) (i64.eqz (local.get 0))
+            (then (i32.const 1))
+            (else (call $odd (i64.sub (local.get 0) (i64.const 1))))))
+    (func $odd (export "odd") (param i64) (result i32)
+        (if (result i32) (i64.eqz (local.get 0))
+            (then (i32.const 0))
+            (else (call $even (i64.sub (local.get 0) (i64.const 1))))))
+    (func $deep (export "deep") (param i32) (local i64 i64 i64 i64 i64 i64 i64 i64 i64)
+        (if (local.get 0) (then (call $deep (i32.sub (local.get 0) (i32.const 1))))))
+)
+"#,
+        )?;
+        let fac = instance.get_typed_func::<i64, i64>(&store, "fac")?;
+        let even = instance.get_typed_func::<i64, i32>(&store, "even")?;
+        let odd = instance.get_typed_func::<i64, i32>(&store, "odd")?;
+        let code = |error: anyhow::Error| error.downcast_ref::<Trap>().map(Trap::code);
+        assert_eq!(StackLimits::default(), store.stack_limits());
+        assert_eq!(7034535277573963776, fac.call(&mut store, 25)?);
+        assert_eq!(1, even.call(&mut store, 77_776)?);
+        assert_eq!(1, odd.call(&mut store, 77_777)?);
+        let overflow = Some(TrapCode::StackOverflow);
+        assert_eq!(overflow, code(fac.call(&mut store, 1_000_000).unwrap_err()));
+        assert_eq!(
+            overflow,
+            code(even.call(&mut store, 1_000_000).unwrap_err())
+        );
+
+        store.set_stack_limits(StackLimits {
+            frames: 100,
+            ..StackLimits::default()
+        });
+        assert_eq!(0, even.call(&mut store, 99)?);
+        assert_eq!(overflow, code(even.call(&mut store, 100).unwrap_err()));
+
+        // Each frame of `deep` holds 10 locals
+        let deep = instance.get_typed_func::<i32, ()>(&store, "deep")?;
+        store.set_stack_limits(StackLimits {
+            values: 1_000,
+            ..StackLimits::default()
+        });
+        deep.call(&mut store, 90)?;
+        assert_eq!(overflow, code(deep.call(&mut store, 100).unwrap_err()));
+        assert_eq!(overflow, code(fac.call(&mut store, 1_000).unwrap_err()));
+        Ok(())
+    }
+
+    #[test]
+    fn stack_limits_in_host_calls() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let mut linker = Linker::new();
+        linker.func_wrap(&mut store, "env", "reenter", |mut caller, n: i32| {
+            let Some(Extern::Func(count)) = caller.get_export("count") else {
+                anyhow::bail!("missing count");
+            };
+            let count = count.typed::<i32, i32>(caller.store())?;
+            count.call(caller.store_mut(), n)
+        })?;
+        let module = binary::Module::decode(wat::parse_str(
+            r#"
+(module
+    (import "env" "reenter" (func $reenter (param i32) (result i32)))
+    (func (export "count") (param i32) (result i32)
+        (if (result i32) (i32.eqz (local.get 0))
+            (then (i32.const 0))
+            (else (i32.add
+                (call $reenter (i32.sub (local.get 0) (i32.const 1)))
+                (i32.const 1)))))
+)
+"#,
+        )?)?;
+        let module = validation::validate(&module, Default::default())?;
+        let instance = linker.instantiate(&mut store, &Rc::new(module))?;
+        let count = instance.get_typed_func::<i32, i32>(&store, "count")?;
+        let code = |error: anyhow::Error| error.downcast_ref::<Trap>().map(Trap::code);
+        let overflow = Some(TrapCode::StackOverflow);
+        assert_eq!(100, count.call(&mut store, 100)?);
+        assert_eq!(overflow, code(count.call(&mut store, 101).unwrap_err()));
+        assert_eq!(
+            overflow,
+            code(count.call(&mut store, 1_000_000).unwrap_err())
+        );
+
+        // Each level holds a frame of `count` and one of `reenter`
+        store.set_stack_limits(StackLimits {
+            frames: 100,
+            ..StackLimits::default()
+        });
+        assert_eq!(49, count.call(&mut store, 49)?);
+        assert_eq!(overflow, code(count.call(&mut store, 50).unwrap_err()));
+        store.set_stack_limits(StackLimits {
+            host_frames: 10,
+            ..StackLimits::default()
+        });
+        assert_eq!(10, count.call(&mut store, 10)?);
+        assert_eq!(overflow, code(count.call(&mut store, 11).unwrap_err()));
+        Ok(())
+    }
+
+    #[test]
+    fn fuel() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (func $add (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+    (func (export "sum") (param $n i32) (result i32) (local $sum i32)
+        (block $done
+            (loop $next
+                (br_if $done (i32.eqz (local.get $n)))
+                (local.set $sum (call $add (local.get $sum) (local.get $n)))
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (br $next)))
+        (local.get $sum))
+)
+"#,
+        )?;
+        let sum = instance.get_typed_func::<i32, i32>(&store, "sum")?;
+        assert_eq!(None, store.fuel());
+        store.set_fuel(Some(10_000));
+        assert_eq!(5050, sum.call(&mut store, 100)?);
+        let consumed = 10_000 - store.fuel().unwrap();
+
+        store.set_fuel(Some(100));
+        let error = sum.call(&mut store, 100).unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!((TrapCode::OutOfFuel, Some(0)), (trap.code(), store.fuel()));
+        assert!(!trap.backtrace().is_empty());
+        store.set_fuel(Some(200));
+        assert!(store.resume().is_err());
+        store.set_fuel(Some(10_000));
+        assert_eq!(vec![Val::I32(5050)], store.resume()?);
+        assert_eq!(consumed, 100 + 200 + 10_000 - store.fuel().unwrap());
+        assert_eq!(
+            "no call ran out of fuel",
+            store.resume().unwrap_err().to_string()
+        );
+
+        // A start function running out of fuel fails the instantiation
+        // without replacing the suspended call
+        store.set_fuel(Some(100));
+        assert!(sum.call(&mut store, 100).is_err());
+        let error = instantiate(
+            &mut store,
+            "(module (func $spin (loop $l (br $l))) (start $spin))",
+        )
+        .unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!(TrapCode::OutOfFuel, trap.code());
+        store.set_fuel(Some(10_000));
+        assert_eq!(vec![Val::I32(5050)], store.resume()?);
+
+        store.set_fuel(None);
+        assert_eq!(5050, sum.call(&mut store, 100)?);
+        assert_eq!(None, store.fuel());
+        Ok(())
+    }
+
+    #[test]
+    fn fuel_in_host_calls() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let mut linker = Linker::new();
+        linker.func_wrap(&mut store, "env", "cb", |mut caller, n: i32| {
+            let Some(Extern::Func(inner)) = caller.get_export("inner") else {
+                anyhow::bail!("missing inner");
+            };
+            let inner = inner.typed::<i32, i32>(caller.store())?;
+            inner.call(caller.store_mut(), n)
+        })?;
+        let module = binary::Module::decode(wat::parse_str(
+            r#"
+(module
+    (import "env" "cb" (func $cb (param i32) (result i32)))
+    (func (export "inner") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+    (func (export "outer") (param i32) (result i32)
+        (i32.add (call $cb (local.get 0)) (i32.const 100)))
+)
+"#,
+        )?)?;
+        let module = validation::validate(&module, Default::default())?;
+        let instance = linker.instantiate(&mut store, &Rc::new(module))?;
+        let outer = instance.get_typed_func::<i32, i32>(&store, "outer")?;
+
+        // Running out in `inner` unwinds `cb`, so `outer` can't be resumed
+        store.set_fuel(Some(3));
+        let error = outer.call(&mut store, 3).unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!(TrapCode::OutOfFuel, trap.code());
+        store.set_fuel(Some(100));
+        assert_eq!(
+            "no call ran out of fuel",
+            store.resume().unwrap_err().to_string()
+        );
+
+        // Running out in `outer` after `cb` returns can
+        store.set_fuel(Some(7));
+        assert!(outer.call(&mut store, 3).is_err());
+        store.set_fuel(Some(100));
+        assert_eq!(vec![Val::I32(104)], store.resume()?);
+
+        let fail = Func::wrap(&mut store, |_, ()| -> anyhow::Result<()> {
+            anyhow::bail!(TrapCode::OutOfFuel)
+        });
+        let error = fail.call(&mut store, &[]).unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!(
+            (TrapCode::OutOfFuel, &[][..]),
+            (trap.code(), trap.backtrace())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn limits() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        store.set_limiter(Some(Box::new(StoreLimits {
+            memory_pages: Some(2),
+            table_elements: Some(3),
+            instances: Some(1),
+            ..StoreLimits::default()
+        })));
+        let source = r#"
+(module
+    (memory (export "memory") 1)
+    (table (export "table") 1 funcref)
+    (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0)))
+    (func (export "grow_table") (param i32) (result i32)
+        (table.grow (ref.null func) (local.get 0)))
+)
+"#;
+        let instance = instantiate(&mut store, source)?;
+        let grow = instance.get_typed_func::<i32, i32>(&store, "grow")?;
+        let grow_table = instance.get_typed_func::<i32, i32>(&store, "grow_table")?;
+        assert_eq!(1, grow.call(&mut store, 1)?);
+        assert_eq!(-1, grow.call(&mut store, 1)?);
+        assert_eq!(1, grow_table.call(&mut store, 2)?);
+        assert_eq!(-1, grow_table.call(&mut store, 1)?);
+        let memory = instance.get_memory(&store, "memory").unwrap();
+        assert!(memory.grow(&mut store, 1).is_err());
+        let limit = ty::Limit {
+            address: ty::Address::I32,
+            min: 3,
+            max: None,
+        };
+        assert!(Memory::new(&mut store, ty::Memory(limit)).is_err());
+        assert_eq!(
+            "failed to create an instance beyond the limit of the store",
+            instantiate(&mut store, source).unwrap_err().to_string()
+        );
+
+        store.set_limiter(Some(Box::new(StoreLimits {
+            memory_pages: Some(2),
+            trap_on_grow_failure: true,
+            ..StoreLimits::default()
+        })));
+        let error = grow.call(&mut store, 1).unwrap_err();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!(TrapCode::ResourceLimitExceeded, trap.code());
+        assert!(!trap.backtrace().is_empty());
+        store.set_limiter(None);
+        assert_eq!(2, grow.call(&mut store, 1)?);
+        Ok(())
+    }
+
+    #[test]
+    fn epoch() -> anyhow::Result<()> {
+        let mut store = Store::default();
+        let instance = instantiate(
+            &mut store,
+            r#"
+(module
+    (func $add (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+    (func (export "sum") (param $n i32) (result i32) (local $sum i32)
+        (block $done
+            (loop $next
+                (br_if $done (i32.eqz (local.get $n)))
+                (local.set $sum (call $add (local.get $sum) (local.get $n)))
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (br $next)))
+        (local.get $sum))
+    (func $spin (export "spin") (loop $again (br $again)))
+)
+"#,
+        )?;
+        let sum = instance.get_typed_func::<i32, i32>(&store, "sum")?;
+        let spin = instance.get_typed_func::<(), ()>(&store, "spin")?;
+        store.set_epoch_deadline(Some(1));
+        assert_eq!(6, sum.call(&mut store, 3)?);
+
+        let counter = store.epoch_counter();
+        let watchdog = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            counter.increment();
+        });
+        let error = spin.call(&mut store, ()).unwrap_err();
+        watchdog.join().unwrap();
+        let trap = error.downcast_ref::<Trap>().unwrap();
+        assert_eq!(TrapCode::Interrupt, trap.code());
+        assert_eq!(Some("spin"), trap.backtrace()[0].name.as_deref());
+        assert_eq!("interrupted", error.to_string());
+
+        // Entering `sum`, each iteration of the loop and each call of `add`
+        // reach a deadline which is never extended
+        let calls = Rc::new(std::cell::Cell::new(0));
+        store.set_epoch_callback({
+            let calls = calls.clone();
+            move |epoch| {
+                calls.set(calls.get() + 1);
+                assert_eq!(1, epoch);
+                Ok(0)
+            }
+        });
+        store.set_epoch_deadline(Some(0));
+        assert_eq!(6, sum.call(&mut store, 3)?);
+        assert_eq!(8, calls.get());
+
+        store.set_epoch_callback(|_| anyhow::bail!("too slow"));
+        assert_eq!("too slow", sum.call(&mut store, 3).unwrap_err().to_string());
+
+        store.set_epoch_deadline(None);
+        assert_eq!(6, sum.call(&mut store, 3)?);
+        Ok(())
+    }
+}
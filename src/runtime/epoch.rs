@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Store, TrapCode};
+
+/// Handle to the epoch counter of a store, which other threads such as a
+/// watchdog can increment to interrupt execution at its deadline
+#[derive(Debug, Clone, Default)]
+pub struct EpochCounter(Arc<AtomicU64>);
+
+impl EpochCounter {
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Callback on reaching the epoch deadline, called with the current epoch
+/// and returning the number of epochs to extend the deadline by
+pub(super) type EpochCallback = Box<dyn FnMut(u64) -> anyhow::Result<u64>>;
+
+/// Checks the epoch against the deadline of the store, trapping or calling
+/// the callback once it is reached
+pub(super) fn check(store: &mut Store) -> anyhow::Result<()> {
+    let Some(deadline) = store.epoch_deadline else {
+        return Ok(());
+    };
+    let epoch = store.epoch.get();
+    if epoch < deadline {
+        return Ok(());
+    }
+    let Some(mut callback) = store.epoch_callback.take() else {
+        anyhow::bail!(TrapCode::Interrupt);
+    };
+    let delta = callback(epoch);
+    store.epoch_callback = Some(callback);
+    store.epoch_deadline = Some(epoch.saturating_add(delta?));
+    Ok(())
+}
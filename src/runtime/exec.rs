@@ -0,0 +1,1023 @@
+use std::rc::Rc;
+
+use super::memory::LittleEndian;
+use super::trap::{FrameInfo, Trap, TrapCode};
+use super::{Func, FuncKind, Instance, Store, Val, epoch};
+use crate::validation::instr::{BlockType, Expression, Instruction, MemArg};
+use crate::validation::ty;
+
+/// Body of a function with the branch targets of its structured
+/// instructions resolved up front
+pub(super) struct Code {
+    params: usize,
+    results: usize,
+    locals: Vec<ty::Value>,
+    instrs: Vec<Instruction>,
+    /// Shapes of `block`, `loop`, `if` and `else` by their index in
+    /// `instrs`, left as default for other instructions
+    blocks: Vec<Block>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Block {
+    params: usize,
+    results: usize,
+    /// Index of the `else` of an `if`
+    else_: Option<usize>,
+    /// Index of the matching `end`
+    end: usize,
+}
+
+impl Code {
+    pub(super) fn new(
+        types: &[ty::Defined],
+        ty: &ty::Func,
+        locals: &[ty::Value],
+        expr: &Expression,
+    ) -> anyhow::Result<Self> {
+        let mut blocks = vec![Block::default(); expr.0.len()];
+        let mut opened = vec![];
+        for (i, instr) in expr.0.iter().enumerate() {
+            match instr {
+                Instruction::Block(block_type)
+                | Instruction::Loop(block_type)
+                | Instruction::If(block_type) => {
+                    let (params, results) = match block_type {
+                        BlockType::Empty => (0, 0),
+                        BlockType::Value(_) => (0, 1),
+                        BlockType::Index(index) => {
+                            let func = types[*index as usize].func();
+                            let func = func.expect("block types are function types");
+                            (func.params.len(), func.returns.len())
+                        }
+                    };
+                    blocks[i] = Block {
+                        params,
+                        results,
+                        ..Block::default()
+                    };
+                    opened.push(i);
+                }
+                Instruction::Else => {
+                    let start = *opened.last().expect("validated");
+                    blocks[start].else_ = Some(i);
+                }
+                Instruction::End => {
+                    // The last `end` closes the body rather than a block
+                    let Some(start) = opened.pop() else {
+                        continue;
+                    };
+                    blocks[start].end = i;
+                    if let Some(else_) = blocks[start].else_ {
+                        blocks[else_].end = i;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            params: ty.params.len(),
+            results: ty.returns.len(),
+            locals: locals.to_vec(),
+            instrs: expr.0.clone(),
+            blocks,
+        })
+    }
+}
+
+/// Capacities of the stacks of a call from the host, including the calls its
+/// host functions make back into WebAssembly, beyond which it traps with
+/// `TrapCode::StackOverflow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackLimits {
+    /// Maximum number of frames of WebAssembly and host functions on the call
+    /// stack
+    pub frames: usize,
+    /// Maximum number of values on the operand stack, including the locals
+    /// of each frame
+    pub values: usize,
+    /// Maximum number of host functions on the call stack, as each of their
+    /// calls back into WebAssembly recurses on the native stack
+    pub host_frames: usize,
+}
+
+impl Default for StackLimits {
+    fn default() -> Self {
+        Self {
+            frames: 100_000,
+            values: 1_000_000,
+            host_frames: 100,
+        }
+    }
+}
+
+struct Frame {
+    code: Rc<Code>,
+    /// Index of the function in the module, or `None` for a constant
+    /// expression
+    func: Option<u32>,
+    /// Address of the instance defining the function
+    instance: usize,
+    /// Index of the next instruction to execute on return to the frame
+    pc: usize,
+    /// Height of the operand stack below the locals, which start with the
+    /// arguments
+    base: usize,
+    /// Height of the label stack below the labels of the blocks in the
+    /// function
+    label_base: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    /// Height of the operand stack below the parameters of the block
+    height: usize,
+    /// Number of values a branch to the label takes
+    arity: usize,
+    /// Index of the instruction a branch to the label continues at
+    target: usize,
+}
+
+/// Interpreter state of a call from the host, which keeps its operands,
+/// labels and call frames in explicit stacks rather than recursing on the
+/// native stack for calls
+#[derive(Default)]
+pub(super) struct Thread {
+    pub(super) vals: Vec<Val>,
+    labels: Vec<Label>,
+    frames: Vec<Frame>,
+}
+
+impl Thread {
+    /// Runs a call from the host on the stacks cached in the store, which a
+    /// call made by a host function finds taken and allocates anew
+    pub(super) fn cached<T>(
+        store: &mut Store,
+        f: impl FnOnce(&mut Self, &mut Store) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut thread = store.thread.take().unwrap_or_default();
+        let result = f(&mut thread, store);
+        // Emptied to keep only the allocations, as a trap leaves frames
+        thread.vals.clear();
+        thread.labels.clear();
+        thread.frames.clear();
+        store.thread = Some(thread);
+        result
+    }
+
+    /// Calls a function with arguments type-checked by the caller,
+    /// returning its results
+    pub(super) fn call(
+        &mut self,
+        store: &mut Store,
+        func: usize,
+        args: &[Val],
+    ) -> anyhow::Result<Vec<Val>> {
+        self.vals.extend_from_slice(args);
+        self.invoke(store, func)?;
+        let results = Func(func).ty(store).returns.len();
+        Ok(self.vals.split_off(self.vals.len() - results))
+    }
+
+    /// Calls a function with arguments pushed on the operand stack, leaving
+    /// its results there
+    pub(super) fn invoke(&mut self, store: &mut Store, func: usize) -> anyhow::Result<()> {
+        let depth = self.frames.len();
+        if let Err(error) = self.enter(store, func) {
+            return Err(self.error(store, depth, error));
+        }
+        self.run(store, depth)
+    }
+
+    /// Continues a call from the host that ran out of fuel before executing
+    /// an instruction, returning its results
+    pub(super) fn resume(&mut self, store: &mut Store) -> anyhow::Result<Vec<Val>> {
+        let results = self.frames[0].code.results;
+        self.frames.last_mut().unwrap().pc -= 1;
+        self.run(store, 0)?;
+        Ok(self.vals.split_off(self.vals.len() - results))
+    }
+
+    /// Evaluates a constant expression in an instance
+    pub(super) fn eval(
+        &mut self,
+        store: &mut Store,
+        instance: usize,
+        ty: &ty::Value,
+        expr: &Expression,
+    ) -> anyhow::Result<Val> {
+        let types = store.instances[instance].module.types.clone();
+        let func = ty::Func {
+            params: vec![],
+            returns: vec![ty.clone()],
+        };
+        let code = Code::new(&types, &func, &[], expr)?;
+        let depth = self.frames.len();
+        self.frames.push(Frame {
+            code: Rc::new(code),
+            func: None,
+            instance,
+            pc: 0,
+            base: self.vals.len(),
+            label_base: self.labels.len(),
+        });
+        self.run(store, depth)?;
+        Ok(self.vals.pop().expect("validated"))
+    }
+
+    /// Pushes the frame of a function whose arguments are on the operand
+    /// stack, or runs a host function to completion
+    fn enter(&mut self, store: &mut Store, func: usize) -> anyhow::Result<()> {
+        let limits = store.stack_limits;
+        let frames = store.outer_frames + self.frames.len();
+        let values = store.outer_values + self.vals.len();
+        let (index, instance, code) = match &store.funcs[func].kind {
+            FuncKind::Wasm {
+                index,
+                instance,
+                code,
+            } => (*index, *instance, code.clone()),
+            FuncKind::Host(host) => {
+                if frames >= limits.frames || store.host_calls >= limits.host_frames {
+                    anyhow::bail!(TrapCode::StackOverflow);
+                }
+                let host = host.clone();
+                let caller = self.frames.last().map(|frame| Instance(frame.instance));
+                // Calls back into WebAssembly run on threads of their own,
+                // whose stacks count towards the limits along with this one
+                let outer = (store.outer_frames, store.outer_values);
+                (store.outer_frames, store.outer_values) = (frames + 1, values);
+                store.host_calls += 1;
+                let result = host(store, caller, &mut self.vals);
+                store.host_calls -= 1;
+                (store.outer_frames, store.outer_values) = outer;
+                return result;
+            }
+        };
+        if frames >= limits.frames || values + code.locals.len() > limits.values {
+            anyhow::bail!(TrapCode::StackOverflow);
+        }
+        epoch::check(store)?;
+        let base = self.vals.len() - code.params;
+        for local in &code.locals {
+            // Non-defaultable locals are validated to be set before use
+            self.vals.push(match local {
+                ty::Value::Ref(reference) => Val::null(&reference.heap),
+                _ => Val::default_for(local).expect("defaultable"),
+            });
+        }
+        self.frames.push(Frame {
+            code,
+            func: Some(index),
+            instance,
+            pc: 0,
+            base,
+            label_base: self.labels.len(),
+        });
+        Ok(())
+    }
+
+    /// Pops the frame of the current function, leaving its results on the
+    /// operand stack
+    fn leave(&mut self) {
+        let frame = self.frames.pop().expect("called in a function");
+        let results = self.vals.len() - frame.code.results;
+        self.vals.drain(frame.base..results);
+        self.labels.truncate(frame.label_base);
+    }
+
+    /// Branches to the label of the given depth, returning the index of the
+    /// instruction to continue at
+    fn branch(&mut self, depth: u32) -> usize {
+        let index = self.labels.len() - 1 - depth as usize;
+        let label = self.labels[index];
+        let values = self.vals.len() - label.arity;
+        self.vals.drain(label.height..values);
+        self.labels.truncate(index);
+        label.target
+    }
+
+    fn pop<T: Operand>(&mut self) -> T {
+        T::from_val(self.vals.pop().expect("validated"))
+    }
+
+    fn push<T: Operand>(&mut self, value: T) {
+        self.vals.push(value.into_val());
+    }
+
+    fn unop<T: Operand, U: Operand>(&mut self, f: impl FnOnce(T) -> U) {
+        let a = self.pop();
+        self.push(f(a));
+    }
+
+    fn binop<T: Operand, U: Operand>(&mut self, f: impl FnOnce(T, T) -> U) {
+        let b = self.pop();
+        let a = self.pop();
+        self.push(f(a, b));
+    }
+
+    fn try_unop<T: Operand, U: Operand>(
+        &mut self,
+        f: impl FnOnce(T) -> anyhow::Result<U>,
+    ) -> anyhow::Result<()> {
+        let a = self.pop();
+        self.push(f(a)?);
+        Ok(())
+    }
+
+    fn try_binop<T: Operand, U: Operand>(
+        &mut self,
+        f: impl FnOnce(T, T) -> anyhow::Result<U>,
+    ) -> anyhow::Result<()> {
+        let b = self.pop();
+        let a = self.pop();
+        self.push(f(a, b)?);
+        Ok(())
+    }
+
+    fn pop_address(&mut self, address: ty::Address) -> u64 {
+        match address {
+            ty::Address::I32 => self.pop::<i32>() as u32 as u64,
+            ty::Address::I64 => self.pop::<i64>() as u64,
+        }
+    }
+
+    /// Pushes an address, or -1 for `u64::MAX`
+    fn push_address(&mut self, address: ty::Address, value: u64) {
+        match address {
+            ty::Address::I32 => self.push(value as i32),
+            ty::Address::I64 => self.push(value as i64),
+        }
+    }
+
+    fn load<T: LittleEndian, U: Operand>(
+        &mut self,
+        store: &Store,
+        instance: usize,
+        mem_arg: &MemArg,
+        f: impl FnOnce(T) -> U,
+    ) -> anyhow::Result<()> {
+        let memory = &store.memories[memory_address(store, instance, mem_arg.memory)];
+        let address = self.pop_address(memory.address());
+        let bytes = memory.bytes(address, mem_arg.offset, T::SIZE)?;
+        self.push(f(T::from_le(bytes)));
+        Ok(())
+    }
+
+    fn store<T: Operand, U: LittleEndian>(
+        &mut self,
+        store: &mut Store,
+        instance: usize,
+        mem_arg: &MemArg,
+        f: impl FnOnce(T) -> U,
+    ) -> anyhow::Result<()> {
+        let memory = memory_address(store, instance, mem_arg.memory);
+        let memory = &mut store.memories[memory];
+        let value = self.pop();
+        let address = self.pop_address(memory.address());
+        f(value).to_le(memory.bytes_mut(address, mem_arg.offset, U::SIZE)?);
+        Ok(())
+    }
+
+    /// Runs until the call stack shrinks back to `depth` frames, turning
+    /// trap codes into traps with a backtrace
+    fn run(&mut self, store: &mut Store, depth: usize) -> anyhow::Result<()> {
+        while self.frames.len() > depth {
+            let mut pc = self.frames.last().unwrap().pc;
+            if let Err(error) = self.execute(store, &mut pc) {
+                self.frames.last_mut().unwrap().pc = pc;
+                return Err(self.error(store, depth, error));
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns a trap code into a trap with a backtrace, leaving other errors
+    /// of host functions as is
+    fn error(&mut self, store: &mut Store, depth: usize, error: anyhow::Error) -> anyhow::Error {
+        match error.downcast::<TrapCode>() {
+            // A call from the host is kept in the store to resume, unless made
+            // by a host function whose caller would be lost
+            Ok(TrapCode::OutOfFuel)
+                if depth == 0
+                    && store.host_calls == 0
+                    && self
+                        .frames
+                        .first()
+                        .is_some_and(|frame| frame.func.is_some()) =>
+            {
+                let trap = self.trap(store, TrapCode::OutOfFuel);
+                store.suspended = Some(std::mem::take(self));
+                trap.into()
+            }
+            Ok(code) => self.trap(store, code).into(),
+            Err(error) => error,
+        }
+    }
+
+    /// Returns a trap with the frames of the thread, whose next instruction
+    /// follows the one executing
+    fn trap(&self, store: &Store, code: TrapCode) -> Trap {
+        let frames = self.frames.iter().rev();
+        let backtrace = frames.filter_map(|frame| {
+            let func = frame.func?;
+            let names = &store.instances[frame.instance].module.func_names;
+            Some(FrameInfo {
+                func,
+                name: names.get(&func).cloned(),
+                offset: frame.pc - 1,
+            })
+        });
+        Trap {
+            code,
+            backtrace: backtrace.collect(),
+        }
+    }
+
+    /// Runs the instructions of the current function until a call or return
+    /// changes the current frame
+    fn execute(&mut self, store: &mut Store, pc: &mut usize) -> anyhow::Result<()> {
+        use Instruction::*;
+        let frame = self.frames.last().unwrap();
+        let code = frame.code.clone();
+        let (instance, base, label_base) = (frame.instance, frame.base, frame.label_base);
+        loop {
+            let instr = &code.instrs[*pc];
+            *pc += 1;
+            if let Some(fuel) = &mut store.fuel {
+                *fuel = fuel.checked_sub(1).ok_or(TrapCode::OutOfFuel)?;
+            }
+            // Checked before each instruction, the stack exceeds the limit by at
+            // most the operands a single instruction pushes
+            if store.outer_values + self.vals.len() > store.stack_limits.values {
+                anyhow::bail!(TrapCode::StackOverflow);
+            }
+            match instr {
+                Unreachable => anyhow::bail!(TrapCode::Unreachable),
+                Nop => {}
+                Block(_) | Loop(_) => {
+                    // Branches back to a loop execute it again
+                    if let Loop(_) = instr {
+                        epoch::check(store)?;
+                    }
+                    let block = code.blocks[*pc - 1];
+                    self.labels.push(Label {
+                        height: self.vals.len() - block.params,
+                        arity: match instr {
+                            Loop(_) => block.params,
+                            _ => block.results,
+                        },
+                        target: match instr {
+                            Loop(_) => *pc - 1,
+                            _ => block.end + 1,
+                        },
+                    });
+                }
+                If(_) => {
+                    let block = code.blocks[*pc - 1];
+                    let condition: bool = self.pop();
+                    self.labels.push(Label {
+                        height: self.vals.len() - block.params,
+                        arity: block.results,
+                        target: block.end + 1,
+                    });
+                    if !condition {
+                        // Without `else`, runs the `end` to pop the label
+                        *pc = block.else_.map_or(block.end, |else_| else_ + 1);
+                    }
+                }
+                Else => *pc = code.blocks[*pc - 1].end,
+                End if self.labels.len() == label_base => {
+                    self.leave();
+                    return Ok(());
+                }
+                End => {
+                    self.labels.pop();
+                }
+                Br(depth) | BrIf(depth) | BrTable(_, depth) => {
+                    let depth = match instr {
+                        BrIf(_) if !self.pop::<bool>() => continue,
+                        BrTable(labels, _) => {
+                            let index: i32 = self.pop();
+                            *labels.get(index as u32 as usize).unwrap_or(depth)
+                        }
+                        _ => *depth,
+                    };
+                    if depth as usize == self.labels.len() - label_base {
+                        self.leave();
+                        return Ok(());
+                    }
+                    *pc = self.branch(depth);
+                }
+                Return => {
+                    self.leave();
+                    return Ok(());
+                }
+                Call(index) => {
+                    self.frames.last_mut().unwrap().pc = *pc;
+                    let func = store.instances[instance].funcs[*index as usize];
+                    self.enter(store, func)?;
+                    return Ok(());
+                }
+                CallIndirect(ty, table) => {
+                    let table = &store.tables[table_address(store, instance, *table)];
+                    let index = self.pop_address(table.address());
+                    let func = match table.get(index) {
+                        Ok(Val::FuncRef(Some(func))) => func.0,
+                        Ok(Val::FuncRef(None)) => anyhow::bail!(TrapCode::UninitializedElement),
+                        Ok(_) => unreachable!("validated"),
+                        Err(_) => anyhow::bail!(TrapCode::UndefinedElement),
+                    };
+                    // Canonical types are equal across modules when their
+                    // recursive groups are, and subtypes match too
+                    let expected = &store.instances[instance].module.types[*ty as usize];
+                    anyhow::ensure!(
+                        store.funcs[func].ty.matches(expected),
+                        TrapCode::IndirectCallTypeMismatch
+                    );
+                    self.frames.last_mut().unwrap().pc = *pc;
+                    self.enter(store, func)?;
+                    return Ok(());
+                }
+                RefNull(heap) => self.vals.push(Val::null(heap)),
+                RefIsNull => {
+                    let reference = self.vals.pop().expect("validated");
+                    self.push(reference.is_null());
+                }
+                RefFunc(index) => {
+                    let func = store.instances[instance].funcs[*index as usize];
+                    self.vals.push(Val::FuncRef(Some(Func(func))));
+                }
+                Drop => {
+                    self.vals.pop();
+                }
+                Select | SelectTyped(_) => {
+                    let condition: bool = self.pop();
+                    let second = self.vals.pop().expect("validated");
+                    if !condition {
+                        *self.vals.last_mut().expect("validated") = second;
+                    }
+                }
+                LocalGet(index) => self.vals.push(self.vals[base + *index as usize]),
+                LocalSet(index) => {
+                    self.vals[base + *index as usize] = self.vals.pop().expect("validated");
+                }
+                LocalTee(index) => {
+                    self.vals[base + *index as usize] = *self.vals.last().expect("validated");
+                }
+                GlobalGet(index) => {
+                    let global = store.instances[instance].globals[*index as usize];
+                    self.vals.push(store.globals[global].value);
+                }
+                GlobalSet(index) => {
+                    let global = store.instances[instance].globals[*index as usize];
+                    store.globals[global].value = self.vals.pop().expect("validated");
+                }
+                TableGet(table) => {
+                    let table = &store.tables[table_address(store, instance, *table)];
+                    let index = self.pop_address(table.address());
+                    self.vals.push(table.get(index)?);
+                }
+                TableSet(table) => {
+                    let table = table_address(store, instance, *table);
+                    let table = &mut store.tables[table];
+                    let value = self.vals.pop().expect("validated");
+                    let index = self.pop_address(table.address());
+                    table.set(index, value)?;
+                }
+                TableInit(elem, table) => {
+                    let table = table_address(store, instance, *table);
+                    let table = &mut store.tables[table];
+                    let elem = &store.instances[instance].elems[*elem as usize];
+                    let len = self.pop::<i32>() as u32 as usize;
+                    let src = self.pop::<i32>() as u32 as usize;
+                    let dst = self.pop_address(table.address());
+                    let src = elem.get(src..).and_then(|elem| elem.get(..len));
+                    let src = src.ok_or(TrapCode::TableOutOfBounds)?;
+                    table.elems_mut(dst, len as u64)?.copy_from_slice(src);
+                }
+                ElemDrop(elem) => {
+                    store.instances[instance].elems[*elem as usize] = Rc::default();
+                }
+                TableCopy(dst, src) => {
+                    let tables = &store.instances[instance].tables;
+                    let (dst, src) = (tables[*dst as usize], tables[*src as usize]);
+                    let dst_address = store.tables[dst].address();
+                    let src_address = store.tables[src].address();
+                    let len = match (dst_address, src_address) {
+                        (ty::Address::I64, ty::Address::I64) => self.pop_address(ty::Address::I64),
+                        _ => self.pop_address(ty::Address::I32),
+                    };
+                    let src_offset = self.pop_address(src_address);
+                    let dst_offset = self.pop_address(dst_address);
+                    if dst == src {
+                        store.tables[dst].copy_within(dst_offset, src_offset, len)?;
+                    } else {
+                        let elems = store.tables[src].elems(src_offset, len)?.to_vec();
+                        store.tables[dst]
+                            .elems_mut(dst_offset, len)?
+                            .copy_from_slice(&elems);
+                    }
+                }
+                TableGrow(table) => {
+                    let table = table_address(store, instance, *table);
+                    let table = &mut store.tables[table];
+                    let delta = self.pop_address(table.address());
+                    let init = self.vals.pop().expect("validated");
+                    let size = table.grow(delta, init, &mut store.limiter)?;
+                    let size = size.unwrap_or(u64::MAX);
+                    self.push_address(table.address(), size);
+                }
+                TableSize(table) => {
+                    let table = &store.tables[table_address(store, instance, *table)];
+                    self.push_address(table.address(), table.size());
+                }
+                TableFill(table) => {
+                    let table = table_address(store, instance, *table);
+                    let table = &mut store.tables[table];
+                    let len = self.pop_address(table.address());
+                    let value = self.vals.pop().expect("validated");
+                    let dst = self.pop_address(table.address());
+                    table.elems_mut(dst, len)?.fill(value);
+                }
+                I32Load(mem_arg) => self.load(store, instance, mem_arg, |a: i32| a)?,
+                I64Load(mem_arg) => self.load(store, instance, mem_arg, |a: i64| a)?,
+                F32Load(mem_arg) => self.load(store, instance, mem_arg, |a: f32| a)?,
+                F64Load(mem_arg) => self.load(store, instance, mem_arg, |a: f64| a)?,
+                I32Load8S(mem_arg) => self.load(store, instance, mem_arg, |a: i8| a as i32)?,
+                I32Load8U(mem_arg) => self.load(store, instance, mem_arg, |a: u8| a as i32)?,
+                I32Load16S(mem_arg) => self.load(store, instance, mem_arg, |a: i16| a as i32)?,
+                I32Load16U(mem_arg) => self.load(store, instance, mem_arg, |a: u16| a as i32)?,
+                I64Load8S(mem_arg) => self.load(store, instance, mem_arg, |a: i8| a as i64)?,
+                I64Load8U(mem_arg) => self.load(store, instance, mem_arg, |a: u8| a as i64)?,
+                I64Load16S(mem_arg) => self.load(store, instance, mem_arg, |a: i16| a as i64)?,
+                I64Load16U(mem_arg) => self.load(store, instance, mem_arg, |a: u16| a as i64)?,
+                I64Load32S(mem_arg) => self.load(store, instance, mem_arg, |a: i32| a as i64)?,
+                I64Load32U(mem_arg) => self.load(store, instance, mem_arg, |a: u32| a as i64)?,
+                I32Store(mem_arg) => self.store(store, instance, mem_arg, |a: i32| a)?,
+                I64Store(mem_arg) => self.store(store, instance, mem_arg, |a: i64| a)?,
+                F32Store(mem_arg) => self.store(store, instance, mem_arg, |a: f32| a)?,
+                F64Store(mem_arg) => self.store(store, instance, mem_arg, |a: f64| a)?,
+                I32Store8(mem_arg) => self.store(store, instance, mem_arg, |a: i32| a as u8)?,
+                I32Store16(mem_arg) => self.store(store, instance, mem_arg, |a: i32| a as u16)?,
+                I64Store8(mem_arg) => self.store(store, instance, mem_arg, |a: i64| a as u8)?,
+                I64Store16(mem_arg) => self.store(store, instance, mem_arg, |a: i64| a as u16)?,
+                I64Store32(mem_arg) => self.store(store, instance, mem_arg, |a: i64| a as u32)?,
+                MemorySize(memory) => {
+                    let memory = &store.memories[memory_address(store, instance, *memory)];
+                    self.push_address(memory.address(), memory.size());
+                }
+                MemoryGrow(memory) => {
+                    let memory = memory_address(store, instance, *memory);
+                    let memory = &mut store.memories[memory];
+                    let delta = self.pop_address(memory.address());
+                    let size = memory.grow(delta, &mut store.limiter)?;
+                    let size = size.unwrap_or(u64::MAX);
+                    self.push_address(memory.address(), size);
+                }
+                MemoryInit(data, memory) => {
+                    let memory = memory_address(store, instance, *memory);
+                    let memory = &mut store.memories[memory];
+                    let data = &store.instances[instance].datas[*data as usize];
+                    let len = self.pop::<i32>() as u32 as usize;
+                    let src = self.pop::<i32>() as u32 as usize;
+                    let dst = self.pop_address(memory.address());
+                    let src = data.get(src..).and_then(|data| data.get(..len));
+                    let src = src.ok_or(TrapCode::MemoryOutOfBounds)?;
+                    memory.bytes_mut(dst, 0, len)?.copy_from_slice(src);
+                }
+                DataDrop(data) => {
+                    store.instances[instance].datas[*data as usize] = Rc::default();
+                }
+                MemoryCopy(dst, src) => {
+                    let memories = &store.instances[instance].memories;
+                    let (dst, src) = (memories[*dst as usize], memories[*src as usize]);
+                    let dst_address = store.memories[dst].address();
+                    let src_address = store.memories[src].address();
+                    let len = match (dst_address, src_address) {
+                        (ty::Address::I64, ty::Address::I64) => self.pop_address(ty::Address::I64),
+                        _ => self.pop_address(ty::Address::I32),
+                    };
+                    let src_offset = self.pop_address(src_address);
+                    let dst_offset = self.pop_address(dst_address);
+                    if dst == src {
+                        store.memories[dst].copy_within(dst_offset, src_offset, len)?;
+                    } else {
+                        let len = usize::try_from(len).unwrap_or(usize::MAX);
+                        let bytes = store.memories[src].bytes(src_offset, 0, len)?.to_vec();
+                        store.memories[dst]
+                            .bytes_mut(dst_offset, 0, len)?
+                            .copy_from_slice(&bytes);
+                    }
+                }
+                MemoryFill(memory) => {
+                    let memory = memory_address(store, instance, *memory);
+                    let memory = &mut store.memories[memory];
+                    let len = self.pop_address(memory.address());
+                    let value: i32 = self.pop();
+                    let dst = self.pop_address(memory.address());
+                    let len = usize::try_from(len).unwrap_or(usize::MAX);
+                    memory.bytes_mut(dst, 0, len)?.fill(value as u8);
+                }
+                I32Const(value) => self.push(*value),
+                I64Const(value) => self.push(*value),
+                F32Const(bits) => self.vals.push(Val::F32(*bits)),
+                F64Const(bits) => self.vals.push(Val::F64(*bits)),
+                I32Eqz => self.unop(|a: i32| a == 0),
+                I32Eq => self.binop(|a: i32, b| a == b),
+                I32Ne => self.binop(|a: i32, b| a != b),
+                I32LtS => self.binop(|a: i32, b| a < b),
+                I32LtU => self.binop(|a: i32, b| (a as u32) < b as u32),
+                I32GtS => self.binop(|a: i32, b| a > b),
+                I32GtU => self.binop(|a: i32, b| a as u32 > b as u32),
+                I32LeS => self.binop(|a: i32, b| a <= b),
+                I32LeU => self.binop(|a: i32, b| a as u32 <= b as u32),
+                I32GeS => self.binop(|a: i32, b| a >= b),
+                I32GeU => self.binop(|a: i32, b| a as u32 >= b as u32),
+                I64Eqz => self.unop(|a: i64| a == 0),
+                I64Eq => self.binop(|a: i64, b| a == b),
+                I64Ne => self.binop(|a: i64, b| a != b),
+                I64LtS => self.binop(|a: i64, b| a < b),
+                I64LtU => self.binop(|a: i64, b| (a as u64) < b as u64),
+                I64GtS => self.binop(|a: i64, b| a > b),
+                I64GtU => self.binop(|a: i64, b| a as u64 > b as u64),
+                I64LeS => self.binop(|a: i64, b| a <= b),
+                I64LeU => self.binop(|a: i64, b| a as u64 <= b as u64),
+                I64GeS => self.binop(|a: i64, b| a >= b),
+                I64GeU => self.binop(|a: i64, b| a as u64 >= b as u64),
+                F32Eq => self.binop(|a: f32, b| a == b),
+                F32Ne => self.binop(|a: f32, b| a != b),
+                F32Lt => self.binop(|a: f32, b| a < b),
+                F32Gt => self.binop(|a: f32, b| a > b),
+                F32Le => self.binop(|a: f32, b| a <= b),
+                F32Ge => self.binop(|a: f32, b| a >= b),
+                F64Eq => self.binop(|a: f64, b| a == b),
+                F64Ne => self.binop(|a: f64, b| a != b),
+                F64Lt => self.binop(|a: f64, b| a < b),
+                F64Gt => self.binop(|a: f64, b| a > b),
+                F64Le => self.binop(|a: f64, b| a <= b),
+                F64Ge => self.binop(|a: f64, b| a >= b),
+                I32Clz => self.unop(|a: i32| a.leading_zeros() as i32),
+                I32Ctz => self.unop(|a: i32| a.trailing_zeros() as i32),
+                I32Popcnt => self.unop(|a: i32| a.count_ones() as i32),
+                I32Add => self.binop(|a: i32, b| a.wrapping_add(b)),
+                I32Sub => self.binop(|a: i32, b| a.wrapping_sub(b)),
+                I32Mul => self.binop(|a: i32, b| a.wrapping_mul(b)),
+                I32DivS => self.try_binop(|a: i32, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    a.checked_div(b)
+                        .ok_or_else(|| anyhow::anyhow!(TrapCode::IntegerOverflow))
+                })?,
+                I32DivU => self.try_binop(|a: i32, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    Ok((a as u32 / b as u32) as i32)
+                })?,
+                I32RemS => self.try_binop(|a: i32, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    Ok(a.wrapping_rem(b))
+                })?,
+                I32RemU => self.try_binop(|a: i32, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    Ok((a as u32 % b as u32) as i32)
+                })?,
+                I32And => self.binop(|a: i32, b| a & b),
+                I32Or => self.binop(|a: i32, b| a | b),
+                I32Xor => self.binop(|a: i32, b| a ^ b),
+                I32Shl => self.binop(|a: i32, b| a.wrapping_shl(b as u32)),
+                I32ShrS => self.binop(|a: i32, b| a.wrapping_shr(b as u32)),
+                I32ShrU => self.binop(|a: i32, b| (a as u32).wrapping_shr(b as u32) as i32),
+                I32Rotl => self.binop(|a: i32, b| a.rotate_left(b as u32)),
+                I32Rotr => self.binop(|a: i32, b| a.rotate_right(b as u32)),
+                I64Clz => self.unop(|a: i64| a.leading_zeros() as i64),
+                I64Ctz => self.unop(|a: i64| a.trailing_zeros() as i64),
+                I64Popcnt => self.unop(|a: i64| a.count_ones() as i64),
+                I64Add => self.binop(|a: i64, b| a.wrapping_add(b)),
+                I64Sub => self.binop(|a: i64, b| a.wrapping_sub(b)),
+                I64Mul => self.binop(|a: i64, b| a.wrapping_mul(b)),
+                I64DivS => self.try_binop(|a: i64, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    a.checked_div(b)
+                        .ok_or_else(|| anyhow::anyhow!(TrapCode::IntegerOverflow))
+                })?,
+                I64DivU => self.try_binop(|a: i64, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    Ok((a as u64 / b as u64) as i64)
+                })?,
+                I64RemS => self.try_binop(|a: i64, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    Ok(a.wrapping_rem(b))
+                })?,
+                I64RemU => self.try_binop(|a: i64, b| {
+                    anyhow::ensure!(b != 0, TrapCode::IntegerDivideByZero);
+                    Ok((a as u64 % b as u64) as i64)
+                })?,
+                I64And => self.binop(|a: i64, b| a & b),
+                I64Or => self.binop(|a: i64, b| a | b),
+                I64Xor => self.binop(|a: i64, b| a ^ b),
+                I64Shl => self.binop(|a: i64, b| a.wrapping_shl(b as u32)),
+                I64ShrS => self.binop(|a: i64, b| a.wrapping_shr(b as u32)),
+                I64ShrU => self.binop(|a: i64, b| (a as u64).wrapping_shr(b as u32) as i64),
+                I64Rotl => self.binop(|a: i64, b| a.rotate_left(b as u32)),
+                I64Rotr => self.binop(|a: i64, b| a.rotate_right(b as u32)),
+                F32Abs => self.unop(f32::abs),
+                F32Neg => self.unop(|a: f32| -a),
+                F32Ceil => self.unop(f32::ceil),
+                F32Floor => self.unop(f32::floor),
+                F32Trunc => self.unop(f32::trunc),
+                F32Nearest => self.unop(f32::round_ties_even),
+                F32Sqrt => self.unop(f32::sqrt),
+                F32Add => self.binop(|a: f32, b| a + b),
+                F32Sub => self.binop(|a: f32, b| a - b),
+                F32Mul => self.binop(|a: f32, b| a * b),
+                F32Div => self.binop(|a: f32, b| a / b),
+                F32Min => self.binop(|a: f32, b| min(a as f64, b as f64) as f32),
+                F32Max => self.binop(|a: f32, b| max(a as f64, b as f64) as f32),
+                F32Copysign => self.binop(f32::copysign),
+                F64Abs => self.unop(f64::abs),
+                F64Neg => self.unop(|a: f64| -a),
+                F64Ceil => self.unop(f64::ceil),
+                F64Floor => self.unop(f64::floor),
+                F64Trunc => self.unop(f64::trunc),
+                F64Nearest => self.unop(f64::round_ties_even),
+                F64Sqrt => self.unop(f64::sqrt),
+                F64Add => self.binop(|a: f64, b| a + b),
+                F64Sub => self.binop(|a: f64, b| a - b),
+                F64Mul => self.binop(|a: f64, b| a * b),
+                F64Div => self.binop(|a: f64, b| a / b),
+                F64Min => self.binop(min),
+                F64Max => self.binop(max),
+                F64Copysign => self.binop(f64::copysign),
+                I32WrapI64 => self.unop(|a: i64| a as i32),
+                I32TruncF32S => self.try_unop(|a: f32| {
+                    trunc(a as f64, -2147483649.0, 2147483648.0).map(|a| a as i32)
+                })?,
+                I32TruncF32U => self.try_unop(|a: f32| {
+                    trunc(a as f64, -1.0, 4294967296.0).map(|a| a as u32 as i32)
+                })?,
+                I32TruncF64S => {
+                    self.try_unop(|a: f64| trunc(a, -2147483649.0, 2147483648.0).map(|a| a as i32))?
+                }
+                I32TruncF64U => {
+                    self.try_unop(|a: f64| trunc(a, -1.0, 4294967296.0).map(|a| a as u32 as i32))?
+                }
+                I64ExtendI32S => self.unop(|a: i32| a as i64),
+                I64ExtendI32U => self.unop(|a: i32| a as u32 as i64),
+                I64TruncF32S => self.try_unop(|a: f32| trunc_i64(a as f64))?,
+                I64TruncF32U => self.try_unop(|a: f32| trunc_u64(a as f64))?,
+                I64TruncF64S => self.try_unop(trunc_i64)?,
+                I64TruncF64U => self.try_unop(trunc_u64)?,
+                F32ConvertI32S => self.unop(|a: i32| a as f32),
+                F32ConvertI32U => self.unop(|a: i32| a as u32 as f32),
+                F32ConvertI64S => self.unop(|a: i64| a as f32),
+                F32ConvertI64U => self.unop(|a: i64| a as u64 as f32),
+                F32DemoteF64 => self.unop(|a: f64| a as f32),
+                F64ConvertI32S => self.unop(|a: i32| a as f64),
+                F64ConvertI32U => self.unop(|a: i32| a as u32 as f64),
+                F64ConvertI64S => self.unop(|a: i64| a as f64),
+                F64ConvertI64U => self.unop(|a: i64| a as u64 as f64),
+                F64PromoteF32 => self.unop(|a: f32| a as f64),
+                I32ReinterpretF32 => self.unop(|a: f32| a.to_bits() as i32),
+                I64ReinterpretF64 => self.unop(|a: f64| a.to_bits() as i64),
+                F32ReinterpretI32 => self.unop(|a: i32| f32::from_bits(a as u32)),
+                F64ReinterpretI64 => self.unop(|a: i64| f64::from_bits(a as u64)),
+                I32Extend8S => self.unop(|a: i32| a as i8 as i32),
+                I32Extend16S => self.unop(|a: i32| a as i16 as i32),
+                I64Extend8S => self.unop(|a: i64| a as i8 as i64),
+                I64Extend16S => self.unop(|a: i64| a as i16 as i64),
+                I64Extend32S => self.unop(|a: i64| a as i32 as i64),
+                // Casts from floats to integers saturate in Rust
+                I32TruncSatF32S => self.unop(|a: f32| a as i32),
+                I32TruncSatF32U => self.unop(|a: f32| a as u32 as i32),
+                I32TruncSatF64S => self.unop(|a: f64| a as i32),
+                I32TruncSatF64U => self.unop(|a: f64| a as u32 as i32),
+                I64TruncSatF32S => self.unop(|a: f32| a as i64),
+                I64TruncSatF32U => self.unop(|a: f32| a as u64 as i64),
+                I64TruncSatF64S => self.unop(|a: f64| a as i64),
+                I64TruncSatF64U => self.unop(|a: f64| a as u64 as i64),
+            }
+        }
+    }
+}
+
+fn table_address(store: &Store, instance: usize, index: u32) -> usize {
+    store.instances[instance].tables[index as usize]
+}
+
+fn memory_address(store: &Store, instance: usize, index: u32) -> usize {
+    store.instances[instance].memories[index as usize]
+}
+
+/// Types of operands, popped from and pushed to the operand stack as values
+/// of the matching variant, with booleans as `i32`
+trait Operand {
+    fn from_val(val: Val) -> Self;
+    fn into_val(self) -> Val;
+}
+
+impl Operand for i32 {
+    fn from_val(val: Val) -> Self {
+        match val {
+            Val::I32(value) => value,
+            _ => unreachable!("validated"),
+        }
+    }
+
+    fn into_val(self) -> Val {
+        Val::I32(self)
+    }
+}
+
+impl Operand for i64 {
+    fn from_val(val: Val) -> Self {
+        match val {
+            Val::I64(value) => value,
+            _ => unreachable!("validated"),
+        }
+    }
+
+    fn into_val(self) -> Val {
+        Val::I64(self)
+    }
+}
+
+impl Operand for f32 {
+    fn from_val(val: Val) -> Self {
+        match val {
+            Val::F32(bits) => f32::from_bits(bits),
+            _ => unreachable!("validated"),
+        }
+    }
+
+    fn into_val(self) -> Val {
+        Val::F32(self.to_bits())
+    }
+}
+
+impl Operand for f64 {
+    fn from_val(val: Val) -> Self {
+        match val {
+            Val::F64(bits) => f64::from_bits(bits),
+            _ => unreachable!("validated"),
+        }
+    }
+
+    fn into_val(self) -> Val {
+        Val::F64(self.to_bits())
+    }
+}
+
+impl Operand for bool {
+    fn from_val(val: Val) -> Self {
+        i32::from_val(val) != 0
+    }
+
+    fn into_val(self) -> Val {
+        Val::I32(self as i32)
+    }
+}
+
+/// Returns the minimum with NaN if either operand is and -0 below +0,
+/// unlike `f64::min`
+fn min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+/// Returns the maximum with NaN if either operand is and +0 above -0,
+/// unlike `f64::max`
+fn max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+/// Truncates a float to an integer of the range between the exclusive
+/// bounds, which are exact in `f64`
+fn trunc(a: f64, min: f64, max: f64) -> anyhow::Result<f64> {
+    anyhow::ensure!(!a.is_nan(), TrapCode::InvalidConversionToInteger);
+    anyhow::ensure!(min < a && a < max, TrapCode::IntegerOverflow);
+    Ok(a.trunc())
+}
+
+fn trunc_i64(a: f64) -> anyhow::Result<i64> {
+    // -2^63 - 1 isn't representable, so the lower bound is inclusive
+    anyhow::ensure!(!a.is_nan(), TrapCode::InvalidConversionToInteger);
+    anyhow::ensure!(
+        (-9223372036854775808.0..9223372036854775808.0).contains(&a),
+        TrapCode::IntegerOverflow
+    );
+    Ok(a as i64)
+}
+
+fn trunc_u64(a: f64) -> anyhow::Result<i64> {
+    Ok(trunc(a, -1.0, 18446744073709551616.0)? as u64 as i64)
+}
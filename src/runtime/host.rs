@@ -0,0 +1,97 @@
+use std::rc::Rc;
+
+use super::{Extern, Func, FuncInst, FuncKind, Instance, Memory, Store, Val, WasmTypeList};
+use crate::validation::{self, ty};
+
+/// Host function called with the calling instance, if any, and its
+/// arguments on the operand stack, which it replaces with its results
+pub(super) type HostFunc =
+    Rc<dyn Fn(&mut Store, Option<Instance>, &mut Vec<Val>) -> anyhow::Result<()>>;
+
+/// Context of a call to a host function, which gives access to the store
+/// and to the instance calling the function
+pub struct Caller<'a> {
+    store: &'a mut Store,
+    instance: Option<Instance>,
+}
+
+impl Caller<'_> {
+    pub fn store(&self) -> &Store {
+        self.store
+    }
+
+    pub fn store_mut(&mut self) -> &mut Store {
+        self.store
+    }
+
+    /// Returns the instance calling the function, or `None` when the host
+    /// calls it directly
+    pub fn instance(&self) -> Option<Instance> {
+        self.instance
+    }
+
+    /// Returns an export of the calling instance
+    pub fn get_export(&self, name: &str) -> Option<Extern> {
+        self.instance?.get_export(self.store, name)
+    }
+
+    /// Returns the first memory of the calling instance, whether or not it
+    /// exports it
+    pub fn memory(&self) -> Option<Memory> {
+        let instance = &self.store.instances[self.instance?.0];
+        instance.memories.first().map(|memory| Memory(*memory))
+    }
+}
+
+impl Func {
+    /// Creates a host function of a type, whose results are checked against
+    /// the type on each call
+    pub fn new(
+        store: &mut Store,
+        ty: ty::Func,
+        f: impl Fn(Caller<'_>, &[Val]) -> anyhow::Result<Vec<Val>> + 'static,
+    ) -> Self {
+        let (params, returns) = (ty.params.len(), ty.returns.clone());
+        let host = move |store: &mut Store, instance, vals: &mut Vec<Val>| {
+            let args = vals.split_off(vals.len() - params);
+            let results = f(Caller { store, instance }, &args)?;
+            let types: Vec<_> = results.iter().map(|result| result.ty(store)).collect();
+            anyhow::ensure!(
+                types.len() == returns.len()
+                    && (types.iter().zip(&returns)).all(|(ty, ret)| ty.matches(ret)),
+                "type mismatch: expected {} but host function returned {}",
+                validation::types(&returns),
+                validation::types(&types),
+            );
+            vals.extend(results);
+            Ok(())
+        };
+        Self::host(store, ty, Rc::new(host))
+    }
+
+    /// Creates a host function from a closure, whose Rust parameter and
+    /// result types give the type of the function
+    pub fn wrap<Params: WasmTypeList, Results: WasmTypeList>(
+        store: &mut Store,
+        f: impl Fn(Caller<'_>, Params) -> anyhow::Result<Results> + 'static,
+    ) -> Self {
+        let ty = ty::Func {
+            params: Params::types(),
+            returns: Results::types(),
+        };
+        let host = move |store: &mut Store, instance, vals: &mut Vec<Val>| {
+            let params = Params::pop(vals);
+            f(Caller { store, instance }, params)?.push(vals);
+            Ok(())
+        };
+        Self::host(store, ty, Rc::new(host))
+    }
+
+    fn host(store: &mut Store, ty: ty::Func, host: HostFunc) -> Self {
+        store.funcs.push(FuncInst {
+            ty: ty::Defined::from(ty),
+            kind: FuncKind::Host(host),
+        });
+        Self(store.funcs.len() - 1)
+    }
+}
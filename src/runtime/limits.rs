@@ -0,0 +1,79 @@
+use super::TrapCode;
+use crate::validation::ty;
+
+/// Policy of a store on the resources of its instances, consulted before
+/// memories and tables are allocated or grown and instances are created
+///
+/// Each hook allows the change with `Ok(true)` or denies it with `Ok(false)`,
+/// on which `memory.grow` and `table.grow` return -1 and creation fails, while
+/// an error stops execution as a host function failing does. An error of a
+/// `TrapCode` such as `TrapCode::ResourceLimitExceeded` traps instead.
+pub trait ResourceLimiter {
+    /// Decides whether a memory of a limit in pages, already within its
+    /// maximum and the bound of its address type, may grow from `current` to
+    /// `desired` pages, with `current` 0 on allocating it
+    fn memory_growing(
+        &mut self,
+        current: u64,
+        desired: u64,
+        limit: &ty::Limit,
+    ) -> anyhow::Result<bool>;
+
+    /// Decides whether a table of a limit in elements may grow from `current`
+    /// to `desired` elements, with `current` 0 on allocating it
+    fn table_growing(
+        &mut self,
+        current: u64,
+        desired: u64,
+        limit: &ty::Limit,
+    ) -> anyhow::Result<bool>;
+
+    /// Decides whether a module may be instantiated in a store of `count`
+    /// instances
+    fn instance_creating(&mut self, count: usize) -> anyhow::Result<bool> {
+        let _ = count;
+        Ok(true)
+    }
+}
+
+/// Resource limiter with fixed caps, each unlimited when `None`
+#[derive(Debug, Clone, Default)]
+pub struct StoreLimits {
+    /// Maximum size of each memory in pages
+    pub memory_pages: Option<u64>,
+    /// Maximum size of each table in elements
+    pub table_elements: Option<u64>,
+    /// Maximum number of instances in the store
+    pub instances: Option<usize>,
+    /// Whether growing beyond a cap traps with
+    /// `TrapCode::ResourceLimitExceeded` instead of returning -1
+    pub trap_on_grow_failure: bool,
+}
+
+impl StoreLimits {
+    fn check(&self, desired: u64, cap: Option<u64>) -> anyhow::Result<bool> {
+        match cap {
+            Some(cap) if desired > cap => {
+                if self.trap_on_grow_failure {
+                    anyhow::bail!(TrapCode::ResourceLimitExceeded);
+                }
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+}
+
+impl ResourceLimiter for StoreLimits {
+    fn memory_growing(&mut self, _: u64, desired: u64, _: &ty::Limit) -> anyhow::Result<bool> {
+        self.check(desired, self.memory_pages)
+    }
+
+    fn table_growing(&mut self, _: u64, desired: u64, _: &ty::Limit) -> anyhow::Result<bool> {
+        self.check(desired, self.table_elements)
+    }
+
+    fn instance_creating(&mut self, count: usize) -> anyhow::Result<bool> {
+        Ok(self.instances.is_none_or(|instances| count < instances))
+    }
+}
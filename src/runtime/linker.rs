@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{Caller, Extern, Func, Instance, Store, WasmTypeList};
+use crate::validation;
+
+/// Definitions of items by module and name, which the imports of modules
+/// are resolved against on instantiation
+#[derive(Default)]
+pub struct Linker {
+    defs: HashMap<(String, String), Extern>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines an item, failing if the name is already defined
+    pub fn define(
+        &mut self,
+        module: &str,
+        name: &str,
+        item: impl Into<Extern>,
+    ) -> anyhow::Result<&mut Self> {
+        let key = (module.to_owned(), name.to_owned());
+        anyhow::ensure!(
+            !self.defs.contains_key(&key),
+            "duplicate definition of {module:?} {name:?}"
+        );
+        self.defs.insert(key, item.into());
+        Ok(self)
+    }
+
+    /// Defines a host function from a closure, as `Func::wrap` creates it
+    pub fn func_wrap<Params: WasmTypeList, Results: WasmTypeList>(
+        &mut self,
+        store: &mut Store,
+        module: &str,
+        name: &str,
+        f: impl Fn(Caller<'_>, Params) -> anyhow::Result<Results> + 'static,
+    ) -> anyhow::Result<&mut Self> {
+        self.define(module, name, Func::wrap(store, f))
+    }
+
+    /// Defines the exports of an instance under a module name
+    pub fn instance(
+        &mut self,
+        store: &Store,
+        module: &str,
+        instance: Instance,
+    ) -> anyhow::Result<&mut Self> {
+        for (name, item) in instance.exports(store) {
+            self.define(module, &name, item)?;
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, module: &str, name: &str) -> Option<Extern> {
+        self.defs
+            .get(&(module.to_owned(), name.to_owned()))
+            .copied()
+    }
+
+    /// Instantiates a validated module with the definitions of its imports
+    pub fn instantiate(
+        &self,
+        store: &mut Store,
+        module: &Rc<validation::Validated>,
+    ) -> anyhow::Result<Instance> {
+        let imports = (module.imports.iter())
+            .map(|import| {
+                let item = self.get(&import.module, &import.name);
+                item.ok_or_else(|| {
+                    anyhow::anyhow!("unknown import {:?} {:?}", import.module, import.name)
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Instance::new(store, module, &imports)
+    }
+}
@@ -0,0 +1,225 @@
+use super::{ResourceLimiter, Store, TrapCode};
+use crate::validation::{self, ty};
+
+/// Size of a page of linear memory in bytes
+pub const PAGE_SIZE: u64 = 1 << 16;
+
+pub(super) struct MemoryInst {
+    ty: ty::Memory,
+    data: Vec<u8>,
+}
+
+impl MemoryInst {
+    /// Allocates the minimum size of the memory type, if the limiter of the
+    /// store allows it
+    pub(super) fn new(
+        ty: ty::Memory,
+        limiter: &mut Option<Box<dyn ResourceLimiter>>,
+    ) -> anyhow::Result<Self> {
+        let mut memory = Self { ty, data: vec![] };
+        let min = memory.ty.0.min;
+        let allowed = match limiter {
+            Some(limiter) => limiter.memory_growing(0, min, &memory.ty.0)?,
+            None => true,
+        };
+        anyhow::ensure!(
+            allowed && memory.resize(min),
+            "failed to allocate a memory of {min} pages"
+        );
+        Ok(memory)
+    }
+
+    pub(super) fn address(&self) -> ty::Address {
+        self.ty.0.address
+    }
+
+    /// Returns the size in pages
+    pub(super) fn size(&self) -> u64 {
+        self.data.len() as u64 / PAGE_SIZE
+    }
+
+    /// Grows the memory by a number of pages, returning the previous size,
+    /// or `None` when the maximum of the type, the bound of the address type,
+    /// the limiter of the store or the host denies it
+    pub(super) fn grow(
+        &mut self,
+        delta: u64,
+        limiter: &mut Option<Box<dyn ResourceLimiter>>,
+    ) -> anyhow::Result<Option<u64>> {
+        let size = self.size();
+        let bound = match self.ty.0.address {
+            ty::Address::I32 => 1 << 16,
+            ty::Address::I64 => 1 << 48,
+        };
+        let max = self.ty.0.max.unwrap_or(bound).min(bound);
+        let Some(pages) = size.checked_add(delta).filter(|pages| *pages <= max) else {
+            return Ok(None);
+        };
+        let allowed = match limiter {
+            Some(limiter) => limiter.memory_growing(size, pages, &self.ty.0)?,
+            None => true,
+        };
+        Ok((allowed && self.resize(pages)).then_some(size))
+    }
+
+    /// Zero-extends the memory to a number of pages, failing without change
+    /// when it can't be allocated
+    fn resize(&mut self, pages: u64) -> bool {
+        let Some(len) = pages.checked_mul(PAGE_SIZE) else {
+            return false;
+        };
+        let Ok(len) = usize::try_from(len) else {
+            return false;
+        };
+        if self.data.try_reserve_exact(len - self.data.len()).is_err() {
+            return false;
+        }
+        self.data.resize(len, 0);
+        true
+    }
+
+    /// Returns the range of `len` bytes at an address plus an offset
+    fn range(
+        &self,
+        address: u64,
+        offset: u64,
+        len: usize,
+    ) -> anyhow::Result<std::ops::Range<usize>> {
+        let start = address.checked_add(offset);
+        let start = start.and_then(|start| usize::try_from(start).ok());
+        let range = start.and_then(|start| Some(start..start.checked_add(len)?));
+        match range {
+            Some(range) if range.end <= self.data.len() => Ok(range),
+            _ => anyhow::bail!(TrapCode::MemoryOutOfBounds),
+        }
+    }
+
+    pub(super) fn bytes(&self, address: u64, offset: u64, len: usize) -> anyhow::Result<&[u8]> {
+        let range = self.range(address, offset, len)?;
+        Ok(&self.data[range])
+    }
+
+    pub(super) fn bytes_mut(
+        &mut self,
+        address: u64,
+        offset: u64,
+        len: usize,
+    ) -> anyhow::Result<&mut [u8]> {
+        let range = self.range(address, offset, len)?;
+        Ok(&mut self.data[range])
+    }
+
+    /// Copies bytes within the memory, checking both ranges before copying
+    pub(super) fn copy_within(&mut self, dst: u64, src: u64, len: u64) -> anyhow::Result<()> {
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        let src = self.range(src, 0, len)?;
+        let dst = self.range(dst, 0, len)?;
+        self.data.copy_within(src, dst.start);
+        Ok(())
+    }
+}
+
+/// Handle to a linear memory in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Memory(pub(super) usize);
+
+impl Memory {
+    /// Creates a memory of the minimum size of a valid memory type
+    pub fn new(store: &mut Store, ty: ty::Memory) -> anyhow::Result<Self> {
+        validation::check_memory(&ty)?;
+        let memory = MemoryInst::new(ty, &mut store.limiter)?;
+        store.memories.push(memory);
+        Ok(Self(store.memories.len() - 1))
+    }
+
+    /// Returns the type of the memory with its current size as minimum
+    pub fn ty(&self, store: &Store) -> ty::Memory {
+        let memory = &store.memories[self.0];
+        ty::Memory(ty::Limit {
+            min: memory.size(),
+            ..memory.ty.0.clone()
+        })
+    }
+
+    /// Returns the size in pages
+    pub fn size(&self, store: &Store) -> u64 {
+        store.memories[self.0].size()
+    }
+
+    /// Grows the memory by a number of pages, returning the previous size
+    pub fn grow(&self, store: &mut Store, delta: u64) -> anyhow::Result<u64> {
+        let size = store.memories[self.0].grow(delta, &mut store.limiter)?;
+        size.ok_or_else(|| anyhow::anyhow!("failed to grow memory by {delta} pages"))
+    }
+
+    pub fn data<'a>(&self, store: &'a Store) -> &'a [u8] {
+        &store.memories[self.0].data
+    }
+
+    pub fn data_mut<'a>(&self, store: &'a mut Store) -> &'a mut [u8] {
+        &mut store.memories[self.0].data
+    }
+
+    /// Reads bytes at an address into the buffer
+    pub fn read(&self, store: &Store, address: u64, buffer: &mut [u8]) -> anyhow::Result<()> {
+        let memory = &store.memories[self.0];
+        buffer.copy_from_slice(memory.bytes(address, 0, buffer.len())?);
+        Ok(())
+    }
+
+    /// Writes bytes to an address
+    pub fn write(&self, store: &mut Store, address: u64, bytes: &[u8]) -> anyhow::Result<()> {
+        let memory = &mut store.memories[self.0];
+        memory
+            .bytes_mut(address, 0, bytes.len())?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reads a value stored in little endian at an address
+    pub fn read_le<T: LittleEndian>(&self, store: &Store, address: u64) -> anyhow::Result<T> {
+        let memory = &store.memories[self.0];
+        Ok(T::from_le(memory.bytes(address, 0, T::SIZE)?))
+    }
+
+    /// Writes a value in little endian to an address
+    pub fn write_le<T: LittleEndian>(
+        &self,
+        store: &mut Store,
+        address: u64,
+        value: T,
+    ) -> anyhow::Result<()> {
+        let memory = &mut store.memories[self.0];
+        value.to_le(memory.bytes_mut(address, 0, T::SIZE)?);
+        Ok(())
+    }
+}
+
+/// Types stored in memory in little endian
+pub trait LittleEndian: Sized {
+    const SIZE: usize;
+    /// Converts `SIZE` bytes
+    fn from_le(bytes: &[u8]) -> Self;
+    /// Writes the value to `SIZE` bytes
+    fn to_le(self, bytes: &mut [u8]);
+}
+
+macro_rules! impl_little_endian {
+    ($($ty:ty)*) => {
+        $(
+            impl LittleEndian for $ty {
+                const SIZE: usize = size_of::<$ty>();
+
+                fn from_le(bytes: &[u8]) -> Self {
+                    Self::from_le_bytes(bytes.try_into().expect("`SIZE` bytes"))
+                }
+
+                fn to_le(self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_little_endian!(u8 i8 u16 i16 u32 i32 u64 i64 u128 f32 f64);
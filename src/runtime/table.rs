@@ -0,0 +1,178 @@
+use super::{ResourceLimiter, Store, TrapCode, Val};
+use crate::validation::{self, ty};
+
+pub(super) struct TableInst {
+    ty: ty::Table,
+    elems: Vec<Val>,
+}
+
+impl TableInst {
+    /// Allocates the minimum size of the table type, filled with a reference,
+    /// if the limiter of the store allows it
+    pub(super) fn new(
+        ty: ty::Table,
+        init: Val,
+        limiter: &mut Option<Box<dyn ResourceLimiter>>,
+    ) -> anyhow::Result<Self> {
+        let mut table = Self { ty, elems: vec![] };
+        let min = table.ty.limit.min;
+        let allowed = match limiter {
+            Some(limiter) => limiter.table_growing(0, min, &table.ty.limit)?,
+            None => true,
+        };
+        anyhow::ensure!(
+            allowed && table.resize(min, init),
+            "failed to allocate a table of {min} elements"
+        );
+        Ok(table)
+    }
+
+    pub(super) fn address(&self) -> ty::Address {
+        self.ty.limit.address
+    }
+
+    pub(super) fn size(&self) -> u64 {
+        self.elems.len() as u64
+    }
+
+    /// Returns the type of the elements
+    fn element(&self) -> ty::Value {
+        ty::Value::Ref(self.ty.reference.clone())
+    }
+
+    /// Grows the table by a number of elements set to a reference, returning
+    /// the previous size, or `None` when the maximum of the type, the bound
+    /// of the address type, the limiter of the store or the host denies it
+    pub(super) fn grow(
+        &mut self,
+        delta: u64,
+        init: Val,
+        limiter: &mut Option<Box<dyn ResourceLimiter>>,
+    ) -> anyhow::Result<Option<u64>> {
+        let size = self.size();
+        let bound = match self.ty.limit.address {
+            ty::Address::I32 => u32::MAX.into(),
+            ty::Address::I64 => u64::MAX,
+        };
+        let max = self.ty.limit.max.unwrap_or(bound).min(bound);
+        let Some(len) = size.checked_add(delta).filter(|len| *len <= max) else {
+            return Ok(None);
+        };
+        let allowed = match limiter {
+            Some(limiter) => limiter.table_growing(size, len, &self.ty.limit)?,
+            None => true,
+        };
+        Ok((allowed && self.resize(len, init)).then_some(size))
+    }
+
+    /// Extends the table to a number of elements, failing without change
+    /// when it can't be allocated
+    fn resize(&mut self, len: u64, init: Val) -> bool {
+        let Ok(len) = usize::try_from(len) else {
+            return false;
+        };
+        if self
+            .elems
+            .try_reserve_exact(len - self.elems.len())
+            .is_err()
+        {
+            return false;
+        }
+        self.elems.resize(len, init);
+        true
+    }
+
+    /// Returns the range of `len` elements at an index
+    fn range(&self, index: u64, len: u64) -> anyhow::Result<std::ops::Range<usize>> {
+        let start = usize::try_from(index).ok();
+        let len = usize::try_from(len).ok();
+        let range = start.zip(len).and_then(|(start, len)| {
+            let end = start.checked_add(len)?;
+            Some(start..end)
+        });
+        match range {
+            Some(range) if range.end <= self.elems.len() => Ok(range),
+            _ => anyhow::bail!(TrapCode::TableOutOfBounds),
+        }
+    }
+
+    pub(super) fn get(&self, index: u64) -> anyhow::Result<Val> {
+        let range = self.range(index, 1)?;
+        Ok(self.elems[range.start])
+    }
+
+    pub(super) fn set(&mut self, index: u64, value: Val) -> anyhow::Result<()> {
+        let range = self.range(index, 1)?;
+        self.elems[range.start] = value;
+        Ok(())
+    }
+
+    pub(super) fn elems_mut(&mut self, index: u64, len: u64) -> anyhow::Result<&mut [Val]> {
+        let range = self.range(index, len)?;
+        Ok(&mut self.elems[range])
+    }
+
+    pub(super) fn elems(&self, index: u64, len: u64) -> anyhow::Result<&[Val]> {
+        let range = self.range(index, len)?;
+        Ok(&self.elems[range])
+    }
+
+    /// Copies elements within the table, checking both ranges before copying
+    pub(super) fn copy_within(&mut self, dst: u64, src: u64, len: u64) -> anyhow::Result<()> {
+        let src = self.range(src, len)?;
+        let dst = self.range(dst, len)?;
+        self.elems.copy_within(src, dst.start);
+        Ok(())
+    }
+}
+
+/// Handle to a table in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Table(pub(super) usize);
+
+impl Table {
+    /// Creates a table of the minimum size of a valid table type, filled with
+    /// a reference of its element type
+    pub fn new(store: &mut Store, ty: ty::Table, init: Val) -> anyhow::Result<Self> {
+        validation::check_table(&ty)?;
+        init.check(store, &ty::Value::Ref(ty.reference.clone()))?;
+        let table = TableInst::new(ty, init, &mut store.limiter)?;
+        store.tables.push(table);
+        Ok(Self(store.tables.len() - 1))
+    }
+
+    /// Returns the type of the table with its current size as minimum
+    pub fn ty(&self, store: &Store) -> ty::Table {
+        let table = &store.tables[self.0];
+        ty::Table {
+            reference: table.ty.reference.clone(),
+            limit: ty::Limit {
+                min: table.size(),
+                ..table.ty.limit.clone()
+            },
+        }
+    }
+
+    pub fn size(&self, store: &Store) -> u64 {
+        store.tables[self.0].size()
+    }
+
+    /// Grows the table by a number of elements set to a reference, returning
+    /// the previous size
+    pub fn grow(&self, store: &mut Store, delta: u64, init: Val) -> anyhow::Result<u64> {
+        init.check(store, &store.tables[self.0].element())?;
+        let size = store.tables[self.0].grow(delta, init, &mut store.limiter)?;
+        size.ok_or_else(|| anyhow::anyhow!("failed to grow table by {delta} elements"))
+    }
+
+    /// Returns the element at an index, or `None` when out of bounds
+    pub fn get(&self, store: &Store, index: u64) -> Option<Val> {
+        store.tables[self.0].get(index).ok()
+    }
+
+    /// Sets the element at an index to a reference of the element type
+    pub fn set(&self, store: &mut Store, index: u64, value: Val) -> anyhow::Result<()> {
+        value.check(store, &store.tables[self.0].element())?;
+        store.tables[self.0].set(index, value)
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+/// Reason for a trap, displayed as the message of the spec interpreter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapCode {
+    Unreachable,
+    IntegerDivideByZero,
+    IntegerOverflow,
+    InvalidConversionToInteger,
+    MemoryOutOfBounds,
+    TableOutOfBounds,
+    /// Index of `call_indirect` out of the bounds of the table
+    UndefinedElement,
+    UninitializedElement,
+    IndirectCallTypeMismatch,
+    NullReference,
+    CastFailure,
+    StackOverflow,
+    /// Fuel of the store exhausted, after which the call can be resumed
+    OutOfFuel,
+    /// Epoch deadline of the store reached
+    Interrupt,
+    /// Growth of a memory or table beyond the resource limiter of the store
+    ResourceLimitExceeded,
+}
+
+impl fmt::Display for TrapCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unreachable => "unreachable",
+            Self::IntegerDivideByZero => "integer divide by zero",
+            Self::IntegerOverflow => "integer overflow",
+            Self::InvalidConversionToInteger => "invalid conversion to integer",
+            Self::MemoryOutOfBounds => "out of bounds memory access",
+            Self::TableOutOfBounds => "out of bounds table access",
+            Self::UndefinedElement => "undefined element",
+            Self::UninitializedElement => "uninitialized element",
+            Self::IndirectCallTypeMismatch => "indirect call type mismatch",
+            Self::NullReference => "null reference",
+            Self::CastFailure => "cast failure",
+            Self::StackOverflow => "call stack exhausted",
+            Self::OutOfFuel => "out of fuel",
+            Self::Interrupt => "interrupted",
+            Self::ResourceLimitExceeded => "resource limit exceeded",
+        })
+    }
+}
+
+impl std::error::Error for TrapCode {}
+
+/// Error of WebAssembly code trapping, which host code can tell apart from
+/// its own errors with `anyhow::Error::downcast_ref::<Trap>()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub(super) code: TrapCode,
+    pub(super) backtrace: Vec<FrameInfo>,
+}
+
+impl Trap {
+    pub fn code(&self) -> TrapCode {
+        self.code
+    }
+
+    /// Returns the frames of the call stack at the trap, innermost first
+    pub fn backtrace(&self) -> &[FrameInfo] {
+        &self.backtrace
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.code.fmt(f)
+    }
+}
+
+impl std::error::Error for Trap {}
+
+/// Frame of a WebAssembly function in a backtrace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Index in the function index space of the module
+    pub func: u32,
+    /// Name of the function from the name section
+    pub name: Option<String>,
+    /// Index in the function body of the instruction executing
+    pub offset: usize,
+}
+
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "${name} (func {})", self.func)?,
+            None => write!(f, "func {}", self.func)?,
+        }
+        write!(f, " at instruction {}", self.offset)
+    }
+}
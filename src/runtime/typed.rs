@@ -0,0 +1,277 @@
+use std::marker::PhantomData;
+
+use super::{AnyRef, ExternRef, Func, Store, Val, exec};
+use crate::validation::{self, ty};
+
+/// Rust types of WebAssembly values
+pub trait WasmTy: Sized {
+    fn ty() -> ty::Value;
+    fn into_val(self) -> Val;
+    /// Converts a value known to have the type
+    fn from_val(val: Val) -> Self;
+}
+
+impl WasmTy for i32 {
+    fn ty() -> ty::Value {
+        ty::Value::Num(ty::Number::I32)
+    }
+
+    fn into_val(self) -> Val {
+        Val::I32(self)
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+impl WasmTy for i64 {
+    fn ty() -> ty::Value {
+        ty::Value::Num(ty::Number::I64)
+    }
+
+    fn into_val(self) -> Val {
+        Val::I64(self)
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+impl WasmTy for f32 {
+    fn ty() -> ty::Value {
+        ty::Value::Num(ty::Number::F32)
+    }
+
+    fn into_val(self) -> Val {
+        self.into()
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+impl WasmTy for f64 {
+    fn ty() -> ty::Value {
+        ty::Value::Num(ty::Number::F64)
+    }
+
+    fn into_val(self) -> Val {
+        self.into()
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+impl WasmTy for u128 {
+    fn ty() -> ty::Value {
+        ty::Value::Vec(ty::Vector::V128)
+    }
+
+    fn into_val(self) -> Val {
+        Val::V128(self)
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+fn reference(is_nullable: bool, heap: ty::AbsHeap) -> ty::Value {
+    ty::Value::Ref(ty::Reference {
+        is_nullable,
+        heap: ty::Heap::Abstract(heap),
+    })
+}
+
+impl WasmTy for Func {
+    fn ty() -> ty::Value {
+        reference(false, ty::AbsHeap::Func)
+    }
+
+    fn into_val(self) -> Val {
+        self.into()
+    }
+
+    fn from_val(val: Val) -> Self {
+        Option::<Func>::from_val(val).expect("type-checked")
+    }
+}
+
+impl WasmTy for Option<Func> {
+    fn ty() -> ty::Value {
+        reference(true, ty::AbsHeap::Func)
+    }
+
+    fn into_val(self) -> Val {
+        self.into()
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+impl WasmTy for ExternRef {
+    fn ty() -> ty::Value {
+        reference(false, ty::AbsHeap::Extern)
+    }
+
+    fn into_val(self) -> Val {
+        self.into()
+    }
+
+    fn from_val(val: Val) -> Self {
+        Option::<ExternRef>::from_val(val).expect("type-checked")
+    }
+}
+
+impl WasmTy for Option<ExternRef> {
+    fn ty() -> ty::Value {
+        reference(true, ty::AbsHeap::Extern)
+    }
+
+    fn into_val(self) -> Val {
+        self.into()
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+impl WasmTy for Option<AnyRef> {
+    fn ty() -> ty::Value {
+        reference(true, ty::AbsHeap::Any)
+    }
+
+    fn into_val(self) -> Val {
+        self.into()
+    }
+
+    fn from_val(val: Val) -> Self {
+        val.try_into().expect("type-checked")
+    }
+}
+
+/// Rust types of sequences of parameters or results: a single type, or a
+/// tuple of up to 8 types
+pub trait WasmTypeList: Sized {
+    fn types() -> Vec<ty::Value>;
+    fn push(self, vals: &mut Vec<Val>);
+    /// Pops values known to have the types, the last one first
+    fn pop(vals: &mut Vec<Val>) -> Self;
+}
+
+impl<T: WasmTy> WasmTypeList for T {
+    fn types() -> Vec<ty::Value> {
+        vec![T::ty()]
+    }
+
+    fn push(self, vals: &mut Vec<Val>) {
+        vals.push(self.into_val());
+    }
+
+    fn pop(vals: &mut Vec<Val>) -> Self {
+        T::from_val(vals.pop().expect("type-checked"))
+    }
+}
+
+impl WasmTypeList for () {
+    fn types() -> Vec<ty::Value> {
+        vec![]
+    }
+
+    fn push(self, _: &mut Vec<Val>) {}
+
+    fn pop(_: &mut Vec<Val>) -> Self {}
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident)+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: WasmTy),+> WasmTypeList for ($($name,)+) {
+            fn types() -> Vec<ty::Value> {
+                vec![$($name::ty()),+]
+            }
+
+            fn push(self, vals: &mut Vec<Val>) {
+                let ($($name,)+) = self;
+                $(vals.push($name.into_val());)+
+            }
+
+            fn pop(vals: &mut Vec<Val>) -> Self {
+                let len = [$(stringify!($name)),+].len();
+                let mut vals = vals.drain(vals.len() - len..);
+                ($($name::from_val(vals.next().expect("type-checked")),)+)
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A B);
+impl_tuple!(A B C);
+impl_tuple!(A B C D);
+impl_tuple!(A B C D E);
+impl_tuple!(A B C D E F);
+impl_tuple!(A B C D E F G);
+impl_tuple!(A B C D E F G H);
+
+/// Function whose signature is checked once against Rust types, so calls
+/// pass arguments and results without checking or collecting them
+pub struct TypedFunc<Params, Results> {
+    func: Func,
+    ty: PhantomData<fn(Params) -> Results>,
+}
+
+impl<Params, Results> Clone for TypedFunc<Params, Results> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Params, Results> Copy for TypedFunc<Params, Results> {}
+
+impl<Params: WasmTypeList, Results: WasmTypeList> TypedFunc<Params, Results> {
+    /// Checks that the function takes `Params` and returns `Results`
+    pub(super) fn new(store: &Store, func: Func) -> anyhow::Result<Self> {
+        let ty = func.ty(store);
+        let matches = |values: &[ty::Value], expected: &[ty::Value]| {
+            values.len() == expected.len()
+                && values
+                    .iter()
+                    .zip(expected)
+                    .all(|(value, other)| value.matches(other))
+        };
+        let (params, results) = (Params::types(), Results::types());
+        anyhow::ensure!(
+            matches(&params, &ty.params) && matches(&ty.returns, &results),
+            "type mismatch: expected {} -> {} but found {} -> {}",
+            validation::types(&params),
+            validation::types(&results),
+            validation::types(&ty.params),
+            validation::types(&ty.returns),
+        );
+        Ok(Self {
+            func,
+            ty: PhantomData,
+        })
+    }
+
+    pub fn func(&self) -> Func {
+        self.func
+    }
+
+    pub fn call(&self, store: &mut Store, params: Params) -> anyhow::Result<Results> {
+        exec::Thread::cached(store, |thread, store| {
+            params.push(&mut thread.vals);
+            thread.invoke(store, self.func.0)?;
+            Ok(Results::pop(&mut thread.vals))
+        })
+    }
+}
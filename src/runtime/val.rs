@@ -0,0 +1,311 @@
+use std::any::Any;
+use std::fmt;
+
+use super::{Func, Store};
+use crate::validation::ty;
+
+/// Value of a function argument or result, a global or an operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Val {
+    I32(i32),
+    I64(i64),
+    /// IEEE 754 bit pattern, which is kept as is to preserve NaN payloads
+    F32(u32),
+    /// IEEE 754 bit pattern, which is kept as is to preserve NaN payloads
+    F64(u64),
+    V128(u128),
+    FuncRef(Option<Func>),
+    ExternRef(Option<ExternRef>),
+    AnyRef(Option<AnyRef>),
+    ExnRef(Option<ExnRef>),
+}
+
+impl Val {
+    /// Returns the most specific type of the value, which is a subtype of
+    /// any type the value has
+    pub fn ty(&self, store: &Store) -> ty::Value {
+        let reference = |is_nullable, heap| {
+            ty::Value::Ref(ty::Reference {
+                is_nullable,
+                heap: ty::Heap::Abstract(heap),
+            })
+        };
+        match self {
+            Self::I32(_) => ty::Value::Num(ty::Number::I32),
+            Self::I64(_) => ty::Value::Num(ty::Number::I64),
+            Self::F32(_) => ty::Value::Num(ty::Number::F32),
+            Self::F64(_) => ty::Value::Num(ty::Number::F64),
+            Self::V128(_) => ty::Value::Vec(ty::Vector::V128),
+            Self::FuncRef(Some(func)) => ty::Value::Ref(ty::Reference {
+                is_nullable: false,
+                heap: ty::Heap::Defined(ty::TypeUse::Defined(store.funcs[func.0].ty.clone())),
+            }),
+            Self::FuncRef(None) => reference(true, ty::AbsHeap::NoFunc),
+            Self::ExternRef(Some(_)) => reference(false, ty::AbsHeap::Extern),
+            Self::ExternRef(None) => reference(true, ty::AbsHeap::NoExtern),
+            Self::AnyRef(Some(AnyRef::I31(_))) => reference(false, ty::AbsHeap::I31),
+            Self::AnyRef(None) => reference(true, ty::AbsHeap::None),
+            Self::ExnRef(Some(_)) => reference(false, ty::AbsHeap::Exception),
+            Self::ExnRef(None) => reference(true, ty::AbsHeap::NoException),
+        }
+    }
+
+    /// Checks if the value has the type, failing with a type mismatch
+    pub fn check(&self, store: &Store, ty: &ty::Value) -> anyhow::Result<()> {
+        let actual = self.ty(store);
+        anyhow::ensure!(
+            actual.matches(ty),
+            "type mismatch: expected {ty} but found {actual}"
+        );
+        Ok(())
+    }
+
+    /// Returns the zero value of the type, which locals and table elements
+    /// start with, or `None` for non-nullable references
+    pub fn default_for(ty: &ty::Value) -> Option<Self> {
+        Some(match ty {
+            ty::Value::Num(ty::Number::I32) => Self::I32(0),
+            ty::Value::Num(ty::Number::I64) => Self::I64(0),
+            ty::Value::Num(ty::Number::F32) => Self::F32(0),
+            ty::Value::Num(ty::Number::F64) => Self::F64(0),
+            ty::Value::Vec(ty::Vector::V128) => Self::V128(0),
+            ty::Value::Ref(reference) if reference.is_nullable => Self::null(&reference.heap),
+            ty::Value::Ref(_) | ty::Value::Bottom => return None,
+        })
+    }
+
+    /// Returns the null reference of the hierarchy the heap type belongs to
+    pub fn null(heap: &ty::Heap) -> Self {
+        let abs = match heap {
+            ty::Heap::Abstract(abs) => *abs,
+            ty::Heap::Defined(ty::TypeUse::Defined(defined)) => defined.sub().ty.abs(),
+            ty::Heap::Defined(_) => unreachable!("types are resolved by validation"),
+        };
+        match abs.bottom() {
+            ty::AbsHeap::NoFunc => Self::FuncRef(None),
+            ty::AbsHeap::NoExtern => Self::ExternRef(None),
+            ty::AbsHeap::NoException => Self::ExnRef(None),
+            _ => Self::AnyRef(None),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(
+            self,
+            Self::FuncRef(None) | Self::ExternRef(None) | Self::AnyRef(None) | Self::ExnRef(None)
+        )
+    }
+}
+
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::I32(value) => write!(f, "i32 {value}"),
+            Self::I64(value) => write!(f, "i64 {value}"),
+            Self::F32(bits) => write!(f, "f32 {}", f32::from_bits(*bits)),
+            Self::F64(bits) => write!(f, "f64 {}", f64::from_bits(*bits)),
+            Self::V128(value) => write!(f, "v128 {value:#034x}"),
+            Self::FuncRef(Some(func)) => write!(f, "ref.func {}", func.0),
+            Self::ExternRef(Some(extern_ref)) => write!(f, "ref.extern {}", extern_ref.0),
+            Self::AnyRef(Some(AnyRef::I31(value))) => write!(f, "ref.i31 {value}"),
+            Self::ExnRef(Some(exn_ref)) => write!(f, "ref.exn {}", exn_ref.0),
+            Self::FuncRef(None) => f.write_str("ref.null func"),
+            Self::ExternRef(None) => f.write_str("ref.null extern"),
+            Self::AnyRef(None) => f.write_str("ref.null any"),
+            Self::ExnRef(None) => f.write_str("ref.null exn"),
+        }
+    }
+}
+
+/// Handle to a host value in a store, passed to WebAssembly as an
+/// `externref`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternRef(usize);
+
+impl ExternRef {
+    pub fn new(store: &mut Store, value: impl Any) -> Self {
+        store.externs.push(Box::new(value));
+        Self(store.externs.len() - 1)
+    }
+
+    pub fn data<'a>(&self, store: &'a Store) -> &'a dyn Any {
+        store.externs[self.0].as_ref()
+    }
+
+    pub fn data_mut<'a>(&self, store: &'a mut Store) -> &'a mut dyn Any {
+        store.externs[self.0].as_mut()
+    }
+}
+
+/// Reference in the `any` hierarchy of the GC proposal. Only unboxed
+/// scalars exist as there are no instructions allocating structs or arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyRef {
+    /// `i31ref` with the 31-bit value in the low bits
+    I31(u32),
+}
+
+impl AnyRef {
+    /// Wraps an integer to 31 bits
+    pub fn i31(value: i32) -> Self {
+        Self::I31(value as u32 & 0x7fff_ffff)
+    }
+}
+
+/// Handle to an exception in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExnRef(pub(super) usize);
+
+impl From<i32> for Val {
+    fn from(value: i32) -> Self {
+        Self::I32(value)
+    }
+}
+
+impl From<i64> for Val {
+    fn from(value: i64) -> Self {
+        Self::I64(value)
+    }
+}
+
+impl From<f32> for Val {
+    fn from(value: f32) -> Self {
+        Self::F32(value.to_bits())
+    }
+}
+
+impl From<f64> for Val {
+    fn from(value: f64) -> Self {
+        Self::F64(value.to_bits())
+    }
+}
+
+impl From<u128> for Val {
+    fn from(value: u128) -> Self {
+        Self::V128(value)
+    }
+}
+
+impl From<Func> for Val {
+    fn from(func: Func) -> Self {
+        Self::FuncRef(Some(func))
+    }
+}
+
+impl From<Option<Func>> for Val {
+    fn from(func: Option<Func>) -> Self {
+        Self::FuncRef(func)
+    }
+}
+
+impl From<ExternRef> for Val {
+    fn from(extern_ref: ExternRef) -> Self {
+        Self::ExternRef(Some(extern_ref))
+    }
+}
+
+impl From<Option<ExternRef>> for Val {
+    fn from(extern_ref: Option<ExternRef>) -> Self {
+        Self::ExternRef(extern_ref)
+    }
+}
+
+impl From<AnyRef> for Val {
+    fn from(any_ref: AnyRef) -> Self {
+        Self::AnyRef(Some(any_ref))
+    }
+}
+
+impl From<Option<AnyRef>> for Val {
+    fn from(any_ref: Option<AnyRef>) -> Self {
+        Self::AnyRef(any_ref)
+    }
+}
+
+impl TryFrom<Val> for i32 {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::I32(value) => Ok(value),
+            _ => anyhow::bail!("type mismatch: expected i32 but found {val}"),
+        }
+    }
+}
+
+impl TryFrom<Val> for i64 {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::I64(value) => Ok(value),
+            _ => anyhow::bail!("type mismatch: expected i64 but found {val}"),
+        }
+    }
+}
+
+impl TryFrom<Val> for f32 {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::F32(bits) => Ok(f32::from_bits(bits)),
+            _ => anyhow::bail!("type mismatch: expected f32 but found {val}"),
+        }
+    }
+}
+
+impl TryFrom<Val> for f64 {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::F64(bits) => Ok(f64::from_bits(bits)),
+            _ => anyhow::bail!("type mismatch: expected f64 but found {val}"),
+        }
+    }
+}
+
+impl TryFrom<Val> for u128 {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::V128(value) => Ok(value),
+            _ => anyhow::bail!("type mismatch: expected v128 but found {val}"),
+        }
+    }
+}
+
+impl TryFrom<Val> for Option<Func> {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::FuncRef(func) => Ok(func),
+            _ => anyhow::bail!("type mismatch: expected funcref but found {val}"),
+        }
+    }
+}
+
+impl TryFrom<Val> for Option<ExternRef> {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::ExternRef(extern_ref) => Ok(extern_ref),
+            _ => anyhow::bail!("type mismatch: expected externref but found {val}"),
+        }
+    }
+}
+
+impl TryFrom<Val> for Option<AnyRef> {
+    type Error = anyhow::Error;
+
+    fn try_from(val: Val) -> anyhow::Result<Self> {
+        match val {
+            Val::AnyRef(any_ref) => Ok(any_ref),
+            _ => anyhow::bail!("type mismatch: expected anyref but found {val}"),
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod lex;
+mod parse;
+mod print;
+
+pub use parse::parse;
@@ -0,0 +1,258 @@
+/// Byte offset range in the source text
+pub type Span = std::ops::Range<usize>;
+
+/// S-expression of tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sexp {
+    List(Vec<Sexp>, Span),
+    Keyword(String, Span),
+    Id(String, Span),
+    /// Numbers and other sequences of identifier characters not starting with
+    /// a lowercase letter or `$`
+    Reserved(String, Span),
+    String(Vec<u8>, Span),
+}
+
+impl Sexp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::List(_, span)
+            | Self::Keyword(_, span)
+            | Self::Id(_, span)
+            | Self::Reserved(_, span)
+            | Self::String(_, span) => span.clone(),
+        }
+    }
+
+    pub fn keyword(&self) -> Option<&str> {
+        match self {
+            Self::Keyword(keyword, _) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    /// Returns the items of a list starting with the keyword
+    pub fn list(&self, keyword: &str) -> Option<&[Sexp]> {
+        match self {
+            Self::List(items, _) if items.first()?.keyword() == Some(keyword) => Some(&items[1..]),
+            _ => None,
+        }
+    }
+}
+
+/// Line and column numbers, both starting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn new(source: &str, offset: usize) -> Self {
+        let before = &source[..offset.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+pub fn error(source: &str, offset: usize, message: impl std::fmt::Display) -> anyhow::Error {
+    anyhow::anyhow!("{}: {message}", Location::new(source, offset))
+}
+
+fn is_idchar(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-./:<=>?@\\^_`|~".contains(&c)
+}
+
+/// Parses the whole source into S-expressions
+pub fn parse(source: &str) -> anyhow::Result<Vec<Sexp>> {
+    let bytes = source.as_bytes();
+    let mut offset = 0;
+    // Items of each list being parsed along with its start offset
+    let mut stack: Vec<(Vec<Sexp>, usize)> = vec![(vec![], 0)];
+    while offset < bytes.len() {
+        let start = offset;
+        match bytes[offset] {
+            b' ' | b'\t' | b'\n' | b'\r' => offset += 1,
+            b';' if bytes.get(offset + 1) == Some(&b';') => {
+                offset = source[offset..]
+                    .find('\n')
+                    .map_or(bytes.len(), |i| offset + i);
+            }
+            b'(' if bytes.get(offset + 1) == Some(&b';') => {
+                let mut depth = 0;
+                loop {
+                    match (bytes.get(offset), bytes.get(offset + 1)) {
+                        (Some(b'('), Some(b';')) => {
+                            depth += 1;
+                            offset += 2;
+                        }
+                        (Some(b';'), Some(b')')) => {
+                            depth -= 1;
+                            offset += 2;
+                            if 0 == depth {
+                                break;
+                            }
+                        }
+                        (Some(_), _) => offset += 1,
+                        (None, _) => return Err(error(source, start, "unclosed block comment")),
+                    }
+                }
+            }
+            b'(' => {
+                stack.push((vec![], start));
+                offset += 1;
+            }
+            b')' => {
+                offset += 1;
+                let (items, list_start) = stack.pop().unwrap();
+                let Some((parent, _)) = stack.last_mut() else {
+                    return Err(error(source, start, "unexpected `)`"));
+                };
+                parent.push(Sexp::List(items, list_start..offset));
+            }
+            b'"' => {
+                let (string, end) = string(source, offset)?;
+                offset = end;
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .0
+                    .push(Sexp::String(string, start..offset));
+            }
+            c if is_idchar(c) => {
+                while offset < bytes.len() && is_idchar(bytes[offset]) {
+                    offset += 1;
+                }
+                let text = &source[start..offset];
+                let span = start..offset;
+                let sexp = if let Some(id) = text.strip_prefix('$') {
+                    // Quoted identifier like `$"name"`
+                    if id.is_empty() && bytes.get(offset) == Some(&b'"') {
+                        let (string, end) = string(source, offset)?;
+                        offset = end;
+                        let id = String::from_utf8(string)
+                            .map_err(|_| error(source, start, "malformed UTF-8 encoding"))?;
+                        anyhow::ensure!(!id.is_empty(), error(source, start, "empty identifier"));
+                        Sexp::Id(id, start..offset)
+                    } else {
+                        anyhow::ensure!(!id.is_empty(), error(source, start, "empty identifier"));
+                        Sexp::Id(id.to_owned(), span)
+                    }
+                } else if text.as_bytes()[0].is_ascii_lowercase() {
+                    Sexp::Keyword(text.to_owned(), span)
+                } else {
+                    Sexp::Reserved(text.to_owned(), span)
+                };
+                stack.last_mut().unwrap().0.push(sexp);
+            }
+            _ => {
+                let c = source[offset..].chars().next().unwrap();
+                return Err(error(source, start, format!("unexpected character {c:?}")));
+            }
+        }
+    }
+    let (items, start) = stack.pop().unwrap();
+    anyhow::ensure!(stack.is_empty(), error(source, start, "unclosed `(`"));
+    Ok(items)
+}
+
+/// Parses a string literal starting at `offset` and returns its bytes and end
+fn string(source: &str, offset: usize) -> anyhow::Result<(Vec<u8>, usize)> {
+    let bytes = source.as_bytes();
+    let mut string = vec![];
+    let mut i = offset + 1;
+    loop {
+        match bytes.get(i) {
+            None => return Err(error(source, offset, "unclosed string")),
+            Some(b'"') => return Ok((string, i + 1)),
+            Some(b'\\') => {
+                let escape = i;
+                i += 1;
+                match bytes.get(i) {
+                    Some(b't') => string.push(b'\t'),
+                    Some(b'n') => string.push(b'\n'),
+                    Some(b'r') => string.push(b'\r'),
+                    Some(b'"') => string.push(b'"'),
+                    Some(b'\'') => string.push(b'\''),
+                    Some(b'\\') => string.push(b'\\'),
+                    Some(b'u') if bytes.get(i + 1) == Some(&b'{') => {
+                        let end = source[i..]
+                            .find('}')
+                            .ok_or_else(|| error(source, escape, "unclosed unicode escape"))?;
+                        let hex = source[i + 2..i + end].replace('_', "");
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| error(source, escape, "malformed unicode escape"))?;
+                        string.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        i += end;
+                    }
+                    Some(c) if c.is_ascii_hexdigit() => {
+                        let hex = source.get(i..i + 2).unwrap_or_default();
+                        let byte = u8::from_str_radix(hex, 16)
+                            .map_err(|_| error(source, escape, "malformed escape"))?;
+                        string.push(byte);
+                        i += 1;
+                    }
+                    _ => return Err(error(source, escape, "unknown escape")),
+                }
+                i += 1;
+            }
+            Some(&c) if c < 0x20 || c == 0x7f => {
+                return Err(error(source, i, "control character in string"));
+            }
+            Some(&c) => {
+                string.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sexps() -> anyhow::Result<()> {
+        let sexps =
+            parse("(module $m ;; comment\n (; (; nested ;) ;) (export \"a\\41\\u{3042}\" 0x1_0))")?;
+        assert_eq!(
+            vec![Sexp::List(
+                vec![
+                    Sexp::Keyword("module".to_owned(), 1..7),
+                    Sexp::Id("m".to_owned(), 8..10),
+                    Sexp::List(
+                        vec![
+                            Sexp::Keyword("export".to_owned(), 43..49),
+                            Sexp::String(b"aA\xe3\x81\x82".to_vec(), 50..64),
+                            Sexp::Reserved("0x1_0".to_owned(), 65..70),
+                        ],
+                        42..71,
+                    ),
+                ],
+                0..72,
+            )],
+            sexps,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        let message = |source| parse(source).unwrap_err().to_string();
+        assert_eq!("2:3: unclosed `(`", message("()\n  (module"));
+        assert_eq!("1:9: unexpected `)`", message("(module))"));
+        assert_eq!("1:9: unclosed string", message("(module \"abc)"));
+        assert_eq!("1:10: unknown escape", message("(module \"\\q\")"));
+    }
+}
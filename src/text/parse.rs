@@ -0,0 +1,1998 @@
+use std::collections::{HashMap, HashSet};
+
+use super::lex::{self, Sexp};
+use crate::binary::{self, instr, ty};
+
+/// Parses a module in the text format, either as a `module` field or as its
+/// fields alone, and reports errors at their line and column
+pub fn parse(source: &str) -> anyhow::Result<binary::Module> {
+    let sexps = lex::parse(source)?;
+    let (id, cursor) = match sexps.as_slice() {
+        [sexp] if sexp.list("module").is_some() => {
+            let mut cursor = Cursor::new(source, sexp);
+            cursor.next();
+            (cursor.id(), cursor)
+        }
+        _ => {
+            let end = source.len();
+            (
+                None,
+                Cursor {
+                    source,
+                    items: &sexps,
+                    end,
+                },
+            )
+        }
+    };
+    let mut parser = ModuleParser::new();
+    parser.names.module = id;
+    parser.module(cursor)
+}
+
+/// Remaining items of a list being parsed
+#[derive(Clone)]
+struct Cursor<'a> {
+    source: &'a str,
+    items: &'a [Sexp],
+    /// Offset of the closing parenthesis to report errors at once all items
+    /// are consumed
+    end: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str, list: &'a Sexp) -> Self {
+        let Sexp::List(items, span) = list else {
+            unreachable!("cursors are only created for lists");
+        };
+        Self {
+            source,
+            items,
+            end: span.end - 1,
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> anyhow::Error {
+        let offset = self.peek().map_or(self.end, |sexp| sexp.span().start);
+        lex::error(self.source, offset, message)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn peek(&self) -> Option<&'a Sexp> {
+        self.items.first()
+    }
+
+    fn next(&mut self) -> Option<&'a Sexp> {
+        let (first, rest) = self.items.split_first()?;
+        self.items = rest;
+        Some(first)
+    }
+
+    fn expect_end(&self) -> anyhow::Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("unexpected token")),
+        }
+    }
+
+    fn id(&mut self) -> Option<String> {
+        match self.peek()? {
+            Sexp::Id(id, _) => {
+                self.next();
+                Some(id.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn has_index(&self) -> bool {
+        matches!(self.peek(), Some(Sexp::Id(..) | Sexp::Reserved(..)))
+    }
+
+    fn peek_keyword(&self) -> Option<&'a str> {
+        self.peek()?.keyword()
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let is_matched = self.peek_keyword() == Some(keyword);
+        if is_matched {
+            self.next();
+        }
+        is_matched
+    }
+
+    fn peek_list(&self, keyword: &str) -> bool {
+        self.peek().is_some_and(|sexp| sexp.list(keyword).is_some())
+    }
+
+    /// Consumes a list starting with the keyword if it comes next, returning
+    /// the items following the keyword
+    fn list(&mut self, keyword: &str) -> Option<Cursor<'a>> {
+        self.peek_list(keyword).then(|| {
+            let mut list = Cursor::new(self.source, self.next().unwrap());
+            list.next();
+            list
+        })
+    }
+
+    fn expect_list(&mut self, keyword: &str) -> anyhow::Result<Cursor<'a>> {
+        self.list(keyword)
+            .ok_or_else(|| self.error(format!("expected `({keyword} ...)`")))
+    }
+
+    /// Consumes a list starting with any keyword, returning the keyword and
+    /// the items following it
+    fn any_list(&mut self) -> Option<(&'a str, Cursor<'a>)> {
+        let Sexp::List(items, _) = self.peek()? else {
+            return None;
+        };
+        let keyword = items.first()?.keyword()?;
+        self.list(keyword).map(|list| (keyword, list))
+    }
+
+    fn string(&mut self) -> anyhow::Result<Vec<u8>> {
+        match self.peek() {
+            Some(Sexp::String(string, _)) => {
+                self.next();
+                Ok(string.clone())
+            }
+            _ => Err(self.error("expected a string")),
+        }
+    }
+
+    fn name(&mut self) -> anyhow::Result<String> {
+        let error = self.error("malformed UTF-8 encoding");
+        String::from_utf8(self.string()?).map_err(|_| error)
+    }
+
+    fn unsigned<T: TryFrom<u128>>(&mut self, name: &str) -> anyhow::Result<T> {
+        let unsigned = match self.peek() {
+            Some(Sexp::Reserved(text, _)) => int(text)
+                .filter(|(is_negative, _)| !is_negative)
+                .and_then(|(_, int)| int.try_into().ok()),
+            _ => None,
+        };
+        let unsigned = unsigned.ok_or_else(|| self.error(format!("expected a `{name}`")))?;
+        self.next();
+        Ok(unsigned)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        self.unsigned("u32")
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        self.unsigned("u64")
+    }
+
+    /// Consumes an integer in the range of either signed or unsigned `N`-bit
+    /// integers and returns its bits sign-extended
+    fn int<const N: u32>(&mut self) -> anyhow::Result<i64> {
+        let Some(Sexp::Reserved(text, _)) = self.peek() else {
+            return Err(self.error("expected an integer"));
+        };
+        let error = || self.error(format!("malformed integer `{text}`"));
+        let (is_negative, int) = int(text).ok_or_else(error)?;
+        let value = if is_negative {
+            (int <= 1 << (N - 1)).then(|| (int as i128).wrapping_neg() as i64)
+        } else {
+            (int < 1 << N).then_some(int as i64)
+        };
+        let value = value.ok_or_else(|| self.error("constant out of range"))?;
+        self.next();
+        Ok(value)
+    }
+
+    fn float(&mut self, format: FloatFormat) -> anyhow::Result<u64> {
+        let Some(Sexp::Keyword(text, _) | Sexp::Reserved(text, _)) = self.peek() else {
+            return Err(self.error("expected a float"));
+        };
+        let bits = match float(text, format) {
+            Some(Some(bits)) => bits,
+            Some(None) => return Err(self.error("constant out of range")),
+            None => return Err(self.error(format!("malformed float `{text}`"))),
+        };
+        self.next();
+        Ok(bits)
+    }
+}
+
+/// Parses digits optionally separated by single underscores
+fn digits(text: &str, radix: u32) -> Option<u128> {
+    let mut value: u128 = 0;
+    for part in text.split('_') {
+        if part.is_empty() {
+            return None;
+        }
+        for c in part.chars() {
+            let digit = c.to_digit(radix)?;
+            value = value.checked_mul(radix.into())?.checked_add(digit.into())?;
+        }
+    }
+    Some(value)
+}
+
+fn sign(text: &str) -> Option<(bool, &str)> {
+    Some(match text.as_bytes().first()? {
+        b'-' => (true, &text[1..]),
+        b'+' => (false, &text[1..]),
+        _ => (false, text),
+    })
+}
+
+/// Parses an integer into whether it's negative and its magnitude
+fn int(text: &str) -> Option<(bool, u128)> {
+    let (is_negative, text) = sign(text)?;
+    let int = match text.strip_prefix("0x") {
+        Some(hex) => digits(hex, 16)?,
+        None => digits(text, 10)?,
+    };
+    Some((is_negative, int))
+}
+
+#[derive(Clone, Copy)]
+struct FloatFormat {
+    mantissa_bits: u32,
+    exponent_bits: u32,
+}
+
+const F32: FloatFormat = FloatFormat {
+    mantissa_bits: 23,
+    exponent_bits: 8,
+};
+
+const F64: FloatFormat = FloatFormat {
+    mantissa_bits: 52,
+    exponent_bits: 11,
+};
+
+/// Parses a float into its bits, or `Some(None)` if it's out of range
+fn float(text: &str, format: FloatFormat) -> Option<Option<u64>> {
+    let (is_negative, text) = sign(text)?;
+    let sign = u64::from(is_negative) << (format.mantissa_bits + format.exponent_bits);
+    let infinity = ((1 << format.exponent_bits) - 1) << format.mantissa_bits;
+    let bits = if "inf" == text {
+        Some(infinity)
+    } else if "nan" == text {
+        Some(infinity | 1 << (format.mantissa_bits - 1))
+    } else if let Some(payload) = text.strip_prefix("nan:0x") {
+        let payload = digits(payload, 16)?;
+        (0 < payload && payload < 1 << format.mantissa_bits).then_some(infinity | payload as u64)
+    } else if let Some(hex) = text.strip_prefix("0x") {
+        hex_float(hex, format)?
+    } else {
+        decimal_float(text, format)?
+    };
+    Some(bits.map(|bits| sign | bits))
+}
+
+fn decimal_float(text: &str, format: FloatFormat) -> Option<Option<u64>> {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    digits(int, 10)?;
+    let mut normalized = int.replace('_', "");
+    if !frac.is_empty() {
+        digits(frac, 10)?;
+        normalized = format!("{normalized}.{}", frac.replace('_', ""));
+    }
+    if let Some(exponent) = exponent {
+        let (is_negative, exponent) = sign(exponent)?;
+        digits(exponent, 10)?;
+        let sign = if is_negative { "-" } else { "" };
+        normalized = format!("{normalized}e{sign}{}", exponent.replace('_', ""));
+    }
+    // Rust rounds decimals to nearest with ties to even as required
+    let bits = if 23 == format.mantissa_bits {
+        let value: f32 = normalized.parse().ok()?;
+        value.is_finite().then(|| value.to_bits().into())
+    } else {
+        let value: f64 = normalized.parse().ok()?;
+        value.is_finite().then(|| value.to_bits())
+    };
+    Some(bits)
+}
+
+/// Parses a hexadecimal float following `0x`, rounding to nearest with ties
+/// to even
+fn hex_float(text: &str, format: FloatFormat) -> Option<Option<u64>> {
+    let (mantissa, exponent) = match text.find(['p', 'P']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    digits(int, 16)?;
+    if !frac.is_empty() {
+        digits(frac, 16)?;
+    }
+    let mut exponent = match exponent {
+        Some(exponent) => {
+            let (is_negative, exponent) = sign(exponent)?;
+            let exponent = digits(exponent, 10)?.min(1 << 20) as i64;
+            if is_negative { -exponent } else { exponent }
+        }
+        None => 0,
+    };
+    // Leading digits fitting in 120 bits, with the rest only telling whether
+    // the value is above them
+    let mut significand: u128 = 0;
+    let mut is_inexact = false;
+    let int_digits = int.chars().map(|c| (c, false));
+    let frac_digits = frac.chars().map(|c| (c, true));
+    for (c, is_frac) in int_digits.chain(frac_digits) {
+        let Some(digit) = c.to_digit(16) else {
+            continue;
+        };
+        if significand < 1 << 116 {
+            significand = significand << 4 | u128::from(digit);
+            exponent -= i64::from(is_frac) * 4;
+        } else {
+            is_inexact |= 0 != digit;
+            exponent += i64::from(!is_frac) * 4;
+        }
+    }
+    if 0 == significand {
+        return Some(Some(0));
+    }
+    let bias = (1 << (format.exponent_bits - 1)) - 1;
+    let bit_count = 128 - i64::from(significand.leading_zeros());
+    // The value is in `[2^e, 2^(e + 1))`
+    let e = bit_count - 1 + exponent;
+    let precision = i64::from(format.mantissa_bits) + 1;
+    // Subnormals have fewer significant bits as their exponent is fixed
+    let kept_bit_count = precision - (1 - bias - e).max(0);
+    let shift = bit_count - kept_bit_count;
+    let mut mantissa = if 0 < shift {
+        let shift = shift.min(127) as u32;
+        let kept = significand >> shift;
+        let remainder = significand & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        let is_rounded_up =
+            half < remainder || (half == remainder && (is_inexact || 1 == kept & 1));
+        kept + u128::from(is_rounded_up)
+    } else {
+        significand << -shift
+    };
+    let mut biased_exponent = (e + bias).max(0);
+    if 0 < biased_exponent && 1 << precision <= mantissa {
+        mantissa >>= 1;
+        biased_exponent += 1;
+    }
+    if (1 << format.exponent_bits) - 1 <= biased_exponent {
+        return Some(None);
+    }
+    // Subnormals rounded up to the smallest normal carry into the exponent
+    let bits = match biased_exponent {
+        0 => mantissa as u64,
+        _ => {
+            let mantissa = mantissa as u64 & ((1 << format.mantissa_bits) - 1);
+            (biased_exponent as u64) << format.mantissa_bits | mantissa
+        }
+    };
+    Some(Some(bits))
+}
+
+/// Index spaces that identifiers refer to
+#[derive(Clone, Copy)]
+enum Space {
+    Type,
+    Func,
+    Table,
+    Memory,
+    Global,
+    Tag,
+    Elem,
+    Data,
+}
+
+impl Space {
+    fn new(keyword: &str) -> Option<Self> {
+        match keyword {
+            "func" => Some(Self::Func),
+            "table" => Some(Self::Table),
+            "memory" => Some(Self::Memory),
+            "global" => Some(Self::Global),
+            "tag" => Some(Self::Tag),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Type => "type",
+            Self::Func => "function",
+            Self::Table => "table",
+            Self::Memory => "memory",
+            Self::Global => "global",
+            Self::Tag => "tag",
+            Self::Elem => "elem segment",
+            Self::Data => "data segment",
+        }
+    }
+
+    fn is_of(self, ty: &ty::External) -> bool {
+        matches!(
+            (self, ty),
+            (Self::Func, ty::External::Func(_))
+                | (Self::Table, ty::External::Table(_))
+                | (Self::Memory, ty::External::Memory(_))
+                | (Self::Global, ty::External::Global(_))
+                | (Self::Tag, ty::External::Tag(_))
+        )
+    }
+
+    fn external_index(self, index: u32) -> binary::ExternalIndex {
+        match self {
+            Self::Func => binary::ExternalIndex::Func(index),
+            Self::Table => binary::ExternalIndex::Table(index),
+            Self::Memory => binary::ExternalIndex::Memory(index),
+            Self::Global => binary::ExternalIndex::Global(index),
+            Self::Tag => binary::ExternalIndex::Tag(index),
+            Self::Type | Self::Elem | Self::Data => {
+                unreachable!("only external items can be exported")
+            }
+        }
+    }
+}
+
+/// Labels and locals in scope while parsing instructions
+#[derive(Default)]
+struct FuncContext {
+    locals: HashMap<String, u32>,
+    labels: Vec<Option<String>>,
+    /// Number of labels of the enclosing folded instructions, which can't be
+    /// closed by flat `end`s
+    folded_depth: usize,
+    instrs: Vec<instr::Instruction>,
+}
+
+struct ModuleParser {
+    ids: [HashMap<String, u32>; 8],
+    /// Types defined by `type` and `rec` fields
+    types: Vec<ty::Recursive>,
+    /// Function types of type uses not matching any defined type, which are
+    /// appended to the defined ones
+    implicit_types: Vec<ty::Recursive>,
+    module: binary::Module,
+    names: binary::NameSection,
+}
+
+impl ModuleParser {
+    fn new() -> Self {
+        Self {
+            ids: Default::default(),
+            types: vec![],
+            implicit_types: vec![],
+            module: binary::Module::default(),
+            names: binary::NameSection::default(),
+        }
+    }
+
+    fn module(mut self, cursor: Cursor) -> anyhow::Result<binary::Module> {
+        self.collect_ids(cursor.clone())?;
+        // Types are needed by type uses in other fields
+        let mut types = cursor.clone();
+        while !types.is_empty() {
+            if let Some(mut rec) = types.list("rec") {
+                let mut subs = vec![];
+                while let Some(def) = rec.list("type") {
+                    subs.push(self.type_def(def)?);
+                }
+                rec.expect_end()?;
+                self.types.push(ty::Recursive(subs));
+            } else if let Some(def) = types.list("type") {
+                let sub = self.type_def(def)?;
+                self.types.push(ty::Recursive(vec![sub]));
+            } else {
+                types.next();
+            }
+        }
+        let mut fields = cursor;
+        while !fields.is_empty() {
+            let error = fields.error("expected a module field");
+            let (keyword, field) = fields.any_list().ok_or(error)?;
+            match keyword {
+                "type" | "rec" => {}
+                "import" => self.import(field)?,
+                "func" => self.func(field)?,
+                "table" => self.table(field)?,
+                "memory" => self.memory(field)?,
+                "global" => self.global(field)?,
+                "export" => self.export(field)?,
+                "start" => self.start(field)?,
+                "elem" => self.elem(field)?,
+                "data" => self.data(field)?,
+                "tag" => self.imported_only(keyword, field)?,
+                _ => return Err(field.error(format!("unsupported module field `{keyword}`"))),
+            }
+        }
+        let mut module = self.module;
+        let types: Vec<_> = self.types.into_iter().chain(self.implicit_types).collect();
+        if !types.is_empty() {
+            module.type_section = Some(binary::TypeSection(types));
+        }
+        if self.names != binary::NameSection::default() {
+            module.name_section = Some(self.names);
+        }
+        // Bodies referencing data segments need their count ahead of them
+        let codes = module.code_section.iter().flat_map(|section| &section.0);
+        let mut instrs = codes.flat_map(|func| &func.expr.0);
+        if instrs.any(|instr| {
+            matches!(
+                instr,
+                instr::Instruction::MemoryInit(..) | instr::Instruction::DataDrop(_)
+            )
+        }) {
+            let count = module
+                .data_section
+                .as_ref()
+                .map_or(0, |section| section.0.len());
+            module.data_count_section = Some(binary::DataCountSection(count as u32));
+        }
+        Ok(module)
+    }
+
+    /// Assigns indices to the identifiers of all items up front, as they can
+    /// be referenced before being defined
+    fn collect_ids(&mut self, mut cursor: Cursor) -> anyhow::Result<()> {
+        let mut counts = [0; 8];
+        let mut is_defined = [false; 8];
+        loop {
+            let start = cursor.clone();
+            let Some((keyword, mut field)) = cursor.any_list() else {
+                break;
+            };
+            let (space, is_import) = match keyword {
+                "type" => {
+                    self.field_names(counts[Space::Type as usize], &field);
+                    self.define(Space::Type, &mut counts, &mut field)?;
+                    continue;
+                }
+                "rec" => {
+                    while let Some(mut def) = field.list("type") {
+                        self.field_names(counts[Space::Type as usize], &def);
+                        self.define(Space::Type, &mut counts, &mut def)?;
+                    }
+                    continue;
+                }
+                "elem" => {
+                    self.define(Space::Elem, &mut counts, &mut field)?;
+                    continue;
+                }
+                "data" => {
+                    self.define(Space::Data, &mut counts, &mut field)?;
+                    continue;
+                }
+                "import" => {
+                    field.string()?;
+                    field.string()?;
+                    let error = field.error("expected an import description");
+                    let (keyword, desc) = field.any_list().ok_or(error)?;
+                    field = desc;
+                    let error = field.error(format!("unknown import kind `{keyword}`"));
+                    (Space::new(keyword).ok_or(error)?, true)
+                }
+                _ => {
+                    let Some(space) = Space::new(keyword) else {
+                        continue;
+                    };
+                    let mut rest = field.clone();
+                    rest.id();
+                    while rest.list("export").is_some() {}
+                    (space, rest.peek_list("import"))
+                }
+            };
+            if is_import {
+                anyhow::ensure!(
+                    !is_defined[space as usize],
+                    start.error("import after definition")
+                );
+            } else {
+                is_defined[space as usize] = true;
+            }
+            self.define(space, &mut counts, &mut field)?;
+        }
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        space: Space,
+        counts: &mut [u32; 8],
+        field: &mut Cursor,
+    ) -> anyhow::Result<()> {
+        let index = counts[space as usize];
+        counts[space as usize] += 1;
+        if let Some(id) = field.id() {
+            let ids = &mut self.ids[space as usize];
+            anyhow::ensure!(
+                !ids.contains_key(&id),
+                field.error(format!("duplicate {} `${id}`", space.name()))
+            );
+            ids.insert(id.clone(), index);
+            let names = match space {
+                Space::Type => &mut self.names.types,
+                Space::Func => &mut self.names.funcs,
+                Space::Table => &mut self.names.tables,
+                Space::Memory => &mut self.names.memories,
+                Space::Global => &mut self.names.globals,
+                Space::Tag | Space::Elem | Space::Data => return Ok(()),
+            };
+            names.push(binary::Naming { index, name: id });
+        }
+        Ok(())
+    }
+
+    /// Names the fields of a struct type with identifiers
+    fn field_names(&mut self, index: u32, def: &Cursor) {
+        let mut def = def.clone();
+        def.id();
+        let mut composite = def.list("sub").unwrap_or(def);
+        while composite
+            .peek()
+            .is_some_and(|sexp| !matches!(sexp, Sexp::List(..)))
+        {
+            composite.next();
+        }
+        let Some(mut fields) = composite.list("struct") else {
+            return;
+        };
+        let mut names = vec![];
+        let mut field_index = 0;
+        while let Some(mut field) = fields.list("field") {
+            match field.id() {
+                Some(name) => {
+                    let index = field_index;
+                    names.push(binary::Naming { index, name });
+                    field_index += 1;
+                }
+                None => field_index += field.items.len() as u32,
+            }
+        }
+        if !names.is_empty() {
+            self.names
+                .fields
+                .push(binary::IndirectNaming { index, names });
+        }
+    }
+
+    fn index(&self, cursor: &mut Cursor, space: Space) -> anyhow::Result<u32> {
+        index(cursor, &self.ids[space as usize], space.name())
+    }
+
+    fn count(&self, space: Space) -> u32 {
+        let imports = self
+            .module
+            .import_section
+            .iter()
+            .flat_map(|section| &section.0);
+        let imported = imports.filter(|import| space.is_of(&import.ty)).count();
+        let module = &self.module;
+        let defined = match space {
+            Space::Func => module.func_section.as_ref().map(|section| section.0.len()),
+            Space::Table => module.table_section.as_ref().map(|section| section.0.len()),
+            Space::Memory => module
+                .memory_section
+                .as_ref()
+                .map(|section| section.0.len()),
+            Space::Global => module
+                .global_section
+                .as_ref()
+                .map(|section| section.0.len()),
+            _ => None,
+        };
+        (imported + defined.unwrap_or(0)) as u32
+    }
+
+    fn type_def(&self, mut def: Cursor) -> anyhow::Result<ty::Sub> {
+        def.id();
+        let sub = match def.list("sub") {
+            Some(mut sub) => {
+                let is_final = sub.keyword("final");
+                let mut supers = vec![];
+                while sub.has_index() {
+                    supers.push(self.index(&mut sub, Space::Type)?);
+                }
+                let composite = self.composite(&mut sub)?;
+                sub.expect_end()?;
+                ty::Sub {
+                    is_final,
+                    supers,
+                    composite,
+                }
+            }
+            None => ty::Sub {
+                is_final: true,
+                supers: vec![],
+                composite: self.composite(&mut def)?,
+            },
+        };
+        def.expect_end()?;
+        Ok(sub)
+    }
+
+    fn composite(&self, cursor: &mut Cursor) -> anyhow::Result<ty::Composite> {
+        if let Some(mut func) = cursor.list("func") {
+            let (params, _) = self.params(&mut func)?;
+            let returns = self.results(&mut func)?;
+            func.expect_end()?;
+            Ok(ty::Composite::Func { params, returns })
+        } else if let Some(mut fields) = cursor.list("struct") {
+            let mut composite = vec![];
+            let mut ids = HashSet::new();
+            while let Some(mut field) = fields.list("field") {
+                let start = field.clone();
+                if let Some(id) = field.id() {
+                    let error = start.error(format!("duplicate field `${id}`"));
+                    anyhow::ensure!(ids.insert(id), error);
+                    composite.push(self.field(&mut field)?);
+                    field.expect_end()?;
+                }
+                while !field.is_empty() {
+                    composite.push(self.field(&mut field)?);
+                }
+            }
+            fields.expect_end()?;
+            Ok(ty::Composite::Struct(composite))
+        } else if let Some(mut array) = cursor.list("array") {
+            let field = self.field(&mut array)?;
+            array.expect_end()?;
+            Ok(ty::Composite::Array(field))
+        } else {
+            Err(cursor.error("expected a composite type"))
+        }
+    }
+
+    fn field(&self, cursor: &mut Cursor) -> anyhow::Result<ty::Field> {
+        let storage = |cursor: &mut Cursor| {
+            if cursor.keyword("i8") {
+                Ok(ty::Storage::Pack(ty::Pack::I8))
+            } else if cursor.keyword("i16") {
+                Ok(ty::Storage::Pack(ty::Pack::I16))
+            } else {
+                self.value(cursor).map(ty::Storage::Value)
+            }
+        };
+        Ok(match cursor.list("mut") {
+            Some(mut inner) => {
+                let storage = storage(&mut inner)?;
+                inner.expect_end()?;
+                ty::Field {
+                    storage,
+                    is_mutable: true,
+                }
+            }
+            None => ty::Field {
+                storage: storage(cursor)?,
+                is_mutable: false,
+            },
+        })
+    }
+
+    /// Parses `param` lists along with the identifiers of the parameters
+    fn params(&self, cursor: &mut Cursor) -> anyhow::Result<(Vec<ty::Value>, Vec<Option<String>>)> {
+        let mut params = vec![];
+        let mut ids = vec![];
+        while let Some(mut param) = cursor.list("param") {
+            if let Some(id) = param.id() {
+                params.push(self.value(&mut param)?);
+                ids.push(Some(id));
+                param.expect_end()?;
+            }
+            while !param.is_empty() {
+                params.push(self.value(&mut param)?);
+                ids.push(None);
+            }
+        }
+        Ok((params, ids))
+    }
+
+    fn results(&self, cursor: &mut Cursor) -> anyhow::Result<Vec<ty::Value>> {
+        let mut results = vec![];
+        while let Some(mut result) = cursor.list("result") {
+            while !result.is_empty() {
+                results.push(self.value(&mut result)?);
+            }
+        }
+        Ok(results)
+    }
+
+    fn value(&self, cursor: &mut Cursor) -> anyhow::Result<ty::Value> {
+        use ty::AbsHeap::*;
+        if let Some(mut reference) = cursor.list("ref") {
+            let is_nullable = reference.keyword("null");
+            let heap = self.heap(&mut reference)?;
+            reference.expect_end()?;
+            return Ok(ty::Value::Ref(ty::Reference { heap, is_nullable }));
+        }
+        let value = |value| Some(value);
+        let nullable = |abs| {
+            Some(ty::Value::Ref(ty::Reference {
+                heap: ty::Heap::Abstract(abs),
+                is_nullable: true,
+            }))
+        };
+        let value = match cursor.peek_keyword() {
+            Some("i32") => value(ty::Value::Num(ty::Number::I32)),
+            Some("i64") => value(ty::Value::Num(ty::Number::I64)),
+            Some("f32") => value(ty::Value::Num(ty::Number::F32)),
+            Some("f64") => value(ty::Value::Num(ty::Number::F64)),
+            Some("v128") => value(ty::Value::Vec(ty::Vector::Vec)),
+            Some("funcref") => nullable(Func),
+            Some("externref") => nullable(Extern),
+            Some("anyref") => nullable(Any),
+            Some("eqref") => nullable(Eq),
+            Some("i31ref") => nullable(I31),
+            Some("structref") => nullable(Struct),
+            Some("arrayref") => nullable(Array),
+            Some("exnref") => nullable(Exception),
+            Some("nullref") => nullable(None),
+            Some("nullexternref") => nullable(NoExtern),
+            Some("nullfuncref") => nullable(NoFunc),
+            Some("nullexnref") => nullable(NoException),
+            _ => Option::None,
+        };
+        let value = value.ok_or_else(|| cursor.error("expected a value type"))?;
+        cursor.next();
+        Ok(value)
+    }
+
+    fn heap(&self, cursor: &mut Cursor) -> anyhow::Result<ty::Heap> {
+        use ty::AbsHeap::*;
+        let abs = match cursor.peek_keyword() {
+            Some("func") => Func,
+            Some("extern") => Extern,
+            Some("any") => Any,
+            Some("eq") => Eq,
+            Some("i31") => I31,
+            Some("struct") => Struct,
+            Some("array") => Array,
+            Some("exn") => Exception,
+            Some("none") => None,
+            Some("noextern") => NoExtern,
+            Some("nofunc") => NoFunc,
+            Some("noexn") => NoException,
+            _ => return self.index(cursor, Space::Type).map(ty::Heap::Concrete),
+        };
+        cursor.next();
+        Ok(ty::Heap::Abstract(abs))
+    }
+
+    fn global_type(&self, cursor: &mut Cursor) -> anyhow::Result<ty::Global> {
+        Ok(match cursor.list("mut") {
+            Some(mut inner) => {
+                let value = self.value(&mut inner)?;
+                inner.expect_end()?;
+                ty::Global {
+                    value,
+                    is_mutable: true,
+                }
+            }
+            None => ty::Global {
+                value: self.value(cursor)?,
+                is_mutable: false,
+            },
+        })
+    }
+
+    fn limit(&self, cursor: &mut Cursor) -> anyhow::Result<ty::Limit> {
+        let address = match cursor.keyword("i64") {
+            true => ty::Address::I64,
+            false => ty::Address::I32,
+        };
+        let min = cursor.u64()?;
+        let max = match cursor.peek() {
+            Some(Sexp::Reserved(..)) => Some(cursor.u64()?),
+            _ => None,
+        };
+        Ok(ty::Limit { address, min, max })
+    }
+
+    fn table_type(&self, cursor: &mut Cursor) -> anyhow::Result<ty::Table> {
+        let limit = self.limit(cursor)?;
+        let error = cursor.error("expected a reference type");
+        let ty::Value::Ref(reference) = self.value(cursor)? else {
+            return Err(error);
+        };
+        Ok(ty::Table { reference, limit })
+    }
+
+    /// Parses a type use and returns the type index and the identifiers of
+    /// the parameters
+    fn type_use(&mut self, cursor: &mut Cursor) -> anyhow::Result<(u32, Vec<Option<String>>)> {
+        let index = match cursor.list("type") {
+            Some(mut ty) => {
+                let index = self.index(&mut ty, Space::Type)?;
+                ty.expect_end()?;
+                Some(index)
+            }
+            None => None,
+        };
+        let inline = cursor.clone();
+        let (params, ids) = self.params(cursor)?;
+        let returns = self.results(cursor)?;
+        let Some(index) = index else {
+            let index = self.implicit_type(ty::Composite::Func { params, returns });
+            return Ok((index, ids));
+        };
+        let defined = self.types.iter().flat_map(|rec| &rec.0).nth(index as usize);
+        let defined = defined.map(|sub| &sub.composite);
+        if params.is_empty() && returns.is_empty() {
+            // Parameters of the referenced type still take local indices
+            let ids = match defined {
+                Some(ty::Composite::Func { params, .. }) => vec![None; params.len()],
+                _ => vec![],
+            };
+            return Ok((index, ids));
+        }
+        anyhow::ensure!(
+            defined == Some(&ty::Composite::Func { params, returns }),
+            inline.error("inline function type doesn't match the type use")
+        );
+        Ok((index, ids))
+    }
+
+    /// Returns the index of the first function type matching the composite
+    /// type that isn't a subtype or in a recursive group, adding it unless
+    /// it's defined
+    fn implicit_type(&mut self, composite: ty::Composite) -> u32 {
+        let rec = ty::Recursive(vec![ty::Sub {
+            is_final: true,
+            supers: vec![],
+            composite,
+        }]);
+        let mut index = 0;
+        for defined in &self.types {
+            if *defined == rec {
+                return index;
+            }
+            index += defined.0.len() as u32;
+        }
+        let implicit = self
+            .implicit_types
+            .iter()
+            .position(|implicit| *implicit == rec);
+        let implicit = implicit.unwrap_or_else(|| {
+            self.implicit_types.push(rec);
+            self.implicit_types.len() - 1
+        });
+        index + implicit as u32
+    }
+
+    fn block_type(&mut self, cursor: &mut Cursor) -> anyhow::Result<instr::BlockType> {
+        if cursor.peek_list("type") {
+            let (index, _) = self.type_use(cursor)?;
+            return Ok(instr::BlockType::Index(index));
+        }
+        let (params, _) = self.params(cursor)?;
+        let mut returns = self.results(cursor)?;
+        Ok(match (params.len(), returns.len()) {
+            (0, 0) => instr::BlockType::Empty,
+            (0, 1) => instr::BlockType::Value(returns.remove(0)),
+            _ => {
+                let index = self.implicit_type(ty::Composite::Func { params, returns });
+                instr::BlockType::Index(index)
+            }
+        })
+    }
+
+    fn import(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        let module = field.name()?;
+        let name = field.name()?;
+        let (keyword, mut desc) = field.any_list().unwrap();
+        field.expect_end()?;
+        desc.id();
+        self.import_desc(module, name, keyword, desc)
+    }
+
+    /// Parses the type of an import following its identifier
+    fn import_desc(
+        &mut self,
+        module: String,
+        name: String,
+        keyword: &str,
+        mut desc: Cursor,
+    ) -> anyhow::Result<()> {
+        let ty = match keyword {
+            "func" => ty::External::Func(self.type_use(&mut desc)?.0),
+            "table" => ty::External::Table(self.table_type(&mut desc)?),
+            "memory" => ty::External::Memory(ty::Memory(self.limit(&mut desc)?)),
+            "global" => ty::External::Global(self.global_type(&mut desc)?),
+            "tag" => ty::External::Tag(ty::Tag(self.type_use(&mut desc)?.0)),
+            _ => unreachable!("checked while collecting identifiers"),
+        };
+        desc.expect_end()?;
+        let imports = self
+            .module
+            .import_section
+            .get_or_insert(binary::ImportSection(vec![]));
+        imports.0.push(binary::Import { module, name, ty });
+        Ok(())
+    }
+
+    /// Parses inline exports of the item of the field, and its inline import
+    /// if any
+    fn inline_exports(
+        &mut self,
+        space: Space,
+        field: &mut Cursor,
+    ) -> anyhow::Result<Option<(String, String)>> {
+        let index = space.external_index(self.count(space));
+        while let Some(mut export) = field.list("export") {
+            let name = export.name()?;
+            export.expect_end()?;
+            self.add_export(name, index);
+        }
+        let Some(mut import) = field.list("import") else {
+            return Ok(None);
+        };
+        let module = import.name()?;
+        let name = import.name()?;
+        import.expect_end()?;
+        Ok(Some((module, name)))
+    }
+
+    fn add_export(&mut self, name: String, index: binary::ExternalIndex) {
+        let exports = self
+            .module
+            .export_section
+            .get_or_insert(binary::ExportSection(vec![]));
+        exports.0.push(binary::Export { name, index });
+    }
+
+    fn export(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        let name = field.name()?;
+        let error = field.error("expected an export description");
+        let (keyword, mut desc) = field.any_list().ok_or(error)?;
+        let error = desc.error(format!("unknown export kind `{keyword}`"));
+        let space = Space::new(keyword).ok_or(error)?;
+        let index = space.external_index(self.index(&mut desc, space)?);
+        desc.expect_end()?;
+        field.expect_end()?;
+        self.add_export(name, index);
+        Ok(())
+    }
+
+    /// Parses a tag field, which is only supported as an import
+    fn imported_only(&mut self, keyword: &str, mut field: Cursor) -> anyhow::Result<()> {
+        field.id();
+        let space = Space::new(keyword).unwrap();
+        let Some((module, name)) = self.inline_exports(space, &mut field)? else {
+            return Err(field.error(format!("unsupported {keyword} definition")));
+        };
+        self.import_desc(module, name, keyword, field)
+    }
+
+    fn func(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        let index = self.count(Space::Func);
+        field.id();
+        if let Some((module, name)) = self.inline_exports(Space::Func, &mut field)? {
+            return self.import_desc(module, name, "func", field);
+        }
+        let (ty, mut ids) = self.type_use(&mut field)?;
+        let mut locals: Vec<binary::Local> = vec![];
+        let mut push = |ty: ty::Value| match locals.last_mut() {
+            Some(last) if last.ty == ty => last.num += 1,
+            _ => locals.push(binary::Local { num: 1, ty }),
+        };
+        while let Some(mut local) = field.list("local") {
+            if let Some(id) = local.id() {
+                push(self.value(&mut local)?);
+                ids.push(Some(id));
+                local.expect_end()?;
+            }
+            while !local.is_empty() {
+                push(self.value(&mut local)?);
+                ids.push(None);
+            }
+        }
+        let mut context = FuncContext::default();
+        for (i, id) in ids.iter().enumerate() {
+            if let Some(id) = id {
+                let is_duplicate = context.locals.insert(id.clone(), i as u32).is_some();
+                anyhow::ensure!(
+                    !is_duplicate,
+                    field.error(format!("duplicate local `${id}`"))
+                );
+            }
+        }
+        self.local_names(index, &ids);
+        let expr = self.expr(context, &mut field)?;
+        let funcs = self
+            .module
+            .func_section
+            .get_or_insert(binary::FuncSection(vec![]));
+        funcs.0.push(ty);
+        let codes = self
+            .module
+            .code_section
+            .get_or_insert(binary::CodeSection(vec![]));
+        codes.0.push(binary::Func { locals, expr });
+        Ok(())
+    }
+
+    fn local_names(&mut self, func: u32, ids: &[Option<String>]) {
+        let names: Vec<_> = ids
+            .iter()
+            .enumerate()
+            .filter_map(|(index, id)| {
+                let name = id.clone()?;
+                let index = index as u32;
+                Some(binary::Naming { index, name })
+            })
+            .collect();
+        if !names.is_empty() {
+            let index = func;
+            self.names
+                .locals
+                .push(binary::IndirectNaming { index, names });
+        }
+    }
+
+    /// Parses a table field, whose elements may be given inline by an active
+    /// element segment instead of limits
+    fn table(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        let index = self.count(Space::Table);
+        field.id();
+        if let Some((module, name)) = self.inline_exports(Space::Table, &mut field)? {
+            return self.import_desc(module, name, "table", field);
+        }
+        let mut limit = field.clone();
+        limit.keyword("i64");
+        let table = if let Some(Sexp::Reserved(..)) = limit.peek() {
+            self.table_type(&mut field)?
+        } else {
+            let address = match field.keyword("i64") {
+                true => ty::Address::I64,
+                false => ty::Address::I32,
+            };
+            let error = field.error("expected a reference type");
+            let ty::Value::Ref(reference) = self.value(&mut field)? else {
+                return Err(error);
+            };
+            let mut elem = field.expect_list("elem")?;
+            let init = match elem.is_empty() || elem.has_index() {
+                true => binary::ElementInit::Funcs(self.func_indices(&mut elem)?),
+                false => binary::ElementInit::Exprs {
+                    ty: reference.clone(),
+                    exprs: self.items(&mut elem)?,
+                },
+            };
+            let count = match &init {
+                binary::ElementInit::Funcs(funcs) => funcs.len(),
+                binary::ElementInit::Exprs { exprs, .. } => exprs.len(),
+            } as u64;
+            let offset = zero_offset(&address);
+            let elements = self
+                .module
+                .element_section
+                .get_or_insert(binary::ElementSection(vec![]));
+            elements.0.push(binary::Element {
+                mode: binary::ElementMode::Active {
+                    table: index,
+                    offset,
+                },
+                init,
+            });
+            let (min, max) = (count, Some(count));
+            let limit = ty::Limit { address, min, max };
+            ty::Table { reference, limit }
+        };
+        field.expect_end()?;
+        let tables = self
+            .module
+            .table_section
+            .get_or_insert(binary::TableSection(vec![]));
+        tables.0.push(table);
+        Ok(())
+    }
+
+    /// Parses a memory field, whose contents may be given inline by an active
+    /// data segment instead of limits
+    fn memory(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        let index = self.count(Space::Memory);
+        field.id();
+        if let Some((module, name)) = self.inline_exports(Space::Memory, &mut field)? {
+            return self.import_desc(module, name, "memory", field);
+        }
+        let mut data = field.clone();
+        data.keyword("i64");
+        let limit = if data.peek_list("data") {
+            let address = match field.keyword("i64") {
+                true => ty::Address::I64,
+                false => ty::Address::I32,
+            };
+            let mut data = field.expect_list("data")?;
+            let mut init = vec![];
+            while !data.is_empty() {
+                init.extend(data.string()?);
+            }
+            let pages = init.len().div_ceil(PAGE_SIZE) as u64;
+            let offset = zero_offset(&address);
+            let segments = self
+                .module
+                .data_section
+                .get_or_insert(binary::DataSection(vec![]));
+            segments.0.push(binary::Data {
+                mode: binary::DataMode::Active {
+                    memory: index,
+                    offset,
+                },
+                init,
+            });
+            let (min, max) = (pages, Some(pages));
+            ty::Limit { address, min, max }
+        } else {
+            self.limit(&mut field)?
+        };
+        field.expect_end()?;
+        let memories = self
+            .module
+            .memory_section
+            .get_or_insert(binary::MemorySection(vec![]));
+        memories.0.push(ty::Memory(limit));
+        Ok(())
+    }
+
+    fn global(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        field.id();
+        if let Some((module, name)) = self.inline_exports(Space::Global, &mut field)? {
+            return self.import_desc(module, name, "global", field);
+        }
+        let ty = self.global_type(&mut field)?;
+        let init = self.expr(FuncContext::default(), &mut field)?;
+        let globals = self
+            .module
+            .global_section
+            .get_or_insert(binary::GlobalSection(vec![]));
+        globals.0.push(binary::Global { ty, init });
+        Ok(())
+    }
+
+    fn start(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.module.start_section.is_none(),
+            field.error("multiple start functions")
+        );
+        let func = self.index(&mut field, Space::Func)?;
+        field.expect_end()?;
+        self.module.start_section = Some(binary::StartSection(func));
+        Ok(())
+    }
+
+    fn elem(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        field.id();
+        let mode = if field.keyword("declare") {
+            binary::ElementMode::Declarative
+        } else if let Some(mut table) = field.list("table") {
+            let table_index = self.index(&mut table, Space::Table)?;
+            table.expect_end()?;
+            binary::ElementMode::Active {
+                table: table_index,
+                offset: self.offset(&mut field)?,
+            }
+        } else if is_offset(&field) {
+            let offset = self.offset(&mut field)?;
+            // Function indices may follow the offset without `func`
+            if field.is_empty() || field.has_index() {
+                let init = binary::ElementInit::Funcs(self.func_indices(&mut field)?);
+                let mode = binary::ElementMode::Active { table: 0, offset };
+                return self.push_element(binary::Element { mode, init });
+            }
+            binary::ElementMode::Active { table: 0, offset }
+        } else {
+            binary::ElementMode::Passive
+        };
+        let init = if field.keyword("func") {
+            binary::ElementInit::Funcs(self.func_indices(&mut field)?)
+        } else {
+            let error = field.error("expected a reference type");
+            let ty::Value::Ref(ty) = self.value(&mut field)? else {
+                return Err(error);
+            };
+            let exprs = self.items(&mut field)?;
+            binary::ElementInit::Exprs { ty, exprs }
+        };
+        self.push_element(binary::Element { mode, init })
+    }
+
+    fn push_element(&mut self, element: binary::Element) -> anyhow::Result<()> {
+        let elements = self
+            .module
+            .element_section
+            .get_or_insert(binary::ElementSection(vec![]));
+        elements.0.push(element);
+        Ok(())
+    }
+
+    fn func_indices(&mut self, cursor: &mut Cursor) -> anyhow::Result<Vec<u32>> {
+        let mut funcs = vec![];
+        while !cursor.is_empty() {
+            funcs.push(self.index(cursor, Space::Func)?);
+        }
+        Ok(funcs)
+    }
+
+    /// Parses element expressions, each given as `(item instr*)` or as a
+    /// single folded instruction
+    fn items(&mut self, cursor: &mut Cursor) -> anyhow::Result<Vec<instr::Expression>> {
+        let mut exprs = vec![];
+        while !cursor.is_empty() {
+            exprs.push(match cursor.list("item") {
+                Some(mut item) => self.expr(FuncContext::default(), &mut item)?,
+                None => self.folded_expr(cursor)?,
+            });
+        }
+        Ok(exprs)
+    }
+
+    /// Parses the offset of an active segment, given as `(offset instr*)` or
+    /// as a single folded instruction
+    fn offset(&mut self, cursor: &mut Cursor) -> anyhow::Result<instr::Expression> {
+        match cursor.list("offset") {
+            Some(mut offset) => self.expr(FuncContext::default(), &mut offset),
+            None => self.folded_expr(cursor),
+        }
+    }
+
+    fn folded_expr(&mut self, cursor: &mut Cursor) -> anyhow::Result<instr::Expression> {
+        let mut context = FuncContext::default();
+        self.folded(&mut context, cursor)?;
+        context.instrs.push(instr::Instruction::End);
+        Ok(instr::Expression(context.instrs))
+    }
+
+    fn data(&mut self, mut field: Cursor) -> anyhow::Result<()> {
+        field.id();
+        let mode = if let Some(mut memory) = field.list("memory") {
+            let memory_index = self.index(&mut memory, Space::Memory)?;
+            memory.expect_end()?;
+            binary::DataMode::Active {
+                memory: memory_index,
+                offset: self.offset(&mut field)?,
+            }
+        } else if is_offset(&field) {
+            binary::DataMode::Active {
+                memory: 0,
+                offset: self.offset(&mut field)?,
+            }
+        } else {
+            binary::DataMode::Passive
+        };
+        let mut init = vec![];
+        while !field.is_empty() {
+            init.extend(field.string()?);
+        }
+        let segments = self
+            .module
+            .data_section
+            .get_or_insert(binary::DataSection(vec![]));
+        segments.0.push(binary::Data { mode, init });
+        Ok(())
+    }
+
+    /// Parses the remaining instructions as an expression
+    fn expr(
+        &mut self,
+        mut context: FuncContext,
+        cursor: &mut Cursor,
+    ) -> anyhow::Result<instr::Expression> {
+        self.instrs(&mut context, cursor)?;
+        context.instrs.push(instr::Instruction::End);
+        Ok(instr::Expression(context.instrs))
+    }
+
+    /// Parses instructions in flat or folded form up to the end of the list
+    fn instrs(&mut self, context: &mut FuncContext, cursor: &mut Cursor) -> anyhow::Result<()> {
+        let folded_depth = std::mem::replace(&mut context.folded_depth, context.labels.len());
+        while let Some(sexp) = cursor.peek() {
+            match sexp {
+                Sexp::List(..) => self.folded(context, cursor)?,
+                Sexp::Keyword(keyword, span) => {
+                    cursor.next();
+                    self.flat(context, keyword, span.start, cursor)?;
+                }
+                _ => return Err(cursor.error("expected an instruction")),
+            }
+        }
+        anyhow::ensure!(
+            context.labels.len() == context.folded_depth,
+            cursor.error("expected `end`")
+        );
+        context.folded_depth = folded_depth;
+        Ok(())
+    }
+
+    /// Parses an instruction given its keyword and the offset of the keyword
+    fn flat(
+        &mut self,
+        context: &mut FuncContext,
+        keyword: &str,
+        offset: usize,
+        cursor: &mut Cursor,
+    ) -> anyhow::Result<()> {
+        let instr = match keyword {
+            "block" | "loop" | "if" => {
+                context.labels.push(cursor.id());
+                let ty = self.block_type(cursor)?;
+                match keyword {
+                    "block" => instr::Instruction::Block(ty),
+                    "loop" => instr::Instruction::Loop(ty),
+                    _ => instr::Instruction::If(ty),
+                }
+            }
+            "else" | "end" => {
+                anyhow::ensure!(
+                    context.folded_depth < context.labels.len(),
+                    lex::error(cursor.source, offset, format!("unexpected `{keyword}`"))
+                );
+                let label = cursor.clone();
+                if let Some(id) = cursor.id() {
+                    anyhow::ensure!(
+                        context.labels.last() == Some(&Some(id.clone())),
+                        label.error(format!("mismatching label `${id}`"))
+                    );
+                }
+                if "else" == keyword {
+                    instr::Instruction::Else
+                } else {
+                    context.labels.pop();
+                    instr::Instruction::End
+                }
+            }
+            _ => self.plain(context, keyword, offset, cursor)?,
+        };
+        context.instrs.push(instr);
+        Ok(())
+    }
+
+    /// Parses the folded instruction coming next
+    fn folded(&mut self, context: &mut FuncContext, cursor: &mut Cursor) -> anyhow::Result<()> {
+        let error = cursor.error("expected a folded instruction");
+        let offset = match cursor.peek() {
+            Some(Sexp::List(items, _)) => items.first().map(Sexp::span),
+            _ => None,
+        };
+        let (keyword, mut folded) = cursor.any_list().ok_or(error)?;
+        match keyword {
+            "block" | "loop" => {
+                context.labels.push(folded.id());
+                let ty = self.block_type(&mut folded)?;
+                context.instrs.push(match keyword {
+                    "block" => instr::Instruction::Block(ty),
+                    _ => instr::Instruction::Loop(ty),
+                });
+                self.instrs(context, &mut folded)?;
+            }
+            "if" => {
+                let label = folded.id();
+                let ty = self.block_type(&mut folded)?;
+                // Folded instructions before `then` compute the condition
+                while !folded.is_empty() && !folded.peek_list("then") {
+                    self.folded(context, &mut folded)?;
+                }
+                context.instrs.push(instr::Instruction::If(ty));
+                context.labels.push(label);
+                let mut then = folded.expect_list("then")?;
+                self.instrs(context, &mut then)?;
+                if let Some(mut alternative) = folded.list("else") {
+                    context.instrs.push(instr::Instruction::Else);
+                    self.instrs(context, &mut alternative)?;
+                }
+                folded.expect_end()?;
+            }
+            _ => {
+                let offset = offset.unwrap().start;
+                let instr = self.plain(context, keyword, offset, &mut folded)?;
+                while !folded.is_empty() {
+                    self.folded(context, &mut folded)?;
+                }
+                context.instrs.push(instr);
+                return Ok(());
+            }
+        }
+        context.labels.pop();
+        context.instrs.push(instr::Instruction::End);
+        Ok(())
+    }
+
+    fn label(&self, context: &FuncContext, cursor: &mut Cursor) -> anyhow::Result<u32> {
+        let Some(Sexp::Id(id, _)) = cursor.peek() else {
+            return cursor.u32();
+        };
+        let mut labels = context.labels.iter().rev();
+        let depth = labels.position(|label| label.as_ref() == Some(id));
+        let depth = depth.ok_or_else(|| cursor.error(format!("unknown label `${id}`")))?;
+        cursor.next();
+        Ok(depth as u32)
+    }
+
+    fn mem_arg(
+        &self,
+        cursor: &mut Cursor,
+        natural_alignment: u32,
+    ) -> anyhow::Result<instr::MemArg> {
+        let memory = match cursor.has_index() {
+            true => self.index(cursor, Space::Memory)?,
+            false => 0,
+        };
+        let mut offset = 0;
+        if let Some(text) = cursor
+            .peek_keyword()
+            .and_then(|k| k.strip_prefix("offset="))
+        {
+            let error = cursor.error(format!("malformed offset `{text}`"));
+            let (_, int) = int(text)
+                .filter(|(is_negative, _)| !is_negative)
+                .ok_or(error)?;
+            offset = int
+                .try_into()
+                .map_err(|_| cursor.error("offset out of range"))?;
+            cursor.next();
+        }
+        let mut align = natural_alignment;
+        if let Some(text) = cursor.peek_keyword().and_then(|k| k.strip_prefix("align=")) {
+            let error = cursor.error("alignment must be a power of two");
+            let int = int(text).filter(|(is_negative, int)| !is_negative && int.is_power_of_two());
+            align = int.ok_or(error)?.1.trailing_zeros();
+            cursor.next();
+        }
+        Ok(instr::MemArg {
+            align,
+            offset,
+            memory,
+        })
+    }
+
+    /// Parses an instruction other than block instructions along with its
+    /// immediates
+    fn plain(
+        &mut self,
+        context: &FuncContext,
+        keyword: &str,
+        offset: usize,
+        cursor: &mut Cursor,
+    ) -> anyhow::Result<instr::Instruction> {
+        use instr::Instruction::*;
+        if let Some(instr) = plain(keyword) {
+            return Ok(instr);
+        }
+        let local = |cursor: &mut Cursor| index(cursor, &context.locals, "local");
+        let memory = |parser: &Self, cursor: &mut Cursor| match cursor.has_index() {
+            true => parser.index(cursor, Space::Memory),
+            false => Ok(0),
+        };
+        let table = |parser: &Self, cursor: &mut Cursor| match cursor.has_index() {
+            true => parser.index(cursor, Space::Table),
+            false => Ok(0),
+        };
+        // The segment index of `table.init` and `memory.init` may be preceded
+        // by a table or memory index
+        let has_two_indices = |cursor: &Cursor| {
+            let mut ahead = cursor.clone();
+            ahead.next();
+            ahead.has_index()
+        };
+        // `table.copy` and `memory.copy` take both indices or neither
+        let copy = |parser: &Self, cursor: &mut Cursor, space: Space| {
+            if !cursor.has_index() {
+                return Ok((0, 0));
+            }
+            let dst = parser.index(cursor, space)?;
+            let error = cursor.error(format!("expected a second {} index", space.name()));
+            anyhow::ensure!(cursor.has_index(), error);
+            Ok((dst, parser.index(cursor, space)?))
+        };
+        Ok(match keyword {
+            "br" => Br(self.label(context, cursor)?),
+            "br_if" => BrIf(self.label(context, cursor)?),
+            "br_table" => {
+                let mut labels = vec![self.label(context, cursor)?];
+                while cursor.has_index() {
+                    labels.push(self.label(context, cursor)?);
+                }
+                let default = labels.pop().unwrap();
+                BrTable(labels, default)
+            }
+            "call" => Call(self.index(cursor, Space::Func)?),
+            "call_indirect" => {
+                let table = match cursor.has_index() {
+                    true => self.index(cursor, Space::Table)?,
+                    false => 0,
+                };
+                let (ty, _) = self.type_use(cursor)?;
+                CallIndirect(ty, table)
+            }
+            "ref.null" => RefNull(self.heap(cursor)?),
+            "ref.func" => RefFunc(self.index(cursor, Space::Func)?),
+            "select" if cursor.peek_list("result") => SelectTyped(self.results(cursor)?),
+            "select" => Select,
+            "local.get" => LocalGet(local(cursor)?),
+            "local.set" => LocalSet(local(cursor)?),
+            "local.tee" => LocalTee(local(cursor)?),
+            "global.get" => GlobalGet(self.index(cursor, Space::Global)?),
+            "global.set" => GlobalSet(self.index(cursor, Space::Global)?),
+            "table.get" => TableGet(table(self, cursor)?),
+            "table.set" => TableSet(table(self, cursor)?),
+            "table.init" => {
+                let table = match has_two_indices(cursor) {
+                    true => table(self, cursor)?,
+                    false => 0,
+                };
+                TableInit(self.index(cursor, Space::Elem)?, table)
+            }
+            "elem.drop" => ElemDrop(self.index(cursor, Space::Elem)?),
+            "table.copy" => {
+                let (dst, src) = copy(self, cursor, Space::Table)?;
+                TableCopy(dst, src)
+            }
+            "table.grow" => TableGrow(table(self, cursor)?),
+            "table.size" => TableSize(table(self, cursor)?),
+            "table.fill" => TableFill(table(self, cursor)?),
+            "memory.size" => MemorySize(memory(self, cursor)?),
+            "memory.grow" => MemoryGrow(memory(self, cursor)?),
+            "memory.init" => {
+                let memory = match has_two_indices(cursor) {
+                    true => memory(self, cursor)?,
+                    false => 0,
+                };
+                MemoryInit(self.index(cursor, Space::Data)?, memory)
+            }
+            "data.drop" => DataDrop(self.index(cursor, Space::Data)?),
+            "memory.copy" => {
+                let (dst, src) = copy(self, cursor, Space::Memory)?;
+                MemoryCopy(dst, src)
+            }
+            "memory.fill" => MemoryFill(memory(self, cursor)?),
+            "i32.const" => I32Const(cursor.int::<32>()? as i32),
+            "i64.const" => I64Const(cursor.int::<64>()?),
+            "f32.const" => F32Const(cursor.float(F32)? as u32),
+            "f64.const" => F64Const(cursor.float(F64)?),
+            _ => {
+                let message = format!("unknown instruction `{keyword}`");
+                let error = lex::error(cursor.source, offset, message);
+                let instr = mem_instr(keyword).ok_or(error)?;
+                let natural_alignment = instr(instr::MemArg {
+                    align: 0,
+                    offset: 0,
+                    memory: 0,
+                })
+                .natural_alignment()
+                .unwrap();
+                instr(self.mem_arg(cursor, natural_alignment)?)
+            }
+        })
+    }
+}
+
+/// Size of a page of linear memory in bytes
+const PAGE_SIZE: usize = 0x1_0000;
+
+/// Returns the offset expression of segments given inline in tables and
+/// memories
+fn zero_offset(address: &ty::Address) -> instr::Expression {
+    let zero = match address {
+        ty::Address::I32 => instr::Instruction::I32Const(0),
+        ty::Address::I64 => instr::Instruction::I64Const(0),
+    };
+    instr::Expression(vec![zero, instr::Instruction::End])
+}
+
+/// Checks if the offset of an active segment comes next, as opposed to the
+/// reference type of a passive segment
+fn is_offset(cursor: &Cursor) -> bool {
+    match cursor.peek() {
+        Some(sexp @ Sexp::List(..)) => sexp.list("ref").is_none() && sexp.list("item").is_none(),
+        _ => false,
+    }
+}
+
+fn index(cursor: &mut Cursor, ids: &HashMap<String, u32>, name: &str) -> anyhow::Result<u32> {
+    match cursor.peek() {
+        Some(Sexp::Id(id, _)) => {
+            let index = ids.get(id).copied();
+            let index = index.ok_or_else(|| cursor.error(format!("unknown {name} `${id}`")))?;
+            cursor.next();
+            Ok(index)
+        }
+        Some(Sexp::Reserved(..)) => cursor.u32(),
+        _ => Err(cursor.error(format!("expected a {name} index"))),
+    }
+}
+
+/// Returns the instruction without immediates named by the keyword
+fn plain(keyword: &str) -> Option<instr::Instruction> {
+    use instr::Instruction::*;
+    Some(match keyword {
+        "unreachable" => Unreachable,
+        "nop" => Nop,
+        "return" => Return,
+        "ref.is_null" => RefIsNull,
+        "drop" => Drop,
+        "i32.eqz" => I32Eqz,
+        "i32.eq" => I32Eq,
+        "i32.ne" => I32Ne,
+        "i32.lt_s" => I32LtS,
+        "i32.lt_u" => I32LtU,
+        "i32.gt_s" => I32GtS,
+        "i32.gt_u" => I32GtU,
+        "i32.le_s" => I32LeS,
+        "i32.le_u" => I32LeU,
+        "i32.ge_s" => I32GeS,
+        "i32.ge_u" => I32GeU,
+        "i64.eqz" => I64Eqz,
+        "i64.eq" => I64Eq,
+        "i64.ne" => I64Ne,
+        "i64.lt_s" => I64LtS,
+        "i64.lt_u" => I64LtU,
+        "i64.gt_s" => I64GtS,
+        "i64.gt_u" => I64GtU,
+        "i64.le_s" => I64LeS,
+        "i64.le_u" => I64LeU,
+        "i64.ge_s" => I64GeS,
+        "i64.ge_u" => I64GeU,
+        "f32.eq" => F32Eq,
+        "f32.ne" => F32Ne,
+        "f32.lt" => F32Lt,
+        "f32.gt" => F32Gt,
+        "f32.le" => F32Le,
+        "f32.ge" => F32Ge,
+        "f64.eq" => F64Eq,
+        "f64.ne" => F64Ne,
+        "f64.lt" => F64Lt,
+        "f64.gt" => F64Gt,
+        "f64.le" => F64Le,
+        "f64.ge" => F64Ge,
+        "i32.clz" => I32Clz,
+        "i32.ctz" => I32Ctz,
+        "i32.popcnt" => I32Popcnt,
+        "i32.add" => I32Add,
+        "i32.sub" => I32Sub,
+        "i32.mul" => I32Mul,
+        "i32.div_s" => I32DivS,
+        "i32.div_u" => I32DivU,
+        "i32.rem_s" => I32RemS,
+        "i32.rem_u" => I32RemU,
+        "i32.and" => I32And,
+        "i32.or" => I32Or,
+        "i32.xor" => I32Xor,
+        "i32.shl" => I32Shl,
+        "i32.shr_s" => I32ShrS,
+        "i32.shr_u" => I32ShrU,
+        "i32.rotl" => I32Rotl,
+        "i32.rotr" => I32Rotr,
+        "i64.clz" => I64Clz,
+        "i64.ctz" => I64Ctz,
+        "i64.popcnt" => I64Popcnt,
+        "i64.add" => I64Add,
+        "i64.sub" => I64Sub,
+        "i64.mul" => I64Mul,
+        "i64.div_s" => I64DivS,
+        "i64.div_u" => I64DivU,
+        "i64.rem_s" => I64RemS,
+        "i64.rem_u" => I64RemU,
+        "i64.and" => I64And,
+        "i64.or" => I64Or,
+        "i64.xor" => I64Xor,
+        "i64.shl" => I64Shl,
+        "i64.shr_s" => I64ShrS,
+        "i64.shr_u" => I64ShrU,
+        "i64.rotl" => I64Rotl,
+        "i64.rotr" => I64Rotr,
+        "f32.abs" => F32Abs,
+        "f32.neg" => F32Neg,
+        "f32.ceil" => F32Ceil,
+        "f32.floor" => F32Floor,
+        "f32.trunc" => F32Trunc,
+        "f32.nearest" => F32Nearest,
+        "f32.sqrt" => F32Sqrt,
+        "f32.add" => F32Add,
+        "f32.sub" => F32Sub,
+        "f32.mul" => F32Mul,
+        "f32.div" => F32Div,
+        "f32.min" => F32Min,
+        "f32.max" => F32Max,
+        "f32.copysign" => F32Copysign,
+        "f64.abs" => F64Abs,
+        "f64.neg" => F64Neg,
+        "f64.ceil" => F64Ceil,
+        "f64.floor" => F64Floor,
+        "f64.trunc" => F64Trunc,
+        "f64.nearest" => F64Nearest,
+        "f64.sqrt" => F64Sqrt,
+        "f64.add" => F64Add,
+        "f64.sub" => F64Sub,
+        "f64.mul" => F64Mul,
+        "f64.div" => F64Div,
+        "f64.min" => F64Min,
+        "f64.max" => F64Max,
+        "f64.copysign" => F64Copysign,
+        "i32.wrap_i64" => I32WrapI64,
+        "i32.trunc_f32_s" => I32TruncF32S,
+        "i32.trunc_f32_u" => I32TruncF32U,
+        "i32.trunc_f64_s" => I32TruncF64S,
+        "i32.trunc_f64_u" => I32TruncF64U,
+        "i64.extend_i32_s" => I64ExtendI32S,
+        "i64.extend_i32_u" => I64ExtendI32U,
+        "i64.trunc_f32_s" => I64TruncF32S,
+        "i64.trunc_f32_u" => I64TruncF32U,
+        "i64.trunc_f64_s" => I64TruncF64S,
+        "i64.trunc_f64_u" => I64TruncF64U,
+        "f32.convert_i32_s" => F32ConvertI32S,
+        "f32.convert_i32_u" => F32ConvertI32U,
+        "f32.convert_i64_s" => F32ConvertI64S,
+        "f32.convert_i64_u" => F32ConvertI64U,
+        "f32.demote_f64" => F32DemoteF64,
+        "f64.convert_i32_s" => F64ConvertI32S,
+        "f64.convert_i32_u" => F64ConvertI32U,
+        "f64.convert_i64_s" => F64ConvertI64S,
+        "f64.convert_i64_u" => F64ConvertI64U,
+        "f64.promote_f32" => F64PromoteF32,
+        "i32.reinterpret_f32" => I32ReinterpretF32,
+        "i64.reinterpret_f64" => I64ReinterpretF64,
+        "f32.reinterpret_i32" => F32ReinterpretI32,
+        "f64.reinterpret_i64" => F64ReinterpretI64,
+        "i32.extend8_s" => I32Extend8S,
+        "i32.extend16_s" => I32Extend16S,
+        "i64.extend8_s" => I64Extend8S,
+        "i64.extend16_s" => I64Extend16S,
+        "i64.extend32_s" => I64Extend32S,
+        "i32.trunc_sat_f32_s" => I32TruncSatF32S,
+        "i32.trunc_sat_f32_u" => I32TruncSatF32U,
+        "i32.trunc_sat_f64_s" => I32TruncSatF64S,
+        "i32.trunc_sat_f64_u" => I32TruncSatF64U,
+        "i64.trunc_sat_f32_s" => I64TruncSatF32S,
+        "i64.trunc_sat_f32_u" => I64TruncSatF32U,
+        "i64.trunc_sat_f64_s" => I64TruncSatF64S,
+        "i64.trunc_sat_f64_u" => I64TruncSatF64U,
+        _ => return None,
+    })
+}
+
+/// Returns the constructor of the memory instruction named by the keyword
+fn mem_instr(keyword: &str) -> Option<fn(instr::MemArg) -> instr::Instruction> {
+    use instr::Instruction::*;
+    let instr: fn(instr::MemArg) -> instr::Instruction = match keyword {
+        "i32.load" => I32Load,
+        "i64.load" => I64Load,
+        "f32.load" => F32Load,
+        "f64.load" => F64Load,
+        "i32.load8_s" => I32Load8S,
+        "i32.load8_u" => I32Load8U,
+        "i32.load16_s" => I32Load16S,
+        "i32.load16_u" => I32Load16U,
+        "i64.load8_s" => I64Load8S,
+        "i64.load8_u" => I64Load8U,
+        "i64.load16_s" => I64Load16S,
+        "i64.load16_u" => I64Load16U,
+        "i64.load32_s" => I64Load32S,
+        "i64.load32_u" => I64Load32U,
+        "i32.store" => I32Store,
+        "i64.store" => I64Store,
+        "f32.store" => F32Store,
+        "f64.store" => F64Store,
+        "i32.store8" => I32Store8,
+        "i32.store16" => I32Store16,
+        "i64.store8" => I64Store8,
+        "i64.store16" => I64Store16,
+        "i64.store32" => I64Store32,
+        _ => return None,
+    };
+    Some(instr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary::Module;
+
+    #[test]
+    fn parse_like_wat() -> anyhow::Result<()> {
+        let fixtures = [
+            "(module)",
+            "(module $m (func))",
+            "(func (param i32 i64)) (memory 1)",
+            "(module (func (param $a i32) (param i32) (local $b f32) (local f64 f64 v128)))",
+            "(module (func (param i32 i32) (result i32) local.get 0 local.get 1 i32.add))",
+            "(module (type (func (param f32 f64 v128) (result externref funcref))))",
+            "(module (type (array (mut i8))) (type $s (struct (field $x i16) (field i32 i64))))",
+            "(module (type (struct (field (ref null exn) (ref none) (ref noextern)))))",
+            "(module (type $t (sub (struct))) (type (sub final $t (struct))))",
+            "(module (rec (type $A (struct (field (mut (ref null $B))))) (type $B (array (ref $A)))))",
+            "(module (type $f (func (param i32))) (func (type $f)) (func (param i32)) (func (result i32) i32.const 0))",
+            r#"(module (import "env" "f" (func $f (param $x i32))) (func $g (export "g") (export "h") call $f))"#,
+            r#"(module (func $f (import "env" "f")) (memory (import "env" "m") i64 1 2) (global (import "env" "g") (mut f32)))"#,
+            r#"(module (import "env" "t" (table $t 1 funcref)) (table (export "u") (import "env" "u") 2 3 externref))"#,
+            r#"(module (memory $m (export "m") 1 2) (global $g i64 (i64.const -1)) (export "g" (global $g)) (export "n" (memory $m)))"#,
+            "(module (memory 1) (func (param i32) (result i64) (i64.load offset=8 align=4 (local.get 0))))",
+            "(module (memory 1) (func i32.const 0 i32.const 0 i32.store16 offset=0x10 memory.size memory.grow drop))",
+            "(module (func (block (loop (br_table 0 1 (i32.const 3)))) (if (i32.const 1) (then) (else))))",
+            "(module (func $f (result i32) (block $a (result i32) (block $b (br_if $a (i32.const 1) (i32.const 2)) (br $b)) (i32.const 3))))",
+            "(module (func $f (block $a block $b br $a end $b loop $c br 1 end if $d (param) else end $d)))",
+            "(module (func (param i32) (result i32 i32) (block (param i32) (result i32 i32) (i32.const 0)) (if (result i32 i32) (local.get 0) (then (i32.const 1) (i32.const 2)) (else unreachable))))",
+            "(module (type (func (param i32))) (func (call_indirect (param i32) (i32.const 0) (i32.const 1))) (func (call_indirect 0 (type 0) (i32.const 0) (i32.const 1))))",
+            "(module (func (drop (ref.null func)) (drop (ref.func 0)) (drop (select (result i64) (i64.const 1) (i64.const 2) (i32.const 0)))))",
+            "(module (func i32.const -0x8000_0000 drop i32.const 0xffff_ffff drop i64.const -9223372036854775808 drop i64.const 18446744073709551615 drop))",
+            "(module (func f32.const 0x1p-149 drop f32.const 0x1.fffffep127 drop f32.const -nan:0x1 drop f32.const +inf drop f32.const 1e-45 drop))",
+            "(module (func f64.const 0x1.fffffffffffff8p0 drop f64.const 0x0.0000000000001p-1022 drop f64.const 1_000.25e-2 drop f64.const nan drop))",
+            "(module (func f32.const 0x1.000001p0 drop f32.const 0x1.000003p0 drop f32.const 0x1.0000010000001p0 drop))",
+            "(module (table $t (export \"t\") 1 2 funcref) (table i64 1 externref) (func $f) (start $f))",
+            "(module (func $f) (table $t funcref (elem $f $f)) (table i64 externref (elem (ref.null extern))))",
+            "(module (table $t 1 funcref) (func $f) (elem (i32.const 0) $f) (elem (table $t) (offset (i32.const 1)) func $f))",
+            "(module (func $f) (elem $e func $f) (elem declare func $f) (elem funcref (ref.func $f) (item ref.null func)))",
+            "(module (func $f) (table 1 1 funcref) (elem (table 0) (i32.const 0) (ref func) (ref.func $f)))",
+            r#"(module (memory $m 1) (memory $n (data "ab" "c")) (data (i32.const 1) "x") (data $d "y") (data (memory $n) (offset i32.const 2)))"#,
+            r#"(module (memory i64 (data "\00")) (global $g i32 (i32.const 0)) (data (global.get $g)))"#,
+            r#"(module (memory $m 1) (memory $n 1) (data $d "a") (func (memory.init $d (i32.const 0) (i32.const 0) (i32.const 1)) (memory.init $n $d (i32.const 0) (i32.const 0) (i32.const 1)) (data.drop $d)))"#,
+            "(module (memory 1) (memory $n 1) (func (memory.copy (i32.const 0) (i32.const 1) (i32.const 2)) (memory.copy $n 0 (i32.const 0) (i32.const 1) (i32.const 2)) (memory.fill $n (i32.const 0) (i32.const 1) (i32.const 2))))",
+            "(module (table $t 1 funcref) (table $u 1 funcref) (elem $e func) (func (table.init $e (i32.const 0) (i32.const 0) (i32.const 0)) (table.init $u $e (i32.const 0) (i32.const 0) (i32.const 0)) (elem.drop $e)))",
+            "(module (table $t 1 funcref) (table $u 1 funcref) (func (table.copy (i32.const 0) (i32.const 0) (i32.const 0)) (table.copy $u $t (i32.const 0) (i32.const 0) (i32.const 0))))",
+            "(module (table $t 1 externref) (func (drop (table.grow $t (ref.null extern) (i32.const 1))) (table.fill (i32.const 0) (ref.null extern) (table.size))))",
+            "(module (table $t 1 externref) (func (table.set (i32.const 0) (table.get $t (i32.const 0)))))",
+        ];
+        for fixture in fixtures {
+            let expected = Module::decode(wat::parse_str(fixture)?)?;
+            assert_eq!(expected, parse(fixture)?, "{fixture}");
+        }
+        let errors = [
+            "(module (memory 1) (func (memory.copy 0 (i32.const 0) (i32.const 0) (i32.const 0))))",
+            "(module (table 1 funcref) (func (table.copy 0 (i32.const 0) (i32.const 0) (i32.const 0))))",
+            "(module (type (struct (field $x i32) (field $x i64))))",
+        ];
+        for fixture in errors {
+            assert!(wat::parse_str(fixture).is_err(), "{fixture}");
+            assert!(parse(fixture).is_err(), "{fixture}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        let message = |source| parse(source).unwrap_err().to_string();
+        assert_eq!(
+            "2:15: unknown function `$g`",
+            message("(module\n  (func (call $g)))"),
+        );
+        assert_eq!(
+            "1:23: unknown instruction `i32.foo`",
+            message("(module (func i32.add i32.foo))"),
+        );
+        assert_eq!(
+            "1:25: constant out of range",
+            message("(module (func i32.const 0x1_0000_0000))"),
+        );
+        assert_eq!(
+            "1:16: import after definition",
+            message(r#"(module (func) (import "a" "b" (func)))"#),
+        );
+        assert_eq!(
+            "1:25: unknown label `$l`",
+            message("(module (func (block br $l)))")
+        );
+        assert_eq!(
+            "1:28: expected `end`",
+            message("(module (func (block) block))")
+        );
+        assert_eq!(
+            "1:22: unexpected `end`",
+            message("(module (func (block end)))")
+        );
+        assert_eq!(
+            "1:33: multiple start functions",
+            message("(module (func) (start 0) (start 0))")
+        );
+        assert_eq!(
+            "1:41: expected a second memory index",
+            message("(module (memory 1) (func (memory.copy 0 (i32.const 0))))")
+        );
+        assert_eq!(
+            "1:45: duplicate field `$x`",
+            message("(module (type (struct (field $x i32) (field $x i64))))")
+        );
+    }
+}
@@ -0,0 +1,1236 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::binary::{self, instr, ty};
+
+impl fmt::Display for binary::Module {
+    /// Prints the module in the text format, with folded instructions if the
+    /// alternate flag is specified as `{:#}`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printer = Printer::new(self, f.alternate());
+        let mut lines = Lines::default();
+        printer.module(&mut lines);
+        for (indent, line) in lines.0 {
+            writeln!(f, "{:width$}{line}", "", width = indent * 2)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lines of text along with their indentation levels
+#[derive(Default)]
+struct Lines(Vec<(usize, String)>);
+
+impl Lines {
+    fn push(&mut self, indent: usize, line: impl Into<String>) {
+        self.0.push((indent, line.into()));
+    }
+
+    /// Closes the parenthesis opened by a preceding line
+    fn close(&mut self) {
+        if let Some((_, line)) = self.0.last_mut() {
+            line.push(')');
+        }
+    }
+}
+
+/// Symbolic identifiers from the name section, limited to names that are
+/// valid and unique identifiers
+#[derive(Default)]
+struct Names {
+    module: Option<String>,
+    funcs: HashMap<u32, String>,
+    locals: HashMap<u32, HashMap<u32, String>>,
+    types: HashMap<u32, String>,
+    tables: HashMap<u32, String>,
+    memories: HashMap<u32, String>,
+    globals: HashMap<u32, String>,
+    fields: HashMap<u32, HashMap<u32, String>>,
+}
+
+impl Names {
+    fn new(section: &binary::NameSection) -> Self {
+        fn map(namings: &[binary::Naming]) -> HashMap<u32, String> {
+            let mut ids = HashMap::new();
+            let mut seen = std::collections::HashSet::new();
+            for naming in namings {
+                if is_id(&naming.name) && seen.insert(&naming.name) {
+                    ids.insert(naming.index, format!("${}", naming.name));
+                }
+            }
+            ids
+        }
+        fn indirect_map(namings: &[binary::IndirectNaming]) -> HashMap<u32, HashMap<u32, String>> {
+            namings
+                .iter()
+                .map(|naming| (naming.index, map(&naming.names)))
+                .collect()
+        }
+        Self {
+            module: section
+                .module
+                .as_ref()
+                .filter(|name| is_id(name))
+                .map(|name| format!("${name}")),
+            funcs: map(&section.funcs),
+            locals: indirect_map(&section.locals),
+            types: map(&section.types),
+            tables: map(&section.tables),
+            memories: map(&section.memories),
+            globals: map(&section.globals),
+            fields: indirect_map(&section.fields),
+        }
+    }
+}
+
+fn is_id(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c))
+}
+
+/// Refers to an item by its identifier if any, or by its index otherwise
+fn index(names: &HashMap<u32, String>, index: u32) -> String {
+    names
+        .get(&index)
+        .cloned()
+        .unwrap_or_else(|| index.to_string())
+}
+
+/// Declares an identifier for an item if it has one
+fn id(names: &HashMap<u32, String>, index: u32) -> String {
+    names
+        .get(&index)
+        .map(|id| format!(" {id}"))
+        .unwrap_or_default()
+}
+
+fn string(s: impl AsRef<[u8]>) -> String {
+    let mut quoted = String::from('"');
+    for &byte in s.as_ref() {
+        match byte {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            0x20..0x7f => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\{byte:02x}")),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn f32(bits: u32) -> String {
+    let value = f32::from_bits(bits);
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    if value.is_nan() {
+        format!("{sign}nan:0x{:x}", bits & 0x007f_ffff)
+    } else if value.is_infinite() {
+        format!("{sign}inf")
+    } else {
+        format!("{value:e}")
+    }
+}
+
+fn f64(bits: u64) -> String {
+    let value = f64::from_bits(bits);
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    if value.is_nan() {
+        format!("{sign}nan:0x{:x}", bits & 0x000f_ffff_ffff_ffff)
+    } else if value.is_infinite() {
+        format!("{sign}inf")
+    } else {
+        format!("{value:e}")
+    }
+}
+
+/// Instructions with their operands folded in, if they're in folded form
+enum Node<'a> {
+    Plain {
+        instr: &'a instr::Instruction,
+        operands: Vec<Node<'a>>,
+    },
+    Block {
+        instr: &'a instr::Instruction,
+        operands: Vec<Node<'a>>,
+        body: Vec<Node<'a>>,
+        alternative: Option<Vec<Node<'a>>>,
+    },
+}
+
+struct Printer<'a> {
+    module: &'a binary::Module,
+    is_folded: bool,
+    names: Names,
+    types: Vec<&'a ty::Sub>,
+    func_types: Vec<u32>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(module: &'a binary::Module, is_folded: bool) -> Self {
+        let types = module
+            .type_section
+            .iter()
+            .flat_map(|section| &section.0)
+            .flat_map(|rec| &rec.0)
+            .collect();
+        let imported_func_types = module
+            .import_section
+            .iter()
+            .flat_map(|section| &section.0)
+            .filter_map(|import| match import.ty {
+                ty::External::Func(ty) => Some(ty),
+                _ => None,
+            });
+        let defined_func_types = module
+            .func_section
+            .iter()
+            .flat_map(|section| section.0.iter().copied());
+        Self {
+            module,
+            is_folded,
+            names: module
+                .name_section
+                .as_ref()
+                .map(Names::new)
+                .unwrap_or_default(),
+            types,
+            func_types: imported_func_types.chain(defined_func_types).collect(),
+        }
+    }
+
+    fn module(&self, lines: &mut Lines) {
+        match &self.names.module {
+            Some(id) => lines.push(0, format!("(module {id}")),
+            None => lines.push(0, "(module"),
+        }
+        let module = self.module;
+        let mut type_index = 0;
+        for rec in module.type_section.iter().flat_map(|section| &section.0) {
+            if let [sub] = rec.0.as_slice() {
+                lines.push(1, self.type_def(type_index, sub));
+                type_index += 1;
+                continue;
+            }
+            lines.push(1, "(rec");
+            for sub in &rec.0 {
+                lines.push(2, self.type_def(type_index, sub));
+                type_index += 1;
+            }
+            lines.close();
+        }
+        let mut counts = [0; 5];
+        for import in module.import_section.iter().flat_map(|section| &section.0) {
+            let desc = match &import.ty {
+                ty::External::Func(ty) => {
+                    counts[0] += 1;
+                    let id = id(&self.names.funcs, counts[0] - 1);
+                    format!("(func{id} (type {}))", index(&self.names.types, *ty))
+                }
+                ty::External::Table(table) => {
+                    counts[1] += 1;
+                    let id = id(&self.names.tables, counts[1] - 1);
+                    format!("(table{id} {})", self.table(table))
+                }
+                ty::External::Memory(memory) => {
+                    counts[2] += 1;
+                    let id = id(&self.names.memories, counts[2] - 1);
+                    format!("(memory{id} {})", self.limit(&memory.0))
+                }
+                ty::External::Global(global) => {
+                    counts[3] += 1;
+                    let id = id(&self.names.globals, counts[3] - 1);
+                    format!("(global{id} {})", self.global_type(global))
+                }
+                ty::External::Tag(tag) => {
+                    counts[4] += 1;
+                    format!("(tag (type {}))", index(&self.names.types, tag.0))
+                }
+            };
+            lines.push(
+                1,
+                format!(
+                    "(import {} {} {desc})",
+                    string(&import.module),
+                    string(&import.name)
+                ),
+            );
+        }
+        let funcs = module.func_section.iter().flat_map(|section| &section.0);
+        let codes = module.code_section.iter().flat_map(|section| &section.0);
+        for (i, (ty, code)) in funcs.zip(codes).enumerate() {
+            self.func(lines, counts[0] + i as u32, *ty, code);
+        }
+        for (i, table) in module
+            .table_section
+            .iter()
+            .flat_map(|section| &section.0)
+            .enumerate()
+        {
+            let id = id(&self.names.tables, counts[1] + i as u32);
+            lines.push(1, format!("(table{id} {})", self.table(table)));
+        }
+        for (i, memory) in module
+            .memory_section
+            .iter()
+            .flat_map(|section| &section.0)
+            .enumerate()
+        {
+            let id = id(&self.names.memories, counts[2] + i as u32);
+            lines.push(1, format!("(memory{id} {})", self.limit(&memory.0)));
+        }
+        for (i, global) in module
+            .global_section
+            .iter()
+            .flat_map(|section| &section.0)
+            .enumerate()
+        {
+            let id = id(&self.names.globals, counts[3] + i as u32);
+            lines.push(1, format!("(global{id} {}", self.global_type(&global.ty)));
+            self.expr(lines, 2, None, &global.init);
+            lines.close();
+        }
+        for export in module.export_section.iter().flat_map(|section| &section.0) {
+            let desc = match export.index {
+                binary::ExternalIndex::Func(i) => format!("func {}", index(&self.names.funcs, i)),
+                binary::ExternalIndex::Table(i) => {
+                    format!("table {}", index(&self.names.tables, i))
+                }
+                binary::ExternalIndex::Memory(i) => {
+                    format!("memory {}", index(&self.names.memories, i))
+                }
+                binary::ExternalIndex::Global(i) => {
+                    format!("global {}", index(&self.names.globals, i))
+                }
+                binary::ExternalIndex::Tag(i) => format!("tag {i}"),
+            };
+            lines.push(1, format!("(export {} ({desc}))", string(&export.name)));
+        }
+        if let Some(start) = &module.start_section {
+            lines.push(1, format!("(start {})", index(&self.names.funcs, start.0)));
+        }
+        for element in module.element_section.iter().flat_map(|section| &section.0) {
+            self.element(lines, element);
+        }
+        for data in module.data_section.iter().flat_map(|section| &section.0) {
+            match &data.mode {
+                binary::DataMode::Passive => lines.push(1, "(data"),
+                binary::DataMode::Active { memory, offset } => {
+                    lines.push(
+                        1,
+                        format!("(data (memory {})", index(&self.names.memories, *memory)),
+                    );
+                    self.offset(lines, offset);
+                }
+            }
+            lines.push(2, format!("{})", string(&data.init)));
+        }
+        lines.close();
+    }
+
+    fn element(&self, lines: &mut Lines, element: &binary::Element) {
+        match &element.mode {
+            binary::ElementMode::Passive => lines.push(1, "(elem"),
+            binary::ElementMode::Active { table, offset } => {
+                lines.push(
+                    1,
+                    format!("(elem (table {})", index(&self.names.tables, *table)),
+                );
+                self.offset(lines, offset);
+            }
+            binary::ElementMode::Declarative => lines.push(1, "(elem declare"),
+        }
+        match &element.init {
+            binary::ElementInit::Funcs(funcs) => {
+                let funcs: Vec<_> = funcs.iter().map(|&i| index(&self.names.funcs, i)).collect();
+                lines.push(2, format!("func {}", funcs.join(" ")).trim_end());
+            }
+            binary::ElementInit::Exprs { ty, exprs } => {
+                lines.push(2, self.reference(ty));
+                for expr in exprs {
+                    lines.push(2, "(item");
+                    self.expr(lines, 3, None, expr);
+                    lines.close();
+                }
+            }
+        }
+        lines.close();
+    }
+
+    /// Prints the offset expression of an active segment
+    fn offset(&self, lines: &mut Lines, offset: &instr::Expression) {
+        lines.push(2, "(offset");
+        self.expr(lines, 3, None, offset);
+        lines.close();
+    }
+
+    fn type_def(&self, type_index: u32, sub: &ty::Sub) -> String {
+        let id = id(&self.names.types, type_index);
+        let composite = self.composite(type_index, &sub.composite);
+        if sub.is_final && sub.supers.is_empty() {
+            return format!("(type{id} {composite})");
+        }
+        let mut def = String::from("(sub");
+        if sub.is_final {
+            def.push_str(" final");
+        }
+        for super_index in &sub.supers {
+            def.push(' ');
+            def.push_str(&index(&self.names.types, *super_index));
+        }
+        format!("(type{id} {def} {composite}))")
+    }
+
+    fn composite(&self, type_index: u32, composite: &ty::Composite) -> String {
+        match composite {
+            ty::Composite::Array(field) => format!("(array {})", self.field_type(field)),
+            ty::Composite::Struct(fields) => {
+                let names = self.names.fields.get(&type_index);
+                let mut s = String::from("(struct");
+                for (i, field) in fields.iter().enumerate() {
+                    let id = names.map(|names| id(names, i as u32)).unwrap_or_default();
+                    s.push_str(&format!(" (field{id} {})", self.field_type(field)));
+                }
+                s.push(')');
+                s
+            }
+            ty::Composite::Func { params, returns } => {
+                format!("(func{})", self.func_type(params, returns))
+            }
+        }
+    }
+
+    fn func_type(&self, params: &[ty::Value], returns: &[ty::Value]) -> String {
+        let mut s = String::new();
+        if !params.is_empty() {
+            s.push_str(&format!(" (param {})", self.values(params)));
+        }
+        if !returns.is_empty() {
+            s.push_str(&format!(" (result {})", self.values(returns)));
+        }
+        s
+    }
+
+    fn field_type(&self, field: &ty::Field) -> String {
+        let storage = match &field.storage {
+            ty::Storage::Value(value) => self.value(value),
+            ty::Storage::Pack(ty::Pack::I8) => "i8".to_owned(),
+            ty::Storage::Pack(ty::Pack::I16) => "i16".to_owned(),
+        };
+        if field.is_mutable {
+            format!("(mut {storage})")
+        } else {
+            storage
+        }
+    }
+
+    fn values(&self, values: &[ty::Value]) -> String {
+        let values: Vec<_> = values.iter().map(|value| self.value(value)).collect();
+        values.join(" ")
+    }
+
+    fn value(&self, value: &ty::Value) -> String {
+        match value {
+            ty::Value::Num(ty::Number::I32) => "i32".to_owned(),
+            ty::Value::Num(ty::Number::I64) => "i64".to_owned(),
+            ty::Value::Num(ty::Number::F32) => "f32".to_owned(),
+            ty::Value::Num(ty::Number::F64) => "f64".to_owned(),
+            ty::Value::Vec(ty::Vector::Vec) => "v128".to_owned(),
+            ty::Value::Ref(reference) => self.reference(reference),
+        }
+    }
+
+    fn reference(&self, reference: &ty::Reference) -> String {
+        match reference {
+            ty::Reference {
+                heap: ty::Heap::Abstract(abs),
+                is_nullable: true,
+            } => format!("{}ref", abs_heap(abs)),
+            ty::Reference {
+                heap,
+                is_nullable: true,
+            } => format!("(ref null {})", self.heap(heap)),
+            ty::Reference {
+                heap,
+                is_nullable: false,
+            } => format!("(ref {})", self.heap(heap)),
+        }
+    }
+
+    fn heap(&self, heap: &ty::Heap) -> String {
+        match heap {
+            ty::Heap::Abstract(abs) => abs_heap(abs).to_owned(),
+            ty::Heap::Concrete(i) => index(&self.names.types, *i),
+        }
+    }
+
+    fn limit(&self, limit: &ty::Limit) -> String {
+        let mut s = match limit.address {
+            ty::Address::I32 => limit.min.to_string(),
+            ty::Address::I64 => format!("i64 {}", limit.min),
+        };
+        if let Some(max) = limit.max {
+            s.push_str(&format!(" {max}"));
+        }
+        s
+    }
+
+    fn table(&self, table: &ty::Table) -> String {
+        format!(
+            "{} {}",
+            self.limit(&table.limit),
+            self.reference(&table.reference)
+        )
+    }
+
+    fn global_type(&self, global: &ty::Global) -> String {
+        let value = self.value(&global.value);
+        if global.is_mutable {
+            format!("(mut {value})")
+        } else {
+            value
+        }
+    }
+
+    fn func(&self, lines: &mut Lines, func_index: u32, type_index: u32, code: &binary::Func) {
+        let func_id = id(&self.names.funcs, func_index);
+        let mut header = format!(
+            "(func{func_id} (type {})",
+            index(&self.names.types, type_index)
+        );
+        let local_names = self.names.locals.get(&func_index);
+        let local_id = |i| local_names.map(|names| id(names, i)).unwrap_or_default();
+        let mut local_index = 0;
+        if let Some(ty::Composite::Func { params, returns }) = self.composite_of(type_index) {
+            for param in params {
+                header.push_str(&format!(
+                    " (param{} {})",
+                    local_id(local_index),
+                    self.value(param)
+                ));
+                local_index += 1;
+            }
+            if !returns.is_empty() {
+                header.push_str(&format!(" (result {})", self.values(returns)));
+            }
+        }
+        lines.push(1, header);
+        for local in &code.locals {
+            for _ in 0..local.num {
+                let local_id = local_id(local_index);
+                lines.push(2, format!("(local{local_id} {})", self.value(&local.ty)));
+                local_index += 1;
+            }
+        }
+        self.expr(lines, 2, Some(func_index), &code.expr);
+        lines.close();
+    }
+
+    fn composite_of(&self, type_index: u32) -> Option<&'a ty::Composite> {
+        self.types
+            .get(type_index as usize)
+            .map(|sub| &sub.composite)
+    }
+
+    /// Prints an expression without its final `end`
+    fn expr(&self, lines: &mut Lines, indent: usize, func: Option<u32>, expr: &instr::Expression) {
+        let instrs = match expr.0.split_last() {
+            Some((instr::Instruction::End, instrs)) => instrs,
+            _ => &expr.0,
+        };
+        if self.is_folded {
+            let (nodes, _) = self.fold(&mut instrs.iter());
+            for node in &nodes {
+                self.node(lines, indent, func, node);
+            }
+            return;
+        }
+        let mut indent = indent;
+        for instr in instrs {
+            match instr {
+                instr::Instruction::Else => {
+                    lines.push(indent - 1, "else");
+                    continue;
+                }
+                instr::Instruction::End => indent -= 1,
+                _ => {}
+            }
+            lines.push(indent, self.instr(func, instr));
+            if let instr::Instruction::Block(_)
+            | instr::Instruction::Loop(_)
+            | instr::Instruction::If(_) = instr
+            {
+                indent += 1;
+            }
+        }
+    }
+
+    fn node(&self, lines: &mut Lines, indent: usize, func: Option<u32>, node: &Node) {
+        match node {
+            Node::Plain { instr, operands } => {
+                lines.push(indent, format!("({}", self.instr(func, instr)));
+                for operand in operands {
+                    self.node(lines, indent + 1, func, operand);
+                }
+                lines.close();
+            }
+            Node::Block {
+                instr,
+                operands,
+                body,
+                alternative,
+            } => {
+                lines.push(indent, format!("({}", self.instr(func, instr)));
+                for operand in operands {
+                    self.node(lines, indent + 1, func, operand);
+                }
+                let is_if = matches!(instr, instr::Instruction::If(_));
+                let body_indent = if is_if { indent + 2 } else { indent + 1 };
+                if is_if {
+                    lines.push(indent + 1, "(then");
+                }
+                for node in body {
+                    self.node(lines, body_indent, func, node);
+                }
+                if is_if {
+                    lines.close();
+                }
+                if let Some(alternative) = alternative {
+                    lines.push(indent + 1, "(else");
+                    for node in alternative {
+                        self.node(lines, indent + 2, func, node);
+                    }
+                    lines.close();
+                }
+                lines.close();
+            }
+        }
+    }
+
+    /// Folds instructions up to `else` or `end`, which is returned as well
+    fn fold(
+        &self,
+        instrs: &mut std::slice::Iter<'a, instr::Instruction>,
+    ) -> (Vec<Node<'a>>, Option<&'a instr::Instruction>) {
+        // Each node along with the number of its results if known
+        let mut nodes: Vec<(Node, Option<usize>)> = vec![];
+        // Operands can be folded only if each of them is a node that pushes
+        // exactly one value
+        fn operands<'a>(nodes: &mut Vec<(Node<'a>, Option<usize>)>, count: usize) -> Vec<Node<'a>> {
+            let Some(start) = nodes.len().checked_sub(count) else {
+                return vec![];
+            };
+            if nodes[start..]
+                .iter()
+                .any(|(_, results)| *results != Some(1))
+            {
+                return vec![];
+            }
+            nodes.drain(start..).map(|(node, _)| node).collect()
+        }
+        while let Some(instr) = instrs.next() {
+            let node = match instr {
+                instr::Instruction::Else | instr::Instruction::End => {
+                    return (
+                        nodes.into_iter().map(|(node, _)| node).collect(),
+                        Some(instr),
+                    );
+                }
+                instr::Instruction::Block(ty)
+                | instr::Instruction::Loop(ty)
+                | instr::Instruction::If(ty) => {
+                    let (params, results) = self.block_arity(ty);
+                    let is_if = matches!(instr, instr::Instruction::If(_));
+                    let operands = if is_if && 0 == params {
+                        operands(&mut nodes, 1)
+                    } else {
+                        vec![]
+                    };
+                    let (body, terminator) = self.fold(instrs);
+                    let alternative = match terminator {
+                        Some(instr::Instruction::Else) => Some(self.fold(instrs).0),
+                        _ => None,
+                    };
+                    let node = Node::Block {
+                        instr,
+                        operands,
+                        body,
+                        alternative,
+                    };
+                    (node, (0 == params).then_some(results))
+                }
+                _ => match self.arity(instr) {
+                    Some((params, results)) => {
+                        let operands = operands(&mut nodes, params);
+                        (Node::Plain { instr, operands }, Some(results))
+                    }
+                    None => {
+                        let operands = vec![];
+                        (Node::Plain { instr, operands }, None)
+                    }
+                },
+            };
+            nodes.push(node);
+        }
+        (nodes.into_iter().map(|(node, _)| node).collect(), None)
+    }
+
+    fn block_arity(&self, ty: &instr::BlockType) -> (usize, usize) {
+        match ty {
+            instr::BlockType::Empty => (0, 0),
+            instr::BlockType::Value(_) => (0, 1),
+            instr::BlockType::Index(i) => match self.composite_of(*i) {
+                Some(ty::Composite::Func { params, returns }) => (params.len(), returns.len()),
+                _ => (0, 0),
+            },
+        }
+    }
+
+    fn func_arity(&self, type_index: u32) -> Option<(usize, usize)> {
+        match self.composite_of(type_index)? {
+            ty::Composite::Func { params, returns } => Some((params.len(), returns.len())),
+            _ => None,
+        }
+    }
+
+    /// Returns the numbers of operands and results of an instruction, unless
+    /// they depend on the enclosing blocks
+    fn arity(&self, instr: &instr::Instruction) -> Option<(usize, usize)> {
+        use instr::Instruction::*;
+        Some(match instr {
+            Nop => (0, 0),
+            Call(func) => self.func_arity(*self.func_types.get(*func as usize)?)?,
+            CallIndirect(ty, _) => {
+                let (params, results) = self.func_arity(*ty)?;
+                (params + 1, results)
+            }
+            RefNull(_) | RefFunc(_) => (0, 1),
+            RefIsNull => (1, 1),
+            Drop => (1, 0),
+            Select | SelectTyped(_) => (3, 1),
+            LocalGet(_) | GlobalGet(_) => (0, 1),
+            LocalSet(_) | GlobalSet(_) => (1, 0),
+            LocalTee(_) => (1, 1),
+            I32Load(_) | I64Load(_) | F32Load(_) | F64Load(_) | I32Load8S(_) | I32Load8U(_)
+            | I32Load16S(_) | I32Load16U(_) | I64Load8S(_) | I64Load8U(_) | I64Load16S(_)
+            | I64Load16U(_) | I64Load32S(_) | I64Load32U(_) => (1, 1),
+            I32Store(_) | I64Store(_) | F32Store(_) | F64Store(_) | I32Store8(_)
+            | I32Store16(_) | I64Store8(_) | I64Store16(_) | I64Store32(_) => (2, 0),
+            TableGet(_) => (1, 1),
+            TableSet(_) => (2, 0),
+            TableInit(..) | TableCopy(..) | TableFill(_) => (3, 0),
+            ElemDrop(_) | DataDrop(_) => (0, 0),
+            TableGrow(_) => (2, 1),
+            TableSize(_) => (0, 1),
+            MemorySize(_) => (0, 1),
+            MemoryGrow(_) => (1, 1),
+            MemoryInit(..) | MemoryCopy(..) | MemoryFill(_) => (3, 0),
+            I32Const(_) | I64Const(_) | F32Const(_) | F64Const(_) => (0, 1),
+            I32Eqz | I64Eqz | I32Clz | I32Ctz | I32Popcnt | I64Clz | I64Ctz | I64Popcnt
+            | F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt | F64Abs
+            | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt | I32WrapI64
+            | I32TruncF32S | I32TruncF32U | I32TruncF64S | I32TruncF64U | I64ExtendI32S
+            | I64ExtendI32U | I64TruncF32S | I64TruncF32U | I64TruncF64S | I64TruncF64U
+            | F32ConvertI32S | F32ConvertI32U | F32ConvertI64S | F32ConvertI64U | F32DemoteF64
+            | F64ConvertI32S | F64ConvertI32U | F64ConvertI64S | F64ConvertI64U | F64PromoteF32
+            | I32ReinterpretF32 | I64ReinterpretF64 | F32ReinterpretI32 | F64ReinterpretI64
+            | I32Extend8S | I32Extend16S | I64Extend8S | I64Extend16S | I64Extend32S
+            | I32TruncSatF32S | I32TruncSatF32U | I32TruncSatF64S | I32TruncSatF64U
+            | I64TruncSatF32S | I64TruncSatF32U | I64TruncSatF64S | I64TruncSatF64U => (1, 1),
+            I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU | I32GeS
+            | I32GeU | I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU | I64LeS | I64LeU
+            | I64GeS | I64GeU | F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge | F64Eq | F64Ne
+            | F64Lt | F64Gt | F64Le | F64Ge | I32Add | I32Sub | I32Mul | I32DivS | I32DivU
+            | I32RemS | I32RemU | I32And | I32Or | I32Xor | I32Shl | I32ShrS | I32ShrU
+            | I32Rotl | I32Rotr | I64Add | I64Sub | I64Mul | I64DivS | I64DivU | I64RemS
+            | I64RemU | I64And | I64Or | I64Xor | I64Shl | I64ShrS | I64ShrU | I64Rotl
+            | I64Rotr | F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign
+            | F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => (2, 1),
+            _ => return None,
+        })
+    }
+
+    /// Prints an instruction with its immediates
+    fn instr(&self, func: Option<u32>, instr: &instr::Instruction) -> String {
+        use instr::Instruction::*;
+        let local = |i: u32| {
+            func.and_then(|func| self.names.locals.get(&func))
+                .map(|names| index(names, i))
+                .unwrap_or_else(|| i.to_string())
+        };
+        let name = mnemonic(instr);
+        match instr {
+            Block(ty) | Loop(ty) | If(ty) => match ty {
+                instr::BlockType::Empty => name.to_owned(),
+                instr::BlockType::Value(value) => format!("{name} (result {})", self.value(value)),
+                instr::BlockType::Index(i) => {
+                    format!("{name} (type {})", index(&self.names.types, *i))
+                }
+            },
+            Br(label) | BrIf(label) => format!("{name} {label}"),
+            BrTable(labels, default) => {
+                let mut s = name.to_owned();
+                for label in labels.iter().chain([default]) {
+                    s.push_str(&format!(" {label}"));
+                }
+                s
+            }
+            Call(i) | RefFunc(i) => format!("{name} {}", index(&self.names.funcs, *i)),
+            CallIndirect(ty, table) => {
+                let mut s = name.to_owned();
+                if 0 != *table {
+                    s.push_str(&format!(" {}", index(&self.names.tables, *table)));
+                }
+                s.push_str(&format!(" (type {})", index(&self.names.types, *ty)));
+                s
+            }
+            RefNull(heap) => format!("{name} {}", self.heap(heap)),
+            SelectTyped(values) => format!("{name} (result {})", self.values(values)),
+            LocalGet(i) | LocalSet(i) | LocalTee(i) => format!("{name} {}", local(*i)),
+            GlobalGet(i) | GlobalSet(i) => format!("{name} {}", index(&self.names.globals, *i)),
+            I32Load(arg) | I64Load(arg) | F32Load(arg) | F64Load(arg) | I32Load8S(arg)
+            | I32Load8U(arg) | I32Load16S(arg) | I32Load16U(arg) | I64Load8S(arg)
+            | I64Load8U(arg) | I64Load16S(arg) | I64Load16U(arg) | I64Load32S(arg)
+            | I64Load32U(arg) | I32Store(arg) | I64Store(arg) | F32Store(arg) | F64Store(arg)
+            | I32Store8(arg) | I32Store16(arg) | I64Store8(arg) | I64Store16(arg)
+            | I64Store32(arg) => {
+                let mut s = name.to_owned();
+                if 0 != arg.memory {
+                    s.push_str(&format!(" {}", index(&self.names.memories, arg.memory)));
+                }
+                if 0 != arg.offset {
+                    s.push_str(&format!(" offset={}", arg.offset));
+                }
+                if instr.natural_alignment() != Some(arg.align) {
+                    s.push_str(&format!(" align={}", 1u64 << arg.align.min(63)));
+                }
+                s
+            }
+            MemorySize(memory) | MemoryGrow(memory) | MemoryFill(memory) => {
+                if 0 == *memory {
+                    name.to_owned()
+                } else {
+                    format!("{name} {}", index(&self.names.memories, *memory))
+                }
+            }
+            MemoryInit(data, memory) => {
+                if 0 == *memory {
+                    format!("{name} {data}")
+                } else {
+                    format!("{name} {} {data}", index(&self.names.memories, *memory))
+                }
+            }
+            MemoryCopy(dst, src) => {
+                if 0 == *dst && 0 == *src {
+                    name.to_owned()
+                } else {
+                    let memories = &self.names.memories;
+                    format!("{name} {} {}", index(memories, *dst), index(memories, *src))
+                }
+            }
+            DataDrop(i) | ElemDrop(i) => format!("{name} {i}"),
+            TableInit(elem, table) => {
+                if 0 == *table {
+                    format!("{name} {elem}")
+                } else {
+                    format!("{name} {} {elem}", index(&self.names.tables, *table))
+                }
+            }
+            TableCopy(dst, src) => {
+                let tables = &self.names.tables;
+                format!("{name} {} {}", index(tables, *dst), index(tables, *src))
+            }
+            TableGet(table) | TableSet(table) | TableGrow(table) | TableSize(table)
+            | TableFill(table) => {
+                format!("{name} {}", index(&self.names.tables, *table))
+            }
+            I32Const(value) => format!("{name} {value}"),
+            I64Const(value) => format!("{name} {value}"),
+            F32Const(bits) => format!("{name} {}", f32(*bits)),
+            F64Const(bits) => format!("{name} {}", f64(*bits)),
+            _ => name.to_owned(),
+        }
+    }
+}
+
+fn abs_heap(abs: &ty::AbsHeap) -> &'static str {
+    match abs {
+        ty::AbsHeap::Exception => "exn",
+        ty::AbsHeap::Array => "array",
+        ty::AbsHeap::Struct => "struct",
+        ty::AbsHeap::I31 => "i31",
+        ty::AbsHeap::Eq => "eq",
+        ty::AbsHeap::Any => "any",
+        ty::AbsHeap::Extern => "extern",
+        ty::AbsHeap::Func => "func",
+        ty::AbsHeap::None => "none",
+        ty::AbsHeap::NoExtern => "noextern",
+        ty::AbsHeap::NoFunc => "nofunc",
+        ty::AbsHeap::NoException => "noexn",
+    }
+}
+
+pub(super) fn mnemonic(instr: &instr::Instruction) -> &'static str {
+    use instr::Instruction::*;
+    match instr {
+        Block(_) => "block",
+        Loop(_) => "loop",
+        If(_) => "if",
+        Br(_) => "br",
+        BrIf(_) => "br_if",
+        BrTable(..) => "br_table",
+        Call(_) => "call",
+        CallIndirect(..) => "call_indirect",
+        RefNull(_) => "ref.null",
+        RefFunc(_) => "ref.func",
+        SelectTyped(_) => "select",
+        LocalGet(_) => "local.get",
+        LocalSet(_) => "local.set",
+        LocalTee(_) => "local.tee",
+        GlobalGet(_) => "global.get",
+        GlobalSet(_) => "global.set",
+        TableGet(_) => "table.get",
+        TableSet(_) => "table.set",
+        TableInit(..) => "table.init",
+        ElemDrop(_) => "elem.drop",
+        TableCopy(..) => "table.copy",
+        TableGrow(_) => "table.grow",
+        TableSize(_) => "table.size",
+        TableFill(_) => "table.fill",
+        MemorySize(_) => "memory.size",
+        MemoryGrow(_) => "memory.grow",
+        MemoryInit(..) => "memory.init",
+        DataDrop(_) => "data.drop",
+        MemoryCopy(..) => "memory.copy",
+        MemoryFill(_) => "memory.fill",
+        I32Const(_) => "i32.const",
+        I64Const(_) => "i64.const",
+        F32Const(_) => "f32.const",
+        F64Const(_) => "f64.const",
+        Unreachable => "unreachable",
+        Nop => "nop",
+        Else => "else",
+        End => "end",
+        Return => "return",
+        RefIsNull => "ref.is_null",
+        Drop => "drop",
+        Select => "select",
+        I32Eqz => "i32.eqz",
+        I32Eq => "i32.eq",
+        I32Ne => "i32.ne",
+        I32LtS => "i32.lt_s",
+        I32LtU => "i32.lt_u",
+        I32GtS => "i32.gt_s",
+        I32GtU => "i32.gt_u",
+        I32LeS => "i32.le_s",
+        I32LeU => "i32.le_u",
+        I32GeS => "i32.ge_s",
+        I32GeU => "i32.ge_u",
+        I64Eqz => "i64.eqz",
+        I64Eq => "i64.eq",
+        I64Ne => "i64.ne",
+        I64LtS => "i64.lt_s",
+        I64LtU => "i64.lt_u",
+        I64GtS => "i64.gt_s",
+        I64GtU => "i64.gt_u",
+        I64LeS => "i64.le_s",
+        I64LeU => "i64.le_u",
+        I64GeS => "i64.ge_s",
+        I64GeU => "i64.ge_u",
+        F32Eq => "f32.eq",
+        F32Ne => "f32.ne",
+        F32Lt => "f32.lt",
+        F32Gt => "f32.gt",
+        F32Le => "f32.le",
+        F32Ge => "f32.ge",
+        F64Eq => "f64.eq",
+        F64Ne => "f64.ne",
+        F64Lt => "f64.lt",
+        F64Gt => "f64.gt",
+        F64Le => "f64.le",
+        F64Ge => "f64.ge",
+        I32Clz => "i32.clz",
+        I32Ctz => "i32.ctz",
+        I32Popcnt => "i32.popcnt",
+        I32Add => "i32.add",
+        I32Sub => "i32.sub",
+        I32Mul => "i32.mul",
+        I32DivS => "i32.div_s",
+        I32DivU => "i32.div_u",
+        I32RemS => "i32.rem_s",
+        I32RemU => "i32.rem_u",
+        I32And => "i32.and",
+        I32Or => "i32.or",
+        I32Xor => "i32.xor",
+        I32Shl => "i32.shl",
+        I32ShrS => "i32.shr_s",
+        I32ShrU => "i32.shr_u",
+        I32Rotl => "i32.rotl",
+        I32Rotr => "i32.rotr",
+        I64Clz => "i64.clz",
+        I64Ctz => "i64.ctz",
+        I64Popcnt => "i64.popcnt",
+        I64Add => "i64.add",
+        I64Sub => "i64.sub",
+        I64Mul => "i64.mul",
+        I64DivS => "i64.div_s",
+        I64DivU => "i64.div_u",
+        I64RemS => "i64.rem_s",
+        I64RemU => "i64.rem_u",
+        I64And => "i64.and",
+        I64Or => "i64.or",
+        I64Xor => "i64.xor",
+        I64Shl => "i64.shl",
+        I64ShrS => "i64.shr_s",
+        I64ShrU => "i64.shr_u",
+        I64Rotl => "i64.rotl",
+        I64Rotr => "i64.rotr",
+        F32Abs => "f32.abs",
+        F32Neg => "f32.neg",
+        F32Ceil => "f32.ceil",
+        F32Floor => "f32.floor",
+        F32Trunc => "f32.trunc",
+        F32Nearest => "f32.nearest",
+        F32Sqrt => "f32.sqrt",
+        F32Add => "f32.add",
+        F32Sub => "f32.sub",
+        F32Mul => "f32.mul",
+        F32Div => "f32.div",
+        F32Min => "f32.min",
+        F32Max => "f32.max",
+        F32Copysign => "f32.copysign",
+        F64Abs => "f64.abs",
+        F64Neg => "f64.neg",
+        F64Ceil => "f64.ceil",
+        F64Floor => "f64.floor",
+        F64Trunc => "f64.trunc",
+        F64Nearest => "f64.nearest",
+        F64Sqrt => "f64.sqrt",
+        F64Add => "f64.add",
+        F64Sub => "f64.sub",
+        F64Mul => "f64.mul",
+        F64Div => "f64.div",
+        F64Min => "f64.min",
+        F64Max => "f64.max",
+        F64Copysign => "f64.copysign",
+        I32WrapI64 => "i32.wrap_i64",
+        I32TruncF32S => "i32.trunc_f32_s",
+        I32TruncF32U => "i32.trunc_f32_u",
+        I32TruncF64S => "i32.trunc_f64_s",
+        I32TruncF64U => "i32.trunc_f64_u",
+        I64ExtendI32S => "i64.extend_i32_s",
+        I64ExtendI32U => "i64.extend_i32_u",
+        I64TruncF32S => "i64.trunc_f32_s",
+        I64TruncF32U => "i64.trunc_f32_u",
+        I64TruncF64S => "i64.trunc_f64_s",
+        I64TruncF64U => "i64.trunc_f64_u",
+        F32ConvertI32S => "f32.convert_i32_s",
+        F32ConvertI32U => "f32.convert_i32_u",
+        F32ConvertI64S => "f32.convert_i64_s",
+        F32ConvertI64U => "f32.convert_i64_u",
+        F32DemoteF64 => "f32.demote_f64",
+        F64ConvertI32S => "f64.convert_i32_s",
+        F64ConvertI32U => "f64.convert_i32_u",
+        F64ConvertI64S => "f64.convert_i64_s",
+        F64ConvertI64U => "f64.convert_i64_u",
+        F64PromoteF32 => "f64.promote_f32",
+        I32ReinterpretF32 => "i32.reinterpret_f32",
+        I64ReinterpretF64 => "i64.reinterpret_f64",
+        F32ReinterpretI32 => "f32.reinterpret_i32",
+        F64ReinterpretI64 => "f64.reinterpret_i64",
+        I32Extend8S => "i32.extend8_s",
+        I32Extend16S => "i32.extend16_s",
+        I64Extend8S => "i64.extend8_s",
+        I64Extend16S => "i64.extend16_s",
+        I64Extend32S => "i64.extend32_s",
+        I32TruncSatF32S => "i32.trunc_sat_f32_s",
+        I32TruncSatF32U => "i32.trunc_sat_f32_u",
+        I32TruncSatF64S => "i32.trunc_sat_f64_s",
+        I32TruncSatF64U => "i32.trunc_sat_f64_u",
+        I64TruncSatF32S => "i64.trunc_sat_f32_s",
+        I64TruncSatF32U => "i64.trunc_sat_f32_u",
+        I64TruncSatF64S => "i64.trunc_sat_f64_s",
+        I64TruncSatF64U => "i64.trunc_sat_f64_u",
+        I32Load(_) => "i32.load",
+        I64Load(_) => "i64.load",
+        F32Load(_) => "f32.load",
+        F64Load(_) => "f64.load",
+        I32Load8S(_) => "i32.load8_s",
+        I32Load8U(_) => "i32.load8_u",
+        I32Load16S(_) => "i32.load16_s",
+        I32Load16U(_) => "i32.load16_u",
+        I64Load8S(_) => "i64.load8_s",
+        I64Load8U(_) => "i64.load8_u",
+        I64Load16S(_) => "i64.load16_s",
+        I64Load16U(_) => "i64.load16_u",
+        I64Load32S(_) => "i64.load32_s",
+        I64Load32U(_) => "i64.load32_u",
+        I32Store(_) => "i32.store",
+        I64Store(_) => "i64.store",
+        F32Store(_) => "f32.store",
+        F64Store(_) => "f64.store",
+        I32Store8(_) => "i32.store8",
+        I32Store16(_) => "i32.store16",
+        I64Store8(_) => "i64.store8",
+        I64Store16(_) => "i64.store16",
+        I64Store32(_) => "i64.store32",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::binary::Module;
+
+    #[test]
+    fn print_flat_and_folded() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            "\
+(module
+    (func $add (param $a i32) (param $b i32) (result i32)
+        (if (result i32) (i32.eqz (local.get $a))
+            (then (local.get $b))
+            (else (i32.add (local.get $a) (local.get $b))))
+    )
+)
+",
+        )?;
+        let module = Module::decode(wasm)?;
+        assert_eq!(
+            "\
+(module
+  (type (func (param i32 i32) (result i32)))
+  (func $add (type 0) (param $a i32) (param $b i32) (result i32)
+    local.get $a
+    i32.eqz
+    if (result i32)
+      local.get $b
+    else
+      local.get $a
+      local.get $b
+      i32.add
+    end))
+",
+            module.to_string(),
+        );
+        assert_eq!(
+            "\
+(module
+  (type (func (param i32 i32) (result i32)))
+  (func $add (type 0) (param $a i32) (param $b i32) (result i32)
+    (if (result i32)
+      (i32.eqz
+        (local.get $a))
+      (then
+        (local.get $b))
+      (else
+        (i32.add
+          (local.get $a)
+          (local.get $b))))))
+",
+            format!("{module:#}"),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn print_types() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            "\
+(module
+    (rec
+        (type $A (sub (struct (field (mut (ref null $B))))))
+        (type $B (sub final $A (struct (field (mut (ref null $B))) (field i8))))
+    )
+    (type (array (ref $A)))
+    (type (func (param funcref) (result (ref null extern))))
+)
+",
+        )?;
+        assert_eq!(
+            "\
+(module
+  (rec
+    (type $A (sub (struct (field (mut (ref null $B))))))
+    (type $B (sub final $A (struct (field (mut (ref null $B))) (field i8)))))
+  (type (array (ref $A)))
+  (type (func (param funcref) (result externref))))
+",
+            Module::decode(wasm)?.to_string(),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn print_round_trip() -> anyhow::Result<()> {
+        let fixtures = [
+            "(module)",
+            "(module (func (param i32 i64) (local f32 f64 v128)))",
+            r#"
+(module $m
+    (import "env" "log" (func $log (param i32)))
+    (import "env" "mem" (memory i64 1))
+    (memory $mem 1 2)
+    (global $g (mut i32) (i32.const 42))
+    (func $f (export "f") (param $x i32) (result i32) (local $t f64)
+        (local.set $t (f64.const -0x1.8p-3))
+        (block $l (br_if $l (i32.const 1)))
+        (loop (br_table 0 0 (i32.const 0)))
+        (drop (select (f32.const nan:0x200000) (f32.const -inf) (i32.const 0)))
+        (i64.store8 offset=3 (i32.const 8) (i64.extend_i32_u (global.get $g)))
+        (call $log (i32.load16_s align=1 (local.get $x)))
+        (i32.trunc_sat_f64_u (f64.convert_i32_s (memory.grow (memory.size))))
+    )
+    (export "\00\u{e9}\"" (global $g))
+)
+"#,
+            r#"
+(module
+    (table $t 2 funcref)
+    (table i64 1 externref)
+    (memory $m 1)
+    (func $f)
+    (start $f)
+    (elem (table $t) (offset (i32.const 1)) func $f $f)
+    (elem declare func $f)
+    (elem func)
+    (elem (table 1) (i64.const 0) externref (ref.null extern) (item (ref.null extern)))
+    (data (memory $m) (i32.const 8) "a\00\ff")
+    (data "")
+)
+"#,
+            r#"
+(module
+    (table $t 1 funcref)
+    (table $u 1 funcref)
+    (memory 1)
+    (memory $n 1)
+    (elem $e func)
+    (data $d "a")
+    (func
+        (table.init $u $e (i32.const 0) (i32.const 0) (i32.const 0))
+        (table.copy $u $t (i32.const 0) (i32.const 0) (table.size $t))
+        (table.fill $t (i32.const 0) (ref.null func) (table.grow $u (ref.null func) (i32.const 1)))
+        (table.set $t (i32.const 0) (table.get $u (i32.const 0)))
+        (elem.drop $e)
+        (memory.init $n $d (i32.const 0) (i32.const 0) (i32.const 1))
+        (memory.copy $n 0 (i32.const 0) (i32.const 1) (i32.const 2))
+        (memory.fill (i32.const 0) (i32.const 1) (i32.const 2))
+        (data.drop $d)
+    )
+)
+"#,
+        ];
+        for fixture in fixtures {
+            let module = Module::decode(wat::parse_str(fixture)?)?;
+            let flat = module.to_string();
+            assert_eq!(module, Module::decode(wat::parse_str(&flat)?)?, "{flat}");
+            let folded = format!("{module:#}");
+            assert_eq!(
+                module,
+                Module::decode(wat::parse_str(&folded)?)?,
+                "{folded}"
+            );
+        }
+        Ok(())
+    }
+}
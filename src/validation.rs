@@ -1,10 +1,497 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context as _;
+
+use crate::binary;
+use crate::features::{Feature, WasmFeatures};
+
+mod check;
 pub mod instr;
 pub mod ty;
 
+/// Maximum number of locals declared by a function, bounding the memory needed
+/// to expand run-length encoded locals
+pub const MAX_LOCALS: u32 = 50_000;
+
+/// Validates a decoded module against the enabled proposals, returning it
+/// lowered with canonical types
+pub fn validate(module: &binary::Module, features: WasmFeatures) -> anyhow::Result<Validated> {
+    let module = Module::try_from(module)?;
+    module.check(features)?;
+    Ok(Validated(module))
+}
+
+/// A module that passed `validate`, which is the only form the runtime
+/// instantiates as it relies on the rules checked there
+#[derive(Debug, PartialEq, Eq)]
+pub struct Validated(Module);
+
+impl std::ops::Deref for Validated {
+    type Target = Module;
+
+    fn deref(&self) -> &Module {
+        &self.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Module {
-    pub types: Vec<ty::Recursive>,
+    /// Type index space with canonicalized types
+    pub types: Vec<ty::Defined>,
+    pub imports: Vec<Import>,
+    /// Function index space of imported then defined functions
+    pub func_types: Vec<ty::Defined>,
+    /// Table index space of imported then defined tables
+    pub tables: Vec<ty::Table>,
+    /// Memory index space of imported then defined memories
+    pub memories: Vec<ty::Memory>,
+    /// Global index space of imported then defined globals
+    pub globals: Vec<ty::Global>,
+    /// Tag index space of imported tags
+    pub tags: Vec<ty::Defined>,
+    /// Bodies of the defined functions
     pub funcs: Vec<Func>,
+    /// Initializers of the defined globals
+    pub global_inits: Vec<instr::Expression>,
+    pub exports: Vec<binary::Export>,
+    pub start: Option<u32>,
+    pub elems: Vec<Element>,
+    pub data_count: Option<u32>,
+    pub datas: Vec<Data>,
+    /// Functions declared outside function bodies, which `ref.func` in the
+    /// bodies may reference
+    pub refs: HashSet<u32>,
+    /// Names of functions from the name section, for diagnostics
+    pub func_names: HashMap<u32, String>,
+}
+
+impl TryFrom<&binary::Module> for Module {
+    type Error = anyhow::Error;
+
+    /// Lowers a decoded module, resolving the types of imports and
+    /// definitions into their index spaces and pairing functions with their
+    /// bodies
+    fn try_from(module: &binary::Module) -> anyhow::Result<Self> {
+        if let Some(section) = &module.type_section {
+            check_types(section)?;
+        }
+        let types = module.type_section.iter().flat_map(|section| &section.0);
+        let types: Vec<_> = types.map(ty::Recursive::from).collect();
+        let types = ty::Defined::canonicalize(&types)?;
+
+        let mut lowered = Self {
+            types,
+            imports: vec![],
+            func_types: vec![],
+            tables: vec![],
+            memories: vec![],
+            globals: vec![],
+            tags: vec![],
+            funcs: vec![],
+            global_inits: vec![],
+            exports: vec![],
+            start: module.start_section.as_ref().map(|section| section.0),
+            elems: vec![],
+            data_count: module.data_count_section.as_ref().map(|section| section.0),
+            datas: vec![],
+            refs: HashSet::new(),
+            func_names: module
+                .name_section
+                .iter()
+                .flat_map(|section| &section.funcs)
+                .map(|naming| (naming.index, naming.name.clone()))
+                .collect(),
+        };
+        let imports = module.import_section.iter().flat_map(|section| &section.0);
+        for (i, import) in imports.enumerate() {
+            let ty = lowered
+                .external(&import.ty)
+                .with_context(|| format!("in import {i}"))?;
+            match &ty {
+                ty::External::Func(defined) => lowered.func_types.push(defined.clone()),
+                ty::External::Table(table) => lowered.tables.push(table.clone()),
+                ty::External::Memory(memory) => lowered.memories.push(memory.clone()),
+                ty::External::Global(global) => lowered.globals.push(global.clone()),
+                ty::External::Tag(defined) => lowered.tags.push(defined.clone()),
+            }
+            lowered.imports.push(Import {
+                module: import.module.clone(),
+                name: import.name.clone(),
+                ty,
+            });
+        }
+
+        let func_types = module
+            .func_section
+            .as_ref()
+            .map_or(&[][..], |section| &section.0);
+        let codes = module
+            .code_section
+            .as_ref()
+            .map_or(&[][..], |section| &section.0);
+        anyhow::ensure!(
+            func_types.len() == codes.len(),
+            "function and code section counts differ: {} and {}",
+            func_types.len(),
+            codes.len(),
+        );
+        let import_count = lowered.func_types.len();
+        for (i, (&type_index, code)) in func_types.iter().zip(codes).enumerate() {
+            let lower = || {
+                let ty = func_type(&lowered.types, type_index)?;
+                Func::new(&lowered.types, ty, code)
+            };
+            let func = lower().with_context(|| format!("in function {}", import_count + i))?;
+            lowered
+                .func_types
+                .push(lowered.types[type_index as usize].clone());
+            lowered.funcs.push(func);
+        }
+
+        for table in module.table_section.iter().flat_map(|section| &section.0) {
+            let table = lowered.table(table)?;
+            lowered.tables.push(table);
+        }
+        for memory in module.memory_section.iter().flat_map(|section| &section.0) {
+            lowered.memories.push(ty::Memory::from(memory));
+        }
+        for global in module.global_section.iter().flat_map(|section| &section.0) {
+            let ty = lowered.global(&global.ty)?;
+            lowered.globals.push(ty);
+            lowered
+                .global_inits
+                .push(instr::Expression::try_from(&global.init)?);
+        }
+        lowered.exports = module
+            .export_section
+            .as_ref()
+            .map_or(vec![], |section| section.0.clone());
+
+        for elem in module.element_section.iter().flat_map(|section| &section.0) {
+            let elem = Element::new(&lowered.types, elem)?;
+            lowered.elems.push(elem);
+        }
+        for data in module.data_section.iter().flat_map(|section| &section.0) {
+            lowered.datas.push(Data::try_from(data)?);
+        }
+        if let Some(count) = lowered.data_count {
+            anyhow::ensure!(
+                count as usize == lowered.datas.len(),
+                "data count and data section have inconsistent lengths: {count} and {}",
+                lowered.datas.len(),
+            );
+        }
+
+        let mut exprs: Vec<_> = lowered.global_inits.iter().collect();
+        for elem in &lowered.elems {
+            exprs.extend(&elem.init);
+            if let ElementMode::Active { offset, .. } = &elem.mode {
+                exprs.push(offset);
+            }
+        }
+        for data in &lowered.datas {
+            if let DataMode::Active { offset, .. } = &data.mode {
+                exprs.push(offset);
+            }
+        }
+        let instrs = exprs.into_iter().flat_map(|expr| &expr.0);
+        let refs = instrs.filter_map(|instr| match instr {
+            instr::Instruction::RefFunc(index) => Some(*index),
+            _ => None,
+        });
+        let exports = lowered
+            .exports
+            .iter()
+            .filter_map(|export| match export.index {
+                binary::ExternalIndex::Func(index) => Some(index),
+                _ => None,
+            });
+        lowered.refs = refs.chain(exports).collect();
+        Ok(lowered)
+    }
+}
+
+impl Module {
+    /// Checks that declared supertypes aren't final and that types match
+    /// them, that limits, exports and the start function are well-formed,
+    /// that initializers and segment offsets are constant expressions of the
+    /// right types, then type-checks the bodies of all functions. Uses of
+    /// proposals not enabled in `features` are rejected.
+    pub fn check(&self, features: WasmFeatures) -> anyhow::Result<()> {
+        for (i, defined) in self.types.iter().enumerate() {
+            let sub = defined.unroll();
+            for sup in &sub.supers {
+                let ty::TypeUse::Defined(sup) = sup else {
+                    unreachable!("canonicalized type {sup}");
+                };
+                let sup = sup.unroll();
+                anyhow::ensure!(!sup.is_final, "type {i} has a final supertype");
+                anyhow::ensure!(
+                    sub.ty.matches(&sup.ty),
+                    "type {i} doesn't match its supertype: {} and {}",
+                    sub.ty,
+                    sup.ty,
+                );
+            }
+        }
+        if 1 < self.tables.len() {
+            features.require(Feature::ReferenceTypes)?;
+        }
+        if 1 < self.memories.len() {
+            features.require(Feature::MultiMemory)?;
+        }
+        let is_mutable_import = |import: &Import| matches!(&import.ty, ty::External::Global(global) if global.is_mutable);
+        if self.imports.iter().any(is_mutable_import) {
+            features.require(Feature::MutableGlobals)?;
+        }
+        for (i, table) in self.tables.iter().enumerate() {
+            check_table(table).with_context(|| format!("in table {i}"))?;
+        }
+        for (i, memory) in self.memories.iter().enumerate() {
+            check_memory(memory).with_context(|| format!("in memory {i}"))?;
+        }
+
+        let import_count = self.globals.len() - self.global_inits.len();
+        for (i, init) in self.global_inits.iter().enumerate() {
+            let index = import_count + i;
+            check::const_expr(self, features, init, &self.globals[index].value, index)
+                .with_context(|| format!("in global {index}"))?;
+        }
+
+        let mut names = HashSet::new();
+        for export in &self.exports {
+            anyhow::ensure!(
+                names.insert(&export.name),
+                "duplicate export name {:?}",
+                export.name,
+            );
+            self.check_index(export.index)?;
+            if let binary::ExternalIndex::Global(index) = export.index
+                && self.globals[index as usize].is_mutable
+            {
+                features.require(Feature::MutableGlobals)?;
+            }
+        }
+        if let Some(start) = self.start {
+            self.check_index(binary::ExternalIndex::Func(start))?;
+            let func = self.func_types[start as usize].func();
+            anyhow::ensure!(
+                func.is_some_and(|func| func.params.is_empty() && func.returns.is_empty()),
+                "start function must have type [] -> []",
+            );
+        }
+
+        for (i, elem) in self.elems.iter().enumerate() {
+            self.check_element(elem, features)
+                .with_context(|| format!("in element segment {i}"))?;
+        }
+        for (i, data) in self.datas.iter().enumerate() {
+            if let DataMode::Active { memory, offset } = &data.mode {
+                let check = || {
+                    self.check_index(binary::ExternalIndex::Memory(*memory))?;
+                    let address = self.memories[*memory as usize].0.address;
+                    check::const_expr(self, features, offset, &address.value(), self.globals.len())
+                };
+                check().with_context(|| format!("in data segment {i}"))?;
+            }
+        }
+
+        for (i, func) in self.funcs.iter().enumerate() {
+            check::func(self, features, func).with_context(|| format!("in function body {i}"))?;
+        }
+        Ok(())
+    }
+
+    /// Checks that an index is in range of its index space
+    fn check_index(&self, index: binary::ExternalIndex) -> anyhow::Result<()> {
+        let (kind, index, count) = match index {
+            binary::ExternalIndex::Func(index) => ("function", index, self.func_types.len()),
+            binary::ExternalIndex::Table(index) => ("table", index, self.tables.len()),
+            binary::ExternalIndex::Memory(index) => ("memory", index, self.memories.len()),
+            binary::ExternalIndex::Global(index) => ("global", index, self.globals.len()),
+            binary::ExternalIndex::Tag(index) => ("tag", index, self.tags.len()),
+        };
+        anyhow::ensure!((index as usize) < count, "unknown {kind} {index}");
+        Ok(())
+    }
+
+    fn check_element(&self, elem: &Element, features: WasmFeatures) -> anyhow::Result<()> {
+        let value = ty::Value::Ref(elem.ty.clone());
+        for init in &elem.init {
+            check::const_expr(self, features, init, &value, self.globals.len())?;
+        }
+        if let ElementMode::Active { table, offset } = &elem.mode {
+            self.check_index(binary::ExternalIndex::Table(*table))?;
+            let table = &self.tables[*table as usize];
+            anyhow::ensure!(
+                elem.ty.matches(&table.reference),
+                "type mismatch: segment of {} for table of {}",
+                elem.ty,
+                table.reference,
+            );
+            let address = table.limit.address.value();
+            check::const_expr(self, features, offset, &address, self.globals.len())?;
+        }
+        Ok(())
+    }
+
+    fn external(&self, external: &binary::ty::External) -> anyhow::Result<ty::External> {
+        Ok(match external {
+            binary::ty::External::Func(index) => {
+                func_type(&self.types, *index)?;
+                ty::External::Func(self.types[*index as usize].clone())
+            }
+            binary::ty::External::Table(table) => ty::External::Table(self.table(table)?),
+            binary::ty::External::Memory(memory) => ty::External::Memory(memory.into()),
+            binary::ty::External::Global(global) => ty::External::Global(self.global(global)?),
+            binary::ty::External::Tag(tag) => {
+                func_type(&self.types, tag.0)?;
+                ty::External::Tag(self.types[tag.0 as usize].clone())
+            }
+        })
+    }
+
+    fn table(&self, table: &binary::ty::Table) -> anyhow::Result<ty::Table> {
+        let table = ty::Table::from(table);
+        Ok(ty::Table {
+            reference: resolve_reference(&self.types, &table.reference)?,
+            ..table
+        })
+    }
+
+    fn global(&self, global: &binary::ty::Global) -> anyhow::Result<ty::Global> {
+        let global = ty::Global::from(global);
+        Ok(ty::Global {
+            value: resolve(&self.types, &global.value)?,
+            ..global
+        })
+    }
+}
+
+/// Checks that limits are within the bound of their address type and that
+/// the minimum doesn't exceed the maximum
+fn check_limit(limit: &ty::Limit, bound: u64, message: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        limit.min <= bound && limit.max.is_none_or(|max| max <= bound),
+        "{message}",
+    );
+    anyhow::ensure!(
+        limit.max.is_none_or(|max| limit.min <= max),
+        "size minimum must not be greater than maximum",
+    );
+    Ok(())
+}
+
+pub(crate) fn check_table(table: &ty::Table) -> anyhow::Result<()> {
+    let (bound, message) = match table.limit.address {
+        ty::Address::I32 => (u32::MAX.into(), "table size must be at most 2^32-1"),
+        ty::Address::I64 => (u64::MAX, "table size must be at most 2^64-1"),
+    };
+    check_limit(&table.limit, bound, message)
+}
+
+pub(crate) fn check_memory(memory: &ty::Memory) -> anyhow::Result<()> {
+    let (bound, message) = match memory.0.address {
+        ty::Address::I32 => (1 << 16, "memory size must be at most 65536 pages (4GiB)"),
+        ty::Address::I64 => (1 << 48, "memory size must be at most 2^48 pages"),
+    };
+    check_limit(&memory.0, bound, message)
+}
+
+/// Checks the well-formedness of a type section on its own: referenced types
+/// must be in range and reach forward only within their recursive group, and
+/// a type may declare at most one supertype, defined before it. Packed storage
+/// needs no check as the binary types only allow it in fields.
+pub fn check_types(section: &binary::TypeSection) -> anyhow::Result<()> {
+    let mut end: u32 = 0;
+    for rec in &section.0 {
+        let start = end;
+        end += rec.0.len() as u32;
+        for (index, sub) in (start..).zip(&rec.0) {
+            let check = || {
+                anyhow::ensure!(sub.supers.len() <= 1, "more than one supertype");
+                for &sup in &sub.supers {
+                    anyhow::ensure!(sup < index, "supertype {sup} isn't defined before the type");
+                }
+                for value in composite_values(&sub.composite) {
+                    if let binary::ty::Value::Ref(binary::ty::Reference {
+                        heap: binary::ty::Heap::Concrete(concrete),
+                        ..
+                    }) = *value
+                    {
+                        anyhow::ensure!(concrete < end, "unknown type {concrete}");
+                    }
+                }
+                Ok(())
+            };
+            check().with_context(|| format!("in type {index}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the value types a composite type consists of
+fn composite_values(composite: &binary::ty::Composite) -> Vec<&binary::ty::Value> {
+    fn field_value(field: &binary::ty::Field) -> Option<&binary::ty::Value> {
+        match &field.storage {
+            binary::ty::Storage::Value(value) => Some(value),
+            binary::ty::Storage::Pack(_) => None,
+        }
+    }
+    match composite {
+        binary::ty::Composite::Func { params, returns } => params.iter().chain(returns).collect(),
+        binary::ty::Composite::Struct(fields) => fields.iter().filter_map(field_value).collect(),
+        binary::ty::Composite::Array(field) => field_value(field).into_iter().collect(),
+    }
+}
+
+/// Formats a sequence of value types as in type mismatch errors
+pub(crate) fn types(values: &[ty::Value]) -> String {
+    let types: Vec<_> = values.iter().map(ty::Value::to_string).collect();
+    format!("[{}]", types.join(" "))
+}
+
+/// Returns the unrolled function type at the index in the type index space
+fn func_type(types: &[ty::Defined], index: u32) -> anyhow::Result<ty::Func> {
+    let defined = types
+        .get(index as usize)
+        .with_context(|| format!("type index {index} out of bounds for {} types", types.len()))?;
+    defined
+        .func()
+        .with_context(|| format!("type {index} is not a function type"))
+}
+
+/// Replaces the type indices in a value type with their canonical types
+fn resolve(types: &[ty::Defined], value: &ty::Value) -> anyhow::Result<ty::Value> {
+    value.map_type_uses(&mut |type_use| match *type_use {
+        ty::TypeUse::Index(index) => {
+            let defined = types.get(index as usize);
+            Ok(ty::TypeUse::Defined(
+                defined
+                    .with_context(|| format!("unknown type {index}"))?
+                    .clone(),
+            ))
+        }
+        _ => Ok(type_use.clone()),
+    })
+}
+
+/// Replaces the type indices in a reference type with their canonical types
+fn resolve_reference(
+    types: &[ty::Defined],
+    reference: &ty::Reference,
+) -> anyhow::Result<ty::Reference> {
+    match resolve(types, &ty::Value::Ref(reference.clone()))? {
+        ty::Value::Ref(reference) => Ok(reference),
+        value => unreachable!("resolved reference type {value}"),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: ty::External,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -13,3 +500,591 @@ pub struct Func {
     pub locals: Vec<ty::Value>,
     pub expr: instr::Expression,
 }
+
+impl Func {
+    /// Lowers a function body, expanding its run-length encoded locals
+    fn new(types: &[ty::Defined], ty: ty::Func, code: &binary::Func) -> anyhow::Result<Self> {
+        let mut count: u32 = 0;
+        for local in &code.locals {
+            count = count
+                .checked_add(local.num)
+                .filter(|&count| count <= MAX_LOCALS)
+                .with_context(|| format!("too many locals, exceeding {MAX_LOCALS}"))?;
+        }
+        let mut locals = Vec::with_capacity(count as usize);
+        for local in &code.locals {
+            let ty = resolve(types, &ty::Value::from(&local.ty))?;
+            locals.extend(std::iter::repeat_n(ty, local.num as usize));
+        }
+        Ok(Self {
+            ty,
+            locals,
+            expr: instr::Expression::try_from(&code.expr)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Element {
+    pub ty: ty::Reference,
+    pub mode: ElementMode,
+    /// Constant expressions of the elements, with function indices lowered
+    /// to `ref.func`
+    pub init: Vec<instr::Expression>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ElementMode {
+    Passive,
+    Active {
+        table: u32,
+        offset: instr::Expression,
+    },
+    Declarative,
+}
+
+impl Element {
+    fn new(types: &[ty::Defined], elem: &binary::Element) -> anyhow::Result<Self> {
+        let mode = match &elem.mode {
+            binary::ElementMode::Passive => ElementMode::Passive,
+            binary::ElementMode::Active { table, offset } => ElementMode::Active {
+                table: *table,
+                offset: instr::Expression::try_from(offset)?,
+            },
+            binary::ElementMode::Declarative => ElementMode::Declarative,
+        };
+        let (ty, init) = match &elem.init {
+            binary::ElementInit::Funcs(funcs) => {
+                let ty = ty::Reference::from(&binary::Element::FUNC_REF);
+                let init = funcs.iter().map(|&func| {
+                    instr::Expression(vec![
+                        instr::Instruction::RefFunc(func),
+                        instr::Instruction::End,
+                    ])
+                });
+                (ty, init.collect())
+            }
+            binary::ElementInit::Exprs { ty, exprs } => {
+                let ty = resolve_reference(types, &ty::Reference::from(ty))?;
+                let init = exprs.iter().map(instr::Expression::try_from);
+                (ty, init.collect::<anyhow::Result<_>>()?)
+            }
+        };
+        Ok(Self { ty, mode, init })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Data {
+    pub mode: DataMode,
+    pub init: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DataMode {
+    Passive,
+    Active {
+        memory: u32,
+        offset: instr::Expression,
+    },
+}
+
+impl TryFrom<&binary::Data> for Data {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &binary::Data) -> anyhow::Result<Self> {
+        Ok(Self {
+            mode: match &data.mode {
+                binary::DataMode::Passive => DataMode::Passive,
+                binary::DataMode::Active { memory, offset } => DataMode::Active {
+                    memory: *memory,
+                    offset: instr::Expression::try_from(offset)?,
+                },
+            },
+            init: data.init.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_module() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            "\
+(module
+    (type (func (param i32) (result i32)))
+    (import \"env\" \"f\" (func (type 0)))
+    (func (type 0) (local i32 i32) (local i32)
+        local.get 1
+        local.get 2
+        i32.add)
+)
+",
+        )?;
+        let module = Module::try_from(&binary::Module::decode(wasm)?)?;
+        let [func] = &module.funcs[..] else {
+            panic!("expected one function: {module:?}");
+        };
+        assert_eq!(
+            module.types[0].sub().ty,
+            ty::Composite::Func(func.ty.clone())
+        );
+        assert_eq!(vec![ty::Value::Num(ty::Number::I32); 3], func.locals);
+        assert_eq!(
+            instr::Expression(vec![
+                instr::Instruction::LocalGet(1),
+                instr::Instruction::LocalGet(2),
+                instr::Instruction::I32Add,
+                instr::Instruction::End,
+            ]),
+            func.expr,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lower_types() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            "\
+(module
+    (rec
+        (type $node (sub (struct (field $next (mut (ref null $node))) (field i8)))))
+    (type (sub final 0 (struct (field (mut (ref null 0))) (field i8) (field v128))))
+    (type (array (mut i16)))
+    (type (func (param i64 f32) (result f64 funcref (ref extern))))
+)
+",
+        )?;
+        let module = Module::try_from(&binary::Module::decode(wasm)?)?;
+        let node = |type_use| {
+            ty::Value::Ref(ty::Reference {
+                is_nullable: true,
+                heap: ty::Heap::Defined(type_use),
+            })
+        };
+        let defined = ty::TypeUse::Defined(module.types[0].clone());
+        let field = |storage, is_mutable| ty::Field {
+            storage,
+            is_mutable,
+        };
+        let sub = |is_final, supers, ty| ty::Sub {
+            is_final,
+            supers,
+            ty,
+        };
+        let abs = |is_nullable, abs| {
+            ty::Value::Ref(ty::Reference {
+                is_nullable,
+                heap: ty::Heap::Abstract(abs),
+            })
+        };
+        assert_eq!(
+            vec![
+                sub(
+                    false,
+                    vec![],
+                    ty::Composite::Struct(vec![
+                        field(ty::Storage::Value(node(ty::TypeUse::Rec(0))), true),
+                        field(ty::Storage::Pack(ty::Pack::I8), false),
+                    ]),
+                ),
+                sub(
+                    true,
+                    vec![defined.clone()],
+                    ty::Composite::Struct(vec![
+                        field(ty::Storage::Value(node(defined)), true),
+                        field(ty::Storage::Pack(ty::Pack::I8), false),
+                        field(ty::Storage::Value(ty::Value::Vec(ty::Vector::V128)), false),
+                    ]),
+                ),
+                sub(
+                    true,
+                    vec![],
+                    ty::Composite::Array(field(ty::Storage::Pack(ty::Pack::I16), true)),
+                ),
+                sub(
+                    true,
+                    vec![],
+                    ty::Composite::Func(ty::Func {
+                        params: vec![
+                            ty::Value::Num(ty::Number::I64),
+                            ty::Value::Num(ty::Number::F32),
+                        ],
+                        returns: vec![
+                            ty::Value::Num(ty::Number::F64),
+                            abs(true, ty::AbsHeap::Func),
+                            abs(false, ty::AbsHeap::Extern),
+                        ],
+                    }),
+                ),
+            ],
+            module
+                .types
+                .iter()
+                .map(ty::Defined::sub)
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!("(ref null 0)", node(ty::TypeUse::Index(0)).to_string());
+        assert_eq!(
+            "(struct (field (mut (ref null (struct (field (mut (ref null rec.0))) (field i8))))) (field i8) (field v128))",
+            module.types[1].to_string(),
+        );
+        Ok(())
+    }
+
+    fn lower(wat: &str) -> anyhow::Result<Module> {
+        Module::try_from(&binary::Module::decode(wat::parse_str(wat)?)?)
+    }
+
+    #[test]
+    fn canonicalize_types() -> anyhow::Result<()> {
+        let rec = |a: u32| {
+            let b = a + 1;
+            format!(
+                "(rec (type (struct (field (ref null {b})))) (type (struct (field (ref null {a})))))"
+            )
+        };
+        let first = lower(&format!("(module (type (func)) {})", rec(1)))?;
+        let second = lower(&format!("(module {} (type (array i8)) {})", rec(0), rec(3)))?;
+        assert_eq!(first.types[1], second.types[0]);
+        assert_eq!(first.types[2], second.types[1]);
+        assert_eq!(second.types[0], second.types[3]);
+        assert_ne!(first.types[1], first.types[2]);
+
+        let swapped = lower(
+            "(module (rec (type $b (struct (field (ref null $a)))) (type $a (struct (field (ref null $b))))) (type (struct (field i32))))",
+        )?;
+        assert_ne!(first.types[1], swapped.types[1]);
+        assert_ne!(swapped.types[0], swapped.types[2]);
+
+        let mut module = binary::Module::decode(wat::parse_str("(module (type (func)))")?)?;
+        module.type_section.as_mut().unwrap().0[0].0[0].supers = vec![1];
+        assert_eq!(
+            "in type 0: supertype 1 isn't defined before the type",
+            format!("{:#}", Module::try_from(&module).unwrap_err()),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn check_type_section() -> anyhow::Result<()> {
+        let wasm = wat::parse_str(
+            "\
+(module
+    (type $s (sub (struct)))
+    (rec
+        (type $a (sub $s (struct (field (ref null $b)))))
+        (type $b (sub $a (struct (field (ref null $a)) (field (mut i8))))))
+    (type (func (param (ref $b)) (result (ref null $s))))
+)
+",
+        )?;
+        let section = binary::Module::decode(wasm)?.type_section.unwrap();
+        check_types(&section)?;
+        let message = |edit: &dyn Fn(&mut Vec<binary::ty::Recursive>)| {
+            let mut section = section.clone();
+            edit(&mut section.0);
+            format!("{:#}", check_types(&section).unwrap_err())
+        };
+        let heap = |index| {
+            binary::ty::Value::Ref(binary::ty::Reference {
+                is_nullable: true,
+                heap: binary::ty::Heap::Concrete(index),
+            })
+        };
+
+        assert_eq!(
+            "in type 1: unknown type 4",
+            message(&|recs| {
+                let binary::ty::Composite::Struct(fields) = &mut recs[1].0[0].composite else {
+                    unreachable!();
+                };
+                fields[0].storage = binary::ty::Storage::Value(heap(4));
+            }),
+        );
+        assert_eq!(
+            "in type 0: unknown type 1",
+            message(&|recs| {
+                recs[0].0[0].composite = binary::ty::Composite::Array(binary::ty::Field {
+                    storage: binary::ty::Storage::Value(heap(1)),
+                    is_mutable: false,
+                });
+            }),
+        );
+        assert_eq!(
+            "in type 3: unknown type 4",
+            message(&|recs| {
+                recs[2].0[0].composite = binary::ty::Composite::Func {
+                    params: vec![],
+                    returns: vec![heap(4)],
+                };
+            }),
+        );
+        assert_eq!(
+            "in type 2: more than one supertype",
+            message(&|recs| recs[1].0[1].supers = vec![0, 1]),
+        );
+        assert_eq!(
+            "in type 1: supertype 2 isn't defined before the type",
+            message(&|recs| recs[1].0[0].supers = vec![2]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subtyping() -> anyhow::Result<()> {
+        let module = lower(
+            "\
+(module
+    (type $s (sub (struct (field i32))))
+    (type $t (sub $s (struct (field i32) (field (mut i64)))))
+    (type $f (sub (func (param (ref $s)) (result (ref null $s)))))
+    (type $g (sub $f (func (param (ref null $s)) (result (ref $t)))))
+    (func (param (ref $t)) (result (ref null $s) structref anyref)
+        local.get 0
+        local.get 0
+        local.get 0)
+)
+",
+        )?;
+        module.check(WasmFeatures::default())?;
+        let [s, t, f, g, _] = &module.types[..] else {
+            panic!("expected five types: {module:?}");
+        };
+        let reference = |is_nullable, heap| ty::Reference { is_nullable, heap };
+        let defined =
+            |defined: &ty::Defined| ty::Heap::Defined(ty::TypeUse::Defined(defined.clone()));
+        let abs = ty::Heap::Abstract;
+
+        assert!(t.matches(s) && !s.matches(t));
+        assert!(g.matches(f) && !f.matches(g));
+        assert!(reference(false, defined(t)).matches(&reference(true, defined(s))));
+        assert!(!reference(true, defined(t)).matches(&reference(false, defined(s))));
+        assert!(reference(false, defined(t)).matches(&reference(false, abs(ty::AbsHeap::Eq))));
+        assert!(!reference(false, defined(g)).matches(&reference(false, abs(ty::AbsHeap::Any))));
+        assert!(reference(true, abs(ty::AbsHeap::None)).matches(&reference(true, defined(t))));
+        assert!(reference(true, abs(ty::AbsHeap::NoFunc)).matches(&reference(true, defined(f))));
+        assert!(!reference(true, abs(ty::AbsHeap::None)).matches(&reference(true, defined(f))));
+        assert!(
+            reference(true, abs(ty::AbsHeap::I31)).matches(&reference(true, abs(ty::AbsHeap::Any)))
+        );
+        assert!(
+            !reference(true, abs(ty::AbsHeap::Extern))
+                .matches(&reference(true, abs(ty::AbsHeap::Any)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn check_supers() -> anyhow::Result<()> {
+        let message = |wat| -> anyhow::Result<_> {
+            Ok(format!(
+                "{:#}",
+                lower(wat)?.check(WasmFeatures::default()).unwrap_err()
+            ))
+        };
+        assert_eq!(
+            "type 1 has a final supertype",
+            message("(module (type $s (struct)) (type (sub $s (struct))))")?,
+        );
+        assert_eq!(
+            "type 1 doesn't match its supertype: (struct (field (mut i64))) and (struct (field i64))",
+            message(
+                "(module (type $s (sub (struct (field i64)))) (type (sub $s (struct (field (mut i64))))))"
+            )?,
+        );
+        assert_eq!(
+            "type 1 doesn't match its supertype: (func (param i32)) and (func)",
+            message("(module (type $f (sub (func))) (type (sub $f (func (param i32)))))")?,
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `End`: type mismatch: expected [(ref (struct (field i32)))] but found [(ref (struct))]",
+            message(
+                "(module (type $s (sub (struct))) (type (sub $s (struct (field i32)))) (func (param (ref $s)) (result (ref 1)) local.get 0))"
+            )?,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lower_errors() -> anyhow::Result<()> {
+        let message =
+            |module: &binary::Module| format!("{:#}", Module::try_from(module).unwrap_err());
+        let wasm = wat::parse_str("(module (func) (func))")?;
+        let mut module = binary::Module::decode(wasm)?;
+
+        module.func_section = Some(binary::FuncSection(vec![0, 1]));
+        assert_eq!(
+            "in function 1: type index 1 out of bounds for 1 types",
+            message(&module),
+        );
+
+        module.func_section = Some(binary::FuncSection(vec![0]));
+        assert_eq!(
+            "function and code section counts differ: 1 and 2",
+            message(&module),
+        );
+
+        let code = binary::Func {
+            locals: vec![binary::Local {
+                num: MAX_LOCALS + 1,
+                ty: binary::ty::Value::Num(binary::ty::Number::I32),
+            }],
+            expr: binary::instr::Expression(vec![binary::instr::Instruction::End]),
+        };
+        module.code_section = Some(binary::CodeSection(vec![code]));
+        assert_eq!(
+            "in function 0: too many locals, exceeding 50000",
+            message(&module),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn validate_module() -> anyhow::Result<()> {
+        let module = validate(
+            &binary::Module::decode(wat::parse_str(
+                "\
+(module
+    (import \"env\" \"f\" (func $f (param i32)))
+    (import \"env\" \"g\" (global $g i32))
+    (table 1 funcref)
+    (memory i64 1 2)
+    (global $h i32 (i32.add (global.get $g) (i32.const 1)))
+    (global (mut funcref) (ref.func $start))
+    (export \"start\" (func $start))
+    (export \"memory\" (memory 0))
+    (start $start)
+    (elem (i32.const 0) $start)
+    (data (i64.const 8) \"data\")
+    (func $start (call $f (global.get $h)))
+)
+",
+            )?)?,
+            WasmFeatures::default(),
+        )?;
+        assert_eq!(2, module.func_types.len());
+        assert_eq!(3, module.globals.len());
+        assert_eq!(
+            vec![instr::Expression(vec![
+                instr::Instruction::RefFunc(1),
+                instr::Instruction::End,
+            ])],
+            module.elems[0].init,
+        );
+        assert_eq!(
+            ty::Limit {
+                address: ty::Address::I64,
+                min: 1,
+                max: Some(2),
+            },
+            module.memories[0].0,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn validate_errors() {
+        let message = |wat: &str| {
+            let module = binary::Module::decode(wat::parse_str(wat).unwrap()).unwrap();
+            format!(
+                "{:#}",
+                validate(&module, WasmFeatures::default()).unwrap_err()
+            )
+        };
+        assert_eq!(
+            "duplicate export name \"f\"",
+            message("(module (func (export \"f\")) (global (export \"f\") i32 (i32.const 0)))"),
+        );
+        assert_eq!(
+            "start function must have type [] -> []",
+            message("(module (func $f (param i32)) (start $f))"),
+        );
+        assert_eq!(
+            "in memory 0: memory size must be at most 65536 pages (4GiB)",
+            message("(module (memory 65537))"),
+        );
+        assert_eq!(
+            "in memory 0: size minimum must not be greater than maximum",
+            message("(module (memory i64 2 1))"),
+        );
+        assert_eq!(
+            "in table 0: size minimum must not be greater than maximum",
+            message("(module (table 2 1 funcref))"),
+        );
+        assert_eq!(
+            "in global 0: at instruction 1 `End`: type mismatch: expected [i64] but found [i32]",
+            message("(module (global i64 (i32.const 0)))"),
+        );
+        assert_eq!(
+            "in global 1: at instruction 0 `GlobalGet(0)`: constant expression required",
+            message("(module (global (mut i32) (i32.const 0)) (global i32 (global.get 0)))"),
+        );
+        assert_eq!(
+            "in global 0: at instruction 0 `GlobalGet(1)`: unknown global 1",
+            message("(module (global i32 (global.get 1)) (global i32 (i32.const 0)))"),
+        );
+        assert_eq!(
+            "in global 0: at instruction 1 `I32Eqz`: constant expression required",
+            message("(module (global i32 (i32.eqz (i32.const 0))))"),
+        );
+        assert_eq!(
+            "in data segment 0: at instruction 1 `End`: type mismatch: expected [i64] but found [i32]",
+            message("(module (memory i64 1) (data (i32.const 0)))"),
+        );
+        assert_eq!(
+            "in element segment 0: type mismatch: segment of (ref null func) for table of (ref null extern)",
+            message("(module (table 1 externref) (elem (i32.const 0) func) (func))"),
+        );
+        assert_eq!(
+            "in element segment 0: at instruction 1 `End`: type mismatch: expected [(ref null func)] but found [(ref null extern)]",
+            message("(module (elem funcref (ref.null extern)))"),
+        );
+        assert_eq!(
+            "unknown function 1",
+            message("(module (func) (export \"f\" (func 0)) (export \"g\" (func 1)))"),
+        );
+    }
+
+    #[test]
+    fn validate_features() -> anyhow::Result<()> {
+        let message = |wat: &str, features| -> anyhow::Result<_> {
+            let module = binary::Module::decode(wat::parse_str(wat)?)?;
+            Ok(format!("{:#}", validate(&module, features).unwrap_err()))
+        };
+        let mvp = WasmFeatures::mvp();
+        assert_eq!(
+            "feature mutable-globals not enabled",
+            message("(module (import \"env\" \"g\" (global (mut i32))))", mvp)?,
+        );
+        assert_eq!(
+            "feature multi-memory not enabled",
+            message("(module (memory 1) (memory 1))", WasmFeatures::wasm2())?,
+        );
+        assert_eq!(
+            "in global 1: at instruction 0 `GlobalGet(0)`: feature gc not enabled",
+            message(
+                "(module (global i32 (i32.const 0)) (global i32 (global.get 0)))",
+                WasmFeatures::wasm2(),
+            )?,
+        );
+        assert_eq!(
+            "in global 0: at instruction 2 `I32Add`: feature extended-const not enabled",
+            message(
+                "(module (global i32 (i32.add (i32.const 0) (i32.const 1))))",
+                mvp,
+            )?,
+        );
+        let wasm = wat::parse_str("(module (memory 1 2) (func (result i32) i32.const 0))")?;
+        validate(&binary::Module::decode(wasm)?, mvp)?;
+        let wasm = wat::parse_str(
+            "(module (type (func)) (table 1 funcref) (func (call_indirect (type 0) (i32.const 0))) (elem (i32.const 0) 0))",
+        )?;
+        validate(&binary::Module::decode_with_features(wasm, mvp)?, mvp)?;
+        Ok(())
+    }
+}
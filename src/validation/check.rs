@@ -0,0 +1,877 @@
+use anyhow::Context as _;
+
+use super::instr::{BlockType, Expression, Instruction, MemArg};
+use super::ty::{self, Value};
+use super::types;
+use crate::features::{Feature, WasmFeatures};
+
+/// Type-checks a function body by the validation algorithm in the appendix of
+/// the specification, with `Value::Bottom` standing for unknown types on the
+/// polymorphic stack of unreachable code
+pub(super) fn func(
+    module: &super::Module,
+    features: WasmFeatures,
+    func: &super::Func,
+) -> anyhow::Result<()> {
+    let locals = func.ty.params.iter().chain(&func.locals).cloned().collect();
+    let params = func.ty.params.len();
+    Checker::new(module, features, locals, &func.ty.returns, None, params).expr(&func.expr)
+}
+
+/// Type-checks a constant expression producing a value of the expected type,
+/// which may only read the first `global_count` globals
+pub(super) fn const_expr(
+    module: &super::Module,
+    features: WasmFeatures,
+    expr: &Expression,
+    expected: &Value,
+    global_count: usize,
+) -> anyhow::Result<()> {
+    let returns = std::slice::from_ref(expected);
+    Checker::new(module, features, vec![], returns, Some(global_count), 0).expr(expr)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+/// Entry of the control stack
+struct Ctrl {
+    kind: Kind,
+    start_types: Vec<Value>,
+    end_types: Vec<Value>,
+    /// Height of the operand stack at the start of the block
+    height: usize,
+    /// Height of the stack of initialized locals at the start of the block
+    init_height: usize,
+    /// Whether the rest of the block is unreachable, making the operand
+    /// stack polymorphic
+    is_unreachable: bool,
+}
+
+impl Ctrl {
+    /// Returns the types of the values a branch to the block takes
+    fn label_types(&self) -> &[Value] {
+        match self.kind {
+            Kind::Loop => &self.start_types,
+            _ => &self.end_types,
+        }
+    }
+}
+
+struct Checker<'a> {
+    module: &'a super::Module,
+    features: WasmFeatures,
+    locals: Vec<Value>,
+    /// Whether each local is initialized, which non-defaultable locals are
+    /// only once set
+    inits: Vec<bool>,
+    /// Non-defaultable locals initialized in the enclosing blocks, which
+    /// become uninitialized again at the end of the block setting them
+    init_stack: Vec<u32>,
+    returns: &'a [Value],
+    vals: Vec<Value>,
+    ctrls: Vec<Ctrl>,
+    /// Number of globals visible to a constant expression, or `None` when
+    /// checking a function body
+    const_globals: Option<usize>,
+}
+
+/// Returns the type of the length operand of a copy between tables or
+/// memories of the address types
+fn min_address(dst: ty::Address, src: ty::Address) -> Value {
+    match (dst, src) {
+        (ty::Address::I64, ty::Address::I64) => ty::Address::I64.value(),
+        _ => ty::Address::I32.value(),
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn new(
+        module: &'a super::Module,
+        features: WasmFeatures,
+        locals: Vec<Value>,
+        returns: &'a [Value],
+        const_globals: Option<usize>,
+        params: usize,
+    ) -> Self {
+        let inits = locals.iter().enumerate();
+        let inits = inits.map(|(i, local)| i < params || local.is_defaultable());
+        Self {
+            module,
+            features,
+            inits: inits.collect(),
+            init_stack: vec![],
+            locals,
+            returns,
+            vals: vec![],
+            ctrls: vec![],
+            const_globals,
+        }
+    }
+
+    fn expr(mut self, expr: &Expression) -> anyhow::Result<()> {
+        self.push_ctrl(Kind::Block, vec![], self.returns.to_vec());
+        for (i, instr) in expr.0.iter().enumerate() {
+            anyhow::ensure!(
+                !self.ctrls.is_empty(),
+                "at instruction {i} `{instr:?}`: instruction after the end of the function"
+            );
+            let mut check = || {
+                if let Some(global_count) = self.const_globals {
+                    self.check_constant(instr, global_count)?;
+                }
+                self.instr(instr)
+            };
+            check().with_context(|| format!("at instruction {i} `{instr:?}`"))?;
+        }
+        anyhow::ensure!(self.ctrls.is_empty(), "function body without `end`");
+        Ok(())
+    }
+
+    /// Checks that an instruction may appear in a constant expression
+    fn check_constant(&self, instr: &Instruction, global_count: usize) -> anyhow::Result<()> {
+        use Instruction::*;
+        match instr {
+            I32Const(_) | I64Const(_) | F32Const(_) | F64Const(_) | RefNull(_) | RefFunc(_)
+            | End => {}
+            I32Add | I32Sub | I32Mul | I64Add | I64Sub | I64Mul => {
+                self.features.require(Feature::ExtendedConst)?;
+            }
+            GlobalGet(index) => {
+                anyhow::ensure!((*index as usize) < global_count, "unknown global {index}");
+                // Only imported globals are available before the GC proposal
+                let import_count = self.module.globals.len() - self.module.global_inits.len();
+                if import_count <= *index as usize {
+                    self.features.require(Feature::Gc)?;
+                }
+                anyhow::ensure!(
+                    !self.global(*index)?.is_mutable,
+                    "constant expression required"
+                );
+            }
+            _ => anyhow::bail!("constant expression required"),
+        }
+        Ok(())
+    }
+
+    fn ctrl(&self) -> &Ctrl {
+        self.ctrls.last().expect("checked before each instruction")
+    }
+
+    /// Checks that the operand stack of the current block ends with the
+    /// expected types, or consists of them exactly
+    fn expect_vals(&self, expected: &[Value], is_exact: bool) -> anyhow::Result<()> {
+        let ctrl = self.ctrl();
+        let stack = &self.vals[ctrl.height..];
+        let count = expected.len().min(stack.len());
+        let is_len_matched = (!is_exact || stack.len() <= expected.len())
+            && (ctrl.is_unreachable || expected.len() <= stack.len());
+        let expected_tail = &expected[expected.len() - count..];
+        let stack_tail = &stack[stack.len() - count..];
+        let is_types_matched = (expected_tail.iter().zip(stack_tail))
+            .all(|(expected, actual)| actual.matches(expected));
+        anyhow::ensure!(
+            is_len_matched && is_types_matched,
+            "type mismatch: expected {} but found {}",
+            types(expected),
+            types(stack),
+        );
+        Ok(())
+    }
+
+    fn push_vals(&mut self, values: &[Value]) {
+        self.vals.extend_from_slice(values);
+    }
+
+    fn pop_vals(&mut self, expected: &[Value]) -> anyhow::Result<Vec<Value>> {
+        self.expect_vals(expected, false)?;
+        let count = expected.len().min(self.vals.len() - self.ctrl().height);
+        let mut popped = vec![Value::Bottom; expected.len() - count];
+        popped.extend(self.vals.drain(self.vals.len() - count..));
+        Ok(popped)
+    }
+
+    fn pop_val(&mut self, expected: Value) -> anyhow::Result<Value> {
+        Ok(self.pop_vals(&[expected])?.remove(0))
+    }
+
+    fn pop_any(&mut self) -> anyhow::Result<Value> {
+        let ctrl = self.ctrl();
+        if self.vals.len() == ctrl.height {
+            anyhow::ensure!(
+                ctrl.is_unreachable,
+                "type mismatch: expected a value but found []"
+            );
+            return Ok(Value::Bottom);
+        }
+        Ok(self.vals.pop().unwrap())
+    }
+
+    fn push_ctrl(&mut self, kind: Kind, start_types: Vec<Value>, end_types: Vec<Value>) {
+        let height = self.vals.len();
+        self.push_vals(&start_types);
+        self.ctrls.push(Ctrl {
+            kind,
+            start_types,
+            end_types,
+            height,
+            init_height: self.init_stack.len(),
+            is_unreachable: false,
+        });
+    }
+
+    fn pop_ctrl(&mut self) -> anyhow::Result<Ctrl> {
+        let ctrl = self.ctrl();
+        self.expect_vals(&ctrl.end_types, true)?;
+        let ctrl = self.ctrls.pop().unwrap();
+        self.vals.truncate(ctrl.height);
+        for index in self.init_stack.drain(ctrl.init_height..) {
+            self.inits[index as usize] = false;
+        }
+        Ok(ctrl)
+    }
+
+    fn unreachable(&mut self) {
+        let height = self.ctrl().height;
+        self.vals.truncate(height);
+        self.ctrls.last_mut().unwrap().is_unreachable = true;
+    }
+
+    fn label_types(&self, label: u32) -> anyhow::Result<Vec<Value>> {
+        let index = (self.ctrls.len().checked_sub(label as usize + 1))
+            .with_context(|| format!("unknown label {label}"))?;
+        Ok(self.ctrls[index].label_types().to_vec())
+    }
+
+    fn block_type(&self, block_type: &BlockType) -> anyhow::Result<(Vec<Value>, Vec<Value>)> {
+        Ok(match block_type {
+            BlockType::Empty => (vec![], vec![]),
+            BlockType::Value(value) => (vec![], vec![super::resolve(&self.module.types, value)?]),
+            BlockType::Index(index) => {
+                let func = super::func_type(&self.module.types, *index)?;
+                (func.params, func.returns)
+            }
+        })
+    }
+
+    fn local(&self, index: u32) -> anyhow::Result<Value> {
+        let local = self.locals.get(index as usize);
+        local
+            .cloned()
+            .with_context(|| format!("unknown local {index}"))
+    }
+
+    fn set_local(&mut self, index: u32) {
+        if !self.inits[index as usize] {
+            self.inits[index as usize] = true;
+            self.init_stack.push(index);
+        }
+    }
+
+    fn global(&self, index: u32) -> anyhow::Result<&'a ty::Global> {
+        let global = self.module.globals.get(index as usize);
+        global.with_context(|| format!("unknown global {index}"))
+    }
+
+    fn func_type(&self, index: u32) -> anyhow::Result<&'a ty::Defined> {
+        let func = self.module.func_types.get(index as usize);
+        func.with_context(|| format!("unknown function {index}"))
+    }
+
+    fn table(&self, index: u32) -> anyhow::Result<&'a ty::Table> {
+        let table = self.module.tables.get(index as usize);
+        table.with_context(|| format!("unknown table {index}"))
+    }
+
+    fn memory(&self, index: u32) -> anyhow::Result<&'a ty::Memory> {
+        let memory = self.module.memories.get(index as usize);
+        memory.with_context(|| format!("unknown memory {index}"))
+    }
+
+    fn elem(&self, index: u32) -> anyhow::Result<&'a super::Element> {
+        let elem = self.module.elems.get(index as usize);
+        elem.with_context(|| format!("unknown elem segment {index}"))
+    }
+
+    /// Checks that a data segment exists, which is known only from the data
+    /// count section as function bodies precede the data section
+    fn data(&self, index: u32) -> anyhow::Result<()> {
+        let count = self.module.data_count;
+        let count = count.context("data count section required")?;
+        anyhow::ensure!(index < count, "unknown data segment {index}");
+        Ok(())
+    }
+
+    /// Checks a memory argument for an access of `2^natural` bytes, returning
+    /// the address type of the memory
+    fn mem_arg(&self, mem_arg: &MemArg, natural: u32) -> anyhow::Result<Value> {
+        let address = self.memory(mem_arg.memory)?.0.address;
+        anyhow::ensure!(
+            mem_arg.align <= natural,
+            "alignment must not be larger than natural"
+        );
+        anyhow::ensure!(
+            ty::Address::I64 == address || mem_arg.offset <= u32::MAX.into(),
+            "offset out of range"
+        );
+        Ok(address.value())
+    }
+
+    fn load(&mut self, mem_arg: &MemArg, natural: u32, value: Value) -> anyhow::Result<()> {
+        let address = self.mem_arg(mem_arg, natural)?;
+        self.op(&[address], &[value])
+    }
+
+    fn store(&mut self, mem_arg: &MemArg, natural: u32, value: Value) -> anyhow::Result<()> {
+        let address = self.mem_arg(mem_arg, natural)?;
+        self.op(&[address, value], &[])
+    }
+
+    fn op(&mut self, params: &[Value], returns: &[Value]) -> anyhow::Result<()> {
+        self.pop_vals(params)?;
+        self.push_vals(returns);
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: &Instruction) -> anyhow::Result<()> {
+        use Instruction::*;
+        const I32: Value = Value::Num(ty::Number::I32);
+        const I64: Value = Value::Num(ty::Number::I64);
+        const F32: Value = Value::Num(ty::Number::F32);
+        const F64: Value = Value::Num(ty::Number::F64);
+        match instr {
+            Unreachable => self.unreachable(),
+            Nop => {}
+            Block(block_type) | Loop(block_type) | If(block_type) => {
+                let (params, returns) = self.block_type(block_type)?;
+                let kind = match instr {
+                    Block(_) => Kind::Block,
+                    Loop(_) => Kind::Loop,
+                    _ => {
+                        self.pop_val(I32)?;
+                        Kind::If
+                    }
+                };
+                self.pop_vals(&params)?;
+                self.push_ctrl(kind, params, returns);
+            }
+            Else => {
+                anyhow::ensure!(Kind::If == self.ctrl().kind, "`else` without `if`");
+                let ctrl = self.pop_ctrl()?;
+                self.push_ctrl(Kind::Else, ctrl.start_types, ctrl.end_types);
+            }
+            End => {
+                let ctrl = self.pop_ctrl()?;
+                anyhow::ensure!(
+                    Kind::If != ctrl.kind || ctrl.start_types == ctrl.end_types,
+                    "type mismatch: `if` without `else` takes {} but returns {}",
+                    types(&ctrl.start_types),
+                    types(&ctrl.end_types),
+                );
+                self.push_vals(&ctrl.end_types);
+            }
+            Br(label) => {
+                let label_types = self.label_types(*label)?;
+                self.pop_vals(&label_types)?;
+                self.unreachable();
+            }
+            BrIf(label) => {
+                self.pop_val(I32)?;
+                let label_types = self.label_types(*label)?;
+                self.pop_vals(&label_types)?;
+                self.push_vals(&label_types);
+            }
+            BrTable(labels, default) => {
+                self.pop_val(I32)?;
+                let arity = self.label_types(*default)?.len();
+                for label in labels {
+                    let label_types = self.label_types(*label)?;
+                    anyhow::ensure!(
+                        arity == label_types.len(),
+                        "type mismatch: label {label} takes {} but the default label takes {arity} values",
+                        types(&label_types),
+                    );
+                    let vals = self.pop_vals(&label_types)?;
+                    self.push_vals(&vals);
+                }
+                let label_types = self.label_types(*default)?;
+                self.pop_vals(&label_types)?;
+                self.unreachable();
+            }
+            Return => {
+                self.pop_vals(self.returns)?;
+                self.unreachable();
+            }
+            Drop => {
+                self.pop_any()?;
+            }
+            Select => {
+                self.pop_val(I32)?;
+                let first = self.pop_any()?;
+                let second = self.pop_any()?;
+                let is_selectable = |value: &Value| !matches!(value, Value::Ref(_));
+                anyhow::ensure!(
+                    is_selectable(&first) && is_selectable(&second),
+                    "type mismatch: `select` without types takes numeric or vector operands but found {second} and {first}",
+                );
+                anyhow::ensure!(
+                    first.matches(&second),
+                    "type mismatch: `select` operands {first} and {second} differ",
+                );
+                self.push_vals(&[if Value::Bottom == first {
+                    second
+                } else {
+                    first
+                }]);
+            }
+            LocalGet(index) => {
+                let local = self.local(*index)?;
+                anyhow::ensure!(self.inits[*index as usize], "uninitialized local {index}",);
+                self.push_vals(&[local]);
+            }
+            LocalSet(index) => {
+                let local = self.local(*index)?;
+                self.pop_val(local)?;
+                self.set_local(*index);
+            }
+            LocalTee(index) => {
+                let local = self.local(*index)?;
+                let value = self.pop_val(local)?;
+                self.set_local(*index);
+                self.push_vals(&[value]);
+            }
+            Call(index) => {
+                let func = self
+                    .func_type(*index)?
+                    .func()
+                    .expect("checked when lowering");
+                self.op(&func.params, &func.returns)?;
+            }
+            CallIndirect(type_index, table) => {
+                let table = self.table(*table)?;
+                let func_ref = ty::Reference {
+                    is_nullable: true,
+                    heap: ty::Heap::Abstract(ty::AbsHeap::Func),
+                };
+                anyhow::ensure!(
+                    table.reference.matches(&func_ref),
+                    "type mismatch: indirect call through a table of {}",
+                    table.reference,
+                );
+                self.pop_val(table.limit.address.value())?;
+                let func = super::func_type(&self.module.types, *type_index)?;
+                self.op(&func.params, &func.returns)?;
+            }
+            RefNull(heap) => {
+                let value = ty::Value::Ref(ty::Reference {
+                    is_nullable: true,
+                    heap: heap.clone(),
+                });
+                let value = super::resolve(&self.module.types, &value)?;
+                self.push_vals(&[value]);
+            }
+            RefIsNull => {
+                let value = self.pop_any()?;
+                anyhow::ensure!(
+                    matches!(value, Value::Ref(_) | Value::Bottom),
+                    "type mismatch: expected a reference but found {value}",
+                );
+                self.push_vals(&[I32]);
+            }
+            RefFunc(index) => {
+                let defined = self.func_type(*index)?.clone();
+                anyhow::ensure!(
+                    self.module.refs.contains(index),
+                    "undeclared function reference {index}",
+                );
+                self.push_vals(&[Value::Ref(ty::Reference {
+                    is_nullable: false,
+                    heap: ty::Heap::Defined(ty::TypeUse::Defined(defined)),
+                })]);
+            }
+            SelectTyped(values) => {
+                let [value] = &values[..] else {
+                    anyhow::bail!("invalid result arity");
+                };
+                let value = super::resolve(&self.module.types, value)?;
+                self.op(&[value.clone(), value.clone(), I32], &[value])?;
+            }
+            GlobalGet(index) => {
+                let global = self.global(*index)?;
+                self.push_vals(std::slice::from_ref(&global.value));
+            }
+            GlobalSet(index) => {
+                let global = self.global(*index)?;
+                anyhow::ensure!(global.is_mutable, "global {index} is immutable");
+                self.pop_val(global.value.clone())?;
+            }
+            I32Load(mem_arg) => self.load(mem_arg, 2, I32)?,
+            I64Load(mem_arg) => self.load(mem_arg, 3, I64)?,
+            F32Load(mem_arg) => self.load(mem_arg, 2, F32)?,
+            F64Load(mem_arg) => self.load(mem_arg, 3, F64)?,
+            I32Load8S(mem_arg) | I32Load8U(mem_arg) => self.load(mem_arg, 0, I32)?,
+            I32Load16S(mem_arg) | I32Load16U(mem_arg) => self.load(mem_arg, 1, I32)?,
+            I64Load8S(mem_arg) | I64Load8U(mem_arg) => self.load(mem_arg, 0, I64)?,
+            I64Load16S(mem_arg) | I64Load16U(mem_arg) => self.load(mem_arg, 1, I64)?,
+            I64Load32S(mem_arg) | I64Load32U(mem_arg) => self.load(mem_arg, 2, I64)?,
+            I32Store(mem_arg) => self.store(mem_arg, 2, I32)?,
+            I64Store(mem_arg) => self.store(mem_arg, 3, I64)?,
+            F32Store(mem_arg) => self.store(mem_arg, 2, F32)?,
+            F64Store(mem_arg) => self.store(mem_arg, 3, F64)?,
+            I32Store8(mem_arg) => self.store(mem_arg, 0, I32)?,
+            I32Store16(mem_arg) => self.store(mem_arg, 1, I32)?,
+            I64Store8(mem_arg) => self.store(mem_arg, 0, I64)?,
+            I64Store16(mem_arg) => self.store(mem_arg, 1, I64)?,
+            I64Store32(mem_arg) => self.store(mem_arg, 2, I64)?,
+            TableGet(table) => {
+                let table = self.table(*table)?;
+                let reference = Value::Ref(table.reference.clone());
+                self.op(&[table.limit.address.value()], &[reference])?;
+            }
+            TableSet(table) => {
+                let table = self.table(*table)?;
+                let reference = Value::Ref(table.reference.clone());
+                self.op(&[table.limit.address.value(), reference], &[])?;
+            }
+            TableInit(elem, table) => {
+                let table = self.table(*table)?;
+                let elem = self.elem(*elem)?;
+                anyhow::ensure!(
+                    elem.ty.matches(&table.reference),
+                    "type mismatch: segment of {} for table of {}",
+                    elem.ty,
+                    table.reference,
+                );
+                self.op(&[table.limit.address.value(), I32, I32], &[])?;
+            }
+            ElemDrop(elem) => {
+                self.elem(*elem)?;
+            }
+            TableCopy(dst, src) => {
+                let (dst, src) = (self.table(*dst)?, self.table(*src)?);
+                anyhow::ensure!(
+                    src.reference.matches(&dst.reference),
+                    "type mismatch: copying {} into a table of {}",
+                    src.reference,
+                    dst.reference,
+                );
+                let (dst, src) = (dst.limit.address, src.limit.address);
+                self.op(&[dst.value(), src.value(), min_address(dst, src)], &[])?;
+            }
+            TableGrow(table) => {
+                let table = self.table(*table)?;
+                let address = table.limit.address.value();
+                let reference = Value::Ref(table.reference.clone());
+                self.op(&[reference, address.clone()], &[address])?;
+            }
+            TableSize(table) => {
+                let address = self.table(*table)?.limit.address.value();
+                self.push_vals(&[address]);
+            }
+            TableFill(table) => {
+                let table = self.table(*table)?;
+                let address = table.limit.address.value();
+                let reference = Value::Ref(table.reference.clone());
+                self.op(&[address.clone(), reference, address], &[])?;
+            }
+            MemorySize(memory) => {
+                let address = self.memory(*memory)?.0.address.value();
+                self.push_vals(&[address]);
+            }
+            MemoryGrow(memory) => {
+                let address = self.memory(*memory)?.0.address.value();
+                self.op(
+                    std::slice::from_ref(&address),
+                    std::slice::from_ref(&address),
+                )?;
+            }
+            MemoryInit(data, memory) => {
+                self.data(*data)?;
+                let address = self.memory(*memory)?.0.address.value();
+                self.op(&[address, I32, I32], &[])?;
+            }
+            DataDrop(data) => self.data(*data)?,
+            MemoryCopy(dst, src) => {
+                let dst = self.memory(*dst)?.0.address;
+                let src = self.memory(*src)?.0.address;
+                self.op(&[dst.value(), src.value(), min_address(dst, src)], &[])?;
+            }
+            MemoryFill(memory) => {
+                let address = self.memory(*memory)?.0.address.value();
+                self.op(&[address.clone(), I32, address], &[])?;
+            }
+            I32Const(_) => self.push_vals(&[I32]),
+            I64Const(_) => self.push_vals(&[I64]),
+            F32Const(_) => self.push_vals(&[F32]),
+            F64Const(_) => self.push_vals(&[F64]),
+            I32Eqz | I32Clz | I32Ctz | I32Popcnt | I32Extend8S | I32Extend16S => {
+                self.op(&[I32], &[I32])?
+            }
+            I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU | I32GeS
+            | I32GeU | I32Add | I32Sub | I32Mul | I32DivS | I32DivU | I32RemS | I32RemU
+            | I32And | I32Or | I32Xor | I32Shl | I32ShrS | I32ShrU | I32Rotl | I32Rotr => {
+                self.op(&[I32, I32], &[I32])?
+            }
+            I64Eqz | I32WrapI64 => self.op(&[I64], &[I32])?,
+            I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU | I64LeS | I64LeU | I64GeS
+            | I64GeU => self.op(&[I64, I64], &[I32])?,
+            I64Clz | I64Ctz | I64Popcnt | I64Extend8S | I64Extend16S | I64Extend32S => {
+                self.op(&[I64], &[I64])?
+            }
+            I64Add | I64Sub | I64Mul | I64DivS | I64DivU | I64RemS | I64RemU | I64And | I64Or
+            | I64Xor | I64Shl | I64ShrS | I64ShrU | I64Rotl | I64Rotr => {
+                self.op(&[I64, I64], &[I64])?
+            }
+            F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge => self.op(&[F32, F32], &[I32])?,
+            F64Eq | F64Ne | F64Lt | F64Gt | F64Le | F64Ge => self.op(&[F64, F64], &[I32])?,
+            F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt => {
+                self.op(&[F32], &[F32])?
+            }
+            F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign => {
+                self.op(&[F32, F32], &[F32])?
+            }
+            F64Abs | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt => {
+                self.op(&[F64], &[F64])?
+            }
+            F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => {
+                self.op(&[F64, F64], &[F64])?
+            }
+            I32TruncF32S | I32TruncF32U | I32ReinterpretF32 | I32TruncSatF32S | I32TruncSatF32U => {
+                self.op(&[F32], &[I32])?
+            }
+            I32TruncF64S | I32TruncF64U | I32TruncSatF64S | I32TruncSatF64U => {
+                self.op(&[F64], &[I32])?
+            }
+            I64ExtendI32S | I64ExtendI32U => self.op(&[I32], &[I64])?,
+            I64TruncF32S | I64TruncF32U | I64TruncSatF32S | I64TruncSatF32U => {
+                self.op(&[F32], &[I64])?
+            }
+            I64TruncF64S | I64TruncF64U | I64ReinterpretF64 | I64TruncSatF64S | I64TruncSatF64U => {
+                self.op(&[F64], &[I64])?
+            }
+            F32ConvertI32S | F32ConvertI32U | F32ReinterpretI32 => self.op(&[I32], &[F32])?,
+            F32ConvertI64S | F32ConvertI64U => self.op(&[I64], &[F32])?,
+            F32DemoteF64 => self.op(&[F64], &[F32])?,
+            F64ConvertI32S | F64ConvertI32U => self.op(&[I32], &[F64])?,
+            F64ConvertI64S | F64ConvertI64U | F64ReinterpretI64 => self.op(&[I64], &[F64])?,
+            F64PromoteF32 => self.op(&[F32], &[F64])?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{binary, validation};
+
+    fn check(source: &str) -> anyhow::Result<()> {
+        let module = binary::Module::decode(wat::parse_str(source)?)?;
+        validation::validate(&module, Default::default()).map(drop)
+    }
+
+    #[test]
+    fn check_valid() -> anyhow::Result<()> {
+        let fixtures = [
+            "(module (func))",
+            "(module (func (param i32) (result i32) local.get 0 i32.const 1 i32.add))",
+            "(module (func (result i32) unreachable))",
+            "(module (func (result i32) unreachable i32.add))",
+            "(module (func (result i32) (block (result i32) (br 0 (i32.const 1)))))",
+            "(module (func (param i32) (result i32) (local.get 0) (loop (param i32) (result i32) (br_if 0 (local.get 0)))))",
+            "(module (func (param i32) (result i32) (if (result i32) (local.get 0) (then (i32.const 1)) (else (i32.const 2)))))",
+            "(module (func (param i32) (result i32) (if (param i32) (result i32) (local.get 0) (local.get 0) (then))))",
+            "(module (func (param i32) (result i32) (block (block (br_table 0 1 (local.get 0)))) (i32.const 0)))",
+            "(module (func (result i32) (return (i32.const 1)) i32.eqz))",
+            "(module (func (param i32) (result i32) (select (local.tee 0 (i32.const 1)) (local.get 0) (i32.const 0))))",
+            "(module (func (result i32) unreachable select))",
+            "(module (memory 1) (func (param i32) (result i64) (i64.store16 (local.get 0) (i64.const 1)) (i64.load32_u align=4 (local.get 0))))",
+            "(module (memory i64 1) (func (result i64) (memory.grow (memory.size))))",
+            "(module (global $g (mut f32) (f32.const 0)) (func (global.set $g (f32.demote_f64 (f64.const 1)))))",
+            "(module (type $t (func (param i64) (result i64))) (table 1 funcref) (func (type $t) (call_indirect (type $t) (local.get 0) (i32.const 0))))",
+            "(module (func $f (result (ref func)) (ref.func $f)) (elem declare func $f))",
+            "(module (func (result i32) (ref.is_null (ref.null extern))))",
+            "(module (func (param externref) (result externref) (select (result externref) (local.get 0) (ref.null extern) (i32.const 1))))",
+            "(module (func $f (export \"f\") (drop (ref.func $f))))",
+            "(module (global funcref (ref.func $f)) (func $f (drop (ref.func $f))))",
+            "(module (memory 1) (data \"a\") (func (memory.init 0 (i32.const 0) (i32.const 0) (i32.const 1)) (data.drop 0)))",
+            "(module (memory i64 1) (func (memory.copy (i64.const 0) (i64.const 1) (i64.const 1)) (memory.fill (i64.const 0) (i32.const 0) (i64.const 1))))",
+            "(module (memory $a i64 1) (memory $b 1) (func (memory.copy $a $b (i64.const 0) (i32.const 1) (i32.const 1))))",
+            "(module (table 1 funcref) (elem funcref (ref.null func)) (func (table.init 0 (i32.const 0) (i32.const 0) (i32.const 1)) (elem.drop 0)))",
+            "(module (table $t 1 funcref) (func (result i32) (table.grow $t (ref.null func) (i32.const 1)) (table.fill $t (i32.const 0) (ref.null func) (table.size $t))))",
+            "(module (table $a 1 funcref) (table $b 1 (ref null func)) (func (table.copy $a $b (i32.const 0) (i32.const 0) (i32.const 1))))",
+            "(module (table $t i64 1 externref) (func (param externref) (result externref) (table.set $t (i64.const 0) (local.get 0)) (table.get $t (i64.const 0))))",
+            "(module (func $f (param (ref func)) (result (ref func)) (local.get 0)))",
+            "(module (func $f (export \"f\") (local (ref func)) (local.set 0 (ref.func $f)) (block (drop (local.get 0)))))",
+            "(module (func $f (export \"f\") (local (ref func)) (block (drop (local.tee 0 (ref.func $f))) (drop (local.get 0)))))",
+        ];
+        for fixture in fixtures {
+            check(fixture).map_err(|error| anyhow::anyhow!("{fixture}: {error:#}"))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn check_invalid() {
+        let message = |source| match check(source) {
+            Ok(()) => panic!("{source} is valid"),
+            Err(error) => format!("{error:#}"),
+        };
+        assert_eq!(
+            "in function body 0: at instruction 1 `I32Add`: type mismatch: expected [i32 i32] but found [i32]",
+            message("(module (func (result i32) i32.const 1 i32.add))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 2 `End`: type mismatch: expected [] but found [i32 i32]",
+            message("(module (func i32.const 1 i32.const 2))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `Br(1)`: type mismatch: expected [i32] but found []",
+            message("(module (func (result i32) (block (result i32) (br 1)) drop))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `Br(2)`: unknown label 2",
+            message("(module (func (block (br 2))))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 3 `End`: type mismatch: `if` without `else` takes [] but returns [i32]",
+            message(
+                "(module (func (param i32) (result i32) (if (result i32) (local.get 0) (then (i32.const 1)))))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 4 `BrTable([1], 0)`: type mismatch: label 1 takes [i32] but the default label takes 0 values",
+            message(
+                "(module (func (result i32) (block (result i32) (block (br_table 1 0 (i32.const 0) (i32.const 0))) (i32.const 0))))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 0 `LocalGet(1)`: unknown local 1",
+            message("(module (func (param i32) local.get 1 drop))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 0 `Drop`: type mismatch: expected a value but found []",
+            message("(module (func drop))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 4 `LocalSet(0)`: type mismatch: expected [i64] but found [i32]",
+            message(
+                "(module (func (local i64) (block (result i32) unreachable (br_if 0 (i32.const 0)) local.set 0) drop))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `I32Load(MemArg { align: 3, offset: 0, memory: 0 })`: alignment must not be larger than natural",
+            message("(module (memory 1) (func (drop (i32.load align=8 (i32.const 0)))))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `I32Load(MemArg { align: 2, offset: 0, memory: 0 })`: unknown memory 0",
+            message("(module (func (drop (i32.load (i32.const 0)))))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `GlobalSet(0)`: global 0 is immutable",
+            message("(module (global i32 (i32.const 0)) (func (global.set 0 (i32.const 1))))"),
+        );
+        assert_eq!(
+            "in function body 1: at instruction 4 `Call(0)`: type mismatch: expected [(ref func)] but found [(ref null func)]",
+            message(
+                "(module (func $f (param (ref func))) (elem declare func $f) (func (block (result funcref) (br_if 0 (ref.func $f) (i32.const 0)) (call $f) (ref.null func)) drop))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `Call(0)`: type mismatch: expected [i32] but found [i64]",
+            message("(module (func (param i32) (call 0 (i64.const 0))))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `CallIndirect(0, 0)`: type mismatch: indirect call through a table of (ref null extern)",
+            message("(module (table 1 externref) (func (call_indirect (i32.const 0))))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 0 `RefFunc(0)`: undeclared function reference 0",
+            message("(module (func $f (drop (ref.func $f))))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `DataDrop(1)`: unknown data segment 1",
+            message("(module (data \"a\") (func (data.drop 0) (data.drop 1)))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 1 `ElemDrop(1)`: unknown elem segment 1",
+            message("(module (elem func) (func (elem.drop 0) (elem.drop 1)))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 3 `TableInit(0, 0)`: type mismatch: segment of (ref null func) for table of (ref null extern)",
+            message(
+                "(module (table 1 externref) (elem func) (func (table.init 0 (i32.const 0) (i32.const 0) (i32.const 0))))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 3 `TableCopy(0, 1)`: type mismatch: copying (ref null func) into a table of (ref null extern)",
+            message(
+                "(module (table 1 externref) (table 1 funcref) (func (table.copy 0 1 (i32.const 0) (i32.const 0) (i32.const 0))))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 2 `TableSet(0)`: type mismatch: expected [i32 (ref null extern)] but found [i32 (ref null func)]",
+            message(
+                "(module (table 1 externref) (func (table.set 0 (i32.const 0) (ref.null func))))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 3 `MemoryCopy(0, 1)`: type mismatch: expected [i64 i32 i32] but found [i64 i32 i64]",
+            message(
+                "(module (memory i64 1) (memory 1) (func (memory.copy 0 1 (i64.const 0) (i32.const 0) (i64.const 0))))"
+            ),
+        );
+    }
+
+    #[test]
+    fn check_local_inits() {
+        let message = |source| match check(source) {
+            Ok(()) => panic!("{source} is valid"),
+            Err(error) => format!("{error:#}"),
+        };
+        assert_eq!(
+            "in function body 0: at instruction 0 `LocalGet(0)`: uninitialized local 0",
+            message("(module (func (local (ref func)) (drop (local.get 0))))"),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 4 `LocalGet(0)`: uninitialized local 0",
+            message(
+                "(module (func $f (export \"f\") (local (ref func)) (block (local.set 0 (ref.func $f))) (drop (local.get 0))))"
+            ),
+        );
+        assert_eq!(
+            "in function body 0: at instruction 5 `LocalGet(1)`: uninitialized local 1",
+            message(
+                "(module (func $f (export \"f\") (param i32) (local (ref func)) (if (local.get 0) (then (local.set 1 (ref.func $f))) (else (drop (local.get 1))))))"
+            ),
+        );
+    }
+
+    #[test]
+    fn check_data_count() -> anyhow::Result<()> {
+        let mut module = binary::Module::decode(wat::parse_str(
+            "(module (memory 1) (data \"a\") (func (data.drop 0)))",
+        )?)?;
+        module.data_count_section = None;
+        assert_eq!(
+            "in function body 0: at instruction 0 `DataDrop(0)`: data count section required",
+            format!(
+                "{:#}",
+                validation::validate(&module, Default::default()).unwrap_err()
+            ),
+        );
+        module.data_count_section = Some(binary::DataCountSection(2));
+        assert_eq!(
+            "data count and data section have inconsistent lengths: 2 and 1",
+            format!(
+                "{:#}",
+                validation::validate(&module, Default::default()).unwrap_err()
+            ),
+        );
+        Ok(())
+    }
+}
@@ -1,16 +1,484 @@
-#[derive(Debug, PartialEq, Eq)]
+use anyhow::Context as _;
+
+use super::ty;
+use crate::binary;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(ty::Value),
+    Index(u32),
+}
+
+impl From<&binary::instr::BlockType> for BlockType {
+    fn from(block_type: &binary::instr::BlockType) -> Self {
+        match block_type {
+            binary::instr::BlockType::Empty => Self::Empty,
+            binary::instr::BlockType::Value(value) => Self::Value(ty::Value::from(value)),
+            binary::instr::BlockType::Index(index) => Self::Index(*index),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemArg {
+    /// Alignment as the exponent of 2
+    pub align: u32,
+    pub offset: u64,
+    pub memory: u32,
+}
+
+impl From<&binary::instr::MemArg> for MemArg {
+    fn from(mem_arg: &binary::instr::MemArg) -> Self {
+        Self {
+            align: mem_arg.align,
+            offset: mem_arg.offset,
+            memory: mem_arg.memory,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
-    // parametric
+    // control
+    Unreachable,
     Nop,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    CallIndirect(u32, u32), // type index, table index
+
+    // reference
+    RefNull(ty::Heap),
+    RefIsNull,
+    RefFunc(u32),
+
+    // parametric
+    Drop,
+    Select,
+    SelectTyped(Vec<ty::Value>),
 
     // variable
     LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+
+    // table
+    TableGet(u32),
+    TableSet(u32),
+    TableInit(u32, u32), // element index, table index
+    ElemDrop(u32),
+    TableCopy(u32, u32), // destination, source table index
+    TableGrow(u32),
+    TableSize(u32),
+    TableFill(u32),
+
+    // memory
+    I32Load(MemArg),
+    I64Load(MemArg),
+    F32Load(MemArg),
+    F64Load(MemArg),
+    I32Load8S(MemArg),
+    I32Load8U(MemArg),
+    I32Load16S(MemArg),
+    I32Load16U(MemArg),
+    I64Load8S(MemArg),
+    I64Load8U(MemArg),
+    I64Load16S(MemArg),
+    I64Load16U(MemArg),
+    I64Load32S(MemArg),
+    I64Load32U(MemArg),
+    I32Store(MemArg),
+    I64Store(MemArg),
+    F32Store(MemArg),
+    F64Store(MemArg),
+    I32Store8(MemArg),
+    I32Store16(MemArg),
+    I64Store8(MemArg),
+    I64Store16(MemArg),
+    I64Store32(MemArg),
+    MemorySize(u32),
+    MemoryGrow(u32),
+    MemoryInit(u32, u32), // data index, memory index
+    DataDrop(u32),
+    MemoryCopy(u32, u32), // destination, source memory index
+    MemoryFill(u32),
 
     // numeric
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(u32), // IEEE 754 bit pattern
+    F64Const(u64), // IEEE 754 bit pattern
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64LtU,
+    I64GtS,
+    I64GtU,
+    I64LeS,
+    I64LeU,
+    I64GeS,
+    I64GeU,
+    F32Eq,
+    F32Ne,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
     I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32DivU,
+    I32RemS,
+    I32RemU,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I32Rotl,
+    I32Rotr,
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemS,
+    I64RemU,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    I64ShrU,
+    I64Rotl,
+    I64Rotr,
+    F32Abs,
+    F32Neg,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F32Sqrt,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    F32Min,
+    F32Max,
+    F32Copysign,
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Sqrt,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    F64Max,
+    F64Copysign,
+    I32WrapI64,
+    I32TruncF32S,
+    I32TruncF32U,
+    I32TruncF64S,
+    I32TruncF64U,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    I64TruncF32S,
+    I64TruncF32U,
+    I64TruncF64S,
+    I64TruncF64U,
+    F32ConvertI32S,
+    F32ConvertI32U,
+    F32ConvertI64S,
+    F32ConvertI64U,
+    F32DemoteF64,
+    F64ConvertI32S,
+    F64ConvertI32U,
+    F64ConvertI64S,
+    F64ConvertI64U,
+    F64PromoteF32,
+    I32ReinterpretF32,
+    I64ReinterpretF64,
+    F32ReinterpretI32,
+    F64ReinterpretI64,
+    I32Extend8S,
+    I32Extend16S,
+    I64Extend8S,
+    I64Extend16S,
+    I64Extend32S,
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
+}
 
-    End,
+impl TryFrom<&binary::instr::Instruction> for Instruction {
+    type Error = anyhow::Error;
+
+    fn try_from(instr: &binary::instr::Instruction) -> anyhow::Result<Self> {
+        use binary::instr::Instruction as Binary;
+        Ok(match instr {
+            Binary::Unreachable => Self::Unreachable,
+            Binary::Nop => Self::Nop,
+            Binary::Block(block_type) => Self::Block(block_type.into()),
+            Binary::Loop(block_type) => Self::Loop(block_type.into()),
+            Binary::If(block_type) => Self::If(block_type.into()),
+            Binary::Else => Self::Else,
+            Binary::End => Self::End,
+            Binary::Br(value) => Self::Br(*value),
+            Binary::BrIf(value) => Self::BrIf(*value),
+            Binary::BrTable(labels, default) => Self::BrTable(labels.clone(), *default),
+            Binary::Return => Self::Return,
+            Binary::Call(value) => Self::Call(*value),
+            Binary::CallIndirect(ty, table) => Self::CallIndirect(*ty, *table),
+            Binary::RefNull(heap) => Self::RefNull(ty::Heap::from(heap)),
+            Binary::RefIsNull => Self::RefIsNull,
+            Binary::RefFunc(value) => Self::RefFunc(*value),
+            Binary::Drop => Self::Drop,
+            Binary::Select => Self::Select,
+            Binary::SelectTyped(values) => {
+                Self::SelectTyped(values.iter().map(ty::Value::from).collect())
+            }
+            Binary::LocalGet(value) => Self::LocalGet(*value),
+            Binary::LocalSet(value) => Self::LocalSet(*value),
+            Binary::LocalTee(value) => Self::LocalTee(*value),
+            Binary::GlobalGet(value) => Self::GlobalGet(*value),
+            Binary::GlobalSet(value) => Self::GlobalSet(*value),
+            Binary::TableGet(value) => Self::TableGet(*value),
+            Binary::TableSet(value) => Self::TableSet(*value),
+            Binary::TableInit(elem, table) => Self::TableInit(*elem, *table),
+            Binary::ElemDrop(value) => Self::ElemDrop(*value),
+            Binary::TableCopy(dst, src) => Self::TableCopy(*dst, *src),
+            Binary::TableGrow(value) => Self::TableGrow(*value),
+            Binary::TableSize(value) => Self::TableSize(*value),
+            Binary::TableFill(value) => Self::TableFill(*value),
+            Binary::I32Load(mem_arg) => Self::I32Load(mem_arg.into()),
+            Binary::I64Load(mem_arg) => Self::I64Load(mem_arg.into()),
+            Binary::F32Load(mem_arg) => Self::F32Load(mem_arg.into()),
+            Binary::F64Load(mem_arg) => Self::F64Load(mem_arg.into()),
+            Binary::I32Load8S(mem_arg) => Self::I32Load8S(mem_arg.into()),
+            Binary::I32Load8U(mem_arg) => Self::I32Load8U(mem_arg.into()),
+            Binary::I32Load16S(mem_arg) => Self::I32Load16S(mem_arg.into()),
+            Binary::I32Load16U(mem_arg) => Self::I32Load16U(mem_arg.into()),
+            Binary::I64Load8S(mem_arg) => Self::I64Load8S(mem_arg.into()),
+            Binary::I64Load8U(mem_arg) => Self::I64Load8U(mem_arg.into()),
+            Binary::I64Load16S(mem_arg) => Self::I64Load16S(mem_arg.into()),
+            Binary::I64Load16U(mem_arg) => Self::I64Load16U(mem_arg.into()),
+            Binary::I64Load32S(mem_arg) => Self::I64Load32S(mem_arg.into()),
+            Binary::I64Load32U(mem_arg) => Self::I64Load32U(mem_arg.into()),
+            Binary::I32Store(mem_arg) => Self::I32Store(mem_arg.into()),
+            Binary::I64Store(mem_arg) => Self::I64Store(mem_arg.into()),
+            Binary::F32Store(mem_arg) => Self::F32Store(mem_arg.into()),
+            Binary::F64Store(mem_arg) => Self::F64Store(mem_arg.into()),
+            Binary::I32Store8(mem_arg) => Self::I32Store8(mem_arg.into()),
+            Binary::I32Store16(mem_arg) => Self::I32Store16(mem_arg.into()),
+            Binary::I64Store8(mem_arg) => Self::I64Store8(mem_arg.into()),
+            Binary::I64Store16(mem_arg) => Self::I64Store16(mem_arg.into()),
+            Binary::I64Store32(mem_arg) => Self::I64Store32(mem_arg.into()),
+            Binary::MemorySize(value) => Self::MemorySize(*value),
+            Binary::MemoryGrow(value) => Self::MemoryGrow(*value),
+            Binary::MemoryInit(data, memory) => Self::MemoryInit(*data, *memory),
+            Binary::DataDrop(value) => Self::DataDrop(*value),
+            Binary::MemoryCopy(dst, src) => Self::MemoryCopy(*dst, *src),
+            Binary::MemoryFill(value) => Self::MemoryFill(*value),
+            Binary::I32Const(value) => Self::I32Const(*value),
+            Binary::I64Const(value) => Self::I64Const(*value),
+            Binary::F32Const(value) => Self::F32Const(*value),
+            Binary::F64Const(value) => Self::F64Const(*value),
+            Binary::I32Eqz => Self::I32Eqz,
+            Binary::I32Eq => Self::I32Eq,
+            Binary::I32Ne => Self::I32Ne,
+            Binary::I32LtS => Self::I32LtS,
+            Binary::I32LtU => Self::I32LtU,
+            Binary::I32GtS => Self::I32GtS,
+            Binary::I32GtU => Self::I32GtU,
+            Binary::I32LeS => Self::I32LeS,
+            Binary::I32LeU => Self::I32LeU,
+            Binary::I32GeS => Self::I32GeS,
+            Binary::I32GeU => Self::I32GeU,
+            Binary::I64Eqz => Self::I64Eqz,
+            Binary::I64Eq => Self::I64Eq,
+            Binary::I64Ne => Self::I64Ne,
+            Binary::I64LtS => Self::I64LtS,
+            Binary::I64LtU => Self::I64LtU,
+            Binary::I64GtS => Self::I64GtS,
+            Binary::I64GtU => Self::I64GtU,
+            Binary::I64LeS => Self::I64LeS,
+            Binary::I64LeU => Self::I64LeU,
+            Binary::I64GeS => Self::I64GeS,
+            Binary::I64GeU => Self::I64GeU,
+            Binary::F32Eq => Self::F32Eq,
+            Binary::F32Ne => Self::F32Ne,
+            Binary::F32Lt => Self::F32Lt,
+            Binary::F32Gt => Self::F32Gt,
+            Binary::F32Le => Self::F32Le,
+            Binary::F32Ge => Self::F32Ge,
+            Binary::F64Eq => Self::F64Eq,
+            Binary::F64Ne => Self::F64Ne,
+            Binary::F64Lt => Self::F64Lt,
+            Binary::F64Gt => Self::F64Gt,
+            Binary::F64Le => Self::F64Le,
+            Binary::F64Ge => Self::F64Ge,
+            Binary::I32Clz => Self::I32Clz,
+            Binary::I32Ctz => Self::I32Ctz,
+            Binary::I32Popcnt => Self::I32Popcnt,
+            Binary::I32Add => Self::I32Add,
+            Binary::I32Sub => Self::I32Sub,
+            Binary::I32Mul => Self::I32Mul,
+            Binary::I32DivS => Self::I32DivS,
+            Binary::I32DivU => Self::I32DivU,
+            Binary::I32RemS => Self::I32RemS,
+            Binary::I32RemU => Self::I32RemU,
+            Binary::I32And => Self::I32And,
+            Binary::I32Or => Self::I32Or,
+            Binary::I32Xor => Self::I32Xor,
+            Binary::I32Shl => Self::I32Shl,
+            Binary::I32ShrS => Self::I32ShrS,
+            Binary::I32ShrU => Self::I32ShrU,
+            Binary::I32Rotl => Self::I32Rotl,
+            Binary::I32Rotr => Self::I32Rotr,
+            Binary::I64Clz => Self::I64Clz,
+            Binary::I64Ctz => Self::I64Ctz,
+            Binary::I64Popcnt => Self::I64Popcnt,
+            Binary::I64Add => Self::I64Add,
+            Binary::I64Sub => Self::I64Sub,
+            Binary::I64Mul => Self::I64Mul,
+            Binary::I64DivS => Self::I64DivS,
+            Binary::I64DivU => Self::I64DivU,
+            Binary::I64RemS => Self::I64RemS,
+            Binary::I64RemU => Self::I64RemU,
+            Binary::I64And => Self::I64And,
+            Binary::I64Or => Self::I64Or,
+            Binary::I64Xor => Self::I64Xor,
+            Binary::I64Shl => Self::I64Shl,
+            Binary::I64ShrS => Self::I64ShrS,
+            Binary::I64ShrU => Self::I64ShrU,
+            Binary::I64Rotl => Self::I64Rotl,
+            Binary::I64Rotr => Self::I64Rotr,
+            Binary::F32Abs => Self::F32Abs,
+            Binary::F32Neg => Self::F32Neg,
+            Binary::F32Ceil => Self::F32Ceil,
+            Binary::F32Floor => Self::F32Floor,
+            Binary::F32Trunc => Self::F32Trunc,
+            Binary::F32Nearest => Self::F32Nearest,
+            Binary::F32Sqrt => Self::F32Sqrt,
+            Binary::F32Add => Self::F32Add,
+            Binary::F32Sub => Self::F32Sub,
+            Binary::F32Mul => Self::F32Mul,
+            Binary::F32Div => Self::F32Div,
+            Binary::F32Min => Self::F32Min,
+            Binary::F32Max => Self::F32Max,
+            Binary::F32Copysign => Self::F32Copysign,
+            Binary::F64Abs => Self::F64Abs,
+            Binary::F64Neg => Self::F64Neg,
+            Binary::F64Ceil => Self::F64Ceil,
+            Binary::F64Floor => Self::F64Floor,
+            Binary::F64Trunc => Self::F64Trunc,
+            Binary::F64Nearest => Self::F64Nearest,
+            Binary::F64Sqrt => Self::F64Sqrt,
+            Binary::F64Add => Self::F64Add,
+            Binary::F64Sub => Self::F64Sub,
+            Binary::F64Mul => Self::F64Mul,
+            Binary::F64Div => Self::F64Div,
+            Binary::F64Min => Self::F64Min,
+            Binary::F64Max => Self::F64Max,
+            Binary::F64Copysign => Self::F64Copysign,
+            Binary::I32WrapI64 => Self::I32WrapI64,
+            Binary::I32TruncF32S => Self::I32TruncF32S,
+            Binary::I32TruncF32U => Self::I32TruncF32U,
+            Binary::I32TruncF64S => Self::I32TruncF64S,
+            Binary::I32TruncF64U => Self::I32TruncF64U,
+            Binary::I64ExtendI32S => Self::I64ExtendI32S,
+            Binary::I64ExtendI32U => Self::I64ExtendI32U,
+            Binary::I64TruncF32S => Self::I64TruncF32S,
+            Binary::I64TruncF32U => Self::I64TruncF32U,
+            Binary::I64TruncF64S => Self::I64TruncF64S,
+            Binary::I64TruncF64U => Self::I64TruncF64U,
+            Binary::F32ConvertI32S => Self::F32ConvertI32S,
+            Binary::F32ConvertI32U => Self::F32ConvertI32U,
+            Binary::F32ConvertI64S => Self::F32ConvertI64S,
+            Binary::F32ConvertI64U => Self::F32ConvertI64U,
+            Binary::F32DemoteF64 => Self::F32DemoteF64,
+            Binary::F64ConvertI32S => Self::F64ConvertI32S,
+            Binary::F64ConvertI32U => Self::F64ConvertI32U,
+            Binary::F64ConvertI64S => Self::F64ConvertI64S,
+            Binary::F64ConvertI64U => Self::F64ConvertI64U,
+            Binary::F64PromoteF32 => Self::F64PromoteF32,
+            Binary::I32ReinterpretF32 => Self::I32ReinterpretF32,
+            Binary::I64ReinterpretF64 => Self::I64ReinterpretF64,
+            Binary::F32ReinterpretI32 => Self::F32ReinterpretI32,
+            Binary::F64ReinterpretI64 => Self::F64ReinterpretI64,
+            Binary::I32Extend8S => Self::I32Extend8S,
+            Binary::I32Extend16S => Self::I32Extend16S,
+            Binary::I64Extend8S => Self::I64Extend8S,
+            Binary::I64Extend16S => Self::I64Extend16S,
+            Binary::I64Extend32S => Self::I64Extend32S,
+            Binary::I32TruncSatF32S => Self::I32TruncSatF32S,
+            Binary::I32TruncSatF32U => Self::I32TruncSatF32U,
+            Binary::I32TruncSatF64S => Self::I32TruncSatF64S,
+            Binary::I32TruncSatF64U => Self::I32TruncSatF64U,
+            Binary::I64TruncSatF32S => Self::I64TruncSatF32S,
+            Binary::I64TruncSatF32U => Self::I64TruncSatF32U,
+            Binary::I64TruncSatF64S => Self::I64TruncSatF64S,
+            Binary::I64TruncSatF64U => Self::I64TruncSatF64U,
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Expression(pub Vec<Instruction>);
+
+impl TryFrom<&binary::instr::Expression> for Expression {
+    type Error = anyhow::Error;
+
+    fn try_from(expr: &binary::instr::Expression) -> anyhow::Result<Self> {
+        let instrs = expr.0.iter().enumerate().map(|(i, instr)| {
+            Instruction::try_from(instr).with_context(|| format!("at instruction {i}"))
+        });
+        Ok(Self(instrs.collect::<anyhow::Result<_>>()?))
+    }
+}
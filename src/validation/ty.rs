@@ -1,36 +1,784 @@
-#[derive(Debug, PartialEq, Eq)]
+use std::fmt;
+use std::rc::Rc;
+
+use crate::binary;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Recursive(pub Vec<Sub>);
 
-#[derive(Debug, PartialEq, Eq)]
+/// Rewrites each reference to a defined type within a type
+type MapTypeUse<'a> = dyn FnMut(&TypeUse) -> anyhow::Result<TypeUse> + 'a;
+
+impl Recursive {
+    fn map_type_uses(&self, f: &mut MapTypeUse) -> anyhow::Result<Self> {
+        let subs = self.0.iter().map(|sub| sub.map_type_uses(f));
+        Ok(Self(subs.collect::<anyhow::Result<_>>()?))
+    }
+}
+
+impl From<&binary::ty::Recursive> for Recursive {
+    fn from(recursive: &binary::ty::Recursive) -> Self {
+        Self(recursive.0.iter().map(Sub::from).collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Sub {
     pub is_final: bool,
     pub supers: Vec<TypeUse>,
     pub ty: Composite,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Sub {
+    fn map_type_uses(&self, f: &mut MapTypeUse) -> anyhow::Result<Self> {
+        Ok(Self {
+            is_final: self.is_final,
+            supers: self
+                .supers
+                .iter()
+                .map(&mut *f)
+                .collect::<anyhow::Result<_>>()?,
+            ty: self.ty.map_type_uses(f)?,
+        })
+    }
+}
+
+impl From<&binary::ty::Sub> for Sub {
+    fn from(sub: &binary::ty::Sub) -> Self {
+        Self {
+            is_final: sub.is_final,
+            supers: sub.supers.iter().copied().map(TypeUse::Index).collect(),
+            ty: Composite::from(&sub.composite),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Composite {
     Func(Func),
+    Struct(Vec<Field>),
+    Array(Field),
+}
+
+impl Composite {
+    /// Returns the abstract heap type of the kind of the composite type
+    pub fn abs(&self) -> AbsHeap {
+        match self {
+            Self::Func(_) => AbsHeap::Func,
+            Self::Struct(_) => AbsHeap::Struct,
+            Self::Array(_) => AbsHeap::Array,
+        }
+    }
+
+    /// Checks if the composite type is a subtype of the other, with
+    /// contravariant parameters, covariant results and width subtyping of
+    /// structs
+    pub fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Func(func), Self::Func(other)) => func.matches(other),
+            (Self::Struct(fields), Self::Struct(others)) => {
+                fields.len() >= others.len()
+                    && (fields.iter().zip(others)).all(|(field, other)| field.matches(other))
+            }
+            (Self::Array(field), Self::Array(other)) => field.matches(other),
+            _ => false,
+        }
+    }
+
+    fn map_type_uses(&self, f: &mut MapTypeUse) -> anyhow::Result<Self> {
+        Ok(match self {
+            Self::Func(func) => Self::Func(Func {
+                params: map_values(&func.params, f)?,
+                returns: map_values(&func.returns, f)?,
+            }),
+            Self::Struct(fields) => {
+                let fields = fields.iter().map(|field| field.map_type_uses(f));
+                Self::Struct(fields.collect::<anyhow::Result<_>>()?)
+            }
+            Self::Array(field) => Self::Array(field.map_type_uses(f)?),
+        })
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+fn map_values(values: &[Value], f: &mut MapTypeUse) -> anyhow::Result<Vec<Value>> {
+    values.iter().map(|value| value.map_type_uses(f)).collect()
+}
+
+impl fmt::Display for Composite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Func(func) => func.fmt(f),
+            Self::Struct(fields) => {
+                f.write_str("(struct")?;
+                for field in fields {
+                    write!(f, " (field {field})")?;
+                }
+                f.write_str(")")
+            }
+            Self::Array(field) => write!(f, "(array {field})"),
+        }
+    }
+}
+
+impl From<&binary::ty::Composite> for Composite {
+    fn from(composite: &binary::ty::Composite) -> Self {
+        let values = |values: &[binary::ty::Value]| values.iter().map(Value::from).collect();
+        match composite {
+            binary::ty::Composite::Func { params, returns } => Self::Func(Func {
+                params: values(params),
+                returns: values(returns),
+            }),
+            binary::ty::Composite::Struct(fields) => {
+                Self::Struct(fields.iter().map(Field::from).collect())
+            }
+            binary::ty::Composite::Array(field) => Self::Array(Field::from(field)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Func {
-    params: Vec<Value>,
-    returns: Vec<Value>,
+    pub params: Vec<Value>,
+    pub returns: Vec<Value>,
+}
+
+impl Func {
+    pub fn matches(&self, other: &Self) -> bool {
+        self.params.len() == other.params.len()
+            && self.returns.len() == other.returns.len()
+            && (other.params.iter().zip(&self.params)).all(|(other, param)| other.matches(param))
+            && (self.returns.iter().zip(&other.returns)).all(|(ret, other)| ret.matches(other))
+    }
+}
+
+impl fmt::Display for Func {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(func")?;
+        if !self.params.is_empty() {
+            f.write_str(" (param")?;
+            for param in &self.params {
+                write!(f, " {param}")?;
+            }
+            f.write_str(")")?;
+        }
+        if !self.returns.is_empty() {
+            f.write_str(" (result")?;
+            for ret in &self.returns {
+                write!(f, " {ret}")?;
+            }
+            f.write_str(")")?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub storage: Storage,
+    pub is_mutable: bool,
+}
+
+impl Field {
+    /// Checks if the field is a subtype of the other, covariantly when
+    /// immutable and invariantly when mutable
+    pub fn matches(&self, other: &Self) -> bool {
+        self.is_mutable == other.is_mutable
+            && self.storage.matches(&other.storage)
+            && (!self.is_mutable || other.storage.matches(&self.storage))
+    }
+
+    fn map_type_uses(&self, f: &mut MapTypeUse) -> anyhow::Result<Self> {
+        Ok(Self {
+            storage: match &self.storage {
+                Storage::Value(value) => Storage::Value(value.map_type_uses(f)?),
+                Storage::Pack(pack) => Storage::Pack(*pack),
+            },
+            is_mutable: self.is_mutable,
+        })
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_mutable {
+            true => write!(f, "(mut {})", self.storage),
+            false => self.storage.fmt(f),
+        }
+    }
+}
+
+impl From<&binary::ty::Field> for Field {
+    fn from(field: &binary::ty::Field) -> Self {
+        Self {
+            storage: match &field.storage {
+                binary::ty::Storage::Value(value) => Storage::Value(Value::from(value)),
+                binary::ty::Storage::Pack(binary::ty::Pack::I8) => Storage::Pack(Pack::I8),
+                binary::ty::Storage::Pack(binary::ty::Pack::I16) => Storage::Pack(Pack::I16),
+            },
+            is_mutable: field.is_mutable,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Storage {
+    Value(Value),
+    Pack(Pack),
+}
+
+impl Storage {
+    pub fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Value(value), Self::Value(other)) => value.matches(other),
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Value(value) => value.fmt(f),
+            Self::Pack(Pack::I8) => f.write_str("i8"),
+            Self::Pack(Pack::I16) => f.write_str("i16"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pack {
+    I8,
+    I16,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Reference to a defined type
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TypeUse {
+    /// Index in the type index space of the module
     Index(u32),
+    /// Index in the enclosing recursive group of a rolled-up type
+    Rec(u32),
+    /// Canonical type, independent of the module it is defined in
+    Defined(Defined),
+}
+
+impl fmt::Display for TypeUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => index.fmt(f),
+            Self::Rec(index) => write!(f, "rec.{index}"),
+            Self::Defined(defined) => defined.fmt(f),
+        }
+    }
+}
+
+/// Type of a recursive group, identified by the group rolled up so that
+/// iso-recursively equivalent types are equal, even across modules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Defined {
+    pub rec: Rc<Recursive>,
+    pub index: u32,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Defined {
+    /// Canonicalizes the recursive groups of a type section into the type
+    /// index space, replacing references within a group with `TypeUse::Rec`
+    /// and references to earlier groups with their canonical types
+    pub fn canonicalize(recs: &[Recursive]) -> anyhow::Result<Vec<Self>> {
+        let mut types: Vec<Self> = vec![];
+        for rec in recs {
+            let start = types.len() as u32;
+            let end = start + rec.0.len() as u32;
+            let rolled = rec.map_type_uses(&mut |type_use| match *type_use {
+                TypeUse::Index(index) if index < start => {
+                    Ok(TypeUse::Defined(types[index as usize].clone()))
+                }
+                TypeUse::Index(index) if index < end => Ok(TypeUse::Rec(index - start)),
+                TypeUse::Index(index) => anyhow::bail!("unknown type {index}"),
+                _ => Ok(type_use.clone()),
+            })?;
+            let rec = Rc::new(rolled);
+            types.extend((start..end).map(|index| Self {
+                rec: rec.clone(),
+                index: index - start,
+            }));
+        }
+        Ok(types)
+    }
+
+    /// Returns the unrolled function type if the type is one
+    pub fn func(&self) -> Option<Func> {
+        match self.unroll().ty {
+            Composite::Func(func) => Some(func),
+            _ => None,
+        }
+    }
+
+    /// Returns the sub type in its rolled-up form
+    pub fn sub(&self) -> &Sub {
+        &self.rec.0[self.index as usize]
+    }
+
+    /// Returns the sub type with references within its group replaced by
+    /// their canonical types
+    pub fn unroll(&self) -> Sub {
+        let sub = self.sub().map_type_uses(&mut |type_use| match *type_use {
+            TypeUse::Rec(index) => Ok(TypeUse::Defined(Self {
+                rec: self.rec.clone(),
+                index,
+            })),
+            _ => Ok(type_use.clone()),
+        });
+        sub.expect("unrolling doesn't fail")
+    }
+
+    /// Checks if the type is the other type or one of its transitive
+    /// declared supertypes
+    pub fn matches(&self, other: &Self) -> bool {
+        self == other
+            || (self.unroll().supers.iter()).any(|sup| match sup {
+                TypeUse::Defined(sup) => sup.matches(other),
+                _ => false,
+            })
+    }
+}
+
+impl fmt::Display for Defined {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.sub().ty.fmt(f)
+    }
+}
+
+impl From<Func> for Defined {
+    /// Canonicalizes a function type as the only type of its recursive
+    /// group, final and without supertypes like `(type (func ...))`
+    fn from(func: Func) -> Self {
+        let sub = Sub {
+            is_final: true,
+            supers: vec![],
+            ty: Composite::Func(func),
+        };
+        Self {
+            rec: Rc::new(Recursive(vec![sub])),
+            index: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Value {
     Num(Number),
+    Vec(Vector),
+    Ref(Reference),
+    /// Unknown type of an operand popped from the polymorphic stack of
+    /// unreachable code
     Bottom,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Value {
+    /// Checks if the value type is a subtype of the other, with the bottom
+    /// type matching any type in both directions as it stands for an
+    /// unknown type
+    pub fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Bottom, _) | (_, Self::Bottom) => true,
+            (Self::Ref(reference), Self::Ref(other)) => reference.matches(other),
+            _ => self == other,
+        }
+    }
+
+    /// Checks if the value type has a default value, which non-nullable
+    /// references lack
+    pub fn is_defaultable(&self) -> bool {
+        !matches!(
+            self,
+            Self::Ref(Reference {
+                is_nullable: false,
+                ..
+            })
+        )
+    }
+
+    pub(super) fn map_type_uses(&self, f: &mut MapTypeUse) -> anyhow::Result<Self> {
+        Ok(match self {
+            Self::Ref(reference) => Self::Ref(Reference {
+                is_nullable: reference.is_nullable,
+                heap: match &reference.heap {
+                    Heap::Defined(type_use) => Heap::Defined(f(type_use)?),
+                    heap => heap.clone(),
+                },
+            }),
+            value => value.clone(),
+        })
+    }
+}
+
+impl From<&binary::ty::Value> for Value {
+    fn from(value: &binary::ty::Value) -> Self {
+        match value {
+            binary::ty::Value::Num(number) => Self::Num(match number {
+                binary::ty::Number::I32 => Number::I32,
+                binary::ty::Number::I64 => Number::I64,
+                binary::ty::Number::F32 => Number::F32,
+                binary::ty::Number::F64 => Number::F64,
+            }),
+            binary::ty::Value::Vec(binary::ty::Vector::Vec) => Self::Vec(Vector::V128),
+            binary::ty::Value::Ref(reference) => Self::Ref(Reference::from(reference)),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Num(number) => number.fmt(f),
+            Self::Vec(vector) => vector.fmt(f),
+            Self::Ref(reference) => reference.fmt(f),
+            Self::Bottom => f.write_str("bot"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Number {
     I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vector {
+    V128,
+}
+
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::V128 => "v128",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub is_nullable: bool,
+    pub heap: Heap,
+}
+
+impl Reference {
+    pub fn matches(&self, other: &Self) -> bool {
+        (!self.is_nullable || other.is_nullable) && self.heap.matches(&other.heap)
+    }
+}
+
+impl From<&binary::ty::Reference> for Reference {
+    fn from(reference: &binary::ty::Reference) -> Self {
+        Self {
+            is_nullable: reference.is_nullable,
+            heap: Heap::from(&reference.heap),
+        }
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_nullable {
+            true => write!(f, "(ref null {})", self.heap),
+            false => write!(f, "(ref {})", self.heap),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Heap {
+    Abstract(AbsHeap),
+    Defined(TypeUse),
+}
+
+impl Heap {
+    /// Checks if the heap type is a subtype of the other, where defined
+    /// types are below the abstract type of their kind and above its bottom
+    /// type, and types in a module are expected to be canonicalized
+    pub fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Abstract(abs), Self::Abstract(other)) => abs.matches(*other),
+            (Self::Defined(TypeUse::Defined(defined)), Self::Abstract(other)) => {
+                defined.sub().ty.abs().matches(*other)
+            }
+            (Self::Abstract(abs), Self::Defined(TypeUse::Defined(other))) => {
+                *abs == other.sub().ty.abs().bottom()
+            }
+            (Self::Defined(TypeUse::Defined(defined)), Self::Defined(TypeUse::Defined(other))) => {
+                defined.matches(other)
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl From<&binary::ty::Heap> for Heap {
+    fn from(heap: &binary::ty::Heap) -> Self {
+        use binary::ty::AbsHeap as Binary;
+        let abs = match heap {
+            binary::ty::Heap::Concrete(index) => return Self::Defined(TypeUse::Index(*index)),
+            binary::ty::Heap::Abstract(abs) => abs,
+        };
+        Self::Abstract(match abs {
+            Binary::Func => AbsHeap::Func,
+            Binary::Extern => AbsHeap::Extern,
+            Binary::Any => AbsHeap::Any,
+            Binary::Eq => AbsHeap::Eq,
+            Binary::I31 => AbsHeap::I31,
+            Binary::Struct => AbsHeap::Struct,
+            Binary::Array => AbsHeap::Array,
+            Binary::Exception => AbsHeap::Exception,
+            Binary::None => AbsHeap::None,
+            Binary::NoExtern => AbsHeap::NoExtern,
+            Binary::NoFunc => AbsHeap::NoFunc,
+            Binary::NoException => AbsHeap::NoException,
+        })
+    }
+}
+
+impl fmt::Display for Heap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Abstract(abs) => abs.fmt(f),
+            Self::Defined(type_use) => type_use.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbsHeap {
+    Func,
+    Extern,
+    Any,
+    Eq,
+    I31,
+    Struct,
+    Array,
+    Exception,
+    None,
+    NoExtern,
+    NoFunc,
+    NoException,
+}
+
+impl AbsHeap {
+    pub fn matches(self, other: Self) -> bool {
+        use AbsHeap::*;
+        self == other
+            || matches!(
+                (self, other),
+                (None, Any | Eq | I31 | Struct | Array)
+                    | (I31 | Struct | Array, Any | Eq)
+                    | (Eq, Any)
+                    | (NoFunc, Func)
+                    | (NoExtern, Extern)
+                    | (NoException, Exception)
+            )
+    }
+
+    /// Returns the bottom type of the hierarchy the type belongs to
+    pub fn bottom(self) -> Self {
+        match self {
+            Self::Func | Self::NoFunc => Self::NoFunc,
+            Self::Extern | Self::NoExtern => Self::NoExtern,
+            Self::Exception | Self::NoException => Self::NoException,
+            _ => Self::None,
+        }
+    }
+}
+
+impl fmt::Display for AbsHeap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Func => "func",
+            Self::Extern => "extern",
+            Self::Any => "any",
+            Self::Eq => "eq",
+            Self::I31 => "i31",
+            Self::Struct => "struct",
+            Self::Array => "array",
+            Self::Exception => "exn",
+            Self::None => "none",
+            Self::NoExtern => "noextern",
+            Self::NoFunc => "nofunc",
+            Self::NoException => "noexn",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    I32,
+    I64,
+}
+
+impl Address {
+    /// Returns the type of addresses and offsets
+    pub fn value(self) -> Value {
+        match self {
+            Self::I32 => Value::Num(Number::I32),
+            Self::I64 => Value::Num(Number::I64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limit {
+    pub address: Address,
+    pub min: u64,
+    pub max: Option<u64>,
+}
+
+impl From<&binary::ty::Limit> for Limit {
+    fn from(limit: &binary::ty::Limit) -> Self {
+        Self {
+            address: match limit.address {
+                binary::ty::Address::I32 => Address::I32,
+                binary::ty::Address::I64 => Address::I64,
+            },
+            min: limit.min,
+            max: limit.max,
+        }
+    }
+}
+
+impl Limit {
+    /// Checks if the limits are within the other ones, with the same
+    /// address type
+    pub fn matches(&self, other: &Self) -> bool {
+        self.address == other.address
+            && self.min >= other.min
+            && other
+                .max
+                .is_none_or(|max| self.max.is_some_and(|own| own <= max))
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.address == Address::I64 {
+            f.write_str("i64 ")?;
+        }
+        write!(f, "{}", self.min)?;
+        if let Some(max) = self.max {
+            write!(f, " {max}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub reference: Reference,
+    pub limit: Limit,
+}
+
+impl From<&binary::ty::Table> for Table {
+    fn from(table: &binary::ty::Table) -> Self {
+        Self {
+            reference: Reference::from(&table.reference),
+            limit: Limit::from(&table.limit),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory(pub Limit);
+
+impl From<&binary::ty::Memory> for Memory {
+    fn from(memory: &binary::ty::Memory) -> Self {
+        Self(Limit::from(&memory.0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Global {
+    pub value: Value,
+    pub is_mutable: bool,
+}
+
+impl From<&binary::ty::Global> for Global {
+    fn from(global: &binary::ty::Global) -> Self {
+        Self {
+            value: Value::from(&global.value),
+            is_mutable: global.is_mutable,
+        }
+    }
+}
+
+/// Type of an imported or exported item, with canonical types of functions
+/// and tags so that they can be matched across modules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum External {
+    Func(Defined),
+    Table(Table),
+    Memory(Memory),
+    Global(Global),
+    Tag(Defined),
+}
+
+impl External {
+    /// Checks if the type of an item matches the type of an import, with
+    /// subtyping of functions and immutable globals, limits within the
+    /// imported ones, and otherwise equal types
+    pub fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Func(func), Self::Func(other)) => func.matches(other),
+            (Self::Table(table), Self::Table(other)) => {
+                table.limit.matches(&other.limit)
+                    && table.reference.matches(&other.reference)
+                    && other.reference.matches(&table.reference)
+            }
+            (Self::Memory(memory), Self::Memory(other)) => memory.0.matches(&other.0),
+            (Self::Global(global), Self::Global(other)) => {
+                global.is_mutable == other.is_mutable
+                    && global.value.matches(&other.value)
+                    && (!global.is_mutable || other.value.matches(&global.value))
+            }
+            (Self::Tag(tag), Self::Tag(other)) => tag == other,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for External {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Func(func) => func.fmt(f),
+            Self::Table(table) => write!(f, "(table {} {})", table.limit, table.reference),
+            Self::Memory(memory) => write!(f, "(memory {})", memory.0),
+            Self::Global(global) if global.is_mutable => {
+                write!(f, "(global (mut {}))", global.value)
+            }
+            Self::Global(global) => write!(f, "(global {})", global.value),
+            Self::Tag(tag) => {
+                f.write_str("(tag")?;
+                let params = tag.func().map_or(vec![], |func| func.params);
+                if !params.is_empty() {
+                    f.write_str(" (param")?;
+                    for param in &params {
+                        write!(f, " {param}")?;
+                    }
+                    f.write_str(")")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
 }
@@ -0,0 +1,166 @@
+//! Runs `.wast` scripts against decoding and validation, reporting the
+//! results of each file. `tests/spec` holds scripts vendored from the official
+//! spec testsuite, whose failures are checked against the expected failures
+//! listed in `tests/spec/expected-failures.txt`, while `tests/wast` holds
+//! chikuwa's own scripts, which must all pass.
+//!
+//! Commands other than `module`, `assert_malformed` and `assert_invalid` need
+//! an execution engine and are skipped. Expected error messages aren't
+//! compared as chikuwa words its errors differently from the reference
+//! interpreter.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use chikuwa::{binary, features::WasmFeatures, text, validation};
+use wast::parser::{self, ParseBuffer};
+use wast::{QuoteWat, QuoteWatTest, Wast, WastDirective};
+
+#[derive(Debug, Default)]
+struct Report {
+    passed: usize,
+    /// Failed commands with their line and column in the script
+    failed: Vec<String>,
+    skipped: usize,
+}
+
+/// Decodes a module of a script, parsing it with chikuwa's text parser when
+/// it is quoted
+fn decode(module: &mut QuoteWat) -> anyhow::Result<binary::Module> {
+    match module.to_test()? {
+        QuoteWatTest::Binary(bytes) => binary::Module::decode(bytes),
+        QuoteWatTest::Text(source) => text::parse(std::str::from_utf8(&source)?),
+    }
+}
+
+fn validate(module: &mut QuoteWat) -> anyhow::Result<validation::Validated> {
+    validation::validate(&decode(module)?, WasmFeatures::default())
+}
+
+fn run(source: &str) -> anyhow::Result<Report> {
+    let buffer = ParseBuffer::new(source)?;
+    let wast: Wast = parser::parse(&buffer)?;
+    let mut report = Report::default();
+    for directive in wast.directives {
+        let (line, col) = directive.span().linecol_in(source);
+        let result = match directive {
+            WastDirective::Module(mut module) | WastDirective::ModuleDefinition(mut module) => {
+                validate(&mut module).map(drop)
+            }
+            WastDirective::AssertMalformed {
+                mut module,
+                message,
+                ..
+            } => match decode(&mut module) {
+                Ok(_) => Err(anyhow::anyhow!("decoded a malformed module: {message}")),
+                Err(_) => Ok(()),
+            },
+            WastDirective::AssertInvalid {
+                mut module,
+                message,
+                ..
+            } => decode(&mut module).and_then(|module| {
+                match validation::validate(&module, WasmFeatures::default()) {
+                    Ok(_) => Err(anyhow::anyhow!("validated an invalid module: {message}")),
+                    Err(_) => Ok(()),
+                }
+            }),
+            _ => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        match result {
+            Ok(()) => report.passed += 1,
+            Err(error) => report
+                .failed
+                .push(format!("{}:{}: {error:#}", line + 1, col + 1)),
+        }
+    }
+    Ok(report)
+}
+
+/// Runs the scripts of a directory in order of their names, returning the
+/// failed commands prefixed with the name of their file, or the file alone
+/// when it can't be parsed
+fn run_dir(dir: &str) -> anyhow::Result<Vec<String>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| "wast" == extension)
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    anyhow::ensure!(!paths.is_empty(), "no .wast scripts in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let source = std::fs::read_to_string(&path)?;
+        let report = match run(&source) {
+            Ok(report) => report,
+            Err(error) => {
+                println!("{name}: failed to run: {error:#}");
+                failures.push(name);
+                continue;
+            }
+        };
+        println!(
+            "{name}: {} passed, {} failed, {} skipped",
+            report.passed,
+            report.failed.len(),
+            report.skipped,
+        );
+        let failed = report.failed.iter();
+        failures.extend(failed.map(|failure| format!("{name}:{failure}")));
+    }
+    Ok(failures)
+}
+
+#[test]
+#[ignore = "needs the spec testsuite, vendored with tests/spec/update.sh"]
+fn spec_testsuite() -> anyhow::Result<()> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/spec/expected-failures.txt");
+    let expected: HashSet<_> = std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect();
+    let failures = run_dir("tests/spec")?;
+    // Failures are listed by their location, without the error
+    let location = |failure: &str| failure.splitn(4, ':').take(3).collect::<Vec<_>>().join(":");
+    let locations: HashSet<_> = failures.iter().map(|failure| location(failure)).collect();
+    let unexpected: Vec<_> = (failures.iter().map(String::as_str))
+        .filter(|failure| !expected.contains(&location(failure)))
+        .collect();
+    let mut fixed: Vec<_> = expected
+        .difference(&locations)
+        .map(String::as_str)
+        .collect();
+    fixed.sort();
+    println!(
+        "spec testsuite: {} failures, {} expected",
+        failures.len(),
+        expected.len(),
+    );
+    assert!(
+        unexpected.is_empty() && fixed.is_empty(),
+        "unexpected failures:\n{}\nexpected failures that now pass:\n{}",
+        unexpected.join("\n"),
+        fixed.join("\n"),
+    );
+    Ok(())
+}
+
+#[test]
+fn wast_scripts() -> anyhow::Result<()> {
+    let failures = run_dir("tests/wast")?;
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    Ok(())
+}
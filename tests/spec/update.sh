@@ -0,0 +1,14 @@
+#!/bin/sh
+# Vendors the top-level scripts of the spec testsuite at a revision
+set -eu
+
+revision=${1:?usage: update.sh <revision>}
+dir=$(cd "$(dirname "$0")" && pwd)
+checkout=$(mktemp -d)
+trap 'rm -rf "$checkout"' EXIT
+
+git clone --quiet https://github.com/WebAssembly/testsuite "$checkout"
+git -C "$checkout" checkout --quiet "$revision"
+rm -f "$dir"/*.wast
+cp "$checkout"/*.wast "$dir"
+git -C "$checkout" rev-parse HEAD > "$dir/REVISION"
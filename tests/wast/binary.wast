@@ -0,0 +1,97 @@
+;; Module header and section encodings, after binary.wast of the spec
+;; testsuite
+
+(module binary "\00asm" "\01\00\00\00")
+(module binary "\00asm\01\00\00\00")
+(module binary
+  "\00asm" "\01\00\00\00"
+  "\01\04\01\60\00\00"        ;; type section: (func)
+  "\03\02\01\00"              ;; func section: 1 function of type 0
+  "\0a\04\01\02\00\0b"        ;; code section: empty body
+)
+
+(assert_malformed (module binary "") "unexpected end")
+(assert_malformed (module binary "\01") "unexpected end")
+(assert_malformed (module binary "\00as") "unexpected end")
+(assert_malformed (module binary "asm\00") "magic header not detected")
+(assert_malformed (module binary "msa\00") "magic header not detected")
+(assert_malformed (module binary "msa\00\01\00\00\00") "magic header not detected")
+(assert_malformed (module binary "\00ASM\01\00\00\00") "magic header not detected")
+(assert_malformed (module binary "\00asm") "unexpected end")
+(assert_malformed (module binary "\00asm\01") "unexpected end")
+(assert_malformed (module binary "\00asm\01\00\00") "unexpected end")
+(assert_malformed (module binary "\00asm\00\00\00\00") "unknown binary version")
+(assert_malformed (module binary "\00asm\0d\00\00\00") "unknown binary version")
+(assert_malformed (module binary "\00asm\00\00\00\01") "unknown binary version")
+
+;; Unknown section ID
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\0e\01\00")
+  "malformed section id"
+)
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\7f\01\00")
+  "malformed section id"
+)
+
+;; Function type with a malformed form
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\01\04\01\61\00\00")
+  "malformed function type"
+)
+
+;; Function type with a malformed value type
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\01\05\01\60\01\40\00")
+  "malformed value type"
+)
+
+;; Section ending early
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\01\03\01\60\00")
+  "unexpected end"
+)
+
+;; Import with a malformed kind
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\02\04\01\00\00\05")
+  "malformed import kind"
+)
+
+;; Global with a malformed mutability
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\06\06\01\7f\02\41\00\0b")
+  "malformed mutability"
+)
+
+;; Element segment with malformed flags
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\09\02\01\08")
+  "malformed elements segment kind"
+)
+
+;; Data segment with malformed flags
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\0b\02\01\03")
+  "malformed data segment kind"
+)
+
+;; Sections out of order, duplicated, or with a wrong byte count
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\01\01\00" "\01\01\00")
+  "unexpected content after last section"
+)
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\03\01\00" "\01\01\00")
+  "unexpected content after last section"
+)
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\01\02\00" "\00")
+  "section size mismatch"
+)
+
+;; Function section without a matching code section
+(assert_malformed
+  (module binary "\00asm" "\01\00\00\00" "\01\04\01\60\00\00" "\03\02\01\00")
+  "function and code section have inconsistent lengths"
+)
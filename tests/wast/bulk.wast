@@ -0,0 +1,89 @@
+;; Bulk memory and table instructions, after bulk.wast and ref_func.wast of
+;; the spec testsuite
+
+(module
+  (memory 1)
+  (data "foo")
+  (table 3 funcref)
+  (elem funcref (ref.func $zero) (ref.func $one) (ref.null func))
+  (func $zero (result i32) (i32.const 0))
+  (func $one (result i32) (i32.const 1))
+  (func (export "init") (param i32 i32 i32)
+    (memory.init 0 (local.get 0) (local.get 1) (local.get 2))
+    (table.init 0 (local.get 0) (local.get 1) (local.get 2))
+  )
+  (func (export "drop")
+    (data.drop 0)
+    (elem.drop 0)
+  )
+  (func (export "copy") (param i32 i32 i32)
+    (memory.copy (local.get 0) (local.get 1) (local.get 2))
+    (table.copy (local.get 0) (local.get 1) (local.get 2))
+  )
+  (func (export "fill") (param i32 i32 i32)
+    (memory.fill (local.get 0) (local.get 1) (local.get 2))
+    (table.fill (local.get 0) (ref.func $zero) (local.get 2))
+  )
+)
+
+(module
+  (func $f (export "f"))
+  (global funcref (ref.func $g))
+  (elem declare func $h)
+  (func $g)
+  (func $h)
+  (func
+    (drop (ref.func $f))
+    (drop (ref.func $g))
+    (drop (ref.func $h))
+  )
+)
+
+(assert_invalid
+  (module (func $f (drop (ref.func $f))))
+  "undeclared function reference"
+)
+(assert_invalid
+  (module (func (data.drop 0)))
+  "unknown data segment"
+)
+(assert_invalid
+  (module (memory 1) (func (memory.init 1 (i32.const 0) (i32.const 0) (i32.const 0))) (data ""))
+  "unknown data segment"
+)
+(assert_invalid
+  (module (func (elem.drop 0)))
+  "unknown elem segment"
+)
+(assert_invalid
+  (module
+    (table 1 externref)
+    (elem funcref (ref.null func))
+    (func (table.init 0 (i32.const 0) (i32.const 0) (i32.const 0)))
+  )
+  "type mismatch"
+)
+(assert_invalid
+  (module (elem funcref (ref.null extern)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (memory 1) (func (memory.fill (i32.const 0) (i64.const 0) (i32.const 0))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (table 1 funcref) (func (table.fill (i32.const 0) (ref.null extern) (i32.const 1))))
+  "type mismatch"
+)
+
+;; A data count section disagreeing with the data section
+(assert_malformed
+  (module binary
+    "\00asm" "\01\00\00\00"
+    "\05\03\01"                     ;; memory section
+    "\00\01"                        ;; memory 0 1
+    "\0c\01"                        ;; data count section
+    "\01"                           ;; 1 segment
+  )
+  "data count and data section have inconsistent lengths"
+)
@@ -0,0 +1,108 @@
+;; Globals and constant expressions, after global.wast of the spec testsuite
+
+(module
+  (global (import "spectest" "global_i32") i32)
+  (global (import "spectest" "global_i64") i64)
+
+  (global $a i32 (i32.const -2))
+  (global (;3;) f32 (f32.const -3))
+  (global (;4;) f64 (f64.const -4))
+  (global $b i64 (i64.const -5))
+
+  (global $x (mut i32) (i32.const -12))
+  (global (;7;) (mut f32) (f32.const -13))
+  (global (;8;) (mut f64) (f64.const -14))
+  (global $y (mut i64) (i64.const -15))
+
+  (global $z1 i32 (global.get 0))
+  (global $z2 i64 (global.get 1))
+
+  (global $r externref (ref.null extern))
+  (global $mr (mut externref) (ref.null extern))
+  (global funcref (ref.null func))
+
+  (func (export "get-a") (result i32) (global.get $a))
+  (func (export "get-x") (result i32) (global.get $x))
+  (func (export "set-x") (param i32) (global.set $x (local.get 0)))
+  (func (export "get-r") (result externref) (global.get $r))
+  (func (export "set-mr") (param externref) (global.set $mr (local.get 0)))
+)
+
+(assert_return (invoke "get-a") (i32.const -2))
+(assert_return (invoke "set-x" (i32.const 6)))
+(assert_return (invoke "get-x") (i32.const 6))
+
+;; Extended constant expressions
+(module
+  (global $g i32 (i32.const 3))
+  (global i32 (i32.add (global.get $g) (i32.mul (i32.const 2) (i32.const 4))))
+  (global i64 (i64.sub (i64.const 10) (i64.const 4)))
+)
+
+(assert_invalid
+  (module (global f32 (f32.const 0)) (func (global.set 0 (f32.const 1))))
+  "immutable global"
+)
+(assert_invalid
+  (module (import "spectest" "global_i32" (global i32)) (func (global.set 0 (i32.const 1))))
+  "immutable global"
+)
+
+(assert_invalid
+  (module (global i32 (i32.ctz (i32.const 0))))
+  "constant expression required"
+)
+(assert_invalid
+  (module (global i32 (i32.const 0) (nop)))
+  "constant expression required"
+)
+(assert_invalid
+  (module (global i32 (nop)))
+  "constant expression required"
+)
+(assert_invalid
+  (module (global (mut i32) (i32.const 0)) (global i32 (global.get 0)))
+  "constant expression required"
+)
+(assert_invalid
+  (module (global i32 (loop (result i32) (i32.const 0))))
+  "constant expression required"
+)
+
+(assert_invalid
+  (module (global i32 (f32.const 0)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (global i32 (i32.const 0) (i32.const 0)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (global i32))
+  "type mismatch"
+)
+(assert_invalid
+  (module (global (import "" "") externref) (global funcref (global.get 0)))
+  "type mismatch"
+)
+
+(assert_invalid
+  (module (global i32 (global.get 0)))
+  "unknown global"
+)
+(assert_invalid
+  (module (global i32 (global.get 1)) (global i32 (i32.const 0)))
+  "unknown global"
+)
+(assert_invalid
+  (module (func (result i32) (global.get 0)))
+  "unknown global"
+)
+(assert_invalid
+  (module (global $g i32 (i32.const 0)) (func (result i64) (global.get $g)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (global $g (mut i32) (i32.const 0)) (func (global.set $g (i64.const 1))))
+  "type mismatch"
+)
@@ -0,0 +1,102 @@
+;; Memory types, after memory.wast of the spec testsuite
+
+(module (memory 0))
+(module (memory 1))
+(module (memory 0 0))
+(module (memory 1 256))
+(module (memory 0 65536))
+(module (memory i64 0 65536))
+(module (memory i64 0x1_0000_0000_0000))
+
+(module (data (memory 0) (i32.const 0)) (memory 1))
+(module (memory 1) (data (i32.const 0) "a" "" "bcd"))
+(module (memory i64 1) (data (i64.const 0) "a" "" "bcd"))
+(module (memory 1) (func (drop (memory.size))))
+(module (memory 1) (func (drop (memory.grow (i32.const 0)))))
+
+(assert_invalid (module (data (i32.const 0))) "unknown memory")
+(assert_invalid (module (data (i32.const 0) "")) "unknown memory")
+(assert_invalid (module (func (drop (memory.size)))) "unknown memory")
+(assert_invalid (module (func (drop (memory.grow (i32.const 0))))) "unknown memory")
+(assert_invalid
+  (module (func $f (drop (i32.load8_s (i32.const 0)))))
+  "unknown memory"
+)
+(assert_invalid
+  (module (func $f (i32.store8 (i32.const 0) (i32.const 0))))
+  "unknown memory"
+)
+
+(assert_invalid
+  (module (memory 1 0))
+  "size minimum must not be greater than maximum"
+)
+(assert_invalid
+  (module (memory 65537))
+  "memory size must be at most 65536 pages (4GiB)"
+)
+(assert_invalid
+  (module (memory 2147483648))
+  "memory size must be at most 65536 pages (4GiB)"
+)
+(assert_invalid
+  (module (memory 4294967295))
+  "memory size must be at most 65536 pages (4GiB)"
+)
+(assert_invalid
+  (module (memory 0 65537))
+  "memory size must be at most 65536 pages (4GiB)"
+)
+(assert_invalid
+  (module (memory i64 0x1_0000_0000_0001))
+  "memory size must be at most 2^48 pages"
+)
+
+(assert_invalid
+  (module (memory 1) (data (i64.const 0)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (memory i64 1) (data (i32.const 0)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (memory 1) (data (i32.ctz (i32.const 0))))
+  "constant expression required"
+)
+
+(assert_invalid
+  (module (memory 1) (func (drop (i32.load align=8 (i32.const 0)))))
+  "alignment must not be larger than natural"
+)
+(assert_invalid
+  (module (memory 1) (func (drop (i64.load16_u align=4 (i32.const 0)))))
+  "alignment must not be larger than natural"
+)
+(assert_invalid
+  (module (memory 1) (func (i32.store8 align=2 (i32.const 0) (i32.const 0))))
+  "alignment must not be larger than natural"
+)
+(assert_invalid
+  (module (memory 1) (func (result i32) (i64.load (i32.const 0))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (memory 1) (func (drop (f32.load (i64.const 0)))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (memory i64 1) (func (drop (f32.load (i32.const 0)))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (memory 1) (func (f64.store (i32.const 0) (f32.const 0))))
+  "type mismatch"
+)
+
+(module
+  (memory 1)
+  (data (i32.const 0) "ABC\a7D")
+  (func (export "data") (result i32) (i32.load8_u (i32.const 0)))
+)
+(assert_return (invoke "data") (i32.const 65))
@@ -0,0 +1,38 @@
+;; Start functions, after start.wast of the spec testsuite
+
+(assert_invalid
+  (module (func) (start 1))
+  "unknown function"
+)
+(assert_invalid
+  (module
+    (func $main (result i32) (return (i32.const 0)))
+    (start $main)
+  )
+  "start function"
+)
+(assert_invalid
+  (module
+    (func $main (param $a i32))
+    (start $main)
+  )
+  "start function"
+)
+
+(module (func $main) (start $main))
+(module (func $main) (start 0))
+(module (func $inc) (start $inc) (export "inc" (func $inc)))
+(module
+  (func $print_i32 (import "spectest" "print_i32") (param i32))
+  (func $main (call $print_i32 (i32.const 1)))
+  (start $main)
+)
+(module
+  (func $print (import "spectest" "print"))
+  (start $print)
+)
+
+(assert_malformed
+  (module quote "(module (func $a (unreachable)) (func $b (unreachable)) (start $a) (start $b))")
+  "multiple start sections"
+)
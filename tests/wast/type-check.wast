@@ -0,0 +1,76 @@
+;; Type checking of instruction sequences, after the type-mismatch cases
+;; spread over block.wast, br.wast, select.wast and others of the spec
+;; testsuite
+
+(module (func (result i32) (unreachable)))
+(module (func (result i32) (unreachable) (i32.add)))
+(module (func (result i32) (block (result i32) (br 0 (i32.const 0)))))
+(module
+  (func (param i32) (result i32)
+    (if (result i32) (local.get 0) (then (i32.const 1)) (else (i32.const 0)))
+  )
+)
+(module (func (param i32) (result i32) (loop (result i32) (br_if 0 (local.get 0)) (i32.const 0))))
+(module (func (result i64) (i64.extend_i32_s (i32.wrap_i64 (i64.const 0)))))
+(module (func (result f64) (f64.promote_f32 (f32.demote_f64 (f64.const 1)))))
+(module (func (result i32) (i32.trunc_sat_f64_u (f64.const 1))))
+(module (func (result i64) (i64.extend32_s (i64.const 1))))
+(module (func (result i32) (i32.reinterpret_f32 (f32.const 1))))
+(module (func (result externref) (select (result externref) (ref.null extern) (ref.null extern) (i32.const 1))))
+(module (func (result i32) (ref.is_null (ref.null func))))
+(module
+  (type $t (func (param i32) (result i32)))
+  (table 1 funcref)
+  (func (type $t) (call_indirect (type $t) (local.get 0) (i32.const 0)))
+)
+(module
+  (type $t (func (param i64) (result i64)))
+  (table i64 1 funcref)
+  (func (type $t) (call_indirect (type $t) (local.get 0) (i64.const 0)))
+)
+
+(assert_invalid (module (func (result i32) (i64.const 0))) "type mismatch")
+(assert_invalid (module (func (i32.add) (drop))) "type mismatch")
+(assert_invalid (module (func (result i32) (f32.add (f32.const 0) (f32.const 0)))) "type mismatch")
+(assert_invalid (module (func (result i32) (unreachable) (i64.const 0))) "type mismatch")
+(assert_invalid (module (func (result i32) (block (result i32) (br 0)))) "type mismatch")
+(assert_invalid (module (func (br 1))) "unknown label")
+(assert_invalid (module (func (drop (local.get 0)))) "unknown local")
+(assert_invalid (module (func (call 1))) "unknown function")
+(assert_invalid (module (func (param i32)) (func (call 0 (i64.const 0)))) "type mismatch")
+(assert_invalid
+  (module (func (if (i32.const 1) (then (i32.const 1)))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func (result i32) (if (result i32) (i32.const 1) (then (i32.const 1)))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func (result i32) (select (i32.const 0) (i64.const 0) (i32.const 1))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func (drop (select (ref.null func) (ref.null func) (i32.const 1)))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func (drop (ref.is_null (i32.const 0)))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (type (func)) (func (call_indirect (type 0) (i32.const 0))))
+  "unknown table"
+)
+(assert_invalid
+  (module (type (func)) (table 1 externref) (func (call_indirect (type 0) (i32.const 0))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (type (func)) (table 1 funcref) (func (call_indirect (type 0) (i64.const 0))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (table 1 funcref) (func (call_indirect (type 1) (i32.const 0))))
+  "unknown type"
+)